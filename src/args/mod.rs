@@ -32,7 +32,7 @@ pub async fn read(options: &mut options::Options) -> Result<Vec<Args>, failure::
     Ok(args)
 }
 
-fn generate_final_args(arg: String, command_parts: &[Vec<String>]) -> Args {
+pub fn generate_final_args(arg: String, command_parts: &[Vec<String>]) -> Args {
     let specific = arg.clone();
     if command_parts.len() == 1 {
         let mut all = command_parts.iter().next().unwrap().clone();
@@ -54,7 +54,7 @@ fn parse_delimiter(null: bool, delimiter: Option<u8>) -> Option<u8> {
     }
 }
 
-fn parse_arg_template(initial_args: &[String], replace: &Option<String>) -> Vec<Vec<String>> {
+pub fn parse_arg_template(initial_args: &[String], replace: &Option<String>) -> Vec<Vec<String>> {
     initial_args
         .split(|part| replace.as_ref().map_or_else(|| part == "{}", |s| part == s))
         .map(|s| s.to_vec())
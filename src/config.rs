@@ -0,0 +1,496 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path;
+
+use crate::ui;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub status: Status,
+    pub control: Control,
+    pub colors: Colors,
+    pub theme: Theme,
+    pub borders: Borders,
+    pub keys: Keys,
+    pub errors: Errors,
+    pub monitor: Monitor,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Status {
+    /// Show the current wall-clock time in the status bar
+    pub clock: bool,
+    /// Show the user and host name in the status bar
+    pub host: bool,
+}
+
+/// Access to the control socket; only read at startup.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Control {
+    /// Let other users connect to the control socket; requires `auth` to be set
+    pub shared: bool,
+    /// A shell command that has to exit successfully for a connection to be accepted; it gets
+    /// the peer's credentials in `MUX_PEER_UID`, `MUX_PEER_GID` and `MUX_PEER_PID`
+    pub auth: Option<String>,
+}
+
+/// Key bindings; the defaults are changed by the bindings given here.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Keys {
+    /// The key that has to be pressed before the keys in `bindings`
+    pub prefix: Key,
+    /// Commands run by pressing a key after the prefix key
+    pub bindings: HashMap<Key, ui::Command>,
+    /// Commands run by pressing a key on its own
+    pub global: HashMap<Key, ui::Command>,
+}
+
+/// Which lines of output are counted in the error badge on the tab of each process
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Errors {
+    /// Regular expressions matched against each line, without colors and other escape sequences
+    pub patterns: Vec<Pattern>,
+}
+
+/// Which tabs are highlighted to draw attention to them, like `monitor-activity` and
+/// `monitor-silence` in tmux; each tab starts out with these and can be toggled on its own.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Monitor {
+    /// Highlight tabs whose process printed something that wasn't seen yet
+    pub activity: bool,
+    /// Highlight tabs whose process hasn't printed anything for this many seconds
+    pub silence: Option<u64>,
+}
+
+/// A regular expression
+#[derive(Clone, Debug)]
+pub struct Pattern(pub regex::Regex);
+
+/// A key written like `a`, `C-t` (with Ctrl), `M-n` (with Alt) or a name like `tab` or `f1`
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Key(pub termion::event::Key);
+
+/// The colors that processes get when they ask for the standard terminal colors; unset colors
+/// are left up to the terminal that mux runs in.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Colors {
+    /// How many colors the terminal that mux runs in can show; colors are downgraded to fit
+    pub depth: Depth,
+    pub foreground: Option<Color>,
+    pub background: Option<Color>,
+    pub cursor: Option<Color>,
+    pub cursor_text: Option<Color>,
+    pub normal: Palette,
+    pub bright: Palette,
+    pub dim: Palette,
+}
+
+/// The number of colors a terminal supports
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize)]
+pub enum Depth {
+    /// Guess from the `COLORTERM` and `TERM` environment variables
+    #[serde(rename = "auto")]
+    Auto,
+    #[serde(rename = "truecolor")]
+    TrueColor,
+    #[serde(rename = "256")]
+    Indexed,
+    #[serde(rename = "16")]
+    Ansi,
+}
+
+/// The colors of mux's own parts of the screen
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    /// The border of the selected pane and the background of prompts
+    pub accent: Option<Color>,
+    /// The border of the selected pane, if it should differ from `accent`
+    pub focused: Option<Color>,
+    /// The border of the other panes
+    pub border: Option<Color>,
+    /// The background of the status bar and the compact tabs
+    pub bar: Option<Color>,
+    /// Processes that exited successfully
+    pub ok: Option<Color>,
+    /// Processes that failed
+    pub failed: Option<Color>,
+}
+
+/// How the borders around the panes look, when showing several processes at once
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Borders {
+    /// The lines that the borders are drawn with
+    pub lines: BorderLines,
+    /// The lines around the selected pane, which typed keys go to
+    pub focused: BorderLines,
+    /// Where the title of the process goes in the top border
+    pub title: TitlePlacement,
+}
+
+/// The kind of lines that a border is drawn with
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BorderLines {
+    Plain,
+    Rounded,
+    Double,
+    Thick,
+}
+
+/// Where a title goes in a border
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TitlePlacement {
+    Left,
+    Center,
+    Right,
+    /// Leave the title out
+    None,
+}
+
+/// One set of the eight standard terminal colors
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Palette {
+    pub black: Option<Color>,
+    pub red: Option<Color>,
+    pub green: Option<Color>,
+    pub yellow: Option<Color>,
+    pub blue: Option<Color>,
+    pub magenta: Option<Color>,
+    pub cyan: Option<Color>,
+    pub white: Option<Color>,
+}
+
+/// A color written as `#rrggbb`, a 256-color palette index like `208`, or a name like `red` or
+/// `light-blue`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Color(pub tui::style::Color);
+
+/// A configuration together with the file it was loaded from.
+///
+/// Loading never fails; if the file can't be parsed, the built-in defaults are used and the
+/// problem is kept around so that it can be shown to the user.
+pub struct Source {
+    pub path: Option<path::PathBuf>,
+    pub config: Config,
+    pub error: Option<Error>,
+}
+
+/// A problem with the config file
+#[derive(Debug)]
+pub struct Error {
+    pub path: path::PathBuf,
+    pub message: String,
+    /// The 1-based line number and contents of the offending line, if known
+    pub line: Option<(usize, String)>,
+}
+
+pub fn default_path() -> Option<path::PathBuf> {
+    dirs::config_dir().map(|mut path| {
+        path.push("mux");
+        path.push("config.toml");
+        path
+    })
+}
+
+impl Source {
+    pub fn load(path: Option<path::PathBuf>) -> Self {
+        let (config, error) = match path {
+            Some(ref path) => match load(path) {
+                Ok(config) => (config, None),
+                Err(error) => {
+                    warn!("{}", error);
+                    (Config::default(), Some(error))
+                }
+            },
+            None => (Config::default(), None),
+        };
+
+        Self {
+            path,
+            config,
+            error,
+        }
+    }
+
+    /// Load the config file again, keeping the current config if that fails.
+    ///
+    /// Returns whether the config was successfully reloaded.
+    pub fn reload(&mut self) -> bool {
+        if let Some(ref path) = self.path {
+            match load(path) {
+                Ok(config) => {
+                    info!("reloaded config from {}", path.display());
+                    self.config = config;
+                    self.error = None;
+                    true
+                }
+                Err(error) => {
+                    warn!("{}", error);
+                    self.error = Some(error);
+                    false
+                }
+            }
+        } else {
+            true
+        }
+    }
+}
+
+fn load(path: &path::Path) -> Result<Config, Error> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => {
+            debug!("no config file at {}, using defaults", path.display());
+            return Ok(Config::default());
+        }
+        Err(error) => {
+            return Err(Error {
+                path: path.to_owned(),
+                message: error.to_string(),
+                line: None,
+            });
+        }
+    };
+
+    toml::from_str(&contents).map_err(|error| {
+        let line = error.line_col().and_then(|(line, _)| {
+            contents
+                .lines()
+                .nth(line)
+                .map(|text| (line + 1, text.to_owned()))
+        });
+
+        Error {
+            path: path.to_owned(),
+            message: error.to_string(),
+            line,
+        }
+    })
+}
+
+impl Default for Keys {
+    fn default() -> Self {
+        Keys {
+            prefix: Key(termion::event::Key::Ctrl('t')),
+            bindings: HashMap::new(),
+            global: HashMap::new(),
+        }
+    }
+}
+
+impl Default for Borders {
+    fn default() -> Self {
+        Borders {
+            lines: BorderLines::Plain,
+            focused: BorderLines::Thick,
+            title: TitlePlacement::Left,
+        }
+    }
+}
+
+impl Default for BorderLines {
+    fn default() -> Self {
+        BorderLines::Plain
+    }
+}
+
+impl Default for TitlePlacement {
+    fn default() -> Self {
+        TitlePlacement::Left
+    }
+}
+
+impl Default for Errors {
+    fn default() -> Self {
+        Errors {
+            patterns: vec![Pattern(regex::Regex::new(r"(?i)\berror\b").unwrap())],
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Pattern {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let pattern = String::deserialize(deserializer)?;
+        regex::Regex::new(&pattern).map(Pattern).map_err(|err| {
+            serde::de::Error::custom(format!("invalid pattern {:?}: {}", pattern, err))
+        })
+    }
+}
+
+impl std::str::FromStr for Key {
+    type Err = String;
+
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        use termion::event::Key::*;
+
+        let single = |s: &str| {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Some(c),
+                _ => None,
+            }
+        };
+
+        if key.starts_with("C-") {
+            if let Some(c) = single(&key[2..]) {
+                return Ok(Key(Ctrl(c.to_ascii_lowercase())));
+            }
+        }
+        if key.starts_with("M-") {
+            if let Some(c) = single(&key[2..]) {
+                return Ok(Key(Alt(c)));
+            }
+        }
+        if let Some(c) = single(key) {
+            return Ok(Key(Char(c)));
+        }
+        if key.starts_with('f') {
+            if let Ok(n @ 1..=12) = key[1..].parse() {
+                return Ok(Key(F(n)));
+            }
+        }
+
+        let key = match key {
+            "space" => Char(' '),
+            "enter" => Char('\n'),
+            "tab" => Char('\t'),
+            "esc" => Esc,
+            "backspace" => Backspace,
+            "up" => Up,
+            "down" => Down,
+            "left" => Left,
+            "right" => Right,
+            "home" => Home,
+            "end" => End,
+            "page-up" => PageUp,
+            "page-down" => PageDown,
+            "insert" => Insert,
+            "delete" => Delete,
+            _ => {
+                return Err(format!(
+                    "invalid key {:?}; expected a character, C-x, M-x or a key name",
+                    key
+                ))
+            }
+        };
+        Ok(Key(key))
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Key {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl Default for Depth {
+    fn default() -> Self {
+        Depth::Auto
+    }
+}
+
+impl Palette {
+    /// The color at `index` in the order black, red, green, yellow, blue, magenta, cyan, white
+    pub fn get(&self, index: usize) -> Option<Color> {
+        match index {
+            0 => self.black,
+            1 => self.red,
+            2 => self.green,
+            3 => self.yellow,
+            4 => self.blue,
+            5 => self.magenta,
+            6 => self.cyan,
+            7 => self.white,
+            _ => None,
+        }
+    }
+}
+
+impl std::str::FromStr for Color {
+    type Err = String;
+
+    fn from_str(color: &str) -> Result<Self, Self::Err> {
+        use tui::style::Color::*;
+
+        if color.starts_with('#') && color.len() == 7 {
+            let component = |i: usize| u8::from_str_radix(&color[i..i + 2], 16);
+            return match (component(1), component(3), component(5)) {
+                (Ok(r), Ok(g), Ok(b)) => Ok(Color(Rgb(r, g, b))),
+                _ => Err(format!("invalid color {:?}", color)),
+            };
+        }
+        if let Ok(index) = color.parse() {
+            return Ok(Color(Indexed(index)));
+        }
+
+        let color = match color {
+            "reset" => Reset,
+            "black" => Black,
+            "red" => Red,
+            "green" => Green,
+            "yellow" => Yellow,
+            "blue" => Blue,
+            "magenta" => Magenta,
+            "cyan" => Cyan,
+            "gray" => Gray,
+            "dark-gray" => DarkGray,
+            "light-red" => LightRed,
+            "light-green" => LightGreen,
+            "light-yellow" => LightYellow,
+            "light-blue" => LightBlue,
+            "light-magenta" => LightMagenta,
+            "light-cyan" => LightCyan,
+            "white" => White,
+            _ => {
+                return Err(format!(
+                    "invalid color {:?}; expected #rrggbb, a number up to 255 or a color name",
+                    color
+                ))
+            }
+        };
+        Ok(Color(color))
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Color {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid config {}: {}",
+            self.path.display(),
+            self.message
+        )
+    }
+}
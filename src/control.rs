@@ -0,0 +1,438 @@
+//! The control socket, which lets scripts inspect and drive a running session with `mux ctl`.
+//!
+//! A client connects, writes its command line as NUL-separated arguments and shuts down its
+//! write half; the server answers with `ok` or `error` on the first line followed by the output
+//! or error message, and closes the connection.
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path;
+use std::process;
+use std::sync;
+
+use crate::config;
+use crate::sys;
+
+/// Environment variable that tells child processes where the control socket is
+pub const SOCKET_VAR: &str = "MUX_SOCKET";
+/// Environment variable that tells child processes the ID of their pane
+pub const PANE_VAR: &str = "MUX_PANE";
+
+#[derive(Debug)]
+pub enum Request {
+    /// List all panes with their ID, state, title and argument
+    List,
+    /// List all panes with their ID, roughly how much memory their output takes up, and title
+    Stats,
+    /// Select (focus) a pane
+    SelectPane { target: Target },
+    /// Type keys into one or more panes
+    SendKeys {
+        target: Target,
+        keys: Vec<String>,
+        /// Confirms that the keys should go to all matching panes if there are several
+        all: bool,
+    },
+    /// Change the title of a pane
+    RenamePane { target: Target, title: String },
+    /// Move panes into a group of tabs, or out of their group with `None`
+    GroupPane {
+        target: Target,
+        group: Option<String>,
+    },
+    /// Save the output of all panes to a file for `mux restore`
+    Snapshot { path: std::path::PathBuf },
+}
+
+/// Which panes a request applies to
+#[derive(Clone, Debug)]
+pub enum Target {
+    /// The pane with this ID, written as `%ID` or just `ID`
+    Pane(usize),
+    /// Panes whose title or argument matches a glob pattern like `host-eu-*`
+    Glob(glob::Pattern),
+    /// Panes whose title or argument matches a regular expression, written as `/regex/`
+    Regex(regex::Regex),
+    /// The panes in a group of tabs, written as `@GROUP`
+    Group(String),
+}
+
+/// The output of a request, or an error message
+pub type Response = Result<String, String>;
+
+/// Sends the response to a request back to the client that made it.
+pub struct Responder(sync::Mutex<Option<futures::sync::oneshot::Sender<Response>>>);
+
+/// The bound control socket; the socket file is removed again when this is dropped.
+pub struct Socket {
+    pub path: path::PathBuf,
+}
+
+/// Where to put the socket; a shared socket can't be in the user's private runtime directory.
+pub fn default_path(settings: &config::Control) -> path::PathBuf {
+    let mut path = if settings.shared {
+        env::temp_dir()
+    } else {
+        dirs::runtime_dir().unwrap_or_else(env::temp_dir)
+    };
+    path.push(format!("mux-{}.sock", process::id()));
+    path
+}
+
+impl Socket {
+    /// Start listening on `path`, returning a stream of incoming requests.
+    pub fn bind(
+        path: path::PathBuf,
+        settings: &config::Control,
+    ) -> Result<
+        (
+            Self,
+            impl futures::stream::Stream<Item = (Request, Responder), Error = failure::Error>,
+        ),
+        failure::Error,
+    > {
+        use futures::stream::Stream;
+        use std::os::unix::fs::PermissionsExt;
+
+        // A left-over socket from a crashed session with the same PID would make bind fail
+        let _ = fs::remove_file(&path);
+        let listener = tokio::net::UnixListener::bind(&path)?;
+
+        let shared = if settings.shared && settings.auth.is_none() {
+            warn!("not sharing the control socket with other users since no auth command is set");
+            false
+        } else {
+            settings.shared
+        };
+        let mode = if shared { 0o666 } else { 0o600 };
+        fs::set_permissions(&path, fs::Permissions::from_mode(mode))?;
+
+        debug!("listening for control requests on {}", path.display());
+
+        let auth = settings.auth.clone();
+        let requests = listener
+            .incoming()
+            .map_err(failure::Error::from)
+            .map(move |stream| {
+                use futures::future::Future;
+
+                handle(stream, auth.clone()).then(|result| {
+                    Ok::<_, failure::Error>(result.unwrap_or_else(|err| {
+                        warn!("control connection failed: {}", err);
+                        None
+                    }))
+                })
+            })
+            .buffer_unordered(16)
+            .filter_map(|request| request);
+
+        Ok((Self { path }, requests))
+    }
+}
+
+/// Check whether the client may connect, and if so read its request.
+fn handle(
+    stream: tokio::net::UnixStream,
+    auth: Option<String>,
+) -> impl futures::future::Future<Item = Option<(Request, Responder)>, Error = failure::Error> {
+    use futures::future::Either;
+    use futures::future::Future;
+    use std::os::unix::io::AsRawFd;
+
+    let peer = sys::peer::credentials(stream.as_raw_fd());
+
+    futures::future::poll_fn(move || tokio_threadpool::blocking(|| authorize(&peer, &auth)))
+        .map_err(failure::Error::from)
+        .and_then(move |allowed| {
+            if allowed? {
+                Ok(Either::A(
+                    tokio::io::read_to_end(stream, Vec::new())
+                        .map_err(failure::Error::from)
+                        .and_then(|(stream, data)| accept(stream, &data)),
+                ))
+            } else {
+                let denied = encode(&Err("permission denied".to_owned()));
+                Ok(Either::B(
+                    tokio::io::write_all(stream, denied)
+                        .map(|_| None)
+                        .map_err(failure::Error::from),
+                ))
+            }
+        })
+        .flatten()
+}
+
+/// Run the auth command, if any, to decide whether to accept a connection from `peer`.
+fn authorize(
+    peer: &std::io::Result<sys::peer::Credentials>,
+    auth: &Option<String>,
+) -> Result<bool, failure::Error> {
+    let command = match *auth {
+        Some(ref command) => command,
+        None => return Ok(true),
+    };
+    let peer = match *peer {
+        Ok(ref peer) => peer,
+        Err(ref err) => {
+            warn!("could not get the credentials of a control client: {}", err);
+            return Ok(false);
+        }
+    };
+
+    let status = process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("MUX_PEER_UID", peer.uid.to_string())
+        .env("MUX_PEER_GID", peer.gid.to_string())
+        .env(
+            "MUX_PEER_PID",
+            peer.pid.map(|pid| pid.to_string()).unwrap_or_default(),
+        )
+        .stdin(process::Stdio::null())
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::null())
+        .status()?;
+
+    if !status.success() {
+        info!(
+            "control connection from uid {} denied by auth command ({})",
+            peer.uid, status
+        );
+    }
+    Ok(status.success())
+}
+
+/// Parse a request read from `stream` and arrange for the response to be written back to it.
+fn accept(
+    stream: tokio::net::UnixStream,
+    data: &[u8],
+) -> Result<Option<(Request, Responder)>, failure::Error> {
+    use futures::future::Future;
+
+    let args = String::from_utf8(data.to_vec())?
+        .split('\0')
+        .map(str::to_owned)
+        .collect::<Vec<_>>();
+    let (sender, receiver) = futures::sync::oneshot::channel();
+
+    tokio::spawn(
+        receiver
+            .or_else(|_| Ok::<_, std::io::Error>(Err("mux is shutting down".to_owned())))
+            .and_then(move |response| tokio::io::write_all(stream, encode(&response)))
+            .map(|_| ())
+            .map_err(|err: std::io::Error| warn!("could not send control response: {}", err)),
+    );
+
+    match Request::parse(&args) {
+        Ok(request) => Ok(Some((request, Responder::new(sender)))),
+        Err(err) => {
+            let _ = sender.send(Err(err));
+            Ok(None)
+        }
+    }
+}
+
+fn encode(response: &Response) -> Vec<u8> {
+    match *response {
+        Ok(ref output) => format!("ok\n{}", output).into_bytes(),
+        Err(ref message) => format!("error\n{}", message).into_bytes(),
+    }
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+impl Request {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let (command, args) = match args.split_first() {
+            Some((command, args)) => (command.as_str(), args),
+            None => return Err("missing command".to_owned()),
+        };
+
+        let mut target = None;
+        let mut all = false;
+        let mut rest = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "-t" {
+                let value = args.next().ok_or("missing value for -t")?;
+                target = Some(Target::parse(value)?);
+            } else if arg == "-y" {
+                all = true;
+            } else {
+                rest.push(arg.clone());
+            }
+        }
+        let require_target = || {
+            target
+                .clone()
+                .ok_or_else(|| format!("{} needs a target (-t)", command))
+        };
+
+        match command {
+            "list" => Ok(Request::List),
+            "stats" => Ok(Request::Stats),
+            "select-pane" => Ok(Request::SelectPane {
+                target: require_target()?,
+            }),
+            "send-keys" => Ok(Request::SendKeys {
+                target: require_target()?,
+                keys: rest,
+                all,
+            }),
+            "rename-pane" => match rest.len() {
+                1 => Ok(Request::RenamePane {
+                    target: require_target()?,
+                    title: rest.remove(0),
+                }),
+                _ => Err("rename-pane needs exactly one title".to_owned()),
+            },
+            "group-pane" => match rest.len() {
+                1 => {
+                    let group = rest.remove(0);
+                    Ok(Request::GroupPane {
+                        target: require_target()?,
+                        group: if group.is_empty() { None } else { Some(group) },
+                    })
+                }
+                _ => Err("group-pane needs exactly one group name".to_owned()),
+            },
+            "snapshot" => match rest.len() {
+                1 => Ok(Request::Snapshot {
+                    path: rest.remove(0).into(),
+                }),
+                _ => Err("snapshot needs exactly one file".to_owned()),
+            },
+            _ => Err(format!("unknown command: {:?}", command)),
+        }
+    }
+}
+
+impl Target {
+    /// Parse a pane ID (`3` or `%3`), a group (`@GROUP`), a regular expression between slashes,
+    /// or a glob pattern.
+    pub fn parse(target: &str) -> Result<Self, String> {
+        if let Ok(id) = target.trim_start_matches('%').parse() {
+            Ok(Target::Pane(id))
+        } else if target.len() > 1 && target.starts_with('@') {
+            Ok(Target::Group(target[1..].to_owned()))
+        } else if target.len() > 1 && target.starts_with('/') && target.ends_with('/') {
+            regex::Regex::new(&target[1..target.len() - 1])
+                .map(Target::Regex)
+                .map_err(|err| format!("invalid regex {:?}: {}", target, err))
+        } else {
+            glob::Pattern::new(target)
+                .map(Target::Glob)
+                .map_err(|err| format!("invalid pattern {:?}: {}", target, err))
+        }
+    }
+
+    /// Whether this targets the pane with the given ID, title, argument and group.
+    pub fn matches(&self, id: usize, title: &str, arg: &str, group: Option<&str>) -> bool {
+        match *self {
+            Target::Pane(target) => id == target,
+            Target::Glob(ref pattern) => pattern.matches(title) || pattern.matches(arg),
+            Target::Regex(ref regex) => regex.is_match(title) || regex.is_match(arg),
+            Target::Group(ref name) => group == Some(name.as_str()),
+        }
+    }
+}
+
+impl Responder {
+    fn new(sender: futures::sync::oneshot::Sender<Response>) -> Self {
+        Responder(sync::Mutex::new(Some(sender)))
+    }
+
+    /// Send the response; only the first response for a request is delivered.
+    pub fn send(&self, response: Response) {
+        if let Some(sender) = self.0.lock().unwrap().take() {
+            let _ = sender.send(response);
+        }
+    }
+}
+
+impl fmt::Debug for Responder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Responder").finish()
+    }
+}
+
+/// The bytes to send to a process for a key given to `send-keys`.
+///
+/// Understands the key names `Enter`, `Tab`, `Space`, `Escape`, `BSpace` and `C-x` for control
+/// characters; anything else is sent as-is.
+pub fn key_bytes(key: &str) -> Vec<u8> {
+    match key {
+        "Enter" => b"\r".to_vec(),
+        "Tab" => b"\t".to_vec(),
+        "Space" => b" ".to_vec(),
+        "Escape" => b"\x1b".to_vec(),
+        "BSpace" => b"\x7f".to_vec(),
+        _ => {
+            let bytes = key.as_bytes();
+            if bytes.len() == 3 && bytes.starts_with(b"C-") && bytes[2].is_ascii_alphabetic() {
+                vec![bytes[2].to_ascii_lowercase() - b'a' + 1]
+            } else {
+                bytes.to_vec()
+            }
+        }
+    }
+}
+
+/// Run `mux ctl ARGS...`, sending the command to the session in `$MUX_SOCKET`.
+pub fn run_client(mut args: Vec<String>) -> Result<(), failure::Error> {
+    let path = take_socket_path(&mut args)?;
+    print!("{}", send(&path, &args)?);
+    Ok(())
+}
+
+/// The socket given with a leading `--socket PATH` in `args` (which is removed), or else the one
+/// in `$MUX_SOCKET`.
+pub fn take_socket_path(args: &mut Vec<String>) -> Result<path::PathBuf, failure::Error> {
+    if args.first().map(String::as_str) == Some("--socket") && args.len() > 1 {
+        let path = path::PathBuf::from(args.remove(1));
+        args.remove(0);
+        Ok(path)
+    } else {
+        env::var_os(SOCKET_VAR)
+            .map(path::PathBuf::from)
+            .ok_or_else(|| {
+                failure::err_msg(format!(
+                    "not running inside mux; set {} or pass --socket PATH",
+                    SOCKET_VAR
+                ))
+            })
+    }
+}
+
+/// Send a command line to the session listening on `path`, returning its output.
+pub fn send(path: &path::Path, args: &[String]) -> Result<String, failure::Error> {
+    use std::io::Read;
+    use std::io::Write;
+    use std::net::Shutdown;
+    use std::os::unix::net::UnixStream;
+
+    let mut stream = UnixStream::connect(path)
+        .map_err(|err| failure::err_msg(format!("{}: {}", path.display(), err)))?;
+    stream.write_all(args.join("\0").as_bytes())?;
+    stream.shutdown(Shutdown::Write)?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+
+    let (status, body) = match response.find('\n') {
+        Some(newline) => (&response[..newline], &response[newline + 1..]),
+        None => (response.as_str(), ""),
+    };
+    match status {
+        "ok" => Ok(body.to_owned()),
+        "error" => Err(failure::err_msg(body.to_owned())),
+        _ => Err(failure::err_msg(format!(
+            "unexpected response from mux: {:?}",
+            response
+        ))),
+    }
+}
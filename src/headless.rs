@@ -0,0 +1,145 @@
+//! Running without the UI, for when standard output isn't a terminal or with `--no-ui`: every line
+//! that a process prints goes to standard output as plain text, tagged with the argument of the
+//! process like `parallel --tag` does.
+use std::io;
+use std::sync;
+
+use crate::args;
+use crate::lines;
+use crate::process;
+use crate::report;
+use crate::tag;
+use crate::title;
+use crate::webhook;
+
+/// Run `command` with each of `args`, at most `max_procs` processes at a time (all of them at
+/// once if it is missing or 0), and fail if any of the processes did.  Every line gets the `tag`
+/// template in front of it, or `tag::DEFAULT_TEMPLATE`.
+pub fn run(
+    command: String,
+    args: Vec<args::Args>,
+    max_procs: Option<u64>,
+    tag: Option<String>,
+    webhook: Option<sync::Arc<webhook::Webhook>>,
+) -> impl futures::future::Future<Item = (), Error = failure::Error> {
+    use futures::future::Future;
+    use futures::stream::Stream;
+
+    #[allow(clippy::cast_possible_truncation)]
+    let max_procs = match max_procs {
+        Some(max_procs) if max_procs > 0 => max_procs as usize,
+        _ => args.len().max(1),
+    };
+    let tag = tag.unwrap_or_else(|| tag::DEFAULT_TEMPLATE.to_owned());
+
+    futures::stream::iter_ok(args.into_iter().enumerate())
+        .map(move |(index, args)| {
+            let command = command.clone();
+            let prefix = title::expand(&tag, &args.specific, index, crate::pane_id(index));
+            let webhook = webhook.clone();
+            // Only spawned once there is room for another process
+            futures::future::lazy(move || run_one(&command, index, &args, prefix, webhook))
+                .flatten()
+        })
+        .buffered(max_procs)
+        .collect()
+        .and_then(|processes| {
+            let report = report::Report {
+                processes,
+                log: crate::log_path(),
+            };
+            // Standard output is for the output of the processes
+            eprint!("{}", report);
+
+            let failed = report
+                .processes
+                .iter()
+                .filter(|p| p.exit_status.map_or(true, |s| !s.success()))
+                .count();
+            if failed == 0 {
+                Ok(())
+            } else {
+                Err(failure::err_msg(format!(
+                    "{} of {} processes failed",
+                    failed,
+                    report.processes.len()
+                )))
+            }
+        })
+}
+
+/// Spawn the process at `index` and print its output until it exits.
+fn run_one(
+    command: &str,
+    index: usize,
+    args: &args::Args,
+    prefix: String,
+    webhook: Option<sync::Arc<webhook::Webhook>>,
+) -> Result<
+    impl futures::future::Future<Item = report::Process, Error = failure::Error>,
+    failure::Error,
+> {
+    use futures::future::Future;
+    use futures::stream::Stream;
+
+    let (_, read) = process::Process::spawn(command, &args.all, &[])?.split();
+    debug!("spawned process {} for {:?}", read.pid, args.specific);
+
+    let arg = args.specific.clone();
+    let output = read
+        .output
+        .fold(Lines::new(prefix), |mut lines, data| {
+            lines.feed(&data).map(|()| lines)
+        })
+        .and_then(Lines::finish);
+
+    Ok(output.join(read.exit).map(move |((), status)| {
+        if let Some(ref webhook) = webhook {
+            webhook.item_finished(crate::pane_id(index), &arg, status);
+        }
+        report::Process {
+            title: arg,
+            exit_status: Some(status),
+        }
+    }))
+}
+
+/// Splits the output of a process into lines of plain text, without colors and other escape
+/// sequences, and prints each of them with a prefix.
+struct Lines {
+    prefix: String,
+    splitter: lines::Splitter,
+}
+
+impl Lines {
+    fn new(prefix: String) -> Self {
+        let splitter = lines::Splitter::default();
+        Self { prefix, splitter }
+    }
+
+    /// Print the lines that `data` finishes; the rest waits for its newline.
+    fn feed(&mut self, data: &[u8]) -> Result<(), failure::Error> {
+        self.splitter.feed(data);
+        self.print()
+    }
+
+    /// Print the last line even though the process didn't finish it.
+    fn finish(mut self) -> Result<(), failure::Error> {
+        self.splitter.finish();
+        self.print()
+    }
+
+    fn print(&mut self) -> Result<(), failure::Error> {
+        use std::io::Write;
+
+        if !self.splitter.complete().is_empty() {
+            // Locked once for all of them, so that lines of other processes can't end up between
+            let stdout = io::stdout();
+            let mut stdout = stdout.lock();
+            for line in self.splitter.take_lines() {
+                writeln!(stdout, "{}{}", self.prefix, line)?;
+            }
+        }
+        Ok(())
+    }
+}
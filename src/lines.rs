@@ -0,0 +1,80 @@
+//! Splitting the output of a process into lines of plain text, without colors and other escape
+//! sequences.
+use std::mem;
+
+/// Lines longer than this are cut off, so that output without newlines can't use up memory
+const MAX_LINE: usize = 4096;
+
+/// Collects the lines that output finishes, until they are taken.
+pub struct Splitter {
+    parser: vte::Parser,
+    text: Text,
+}
+
+#[derive(Default)]
+struct Text {
+    current: String,
+    complete: Vec<String>,
+}
+
+impl Splitter {
+    /// Parse `data`; the last line waits for its newline.
+    pub fn feed(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.parser.advance(&mut self.text, byte);
+        }
+    }
+
+    /// Finish the last line even though it has no newline.
+    pub fn finish(&mut self) {
+        if !self.text.current.is_empty() {
+            let line = mem::replace(&mut self.text.current, String::new());
+            self.text.complete.push(line);
+        }
+    }
+
+    /// The lines that were finished since they were last taken
+    pub fn complete(&self) -> &[String] {
+        &self.text.complete
+    }
+
+    /// Take the finished lines, leaving the one that is still being printed
+    pub fn take_lines(&mut self) -> Vec<String> {
+        mem::replace(&mut self.text.complete, Vec::new())
+    }
+}
+
+impl Default for Splitter {
+    fn default() -> Self {
+        let parser = vte::Parser::new();
+        let text = Text::default();
+        Self { parser, text }
+    }
+}
+
+impl vte::Perform for Text {
+    fn print(&mut self, c: char) {
+        if self.current.len() < MAX_LINE {
+            self.current.push(c);
+        }
+    }
+
+    fn execute(&mut self, byte: u8) {
+        if byte == b'\n' {
+            let line = mem::replace(&mut self.current, String::new());
+            self.complete.push(line);
+        }
+    }
+
+    fn hook(&mut self, _params: &[i64], _intermediates: &[u8], _ignore: bool) {}
+
+    fn put(&mut self, _byte: u8) {}
+
+    fn unhook(&mut self) {}
+
+    fn osc_dispatch(&mut self, _params: &[&[u8]]) {}
+
+    fn csi_dispatch(&mut self, _params: &[i64], _intermediates: &[u8], _ignore: bool, _c: char) {}
+
+    fn esc_dispatch(&mut self, _params: &[i64], _intermediates: &[u8], _ignore: bool, _byte: u8) {}
+}
@@ -4,6 +4,10 @@
 #[macro_use]
 extern crate log;
 #[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate serde_json;
+#[macro_use]
 extern crate structopt;
 #[macro_use]
 extern crate tokio;
@@ -13,12 +17,24 @@ extern crate tokio;
 mod sys;
 
 mod args;
+mod config;
+mod control;
+mod headless;
+mod lines;
 mod options;
 mod process;
+mod report;
+mod respawn;
+mod server;
+mod session;
 mod sinks;
+mod snapshot;
 mod streams;
+mod tag;
+mod title;
 mod tty;
 mod ui;
+mod webhook;
 
 fn main() {
     use std::process;
@@ -31,19 +47,29 @@ fn main() {
 }
 
 fn run() -> Result<(), failure::Error> {
-    use futures::future::Future;
+    use std::env;
     use std::fs;
-    use std::sync;
     use structopt::StructOpt;
 
     log_panics::init();
 
+    // Only the first argument can be a subcommand, so that `mux -- ctl` or `mux -P 4 ctl` runs a
+    // command called `ctl` like `xargs` would
+    let mut args = env::args().skip(1);
+    match args.next().as_ref().map(String::as_str) {
+        Some("ctl") => return control::run_client(args.collect()),
+        Some("apply") => return session::run_apply(args.collect()),
+        Some("attach") => return server::run_attach(&args.collect::<Vec<_>>()),
+        Some("restore") => return restore(&args.collect::<Vec<_>>()),
+        _ => {}
+    }
+
     let options = options::Options::from_args();
 
-    if let Some(mut log) = dirs::cache_dir() {
-        log.push("mux");
-        fs::create_dir_all(&log)?;
-        log.push("session.log");
+    if let Some(log) = log_path() {
+        if let Some(dir) = log.parent() {
+            fs::create_dir_all(dir)?;
+        }
 
         fern::Dispatch::new()
             .level(match options.log_verbose {
@@ -66,17 +92,51 @@ fn run() -> Result<(), failure::Error> {
             .apply()?;
     }
 
+    if let Some(ref name) = options.session {
+        if env::var_os(server::SERVER_VAR).is_none() {
+            if options.height.is_some() || options.no_alt_screen {
+                return Err(failure::err_msg(
+                    "sessions always use the whole screen, leave out --height and --no-alt-screen",
+                ));
+            }
+            return server::start(name);
+        }
+    }
+
     info!("starting");
 
+    let result = run_async(run_with_options(options));
+
+    info!("done");
+
+    result
+}
+
+/// Run `mux restore FILE`, showing the output saved in a snapshot.
+fn restore(args: &[String]) -> Result<(), failure::Error> {
+    match args {
+        [path] => {
+            let snapshot = snapshot::Snapshot::load(std::path::Path::new(path))?;
+            run_async(run_restore(snapshot))
+        }
+        _ => Err(failure::err_msg("usage: mux restore FILE")),
+    }
+}
+
+/// Run `future` to completion on a new tokio runtime.
+fn run_async(
+    future: impl std::future::Future<Output = Result<(), failure::Error>> + Send + 'static,
+) -> Result<(), failure::Error> {
+    use futures::future::Future;
+    use std::sync;
+
     let result = sync::Arc::new(sync::Mutex::new(None));
     let result_clone = sync::Arc::clone(&result);
     tokio::run(
-        tokio_async_await::compat::backward::Compat::new(run_with_options(options))
+        tokio_async_await::compat::backward::Compat::new(future)
             .then(move |r| futures::future::ok(*result_clone.lock().unwrap() = Some(r))),
     );
 
-    info!("done");
-
     let mut guard = result.lock().unwrap();
     guard.take().unwrap_or_else(|| {
         Err(failure::err_msg(
@@ -90,12 +150,109 @@ async fn run_with_options(mut options: options::Options) -> Result<(), failure::
     use futures::stream::Stream;
 
     let template_placeholder = options.replace.clone().unwrap_or_else(|| "{}".to_owned());
+    let title_template = options.title.take();
+    let group_template = options.group.take();
+    let tag_template = options.tag.take();
+    let tabstop = options.tabstop;
+    let invalid_utf8 = options.invalid_utf8;
+    let c1_controls = options.c1_controls;
+    let scrollback_budget = options
+        .scrollback_memory
+        .map(|megabytes| megabytes.saturating_mul(1 << 20));
+    let mut settings = ui::Settings {
+        command: options.command.clone(),
+        status: ui::StatusSettings {
+            clock: options.status_clock,
+            host: options.status_host,
+        },
+        title_truncate: options.title_truncate,
+        mouse: !options.no_mouse,
+        compact_tabs: options.height.is_some(),
+        tab_width: options.tab_width,
+        tiling: options.layout,
+        input_mode: options.input_mode,
+        log_dir: options.log_dir.take(),
+        save_attributes: options.save_attributes,
+        dim_idle: options.dim_idle.map(std::time::Duration::from_secs),
+        inactive_cursors: options.inactive_cursors,
+        pane_header: options.pane_header,
+        hide_finished: match (options.hide_finished, options.hide_finished_after) {
+            (_, Some(secs)) => Some(std::time::Duration::from_secs(secs)),
+            (true, None) => Some(std::time::Duration::from_secs(0)),
+            (false, None) => None,
+        },
+        defer_hidden: options.defer_hidden.map(std::time::Duration::from_millis),
+        detach: None,
+    };
+    if let Some(ref dir) = settings.log_dir {
+        std::fs::create_dir_all(dir)?;
+    }
+    let inline_height = match options.height {
+        Some(height) => Some(height),
+        None if options.no_alt_screen => Some(ui::Height::Percent(50)),
+        None => None,
+    };
+    let config = config::Source::load(options.config.take().or_else(config::default_path));
+
+    // Listen before reading the arguments, so that the client can attach while they are read
+    let session = match std::env::var_os(server::SERVER_VAR) {
+        Some(path) => {
+            // Processes that run mux themselves shouldn't think they are the server
+            std::env::remove_var(server::SERVER_VAR);
+            Some(server::Server::bind(path.into())?)
+        }
+        None => None,
+    };
+
+    let arg_template = args::parse_arg_template(&options.initial_args, &options.replace);
     let args = await!(args::read(&mut options))?;
     let command = options.command;
 
+    let webhook = if options.webhook.is_empty() {
+        None
+    } else {
+        let webhook = webhook::Webhook::new(
+            options.webhook.clone(),
+            options.webhook_failure_threshold,
+        )?;
+        webhook.run_started(
+            &command,
+            &args.iter().map(|a| a.specific.clone()).collect::<Vec<_>>(),
+        );
+        Some(std::sync::Arc::new(webhook))
+    };
+
+    // Nothing to draw on, so just pass on what the processes print
+    if session.is_none() && (options.no_ui || !termion::is_tty(&std::io::stdout())) {
+        debug!("running without the UI");
+        let result = await!(headless::run(
+            command,
+            args,
+            options.max_procs,
+            tag_template,
+            webhook.clone()
+        ));
+        finish_webhook(webhook);
+        return result;
+    }
+
+    let control_settings = config.config.control.clone();
+    let (socket, control_requests) = control::Socket::bind(
+        control::default_path(&control_settings),
+        &control_settings,
+    )?;
+
     let processes = args
         .iter()
-        .map(|args| process::Process::spawn(&command, &args.all))
+        .enumerate()
+        .map(|(index, args)| {
+            let pane = pane_id(index).to_string();
+            let env = [
+                (control::SOCKET_VAR, socket.path.as_os_str()),
+                (control::PANE_VAR, std::ffi::OsStr::new(&pane)),
+            ];
+            process::Process::spawn(&command, &args.all, &env)
+        })
         .collect::<Result<Vec<_>, _>>()?;
 
     debug!("spawned {} processes", processes.len());
@@ -103,26 +260,109 @@ async fn run_with_options(mut options: options::Options) -> Result<(), failure::
     let (process_writes, process_reads): (Vec<_>, Vec<_>) =
         processes.into_iter().map(|p| p.split()).unzip();
 
-    let mut tty_output = tty::Tty::open()?.into_raw_mode()?;
-    let tty_input = tty_output.try_clone()?;
-
-    debug!("opened tty");
-
-    let mut terminal = await!(create_terminal(tty_output))?;
+    let emulators = process_reads
+        .iter()
+        .map(|_| {
+            let emulator = ui::Emulator::shared();
+            let mut locked = emulator.lock().unwrap();
+            locked.term.set_tabspaces(tabstop);
+            locked.term.set_scrollback_budget(scrollback_budget);
+            locked.set_invalid_utf8(invalid_utf8);
+            locked.set_c1_controls(c1_controls);
+            drop(locked);
+            emulator
+        })
+        .collect::<Vec<_>>();
+    let (respawned_events, respawned) = futures::sync::mpsc::unbounded();
+    let (replacements, replacement_receivers): (Vec<_>, Vec<_>) = process_writes
+        .iter()
+        .map(|_| futures::sync::mpsc::unbounded())
+        .unzip();
+    let process_writes = process_writes
+        .into_iter()
+        .zip(replacement_receivers)
+        .map(|(write, replacements)| sinks::Replaceable::new(write, replacements))
+        .collect::<Vec<_>>();
+    let respawner = respawn::Respawner {
+        command: command.clone(),
+        arg_template,
+        title_template: title_template.clone(),
+        tag_template: tag_template.clone(),
+        template_placeholder: template_placeholder.clone(),
+        socket: socket.path.clone(),
+        events: respawned_events,
+        replacements,
+        emulators: emulators.clone(),
+    };
+
+    let mut server = None;
+    let mut report_output = None;
+    let mut attached: Box<dyn Fn() -> bool + Send> = Box::new(|| false);
+    let (terminal_output, terminal_input, inline, remote_size): (
+        Box<dyn std::io::Write + Send>,
+        Box<dyn std::io::Read + Send>,
+        _,
+        _,
+    ) = match session {
+        Some((session_server, output, input)) => {
+            settings.detach = Some(Box::new(session_server.detacher()));
+            attached = Box::new(session_server.attach_watcher());
+            report_output = Some(output.clone());
+            let size = session_server.size();
+            server = Some(session_server);
+
+            debug!("listening for clients");
+
+            (Box::new(output), Box::new(input), None, Some(size))
+        }
+        None => {
+            let mut tty_output = tty::Tty::open()?.into_raw_mode()?;
+            let tty_input = tty_output.try_clone()?;
+
+            debug!("opened tty");
+
+            let inline = match inline_height {
+                Some(height) => Some((tty_output.cursor_position()?.1, height)),
+                None => None,
+            };
+
+            (Box::new(tty_output), Box::new(tty_input), inline, None)
+        }
+    };
+
+    let mut terminal = await!(create_terminal(
+        terminal_output,
+        settings.mouse,
+        inline,
+        remote_size
+    ))?;
     terminal.hide_cursor()?;
 
     debug!("created terminal");
 
-    let events = read_events(tty_input);
-    let input = await!(run_gui(
+    let events = read_events(terminal_input);
+    let (ui, input) = await!(run_gui(
         process_reads,
+        emulators,
         terminal,
         events,
-        args.into_iter()
-            .map(|args| args.specific)
-            .collect::<Vec<_>>(),
+        args,
         template_placeholder,
+        title_template,
+        group_template,
+        tag_template,
+        settings,
+        config,
+        control_requests,
+        webhook.clone(),
+        attached,
+        respawned.map_err(|()| failure::err_msg("respawned processes are gone")),
     ))?;
+    let input = input.inspect(move |action| {
+        if let ui::Action::Respawn { index, ref arg } = *action {
+            respawner.respawn(index, arg.clone());
+        }
+    });
 
     let rest = await!(forward_stdin(process_writes, input))?;
 
@@ -132,84 +372,251 @@ async fn run_with_options(mut options: options::Options) -> Result<(), failure::
 
     debug!("end of input");
 
+    // Handed back now that the actions, and with them the UI's task, are gone
+    let mut ui = await!(ui).map_err(|_| failure::err_msg("the UI stopped"))?;
+    let report = ui.report(log_path());
+    if let Some(ref path) = options.snapshot {
+        ui.save_snapshot(path)?;
+    }
+    // Restore the terminal before printing the report, so that it ends up on the normal screen
+    drop(ui);
+    match report_output {
+        Some(mut output) => output.finish(&report.to_string()),
+        None => print!("{}", report),
+    }
+
+    drop(socket);
+    drop(server);
+
+    finish_webhook(webhook);
+
     Ok(())
 }
 
-async fn run_gui(
+/// Wait for the events that are still being sent, unless someone else holds on to the webhook.
+fn finish_webhook(webhook: Option<std::sync::Arc<webhook::Webhook>>) {
+    if let Some(webhook) = webhook {
+        match std::sync::Arc::try_unwrap(webhook) {
+            Ok(webhook) => webhook.finish(),
+            Err(_) => warn!("webhook still in use, not waiting for remaining events"),
+        }
+    }
+}
+
+/// The stable ID of the pane for the process spawned at `index`, as shown to users and scripts.
+fn pane_id(index: usize) -> usize {
+    index + 1
+}
+
+/// Show the panes of a snapshot until the user quits.
+async fn run_restore(snapshot: snapshot::Snapshot) -> Result<(), failure::Error> {
+    use futures::future::Future;
+    use futures::stream::Stream;
+
+    let mut tty_output = tty::Tty::open()?.into_raw_mode()?;
+    let tty_input = tty_output.try_clone()?;
+    let mut terminal = await!(create_terminal(tty_output, true, None, None))?;
+    terminal.hide_cursor()?;
+
+    let processes = snapshot
+        .panes
+        .iter()
+        .map(|pane| ui::ProcessSettings {
+            id: pane.id,
+            pid: 0,
+            arg: pane.arg.clone(),
+            initial_title: pane.title.clone(),
+            group: pane.group.clone(),
+            command_line: String::new(),
+            tag: None,
+            emulator: ui::Emulator::shared(),
+        })
+        .collect::<Vec<_>>();
+    let settings = ui::Settings {
+        command: snapshot.command,
+        status: ui::StatusSettings::default(),
+        title_truncate: ui::Truncate::End,
+        mouse: true,
+        compact_tabs: false,
+        tab_width: 40,
+        tiling: ui::Tiling::Tabs,
+        input_mode: ui::InputMode::Selected,
+        log_dir: None,
+        save_attributes: false,
+        dim_idle: None,
+        inactive_cursors: false,
+        pane_header: false,
+        hide_finished: None,
+        defer_hidden: None,
+        detach: None,
+    };
+    let config = config::Source::load(config::default_path());
+
+    let mut ui = ui::Ui::new(terminal, processes, settings, config)?;
+    ui.restore(snapshot.panes)?;
+    let (task, _) = ui::Task::new(ui, read_events(tty_input), Box::new(|| false));
+
+    // There are no processes to send input to, so only quitting matters
+    let quit = task
+        .filter(|action| match action {
+            ui::Action::Quit => true,
+            _ => false,
+        })
+        .into_future()
+        .map_err(|(e, _)| e);
+    await!(quit)?;
+
+    Ok(())
+}
+
+/// The title of the pane for the process spawned at `index`, until the process sets one itself.
+fn initial_title(
+    title_template: Option<&str>,
+    template_placeholder: &str,
+    arg: &str,
+    index: usize,
+) -> String {
+    match title_template {
+        Some(template) => title::expand(template, arg, index, pane_id(index)),
+        None => format!("{}={}", template_placeholder, arg),
+    }
+}
+
+/// The command line that a process runs, with the words quoted where a shell would need it.
+fn command_line(command: &str, args: &[String]) -> String {
+    std::iter::once(command)
+        .chain(args.iter().map(String::as_str))
+        .map(shell_quote)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn shell_quote(word: &str) -> std::borrow::Cow<str> {
+    let plain = !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+    if plain {
+        word.into()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''")).into()
+    }
+}
+
+fn log_path() -> Option<std::path::PathBuf> {
+    dirs::cache_dir().map(|mut log| {
+        log.push("mux");
+        log.push("session.log");
+        log
+    })
+}
+
+async fn run_gui<B>(
     process_reads: Vec<process::Read>,
-    terminal: tui::Terminal<impl tui::backend::Backend + 'static>,
+    emulators: Vec<std::sync::Arc<std::sync::Mutex<ui::Emulator>>>,
+    terminal: tui::Terminal<ui::Viewport<B>>,
     user_input: impl futures::stream::Stream<Item = ui::Event, Error = failure::Error>,
-    args: Vec<String>,
+    args: Vec<args::Args>,
     template_placeholder: String,
-) -> Result<impl futures::Stream<Item = ui::Action, Error = failure::Error>, failure::Error> {
+    title_template: Option<String>,
+    group_template: Option<String>,
+    tag_template: Option<String>,
+    settings: ui::Settings,
+    config: config::Source,
+    control_requests: impl futures::stream::Stream<
+        Item = (control::Request, control::Responder),
+        Error = failure::Error,
+    >,
+    webhook: Option<std::sync::Arc<webhook::Webhook>>,
+    attached: Box<dyn Fn() -> bool + Send>,
+    respawned: impl futures::stream::Stream<Item = ui::Event, Error = failure::Error>,
+) -> Result<
+    (
+        futures::sync::oneshot::Receiver<ui::Ui<B>>,
+        impl futures::Stream<Item = ui::Action, Error = failure::Error>,
+    ),
+    failure::Error,
+>
+where
+    B: tui::backend::Backend + std::io::Write + 'static,
+{
     use futures::future::Future;
     use futures::stream::Stream;
     use std::sync;
-    use std::time;
 
+    let pids = process_reads.iter().map(|p| p.pid).collect::<Vec<_>>();
     let (outputs, exits): (Vec<_>, Vec<_>) = process_reads
         .into_iter()
         .map(|p| (p.output, p.exit))
         .unzip();
 
-    let output = streams::select_all(
-        outputs
-            .into_iter()
-            .enumerate()
-            .map(|(i, o)| o.map(move |b| ui::Event::ProcessOutput(i, b.freeze()))),
-    );
-
-    let exit = futures::stream::futures_unordered(
-        exits
-            .into_iter()
-            .enumerate()
-            .map(|(i, e)| e.map(move |e| ui::Event::ProcessExit(i, e))),
-    );
-
-    let processes = args.into_iter().map(|arg| ui::ProcessSettings {
-        initial_title: format!("{}={}", template_placeholder, arg),
-    });
-
-    let mut ui = ui::Ui::new(terminal, processes)?;
-
-    await!(futures::future::poll_fn(|| tokio_threadpool::blocking(
-        || ui.draw()
-    )))??;
-
-    let ui = sync::Arc::new(sync::Mutex::new(ui));
-
-    let resize_ui = sync::Arc::clone(&ui);
-    let resizes = tokio::timer::Interval::new_interval(time::Duration::from_millis(10))
-        .filter_map(move |_| {
-            if resize_ui.lock().unwrap().check_resized() {
-                Some(ui::Event::Resized)
-            } else {
-                None
+    // Each process's output goes through its terminal on a task of its own before the UI sees it
+    let output = streams::select_all(outputs.into_iter().zip(emulators.iter()).enumerate().map(
+        |(i, (o, emulator))| {
+            ui::Emulator::parse_output(sync::Arc::clone(emulator), o)
+                .map(move |data| ui::Event::ProcessOutput(i, data))
+        },
+    ));
+
+    let exit = futures::stream::futures_unordered(exits.into_iter().enumerate().map(|(i, e)| {
+        let webhook = webhook.clone();
+        let arg = args[i].specific.clone();
+        e.map(move |e| {
+            if let Some(ref webhook) = webhook {
+                webhook.item_finished(pane_id(i), &arg, e);
             }
+            ui::Event::ProcessExit(i, e)
         })
-        .map_err(failure::Error::from);
+    }));
+
+    let processes = args
+        .into_iter()
+        .enumerate()
+        .map(|(index, args)| ui::ProcessSettings {
+            id: pane_id(index),
+            pid: pids[index],
+            initial_title: initial_title(
+                title_template.as_ref().map(String::as_str),
+                &template_placeholder,
+                &args.specific,
+                index,
+            ),
+            group: group_template
+                .as_ref()
+                .map(|template| title::expand(template, &args.specific, index, pane_id(index))),
+            command_line: command_line(&settings.command, &args.all),
+            tag: tag_template
+                .as_ref()
+                .map(|template| title::expand(template, &args.specific, index, pane_id(index))),
+            arg: args.specific,
+            emulator: sync::Arc::clone(&emulators[index]),
+        })
+        .collect::<Vec<_>>();
+
+    let mut ui = ui::Ui::new(terminal, processes, settings, config)?;
+    ui.draw()?;
 
     let events = user_input
         .chain(futures::stream::once(Ok(ui::Event::EndOfUserInput)))
         .select(output)
         .select(exit)
-        .select(resizes)
-        .take_while(|e| futures::future::ok(*e != ui::Event::EndOfUserInput));
-
-    Ok(events
-        .and_then(move |event| {
-            let event = sync::Arc::new(event);
-            let ui = sync::Arc::clone(&ui);
-            futures::future::poll_fn(move || {
-                let event = sync::Arc::clone(&event);
-                let ui = sync::Arc::clone(&ui);
-                tokio_threadpool::blocking(move || ui.lock().unwrap().on_event(&event))
-            })
-            .map_err(failure::Error::from)
-            .and_then(|r| r)
-            .map(futures::stream::iter_ok)
-        })
-        .flatten())
+        .select(respawned)
+        .select(
+            control_requests.map(|(request, responder)| ui::Event::Control(request, responder)),
+        )
+        .take_while(|e| match e {
+            ui::Event::EndOfUserInput => futures::future::ok(false),
+            _ => futures::future::ok(true),
+        });
+
+    // The UI only ever runs on this task, which owns it until the actions stop
+    let (task, ui) = ui::Task::new(ui, events, attached);
+    let actions = task.take_while(|action| match action {
+        ui::Action::Quit => futures::future::ok(false),
+        _ => futures::future::ok(true),
+    });
+
+    Ok((ui, actions))
 }
 
 fn read_events(
@@ -220,25 +627,44 @@ fn read_events(
 
     let event_iterator = read.events_and_raw();
 
+    // Everything between the start and end of a bracketed paste is collected into one event, so
+    // that pasted text is never mistaken for key bindings
+    let mut paste: Option<Vec<u8>> = None;
+
     let raw_events_stream = streams::blocking_iter_to_stream(
         event_iterator
             .inspect(|e| debug!("received tty event: {:?}", e))
-            .take_while(|e| match e {
-                Ok((termion::event::Event::Key(termion::event::Key::Ctrl('t')), _)) => false,
-                _ => true,
+            .filter_map(move |e| match e {
+                Ok((termion::event::Event::Unsupported(ref raw), _))
+                    if raw.as_slice() == tty::PASTE_START =>
+                {
+                    paste = Some(Vec::new());
+                    None
+                }
+                Ok((termion::event::Event::Unsupported(ref raw), _))
+                    if raw.as_slice() == tty::PASTE_END =>
+                {
+                    paste.take().map(|data| Ok(ui::Event::Paste(data.into())))
+                }
+                Ok((event, data)) => match paste {
+                    Some(ref mut pasted) => {
+                        pasted.extend_from_slice(&data);
+                        None
+                    }
+                    None => Some(Ok(ui::Event::UserInput(event, data.into()))),
+                },
+                Err(err) => Some(Err(err)),
             }),
     )
     .map_err(failure::Error::from);
 
     raw_events_stream
-        .and_then(move |event| match event? {
-            (event, data) => Ok(ui::Event::UserInput(event, data.into())),
-        })
+        .and_then(|event| event.map_err(failure::Error::from))
         .fuse()
 }
 
 async fn forward_stdin(
-    inputs: Vec<process::Write>,
+    inputs: Vec<sinks::Replaceable<process::Write>>,
     stdin: impl futures::stream::Stream<Item = ui::Action, Error = failure::Error> + Send + 'static,
 ) -> Result<
     impl futures::stream::Stream<Item = (), Error = failure::Error> + Send + 'static,
@@ -248,14 +674,20 @@ async fn forward_stdin(
 
     let (rest, _) = await!(
         stdin.forward(sinks::Fanout::new(inputs.into_iter().enumerate().map(
-            |(my_index, p)| {
-                p.input
+            |(my_index, input)| {
+                input
                     .with_flat_map(move |data| {
                         futures::stream::iter_ok(match data {
-                            ui::Action::ProcessInputAll { data, .. } => Some(data),
-                            ui::Action::ProcessInput { data, .. } => Some(data),
-                            // TODO: find a way to process other events
-                            _ => None,
+                            ui::Action::ProcessInputAll { data, .. } => {
+                                Some(process::Message::Input(data))
+                            }
+                            ui::Action::ProcessInput { data, .. } => {
+                                Some(process::Message::Input(data))
+                            }
+                            ui::Action::ProcessTermResize { width, height, .. } => {
+                                Some(process::Message::Resize { width, height })
+                            }
+                            ui::Action::Respawn { .. } | ui::Action::Quit => None,
                         })
                     })
                     .with_flat_map(move |data: ui::Action| {
@@ -274,10 +706,24 @@ async fn forward_stdin(
 
 async fn create_terminal(
     output: impl std::io::Write,
-) -> Result<tui::Terminal<impl tui::backend::Backend>, failure::Error> {
-    let mouse_terminal = termion::input::MouseTerminal::from(output);
-    let alternate_screen_terminal = termion::screen::AlternateScreen::from(mouse_terminal);
-    let backend = tui::backend::TermionBackend::new(alternate_screen_terminal);
+    mouse: bool,
+    inline: Option<(u16, ui::Height)>,
+    remote_size: Option<std::sync::Arc<std::sync::Mutex<tui::layout::Rect>>>,
+) -> Result<
+    tui::Terminal<ui::Viewport<impl tui::backend::Backend + std::io::Write>>,
+    failure::Error,
+> {
+    let input_terminal = tty::Input::new(output)?;
+    let mouse_terminal = tty::Mouse::new(input_terminal, mouse)?;
+    let screen_terminal = tty::Screen::new(mouse_terminal, inline.is_none())?;
+    let termion_backend = tui::backend::TermionBackend::new(screen_terminal);
+    let backend = match inline {
+        Some((row, height)) => ui::Viewport::inline(termion_backend, row, height)?,
+        None => match remote_size {
+            Some(size) => ui::Viewport::remote(termion_backend, size),
+            None => ui::Viewport::fullscreen(termion_backend),
+        },
+    };
 
     let terminal = tui::Terminal::new(backend)?;
     Ok(terminal)
@@ -1,5 +1,7 @@
 use std::path;
 
+use crate::ui;
+
 #[derive(Debug, StructOpt)]
 #[structopt(name = "mux")]
 pub struct Options {
@@ -78,12 +80,189 @@ pub struct Options {
     #[structopt(short = "x", long = "exit")]
     pub exit: bool,
 
+    /// The command to run for the items; put `--` in front of a command called `ctl`, `apply`,
+    /// `attach` or `restore` to run it instead of the subcommand of mux.
     #[structopt(value_name = "COMMAND")]
     pub command: String,
 
     #[structopt(value_name = "INITIAL-ARGS")]
     pub initial_args: Vec<String>,
 
+    /// Read the mux configuration from FILE instead of the default location.
+    #[structopt(long = "config", value_name = "FILE")]
+    pub config: Option<path::PathBuf>,
+
+    /// Show the current wall-clock time in the status bar.
+    #[structopt(long = "status-clock")]
+    pub status_clock: bool,
+
+    /// Show the user and host name in the status bar.
+    #[structopt(long = "status-host")]
+    pub status_host: bool,
+
+    /// Use TEMPLATE for tab titles; supports the replacement strings {}, {/}, {//}, {.}, {/.} and
+    /// {#} like GNU parallel, and {pane} for the pane ID used by `mux ctl`.
+    #[structopt(long = "title", value_name = "TEMPLATE")]
+    pub title: Option<String>,
+
+    /// Group the tabs by TEMPLATE, which supports the same replacement strings as --title; tabs
+    /// with the same expansion are listed together under a header that can be collapsed.
+    #[structopt(long = "group", value_name = "TEMPLATE")]
+    pub group: Option<String>,
+
+    /// Put TEMPLATE in front of every line of output in the files of --log-dir, saved output, the
+    /// ALL tab and the output without the UI, which supports the same replacement strings as
+    /// --title, for example '[{#}:{}] '; without the UI, the default is the argument and a tab.
+    #[structopt(long = "tag", value_name = "TEMPLATE")]
+    pub tag: Option<String>,
+
+    /// Shorten tab titles that don't fit by cutting off the start, middle or end.
+    #[structopt(
+        long = "title-truncate",
+        value_name = "WHERE",
+        default_value = "end",
+        parse(try_from_str = "parse_truncate")
+    )]
+    pub title_truncate: ui::Truncate,
+
+    /// Make the list of tabs at most WIDTH columns wide; it shrinks to fit the longest title,
+    /// and Ctrl+t b hides and shows it while running.
+    #[structopt(long = "tab-width", value_name = "WIDTH", default_value = "40")]
+    pub tab_width: u16,
+
+    /// Start with the processes arranged as tabs, even-horizontal, even-vertical or grid; press
+    /// Ctrl+t l to cycle through these while running.
+    #[structopt(
+        long = "layout",
+        value_name = "LAYOUT",
+        default_value = "tabs",
+        parse(try_from_str = "parse_tiling")
+    )]
+    pub layout: ui::Tiling,
+
+    /// Send typed keys to all processes, only to the selected one, or to the processes in the
+    /// group of the selected one; press Ctrl+t i to switch while running, and Ctrl+t n and
+    /// Ctrl+t p to select the next and previous process.
+    #[structopt(
+        long = "input",
+        value_name = "MODE",
+        default_value = "all",
+        parse(try_from_str = "parse_input_mode")
+    )]
+    pub input_mode: ui::InputMode,
+
+    /// Don't capture the mouse, so that the terminal's own text selection keeps working; press
+    /// Ctrl+t m to toggle mouse capture while running.
+    #[structopt(long = "no-mouse")]
+    pub no_mouse: bool,
+
+    /// Draw in the lower half of the normal screen instead of switching to the alternate screen,
+    /// so that earlier output stays visible above.
+    #[structopt(long = "no-alt-screen")]
+    pub no_alt_screen: bool,
+
+    /// Draw in the normal screen like --no-alt-screen, using only HEIGHT rows (a number of rows,
+    /// or a percentage of the terminal height like 40%) and a single line of tabs.
+    #[structopt(
+        long = "height",
+        value_name = "HEIGHT",
+        parse(try_from_str = "parse_height")
+    )]
+    pub height: Option<ui::Height>,
+
+    /// POST a JSON event to URL when the run starts, each process finishes, the run completes
+    /// and the failure threshold is reached; may be given several times.
+    #[structopt(long = "webhook", value_name = "URL")]
+    pub webhook: Vec<String>,
+
+    /// Send a failure_threshold_reached webhook event once N processes have failed.
+    #[structopt(long = "webhook-failure-threshold", value_name = "N")]
+    pub webhook_failure_threshold: Option<usize>,
+
+    /// Save everything each process outputs to DIR/<pane>-<arg>.log, no matter how much
+    /// scrollback is kept.
+    #[structopt(long = "log-dir", value_name = "DIR")]
+    pub log_dir: Option<path::PathBuf>,
+
+    /// Keep colors and text styles as escape sequences when saving the scrollback of a process
+    /// with Ctrl+t s.
+    #[structopt(long = "save-attributes")]
+    pub save_attributes: bool,
+
+    /// Dim the panes of processes that haven't printed anything for SECS seconds, when showing
+    /// all processes at once.
+    #[structopt(long = "dim-idle", value_name = "SECS")]
+    pub dim_idle: Option<u64>,
+
+    /// Show a dimmed cursor in the processes that are not selected, when showing all processes
+    /// at once.
+    #[structopt(long = "inactive-cursors")]
+    pub inactive_cursors: bool,
+
+    /// Show a line above the selected process with its complete command line, working directory
+    /// and start time.
+    #[structopt(long = "pane-header")]
+    pub pane_header: bool,
+
+    /// Hide the tabs of processes that exited successfully, keeping the list focused on the
+    /// running and failed ones; press Ctrl+t h to show all tabs again while running.
+    #[structopt(long = "hide-finished")]
+    pub hide_finished: bool,
+
+    /// Like --hide-finished, but only hide a tab SECS seconds after its process exited.
+    #[structopt(long = "hide-finished-after", value_name = "SECS")]
+    pub hide_finished_after: Option<u64>,
+
+    /// Save CPU with many busy processes by only running the output of processes that aren't on
+    /// the screen through their terminals every MILLIS milliseconds, or when they are shown.
+    #[structopt(long = "defer-hidden", value_name = "MILLIS")]
+    pub defer_hidden: Option<u64>,
+
+    /// Keep the scrollback of each process within about MB megabytes of memory, dropping its
+    /// oldest lines beyond that.
+    #[structopt(long = "scrollback-memory", value_name = "MB")]
+    pub scrollback_memory: Option<usize>,
+
+    /// How to show output that isn't UTF-8: as replacement characters (replace), as the Latin-1
+    /// characters of its bytes (latin1) or as the values of its bytes in hex (hex); press
+    /// Ctrl+t B to switch the selected process to the next one while running.
+    #[structopt(
+        long = "invalid-utf8",
+        value_name = "MODE",
+        default_value = "replace",
+        parse(try_from_str = "parse_invalid_utf8")
+    )]
+    pub invalid_utf8: terminal_emulator::ansi::InvalidUtf8,
+
+    /// Understand 8-bit C1 controls, like the byte 0x9b for CSI, in the output of the processes;
+    /// press Ctrl+t C to turn this on or off for the selected process while running.
+    #[structopt(long = "c1-controls")]
+    pub c1_controls: bool,
+
+    /// Put the tab stops of the processes' terminals every COLUMNS columns.
+    #[structopt(
+        long = "tabstop",
+        value_name = "COLUMNS",
+        default_value = "8",
+        parse(try_from_str = "parse_tabstop")
+    )]
+    pub tabstop: usize,
+
+    /// Don't show the UI, but print every line of output tagged with the argument of its process,
+    /// like `parallel --tag`; this is the default when standard output isn't a terminal.
+    #[structopt(long = "no-ui")]
+    pub no_ui: bool,
+
+    /// Run the processes in a background session called NAME, which keeps running after
+    /// detaching from it with the prefix key and d; attach to it again with `mux attach NAME`.
+    #[structopt(long = "session", value_name = "NAME")]
+    pub session: Option<String>,
+
+    /// Save the output of all processes to FILE when quitting, to look at again later with
+    /// `mux restore FILE`.
+    #[structopt(long = "snapshot", value_name = "FILE", parse(from_os_str))]
+    pub snapshot: Option<std::path::PathBuf>,
+
     /// Log more verbose messages to the log (defaulting to errors only).
     #[structopt(short = "v", long = "log-verbose", parse(from_occurrences))]
     pub log_verbose: u8,
@@ -100,3 +279,82 @@ fn parse_delimiter(delimiter: &str) -> Result<u8, failure::Error> {
         )))
     }
 }
+
+fn parse_tabstop(tabstop: &str) -> Result<usize, failure::Error> {
+    match tabstop.parse() {
+        Ok(columns) if columns > 0 => Ok(columns),
+        _ => Err(failure::err_msg(format!(
+            "expected a positive number of columns: {:?}",
+            tabstop
+        ))),
+    }
+}
+
+fn parse_truncate(truncate: &str) -> Result<ui::Truncate, failure::Error> {
+    match truncate {
+        "start" => Ok(ui::Truncate::Start),
+        "middle" => Ok(ui::Truncate::Middle),
+        "end" => Ok(ui::Truncate::End),
+        _ => Err(failure::err_msg(format!(
+            "expected one of start, middle or end: {:?}",
+            truncate
+        ))),
+    }
+}
+
+fn parse_tiling(tiling: &str) -> Result<ui::Tiling, failure::Error> {
+    match tiling {
+        "tabs" => Ok(ui::Tiling::Tabs),
+        "even-horizontal" => Ok(ui::Tiling::EvenHorizontal),
+        "even-vertical" => Ok(ui::Tiling::EvenVertical),
+        "grid" => Ok(ui::Tiling::Grid),
+        _ => Err(failure::err_msg(format!(
+            "expected one of tabs, even-horizontal, even-vertical or grid: {:?}",
+            tiling
+        ))),
+    }
+}
+
+fn parse_invalid_utf8(mode: &str) -> Result<terminal_emulator::ansi::InvalidUtf8, failure::Error> {
+    use terminal_emulator::ansi::InvalidUtf8;
+
+    match mode {
+        "replace" => Ok(InvalidUtf8::Replace),
+        "latin1" => Ok(InvalidUtf8::Latin1),
+        "hex" => Ok(InvalidUtf8::Hex),
+        _ => Err(failure::err_msg(format!(
+            "expected one of replace, latin1 or hex: {:?}",
+            mode
+        ))),
+    }
+}
+
+fn parse_input_mode(mode: &str) -> Result<ui::InputMode, failure::Error> {
+    match mode {
+        "all" => Ok(ui::InputMode::All),
+        "selected" => Ok(ui::InputMode::Selected),
+        "group" => Ok(ui::InputMode::Group),
+        _ => Err(failure::err_msg(format!(
+            "expected one of all, selected or group: {:?}",
+            mode
+        ))),
+    }
+}
+
+fn parse_height(height: &str) -> Result<ui::Height, failure::Error> {
+    let result = if height.ends_with('%') {
+        height[..height.len() - 1].parse().map(ui::Height::Percent)
+    } else {
+        height.parse().map(ui::Height::Lines)
+    };
+
+    match result {
+        Ok(ui::Height::Lines(0)) | Ok(ui::Height::Percent(0)) | Err(_) => {
+            Err(failure::err_msg(format!(
+                "expected a positive number of rows or a percentage: {:?}",
+                height
+            )))
+        }
+        Ok(height) => Ok(height),
+    }
+}
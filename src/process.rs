@@ -1,22 +1,34 @@
 use std::ffi;
 use std::io;
+use std::os;
 use std::process;
 
 pub struct Process {
+    pub pid: u32,
     pub input: Input,
     pub output: Output,
     pub exit: Exit,
+    pub pty: Pty,
 }
 
 pub struct Write {
     pub input: Input,
+    pub pty: Pty,
 }
 
 pub struct Read {
+    pub pid: u32,
     pub output: Output,
     pub exit: Exit,
 }
 
+/// Something to send to a running process through its `Write` half
+#[derive(Clone, Debug)]
+pub enum Message {
+    Input(bytes::Bytes),
+    Resize { width: u16, height: u16 },
+}
+
 pub struct Input {
     sink: Option<
         tokio::codec::FramedWrite<
@@ -40,20 +52,32 @@ pub struct Exit {
     future: tokio_pty_process::Child,
 }
 
+/// A handle for changing the size of a process' pseudo-terminal
+#[derive(Clone, Copy, Debug)]
+pub struct Pty {
+    fd: os::unix::io::RawFd,
+}
+
 impl Process {
     pub fn spawn(
         command: impl AsRef<ffi::OsStr>,
         args: &[impl AsRef<ffi::OsStr>],
+        env: &[(&str, &ffi::OsStr)],
     ) -> Result<Self, failure::Error> {
+        use std::os::unix::io::AsRawFd;
         use tokio::io::AsyncRead;
         use tokio_pty_process::CommandExt;
 
         let pty = tokio_pty_process::AsyncPtyMaster::open()?;
+        // The split halves keep the master open, so the fd stays valid for as long as we use it
+        let fd = pty.as_raw_fd();
 
         let child = process::Command::new(command)
             .args(args)
+            .envs(env.iter().cloned())
             .spawn_pty_async(&pty)?;
 
+        let pid = child.id();
         let (output, input) = pty.split();
 
         let input = Input::new(tokio::codec::FramedWrite::new(
@@ -65,22 +89,27 @@ impl Process {
             tokio::codec::BytesCodec::new(),
         ));
         let exit = Exit::new(child);
+        let pty = Pty { fd };
 
         Ok(Self {
+            pid,
             input,
             output,
             exit,
+            pty,
         })
     }
 
     pub fn split(self) -> (Write, Read) {
         let Self {
+            pid,
             input,
             output,
             exit,
+            pty,
         } = self;
 
-        (Write { input }, Read { output, exit })
+        (Write { input, pty }, Read { pid, output, exit })
     }
 }
 
@@ -116,6 +145,12 @@ impl Exit {
     }
 }
 
+impl Pty {
+    pub fn resize(self, width: u16, height: u16) -> Result<(), failure::Error> {
+        crate::sys::pty::set_size(self.fd, width, height).map_err(failure::Error::from)
+    }
+}
+
 impl futures::sink::Sink for Input {
     type SinkItem = bytes::Bytes;
     type SinkError = failure::Error;
@@ -165,6 +200,34 @@ impl futures::sink::Sink for Input {
     }
 }
 
+impl futures::sink::Sink for Write {
+    type SinkItem = Message;
+    type SinkError = failure::Error;
+
+    fn start_send(
+        &mut self,
+        item: Self::SinkItem,
+    ) -> Result<futures::AsyncSink<Self::SinkItem>, Self::SinkError> {
+        match item {
+            Message::Input(data) => Ok(self.input.start_send(data)?.map(Message::Input)),
+            Message::Resize { width, height } => {
+                if let Err(err) = self.pty.resize(width, height) {
+                    warn!("could not resize process: {}", err);
+                }
+                Ok(futures::AsyncSink::Ready)
+            }
+        }
+    }
+
+    fn poll_complete(&mut self) -> Result<futures::Async<()>, Self::SinkError> {
+        self.input.poll_complete()
+    }
+
+    fn close(&mut self) -> Result<futures::Async<()>, Self::SinkError> {
+        self.input.close()
+    }
+}
+
 impl futures::stream::Stream for Output {
     type Item = bytes::BytesMut;
     type Error = failure::Error;
@@ -0,0 +1,60 @@
+//! A plain-text summary of a run, printed after the UI has been closed
+use std::fmt;
+use std::path;
+use std::process;
+
+pub struct Report {
+    pub processes: Vec<Process>,
+    pub log: Option<path::PathBuf>,
+}
+
+pub struct Process {
+    pub title: String,
+    /// How the process exited, or `None` if it was still running when mux quit
+    pub exit_status: Option<process::ExitStatus>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let succeeded = self
+            .processes
+            .iter()
+            .filter(|p| p.exit_status.map_or(false, |s| s.success()))
+            .count();
+        let running = self
+            .processes
+            .iter()
+            .filter(|p| p.exit_status.is_none())
+            .count();
+        let failed = self.processes.len() - succeeded - running;
+
+        write!(
+            f,
+            "mux: {} processes, {} succeeded, {} failed",
+            self.processes.len(),
+            succeeded,
+            failed
+        )?;
+        if running > 0 {
+            write!(f, ", {} still running", running)?;
+        }
+        writeln!(f)?;
+
+        for process in &self.processes {
+            match process.exit_status {
+                Some(status) if !status.success() => match status.code() {
+                    Some(code) => writeln!(f, "  failed: {} (exit code {})", process.title, code)?,
+                    None => writeln!(f, "  failed: {} ({})", process.title, status)?,
+                },
+                None => writeln!(f, "  running: {}", process.title)?,
+                _ => {}
+            }
+        }
+
+        if let Some(ref log) = self.log {
+            writeln!(f, "log: {}", log.display())?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+//! Starting the process of a pane again, with a different argument
+use std::ffi;
+use std::path;
+use std::sync;
+
+use crate::args;
+use crate::control;
+use crate::process;
+use crate::ui;
+
+pub struct Respawner {
+    pub command: String,
+    /// The initial arguments, split where the argument goes
+    pub arg_template: Vec<Vec<String>>,
+    pub title_template: Option<String>,
+    /// What to put in front of the lines of the process, from `--tag`
+    pub tag_template: Option<String>,
+    pub template_placeholder: String,
+    /// The control socket, for the environment of the processes
+    pub socket: path::PathBuf,
+    /// Where the UI gets the events of the new processes from
+    pub events: futures::sync::mpsc::UnboundedSender<ui::Event>,
+    /// Where the input of the new process of each pane goes
+    pub replacements: Vec<futures::sync::mpsc::UnboundedSender<process::Write>>,
+    /// The terminal of each pane, which the output of the new process goes through
+    pub emulators: Vec<sync::Arc<sync::Mutex<ui::Emulator>>>,
+}
+
+impl Respawner {
+    /// Start the process of the pane at `index` again with `arg`, sending its input to the
+    /// process from now on, and its output and exit status to the UI.
+    pub fn respawn(&self, index: usize, arg: String) {
+        use futures::future::Future;
+        use futures::sink::Sink;
+        use futures::stream::Stream;
+
+        let args = args::generate_final_args(arg, &self.arg_template);
+        let pane = crate::pane_id(index).to_string();
+        let env = [
+            (control::SOCKET_VAR, self.socket.as_os_str()),
+            (control::PANE_VAR, ffi::OsStr::new(&pane)),
+        ];
+        let (write, read) = match process::Process::spawn(&self.command, &args.all, &env) {
+            Ok(process) => process.split(),
+            Err(err) => {
+                // Shown in the pane, below what the previous process printed
+                let message = format!("\r\nmux: could not start {}: {}\r\n", self.command, err);
+                self.emulators[index]
+                    .lock()
+                    .unwrap()
+                    .feed(message.as_bytes());
+                let _ = self
+                    .events
+                    .unbounded_send(ui::Event::ProcessOutput(index, message.into()));
+                return;
+            }
+        };
+
+        debug!("respawned process {} with pid {}", index, read.pid);
+
+        let _ = self.replacements[index].unbounded_send(write);
+        let title = crate::initial_title(
+            self.title_template.as_ref().map(String::as_str),
+            &self.template_placeholder,
+            &args.specific,
+            index,
+        );
+        let tag = self.tag_template.as_ref().map(|template| {
+            crate::title::expand(template, &args.specific, index, crate::pane_id(index))
+        });
+        // Before the new process's output can go through the terminal
+        self.emulators[index].lock().unwrap().reset(&title);
+        let _ = self.events.unbounded_send(ui::Event::ProcessStarted {
+            index,
+            pid: read.pid,
+            arg: args.specific,
+            title,
+            tag,
+            command_line: crate::command_line(&self.command, &args.all),
+        });
+
+        let process::Read { output, exit, .. } = read;
+        let events = ui::Emulator::parse_output(sync::Arc::clone(&self.emulators[index]), output)
+            .map(move |data| ui::Event::ProcessOutput(index, data))
+            .chain(
+                exit.map(move |status| ui::Event::ProcessExit(index, status))
+                    .into_stream(),
+            );
+        let sink = self
+            .events
+            .clone()
+            .sink_map_err(|_| failure::err_msg("the UI stopped"));
+        tokio::spawn(events.forward(sink).map(|_| ()).map_err(move |err| {
+            warn!("stopped reading from process {}: {}", index, err);
+        }));
+    }
+}
@@ -0,0 +1,481 @@
+//! Detachable sessions.
+//!
+//! `mux --session NAME` starts mux as a background server that owns the processes, and attaches
+//! to it.  The server draws to whichever client is attached over a unix socket, so that the
+//! client can detach (with the prefix key and `d`) and `mux attach NAME` can pick the session up
+//! again later, for example after logging out.
+//!
+//! Clients send framed messages: a kind byte, a big-endian `u32` length and the payload.  The
+//! server sends back what it draws, unframed.
+use std::env;
+use std::fs;
+use std::io;
+use std::os::unix::net;
+use std::path;
+use std::process;
+use std::sync;
+use std::thread;
+use std::time;
+
+use crate::sys;
+use crate::tty;
+
+/// Set for the server process to the path of the socket it should listen on
+pub const SERVER_VAR: &str = "MUX_SERVER";
+
+/// Bytes typed in the client's terminal
+const INPUT: u8 = 0;
+/// The client's terminal size, as big-endian `u16` columns and rows
+const RESIZE: u8 = 1;
+
+/// The longest message that a client may send; the client sends its input in smaller chunks
+const MAX_MESSAGE: usize = 64 * 1024;
+
+/// Switches the client's terminal to what the server expects when it attaches.
+const SETUP: &[u8] = b"\x1b[?1049h\x1b[?2004h\x1b=\x1b[?25l";
+/// Undoes everything the server might have enabled in the client's terminal when it detaches.
+/// When the session ends, the UI undoes it itself before the report is sent.
+const RESET: &[u8] =
+    b"\x1b[?1006l\x1b[?1015l\x1b[?1002l\x1b[?1000l\x1b>\x1b[?2004l\x1b[?25h\x1b[?1049l";
+
+/// The server end of a session: the terminal that the UI draws to is whichever client is
+/// attached.
+pub struct Server {
+    path: path::PathBuf,
+    client: sync::Arc<sync::Mutex<Option<net::UnixStream>>>,
+    size: sync::Arc<sync::Mutex<tui::layout::Rect>>,
+    attached: sync::Arc<sync::atomic::AtomicBool>,
+}
+
+/// What the server draws, sent to the attached client; dropped while no client is attached.
+#[derive(Clone)]
+pub struct Output {
+    client: sync::Arc<sync::Mutex<Option<net::UnixStream>>>,
+}
+
+/// What the attached client types, which blocks while no client is attached.
+pub struct Input {
+    receiver: sync::mpsc::Receiver<Vec<u8>>,
+    pending: io::Cursor<Vec<u8>>,
+}
+
+/// Where the sockets of the sessions are kept
+fn sessions_dir() -> path::PathBuf {
+    let mut dir = dirs::runtime_dir().unwrap_or_else(env::temp_dir);
+    dir.push("mux-sessions");
+    dir
+}
+
+/// Make sure that only the user can get at the sessions in `dir`, which might be in the temporary
+/// directory that everyone shares.
+fn check_sessions_dir(dir: &path::Path) -> Result<(), failure::Error> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = fs::symlink_metadata(dir)?;
+    let private = metadata.uid() == sys::host::user_id() && metadata.mode() & 0o077 == 0;
+    if !metadata.is_dir() || !private {
+        return Err(failure::err_msg(format!(
+            "{} must be a directory that only you can access",
+            dir.display()
+        )));
+    }
+    Ok(())
+}
+
+fn session_path(name: &str) -> path::PathBuf {
+    let mut path = sessions_dir();
+    path.push(format!("{}.sock", name));
+    path
+}
+
+/// Start a server for the session `name` that runs mux with the same arguments, and attach to
+/// it.
+pub fn start(name: &str) -> Result<(), failure::Error> {
+    use std::os::unix::fs::DirBuilderExt;
+
+    if name.is_empty() || name.contains('/') {
+        return Err(failure::err_msg(format!("invalid session name {:?}", name)));
+    }
+
+    let dir = sessions_dir();
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&dir)?;
+    check_sessions_dir(&dir)?;
+    let path = session_path(name);
+    if net::UnixStream::connect(&path).is_ok() {
+        return Err(failure::err_msg(format!(
+            "session {:?} is already running; use `mux attach {}`",
+            name, name
+        )));
+    }
+
+    let mut command = process::Command::new(env::current_exe()?);
+    command
+        .args(env::args_os().skip(1))
+        .env(SERVER_VAR, &path)
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::null());
+    sys::process::new_session(&mut command);
+    command.spawn()?;
+
+    attach(&path)
+}
+
+/// Run `mux attach [NAME]`; the name can be left out if there is only one session.
+pub fn run_attach(args: &[String]) -> Result<(), failure::Error> {
+    let name = match args {
+        [name] => name.clone(),
+        [] => {
+            let sessions = list()?;
+            match sessions.as_slice() {
+                [name] => name.clone(),
+                [] => return Err(failure::err_msg("there are no sessions to attach to")),
+                _ => {
+                    return Err(failure::err_msg(format!(
+                        "there are several sessions, pick one: {}",
+                        sessions.join(", ")
+                    )))
+                }
+            }
+        }
+        _ => return Err(failure::err_msg("usage: mux attach [NAME]")),
+    };
+
+    let path = session_path(&name);
+    if !path.exists() {
+        return Err(failure::err_msg(format!("there is no session {:?}", name)));
+    }
+    check_sessions_dir(&sessions_dir())?;
+    attach(&path)
+}
+
+/// The names of the sessions that have a socket.
+fn list() -> Result<Vec<String>, failure::Error> {
+    let entries = match fs::read_dir(sessions_dir()) {
+        Ok(entries) => entries,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut names = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().map_or(false, |e| e == "sock") {
+            if let Some(name) = path.file_stem().and_then(|s| s.to_str()) {
+                names.push(name.to_owned());
+            }
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Connect to the session at `path` and relay the terminal to it until the server closes the
+/// connection, because the user detached or the session ended.
+fn attach(path: &path::Path) -> Result<(), failure::Error> {
+    let stream = connect(path)?;
+    let mut tty_output = tty::Tty::open()?.into_raw_mode()?;
+    let tty_input = tty_output.try_clone()?;
+    let writer = sync::Arc::new(sync::Mutex::new(stream.try_clone()?));
+
+    send_size(&writer, tty_output.size()?)?;
+
+    let input_writer = sync::Arc::clone(&writer);
+    thread::spawn(move || forward_input(tty_input, &input_writer));
+    let size_writer = sync::Arc::clone(&writer);
+    let size_tty = tty::Tty::open()?;
+    thread::spawn(move || forward_size(&size_tty, &size_writer));
+
+    let mut stream = stream;
+    io::copy(&mut stream, &mut tty_output)?;
+    Ok(())
+}
+
+/// Connect to a session socket, waiting a bit for a server that is still starting up.
+fn connect(path: &path::Path) -> Result<net::UnixStream, failure::Error> {
+    let started = time::Instant::now();
+    loop {
+        match net::UnixStream::connect(path) {
+            Ok(stream) => return Ok(stream),
+            Err(ref err) if started.elapsed() < time::Duration::from_secs(5) => {
+                debug!("waiting for session at {}: {}", path.display(), err);
+                thread::sleep(time::Duration::from_millis(50));
+            }
+            Err(err) => {
+                return Err(failure::err_msg(format!(
+                    "could not connect to session at {}: {}",
+                    path.display(),
+                    err
+                )))
+            }
+        }
+    }
+}
+
+fn send(
+    writer: &sync::Mutex<net::UnixStream>,
+    kind: u8,
+    payload: &[u8],
+) -> Result<(), failure::Error> {
+    use std::io::Write;
+
+    let mut frame = Vec::with_capacity(payload.len() + 5);
+    frame.push(kind);
+    #[allow(clippy::cast_possible_truncation)]
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(payload);
+    writer.lock().unwrap().write_all(&frame)?;
+    Ok(())
+}
+
+fn send_size(
+    writer: &sync::Mutex<net::UnixStream>,
+    (columns, rows): (u16, u16),
+) -> Result<(), failure::Error> {
+    let mut payload = Vec::with_capacity(4);
+    payload.extend_from_slice(&columns.to_be_bytes());
+    payload.extend_from_slice(&rows.to_be_bytes());
+    send(writer, RESIZE, &payload)
+}
+
+fn forward_input(mut tty: tty::Raw, writer: &sync::Mutex<net::UnixStream>) {
+    use std::io::Read;
+
+    let mut buf = [0; 4096];
+    loop {
+        match tty.read(&mut buf) {
+            Ok(0) | Err(_) => return,
+            Ok(n) => {
+                if send(writer, INPUT, &buf[..n]).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Tell the server about size changes of the client's terminal.
+fn forward_size(tty: &tty::Tty, writer: &sync::Mutex<net::UnixStream>) {
+    let mut last = None;
+    loop {
+        if let Ok(size) = tty.size() {
+            if last != Some(size) {
+                last = Some(size);
+                if send_size(writer, size).is_err() {
+                    return;
+                }
+            }
+        }
+        thread::sleep(time::Duration::from_millis(100));
+    }
+}
+
+impl Server {
+    /// Listen for clients on `path`, which is removed again when the server is dropped.
+    pub fn bind(path: path::PathBuf) -> Result<(Self, Output, Input), failure::Error> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let _ = fs::remove_file(&path);
+        let listener = net::UnixListener::bind(&path)?;
+        // Whoever connects gets to type into the processes
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+
+        let client = sync::Arc::new(sync::Mutex::new(None));
+        let size = sync::Arc::new(sync::Mutex::new(tui::layout::Rect::new(0, 0, 80, 24)));
+        let attached = sync::Arc::new(sync::atomic::AtomicBool::new(false));
+        let (sender, receiver) = sync::mpsc::channel();
+
+        {
+            let client = sync::Arc::clone(&client);
+            let size = sync::Arc::clone(&size);
+            let attached = sync::Arc::clone(&attached);
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    match stream {
+                        Ok(stream) => {
+                            if let Err(err) = accept(stream, &client, &size, &attached, &sender) {
+                                warn!("could not attach client: {}", err);
+                            }
+                        }
+                        Err(err) => warn!("could not accept client: {}", err),
+                    }
+                }
+            });
+        }
+
+        let output = Output {
+            client: sync::Arc::clone(&client),
+        };
+        let input = Input {
+            receiver,
+            pending: io::Cursor::new(Vec::new()),
+        };
+        let server = Server {
+            path,
+            client,
+            size,
+            attached,
+        };
+        Ok((server, output, input))
+    }
+
+    /// The size of the attached client's terminal, shared with the UI's backend
+    pub fn size(&self) -> sync::Arc<sync::Mutex<tui::layout::Rect>> {
+        sync::Arc::clone(&self.size)
+    }
+
+    /// A function that tells whether a client attached since it was last called, so that the
+    /// screen has to be drawn again for it.
+    pub fn attach_watcher(&self) -> impl Fn() -> bool + Send {
+        let attached = sync::Arc::clone(&self.attached);
+        move || attached.swap(false, sync::atomic::Ordering::SeqCst)
+    }
+
+    /// A function that detaches the current client, if any.
+    pub fn detacher(&self) -> impl Fn() + Send {
+        let client = sync::Arc::clone(&self.client);
+        move || {
+            if let Some(stream) = client.lock().unwrap().take() {
+                detach(stream);
+            }
+        }
+    }
+}
+
+/// Restore the terminal of a client and disconnect it.
+fn detach(mut stream: net::UnixStream) {
+    use std::io::Write;
+
+    let _ = stream.write_all(RESET);
+    let _ = stream.shutdown(net::Shutdown::Both);
+}
+
+/// Make `stream` the attached client, detaching the previous one.
+fn accept(
+    mut stream: net::UnixStream,
+    client: &sync::Arc<sync::Mutex<Option<net::UnixStream>>>,
+    size: &sync::Arc<sync::Mutex<tui::layout::Rect>>,
+    attached: &sync::Arc<sync::atomic::AtomicBool>,
+    sender: &sync::mpsc::Sender<Vec<u8>>,
+) -> Result<(), failure::Error> {
+    use std::io::Write;
+
+    let reader = stream.try_clone()?;
+    stream.write_all(SETUP)?;
+
+    let previous = client.lock().unwrap().replace(stream);
+    if let Some(previous) = previous {
+        detach(previous);
+    }
+    info!("client attached");
+
+    let size = sync::Arc::clone(size);
+    let attached = sync::Arc::clone(attached);
+    let sender = sender.clone();
+    thread::spawn(move || {
+        if let Err(err) = read_client(reader, &size, &attached, &sender) {
+            debug!("client went away: {}", err);
+        }
+    });
+    Ok(())
+}
+
+/// Handle the messages from a client until it disconnects.
+fn read_client(
+    mut reader: net::UnixStream,
+    size: &sync::Mutex<tui::layout::Rect>,
+    attached: &sync::atomic::AtomicBool,
+    sender: &sync::mpsc::Sender<Vec<u8>>,
+) -> Result<(), failure::Error> {
+    use std::io::Read;
+
+    loop {
+        let mut header = [0; 5];
+        reader.read_exact(&mut header)?;
+        let kind = header[0];
+        let length = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+        if length > MAX_MESSAGE {
+            return Err(failure::err_msg(format!(
+                "message of {} bytes from client is too long",
+                length
+            )));
+        }
+        let mut payload = vec![0; length];
+        reader.read_exact(&mut payload)?;
+
+        match kind {
+            INPUT => sender.send(payload)?,
+            RESIZE if length == 4 => {
+                let columns = u16::from_be_bytes([payload[0], payload[1]]);
+                let rows = u16::from_be_bytes([payload[2], payload[3]]);
+                *size.lock().unwrap() = tui::layout::Rect::new(0, 0, columns, rows);
+                // The first size arrives right after attaching, so redraw for the new client
+                attached.store(true, sync::atomic::Ordering::SeqCst);
+            }
+            _ => warn!("unknown message {} from client", kind),
+        }
+    }
+}
+
+impl Output {
+    /// Tell the attached client something once the session has ended.
+    pub fn finish(&mut self, message: &str) {
+        use std::io::Write;
+
+        if let Some(mut stream) = self.client.lock().unwrap().take() {
+            // The client's terminal is still in raw mode
+            let _ = stream.write_all(message.replace('\n', "\r\n").as_bytes());
+            let _ = stream.shutdown(net::Shutdown::Both);
+        }
+    }
+}
+
+impl io::Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        use std::io::Write;
+
+        let mut client = self.client.lock().unwrap();
+        let failed = match *client {
+            Some(ref mut stream) => stream.write_all(buf).is_err(),
+            None => false,
+        };
+        if failed {
+            // The client went away without detaching; keep running until another one attaches
+            *client = None;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        use std::io::Write;
+
+        if let Some(ref mut stream) = *self.client.lock().unwrap() {
+            let _ = stream.flush();
+        }
+        Ok(())
+    }
+}
+
+impl io::Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        use std::io::Read;
+
+        #[allow(clippy::cast_possible_truncation)]
+        let consumed = self.pending.position() as usize;
+        if consumed == self.pending.get_ref().len() {
+            match self.receiver.recv() {
+                Ok(data) => self.pending = io::Cursor::new(data),
+                Err(_) => return Ok(0),
+            }
+        }
+        self.pending.read(buf)
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
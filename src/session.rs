@@ -0,0 +1,169 @@
+//! Declarative session files, and comparing them against a running session with `mux apply`.
+//!
+//! A session file lists the panes that should exist, identified by their argument:
+//!
+//! ```yaml
+//! panes:
+//!   - arg: host-eu-1
+//!   - arg: host-eu-2
+//!     title: eu-2 (canary)
+//! ```
+use std::fmt;
+use std::fs;
+use std::path;
+
+use crate::control;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Session {
+    pub panes: Vec<Pane>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pane {
+    pub arg: String,
+    /// The title the pane should have; left alone if not given
+    pub title: Option<String>,
+}
+
+/// A pane in a running session, as reported by `mux ctl list`
+struct Running {
+    id: usize,
+    title: String,
+    arg: String,
+}
+
+/// A difference between a session file and a running session
+#[derive(Debug)]
+enum Change {
+    Add { arg: String },
+    Remove { id: usize, arg: String },
+    Rename { id: usize, from: String, to: String },
+}
+
+impl Session {
+    pub fn load(path: &path::Path) -> Result<Self, failure::Error> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| failure::err_msg(format!("{}: {}", path.display(), err)))?;
+        serde_yaml::from_str(&contents)
+            .map_err(|err| failure::err_msg(format!("{}: {}", path.display(), err)))
+    }
+
+    fn diff(&self, running: &[Running]) -> Vec<Change> {
+        let mut changes = Vec::new();
+
+        for pane in &self.panes {
+            match running.iter().find(|r| r.arg == pane.arg) {
+                None => changes.push(Change::Add {
+                    arg: pane.arg.clone(),
+                }),
+                Some(r) => match pane.title {
+                    Some(ref title) if *title != r.title => changes.push(Change::Rename {
+                        id: r.id,
+                        from: r.title.clone(),
+                        to: title.clone(),
+                    }),
+                    _ => {}
+                },
+            }
+        }
+
+        for r in running {
+            if !self.panes.iter().any(|pane| pane.arg == r.arg) {
+                changes.push(Change::Remove {
+                    id: r.id,
+                    arg: r.arg.clone(),
+                });
+            }
+        }
+
+        changes
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Change::Add { ref arg } => write!(f, "+ {}", arg),
+            Change::Remove { id, ref arg } => write!(f, "- %{} {}", id, arg),
+            Change::Rename {
+                id,
+                ref from,
+                ref to,
+            } => write!(f, "~ %{} title {:?} -> {:?}", id, from, to),
+        }
+    }
+}
+
+/// Run `mux apply [--socket PATH] [--diff] FILE`, printing how the running session differs from
+/// the session file and, unless `--diff` is given, applying the changes.
+pub fn run_apply(mut args: Vec<String>) -> Result<(), failure::Error> {
+    let socket = control::take_socket_path(&mut args)?;
+    let dry_run = match args.iter().position(|arg| arg == "--diff") {
+        Some(position) => {
+            args.remove(position);
+            true
+        }
+        None => false,
+    };
+    let file = match args.as_slice() {
+        [file] => path::PathBuf::from(file),
+        _ => return Err(failure::err_msg("usage: mux apply [--diff] FILE")),
+    };
+
+    let session = Session::load(&file)?;
+    let running = control::send(&socket, &["list".to_owned()])?
+        .lines()
+        .filter_map(parse_list_line)
+        .collect::<Vec<_>>();
+    let changes = session.diff(&running);
+
+    for change in &changes {
+        println!("{}", change);
+    }
+    if dry_run || changes.is_empty() {
+        return Ok(());
+    }
+
+    let mut unsupported = 0;
+    for change in &changes {
+        match *change {
+            Change::Rename { id, ref to, .. } => {
+                control::send(
+                    &socket,
+                    &[
+                        "rename-pane".to_owned(),
+                        "-t".to_owned(),
+                        id.to_string(),
+                        to.clone(),
+                    ],
+                )?;
+            }
+            Change::Add { .. } | Change::Remove { .. } => unsupported += 1,
+        }
+    }
+
+    if unsupported > 0 {
+        Err(failure::err_msg(format!(
+            "{} panes can't be added or removed in a running session; restart mux to apply them",
+            unsupported
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+/// Parse a line of `mux ctl list` output: ID, state, title and argument separated by tabs.
+fn parse_list_line(line: &str) -> Option<Running> {
+    let fields = line.split('\t').collect::<Vec<_>>();
+    match fields.as_slice() {
+        [id, _state, title, arg] => Some(Running {
+            id: id.parse().ok()?,
+            title: (*title).to_owned(),
+            arg: (*arg).to_owned(),
+        }),
+        _ => None,
+    }
+}
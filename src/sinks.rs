@@ -118,3 +118,49 @@ where
         }
     }
 }
+
+/// A sink that is swapped out for the ones sent to `replacements`, for example when the process
+/// that it writes to is started again.
+pub struct Replaceable<S> {
+    sink: S,
+    replacements: futures::sync::mpsc::UnboundedReceiver<S>,
+}
+
+impl<S> Replaceable<S> {
+    pub fn new(sink: S, replacements: futures::sync::mpsc::UnboundedReceiver<S>) -> Self {
+        Self { sink, replacements }
+    }
+
+    fn replace(&mut self) {
+        use futures::stream::Stream;
+
+        while let Ok(futures::Async::Ready(Some(sink))) = self.replacements.poll() {
+            self.sink = sink;
+        }
+    }
+}
+
+impl<S> futures::sink::Sink for Replaceable<S>
+where
+    S: futures::sink::Sink,
+{
+    type SinkItem = S::SinkItem;
+    type SinkError = S::SinkError;
+
+    fn start_send(
+        &mut self,
+        item: Self::SinkItem,
+    ) -> Result<futures::AsyncSink<Self::SinkItem>, Self::SinkError> {
+        self.replace();
+        self.sink.start_send(item)
+    }
+
+    fn poll_complete(&mut self) -> Result<futures::Async<()>, Self::SinkError> {
+        self.replace();
+        self.sink.poll_complete()
+    }
+
+    fn close(&mut self) -> Result<futures::Async<()>, Self::SinkError> {
+        self.sink.close()
+    }
+}
@@ -0,0 +1,55 @@
+//! The saved output of a session's panes, written with `--snapshot FILE` or `mux ctl snapshot
+//! FILE` and browsed again later with `mux restore FILE`.
+use std::fs;
+use std::io;
+use std::path;
+
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    /// The command template that the processes were started from
+    pub command: String,
+    pub panes: Vec<Pane>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Pane {
+    pub id: usize,
+    pub arg: String,
+    pub title: String,
+    /// The group that the tab was listed under, if any
+    pub group: Option<String>,
+    /// The raw wait status of the process, or `None` if it was still running
+    pub exit_status: Option<i32>,
+    /// Everything that the process printed, including the scrollback
+    pub grid: terminal_emulator::Grid<terminal_emulator::term::cell::Cell>,
+    /// The zero-width characters that the cells of the grid have
+    pub zero_width: terminal_emulator::term::cell::ZeroWidthRuns,
+    /// How many columns apart the tab stops were, from `--tabstop`
+    #[serde(default = "default_tabspaces")]
+    pub tabspaces: usize,
+    /// How much memory the scrollback could take up, from `--scrollback-memory`
+    #[serde(default)]
+    pub scrollback_budget: Option<usize>,
+}
+
+/// The tab stops of snapshots from before they were saved
+fn default_tabspaces() -> usize {
+    8
+}
+
+impl Snapshot {
+    pub fn save(&self, path: &path::Path) -> Result<(), failure::Error> {
+        let file = fs::File::create(path)
+            .map_err(|err| failure::err_msg(format!("{}: {}", path.display(), err)))?;
+        serde_json::to_writer(io::BufWriter::new(file), self)?;
+        Ok(())
+    }
+
+    pub fn load(path: &path::Path) -> Result<Self, failure::Error> {
+        let file = fs::File::open(path)
+            .map_err(|err| failure::err_msg(format!("{}: {}", path.display(), err)))?;
+        let snapshot = serde_json::from_reader(io::BufReader::new(file))
+            .map_err(|err| failure::err_msg(format!("{}: {}", path.display(), err)))?;
+        Ok(snapshot)
+    }
+}
@@ -0,0 +1,26 @@
+use std::{env, ffi, io};
+
+use super::cvt;
+use super::libc::{c_char, geteuid, gethostname};
+
+/// Get the host name of the machine we are running on.
+pub fn name() -> io::Result<String> {
+    let mut buf = [0 as c_char; 256];
+
+    cvt(unsafe { gethostname(buf.as_mut_ptr(), buf.len()) })?;
+    // The name is not guaranteed to be NUL-terminated if it was truncated
+    buf[buf.len() - 1] = 0;
+
+    let name = unsafe { ffi::CStr::from_ptr(buf.as_ptr()) };
+    Ok(name.to_string_lossy().into_owned())
+}
+
+/// Get the ID of the user running this process.
+pub fn user_id() -> u32 {
+    unsafe { geteuid() }
+}
+
+/// Get the name of the user running this process, if known.
+pub fn user() -> Option<String> {
+    env::var("USER").or_else(|_| env::var("LOGNAME")).ok()
+}
@@ -5,6 +5,10 @@ use std::io;
 pub use self::libc::termios as Termios;
 
 pub mod attr;
+pub mod host;
+pub mod peer;
+pub mod process;
+pub mod pty;
 pub mod tty;
 
 // Support functions for converting libc return values to io errors {
@@ -0,0 +1,61 @@
+use std::io;
+use std::os::unix::io::RawFd;
+
+/// Who is on the other end of a unix socket
+#[derive(Clone, Copy, Debug)]
+pub struct Credentials {
+    pub uid: u32,
+    pub gid: u32,
+    /// Only known on platforms that report it
+    pub pid: Option<i32>,
+}
+
+/// Get the credentials of the process connected to the unix socket `fd`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn credentials(fd: RawFd) -> io::Result<Credentials> {
+    use std::mem;
+
+    use super::cvt;
+    use super::libc::{c_void, getsockopt, socklen_t, ucred, SOL_SOCKET, SO_PEERCRED};
+
+    let mut cred = ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    #[allow(clippy::cast_possible_truncation)]
+    let mut size = mem::size_of::<ucred>() as socklen_t;
+
+    cvt(unsafe {
+        getsockopt(
+            fd,
+            SOL_SOCKET,
+            SO_PEERCRED,
+            &mut cred as *mut ucred as *mut c_void,
+            &mut size,
+        )
+    })?;
+
+    Ok(Credentials {
+        uid: cred.uid,
+        gid: cred.gid,
+        pid: Some(cred.pid),
+    })
+}
+
+/// Get the credentials of the process connected to the unix socket `fd`.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn credentials(fd: RawFd) -> io::Result<Credentials> {
+    use super::cvt;
+    use super::libc::getpeereid;
+
+    let mut uid = 0;
+    let mut gid = 0;
+    cvt(unsafe { getpeereid(fd, &mut uid, &mut gid) })?;
+
+    Ok(Credentials {
+        uid,
+        gid,
+        pid: None,
+    })
+}
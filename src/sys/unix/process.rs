@@ -0,0 +1,86 @@
+use std::io;
+use std::process;
+
+use super::libc::c_int;
+
+/// Whether the process with ID `pid` is stopped, for example by `Ctrl+Z`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn is_stopped(pid: u32) -> io::Result<bool> {
+    use std::fs;
+
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid))?;
+    // The command name in parentheses can contain anything, so look for the state after it
+    let state = stat
+        .rfind(')')
+        .and_then(|end| stat[end + 1..].trim_start().chars().next());
+
+    Ok(state == Some('T') || state == Some('t'))
+}
+
+/// Whether the process with ID `pid` is stopped; not known on this platform.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn is_stopped(_pid: u32) -> io::Result<bool> {
+    Ok(false)
+}
+
+/// Ask the process with ID `pid` to exit with `SIGTERM`, together with the other processes in its
+/// process group, like the ones started by a shell script.
+pub fn terminate(pid: u32) -> io::Result<()> {
+    signal(pid, super::libc::SIGTERM)
+}
+
+/// Send `signal` to the process with ID `pid` and the other processes in its process group.
+///
+/// The ID 0 is refused, since `kill` would take it to mean the process group of mux itself.
+pub fn signal(pid: u32, signal: c_int) -> io::Result<()> {
+    if pid == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "no process"));
+    }
+    #[allow(clippy::cast_possible_wrap)]
+    let group = -(pid as super::libc::pid_t);
+    unsafe { super::cvt(super::libc::kill(group, signal)) }?;
+    Ok(())
+}
+
+/// The number of the signal written as `name`, like `TERM`, `SIGINT` or `9`.
+pub fn signal_number(name: &str) -> Option<c_int> {
+    use super::libc::{
+        SIGALRM, SIGCONT, SIGHUP, SIGINT, SIGKILL, SIGQUIT, SIGSTOP, SIGTERM, SIGTSTP, SIGUSR1,
+        SIGUSR2, SIGWINCH,
+    };
+
+    if let Ok(number) = name.parse() {
+        return Some(number);
+    }
+
+    let name = name.to_ascii_uppercase();
+    let number = match name.trim_start_matches("SIG") {
+        "HUP" => SIGHUP,
+        "INT" => SIGINT,
+        "QUIT" => SIGQUIT,
+        "KILL" => SIGKILL,
+        "USR1" => SIGUSR1,
+        "USR2" => SIGUSR2,
+        "ALRM" => SIGALRM,
+        "TERM" => SIGTERM,
+        "CONT" => SIGCONT,
+        "STOP" => SIGSTOP,
+        "TSTP" => SIGTSTP,
+        "WINCH" => SIGWINCH,
+        _ => return None,
+    };
+    Some(number)
+}
+
+/// Make `command` start the process in a new session, so that it keeps running when the
+/// terminal it was started from goes away.
+pub fn new_session(command: &mut process::Command) {
+    use std::os::unix::process::CommandExt;
+
+    unsafe {
+        command.pre_exec(|| {
+            super::cvt(super::libc::setsid())?;
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,18 @@
+use std::io;
+use std::os::unix::io::RawFd;
+
+use super::cvt;
+use super::libc::{ioctl, winsize, TIOCSWINSZ};
+
+/// Set the size of the pseudo-terminal with the master `fd`; the processes running in it get a
+/// `SIGWINCH`.
+pub fn set_size(fd: RawFd, columns: u16, rows: u16) -> io::Result<()> {
+    let size = winsize {
+        ws_row: rows,
+        ws_col: columns,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+
+    cvt(unsafe { ioctl(fd, TIOCSWINSZ, &size as *const winsize) }).and(Ok(()))
+}
@@ -1,5 +1,9 @@
+use std::os::unix::io::AsRawFd;
 use std::{fs, io};
 
+use super::cvt;
+use super::libc::{ioctl, winsize, TIOCGWINSZ};
+
 /// Get the TTY device.
 ///
 /// This allows for getting stdio representing _only_ the TTY, and not other streams.
@@ -9,3 +13,16 @@ pub fn get() -> io::Result<fs::File> {
         .write(true)
         .open("/dev/tty")
 }
+
+/// Get the size of the terminal `tty` as `(columns, rows)`.
+pub fn size(tty: &fs::File) -> io::Result<(u16, u16)> {
+    let mut size = winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+
+    cvt(unsafe { ioctl(tty.as_raw_fd(), TIOCGWINSZ, &mut size as *mut winsize) })?;
+    Ok((size.ws_col, size.ws_row))
+}
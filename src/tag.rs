@@ -0,0 +1,45 @@
+//! Prefixes for the lines that a process prints, so that lines of several processes that end up
+//! in the same place can still be told apart.  The prefix comes from a template with the same
+//! replacement strings as `--title`.
+
+/// The template when `--tag` wasn't given, for the places that always need a prefix
+pub const DEFAULT_TEMPLATE: &str = "{}\t";
+
+/// Puts a prefix in front of every line of output, as the output comes in.
+pub struct Tagger {
+    prefix: Vec<u8>,
+    /// Whether the next byte starts a new line
+    line_start: bool,
+}
+
+impl Tagger {
+    pub fn new(prefix: &str) -> Self {
+        let prefix = prefix.as_bytes().to_vec();
+        let line_start = true;
+        Self { prefix, line_start }
+    }
+
+    /// `data` with the prefix in front of every line that starts in it.
+    pub fn tag(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut tagged = Vec::with_capacity(data.len() + self.prefix.len());
+        for &byte in data {
+            if self.line_start {
+                tagged.extend_from_slice(&self.prefix);
+            }
+            tagged.push(byte);
+            self.line_start = byte == b'\n';
+        }
+        tagged
+    }
+}
+
+/// `text` with `prefix` in front of every line.
+pub fn lines(prefix: &str, text: &str) -> String {
+    let mut tagged = String::with_capacity(text.len());
+    for line in text.lines() {
+        tagged.push_str(prefix);
+        tagged.push_str(line);
+        tagged.push('\n');
+    }
+    tagged
+}
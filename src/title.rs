@@ -0,0 +1,79 @@
+//! Expansion of tab title templates
+//!
+//! Templates use the same replacement strings as GNU parallel, so that long arguments such as
+//! paths or URLs can be shown in a more compact form.
+use std::path;
+
+/// Expand the replacement strings in `template` for the argument `arg` of the job at `index`.
+///
+/// Supported replacement strings are:
+///
+///   * `{}` - the full argument
+///   * `{/}` - the basename of the argument
+///   * `{//}` - the dirname of the argument
+///   * `{.}` - the argument without its extension
+///   * `{/.}` - the basename of the argument without its extension
+///   * `{#}` - the 1-based job number
+///   * `{pane}` - the ID of the pane, as used by `mux ctl`
+///
+/// Anything else in braces is left as-is.
+pub fn expand(template: &str, arg: &str, index: usize, pane: usize) -> String {
+    let mut result = String::with_capacity(template.len() + arg.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let tail = &rest[start..];
+
+        if let Some(end) = tail.find('}') {
+            match replacement(&tail[1..end], arg, index, pane) {
+                Some(value) => result.push_str(&value),
+                None => result.push_str(&tail[..=end]),
+            }
+            rest = &tail[end + 1..];
+        } else {
+            result.push_str(tail);
+            rest = "";
+        }
+    }
+    result.push_str(rest);
+
+    result
+}
+
+fn replacement(token: &str, arg: &str, index: usize, pane: usize) -> Option<String> {
+    match token {
+        "" => Some(arg.to_owned()),
+        "/" => Some(basename(arg).to_owned()),
+        "//" => Some(dirname(arg)),
+        "." => Some(strip_extension(arg).to_owned()),
+        "/." => Some(strip_extension(basename(arg)).to_owned()),
+        "#" => Some((index + 1).to_string()),
+        "pane" => Some(pane.to_string()),
+        _ => None,
+    }
+}
+
+fn basename(arg: &str) -> &str {
+    path::Path::new(arg)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(arg)
+}
+
+fn dirname(arg: &str) -> String {
+    match path::Path::new(arg).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.display().to_string(),
+        _ => ".".to_owned(),
+    }
+}
+
+fn strip_extension(arg: &str) -> &str {
+    match path::Path::new(arg)
+        .extension()
+        .and_then(|ext| ext.to_str())
+    {
+        Some(ext) if arg.ends_with(ext) => &arg[..arg.len() - ext.len() - 1],
+        _ => arg,
+    }
+}
@@ -13,6 +13,67 @@ pub struct Raw {
     prev_ios: sys::Termios,
 }
 
+/// Enables mouse reporting in the terminal (button, drag, urxvt and SGR modes).
+pub const ENABLE_MOUSE: &[u8] = b"\x1b[?1000h\x1b[?1002h\x1b[?1015h\x1b[?1006h";
+/// Disables all mouse reporting modes enabled by `ENABLE_MOUSE`.
+pub const DISABLE_MOUSE: &[u8] = b"\x1b[?1006l\x1b[?1015l\x1b[?1002l\x1b[?1000l";
+
+/// Makes the terminal wrap pasted text in `PASTE_START` and `PASTE_END`.
+pub const ENABLE_BRACKETED_PASTE: &[u8] = b"\x1b[?2004h";
+/// Disables bracketed paste again.
+pub const DISABLE_BRACKETED_PASTE: &[u8] = b"\x1b[?2004l";
+/// Sent before pasted text while bracketed paste is enabled.
+pub const PASTE_START: &[u8] = b"\x1b[200~";
+/// Sent after pasted text while bracketed paste is enabled.
+pub const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// Wrap pasted text in `PASTE_START` and `PASTE_END`, leaving out its escape characters so that
+/// a `PASTE_END` in the text can't end the paste early and have the rest run as typed input.
+pub fn bracketed_paste(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(PASTE_START.len() + data.len() + PASTE_END.len());
+    result.extend_from_slice(PASTE_START);
+    result.extend(data.iter().filter(|&&byte| byte != 0x1b));
+    result.extend_from_slice(PASTE_END);
+    result
+}
+
+/// Makes the cursor blink, keeping its shape (the mode that `xterm` calls `att610`).
+pub const ENABLE_CURSOR_BLINK: &[u8] = b"\x1b[?12h";
+/// Makes the cursor steady again.
+pub const DISABLE_CURSOR_BLINK: &[u8] = b"\x1b[?12l";
+
+/// Makes the keypad send SS3 sequences instead of plain characters (DECKPAM).
+pub const ENABLE_APP_KEYPAD: &[u8] = b"\x1b=";
+/// Makes the keypad send plain characters again (DECKPNM).
+pub const DISABLE_APP_KEYPAD: &[u8] = b"\x1b>";
+
+/// Like `termion::input::MouseTerminal`, but mouse reporting can be left off initially and toggled
+/// later by writing `ENABLE_MOUSE` or `DISABLE_MOUSE`.  It is always turned off on drop.
+pub struct Mouse<W>
+where
+    W: io::Write,
+{
+    output: W,
+}
+
+/// Enables bracketed paste and the application keypad while it exists, so that pastes and keypad
+/// keys can be told apart from typing.
+pub struct Input<W>
+where
+    W: io::Write,
+{
+    output: W,
+}
+
+/// Like `termion::screen::AlternateScreen`, but the alternate screen is optional.
+pub struct Screen<W>
+where
+    W: io::Write,
+{
+    output: W,
+    alternate: bool,
+}
+
 impl Tty {
     pub fn open() -> Result<Self, failure::Error> {
         let file = sys::tty::get()?;
@@ -32,12 +93,90 @@ impl Tty {
         Ok(Raw { tty, prev_ios })
     }
 
+    /// The size of the terminal as `(columns, rows)`.
+    pub fn size(&self) -> Result<(u16, u16), failure::Error> {
+        sys::tty::size(&self.file).map_err(failure::Error::from)
+    }
+
     pub fn try_clone(&mut self) -> Result<Self, failure::Error> {
         self.file
             .try_clone()
             .map(|file| Self { file })
             .map_err(failure::Error::from)
     }
+
+    /// Ask the terminal where the cursor is, returning its 0-based (column, row).
+    ///
+    /// The terminal must be in raw mode, or the response will not arrive until the user presses
+    /// enter.
+    pub fn cursor_position(&mut self) -> Result<(u16, u16), failure::Error> {
+        use std::io::Read;
+        use std::io::Write;
+
+        self.write_all(b"\x1b[6n")?;
+        self.flush()?;
+
+        // The response looks like ESC [ row ; column R
+        let mut response = Vec::new();
+        let mut byte = [0];
+        loop {
+            self.read_exact(&mut byte)?;
+            match byte[0] {
+                b'R' => break,
+                b if response.len() < 32 => response.push(b),
+                _ => return Err(failure::err_msg("unexpected cursor position response")),
+            }
+        }
+
+        let response = String::from_utf8_lossy(&response);
+        let mut parts = response.trim_start_matches("\x1b[").split(';');
+        match (parts.next(), parts.next()) {
+            (Some(row), Some(column)) => {
+                let row: u16 = row.parse()?;
+                let column: u16 = column.parse()?;
+                Ok((column.saturating_sub(1), row.saturating_sub(1)))
+            }
+            _ => Err(failure::err_msg(format!(
+                "malformed cursor position response: {:?}",
+                response
+            ))),
+        }
+    }
+}
+
+impl<W> Mouse<W>
+where
+    W: io::Write,
+{
+    pub fn new(mut output: W, enabled: bool) -> io::Result<Self> {
+        if enabled {
+            output.write_all(ENABLE_MOUSE)?;
+        }
+        Ok(Self { output })
+    }
+}
+
+impl<W> Input<W>
+where
+    W: io::Write,
+{
+    pub fn new(mut output: W) -> io::Result<Self> {
+        output.write_all(ENABLE_BRACKETED_PASTE)?;
+        output.write_all(ENABLE_APP_KEYPAD)?;
+        Ok(Self { output })
+    }
+}
+
+impl<W> Screen<W>
+where
+    W: io::Write,
+{
+    pub fn new(mut output: W, alternate: bool) -> io::Result<Self> {
+        if alternate {
+            write!(output, "{}", termion::screen::ToAlternateScreen)?;
+        }
+        Ok(Self { output, alternate })
+    }
 }
 
 impl Raw {
@@ -98,3 +237,98 @@ impl io::Write for Raw {
         self.tty.flush()
     }
 }
+
+impl<W> io::Write for Mouse<W>
+where
+    W: io::Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+impl<W> Drop for Mouse<W>
+where
+    W: io::Write,
+{
+    fn drop(&mut self) {
+        let _ = self.output.write_all(DISABLE_MOUSE);
+        let _ = self.output.flush();
+    }
+}
+
+impl<W> io::Write for Input<W>
+where
+    W: io::Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+impl<W> Drop for Input<W>
+where
+    W: io::Write,
+{
+    fn drop(&mut self) {
+        let _ = self.output.write_all(DISABLE_APP_KEYPAD);
+        let _ = self.output.write_all(DISABLE_BRACKETED_PASTE);
+        let _ = self.output.flush();
+    }
+}
+
+impl<W> io::Write for Screen<W>
+where
+    W: io::Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+impl<W> Drop for Screen<W>
+where
+    W: io::Write,
+{
+    fn drop(&mut self) {
+        // Processes may have left the cursor blinking
+        let _ = self.output.write_all(DISABLE_CURSOR_BLINK);
+        if self.alternate {
+            let _ = write!(self.output, "{}", termion::screen::ToMainScreen);
+            let _ = self.output.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bracketed_paste_wraps_the_text() {
+        assert_eq!(
+            bracketed_paste(b"echo hi\n"),
+            b"\x1b[200~echo hi\n\x1b[201~".to_vec()
+        );
+    }
+
+    #[test]
+    fn bracketed_paste_cannot_be_ended_by_the_text() {
+        assert_eq!(
+            bracketed_paste(b"a\x1b[201~rm -rf ~\n"),
+            b"\x1b[200~a[201~rm -rf ~\n\x1b[201~".to_vec()
+        );
+    }
+}
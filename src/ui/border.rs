@@ -0,0 +1,111 @@
+//! The border around a pane, with the title of its process in the top line.
+use crate::config;
+
+#[derive(Default)]
+pub struct Border<'a> {
+    title: &'a str,
+    lines: config::BorderLines,
+    placement: config::TitlePlacement,
+    style: tui::style::Style,
+}
+
+/// The characters that a border is drawn with
+struct Symbols {
+    horizontal: &'static str,
+    vertical: &'static str,
+    top_left: &'static str,
+    top_right: &'static str,
+    bottom_left: &'static str,
+    bottom_right: &'static str,
+}
+
+impl<'a> Border<'a> {
+    pub fn title(mut self, title: &'a str) -> Self {
+        self.title = title;
+        self
+    }
+
+    pub fn lines(mut self, lines: config::BorderLines) -> Self {
+        self.lines = lines;
+        self
+    }
+
+    pub fn placement(mut self, placement: config::TitlePlacement) -> Self {
+        self.placement = placement;
+        self
+    }
+
+    pub fn style(mut self, style: tui::style::Style) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// The part of `area` inside of the border
+    pub fn inner(area: tui::layout::Rect) -> tui::layout::Rect {
+        if area.width < 2 || area.height < 2 {
+            return tui::layout::Rect::default();
+        }
+        tui::layout::Rect {
+            x: area.x + 1,
+            y: area.y + 1,
+            width: area.width - 2,
+            height: area.height - 2,
+        }
+    }
+}
+
+impl<'a> tui::widgets::Widget for Border<'a> {
+    fn draw(&mut self, area: tui::layout::Rect, buf: &mut tui::buffer::Buffer) {
+        if area.width < 2 || area.height < 2 {
+            return;
+        }
+
+        let symbols = symbols(self.lines);
+        let style = self.style;
+        let (left, top) = (area.left(), area.top());
+        let (right, bottom) = (area.right() - 1, area.bottom() - 1);
+        for x in left + 1..right {
+            set(buf, x, top, symbols.horizontal, style);
+            set(buf, x, bottom, symbols.horizontal, style);
+        }
+        for y in top + 1..bottom {
+            set(buf, left, y, symbols.vertical, style);
+            set(buf, right, y, symbols.vertical, style);
+        }
+        set(buf, left, top, symbols.top_left, style);
+        set(buf, right, top, symbols.top_right, style);
+        set(buf, left, bottom, symbols.bottom_left, style);
+        set(buf, right, bottom, symbols.bottom_right, style);
+
+        let room = area.width - 2;
+        let width = super::text_width(self.title).min(room);
+        let x = match self.placement {
+            config::TitlePlacement::None => return,
+            config::TitlePlacement::Left => left + 1,
+            config::TitlePlacement::Center => left + 1 + (room - width) / 2,
+            config::TitlePlacement::Right => right - width,
+        };
+        buf.set_stringn(x, top, self.title, width as usize, style);
+    }
+}
+
+fn set(buf: &mut tui::buffer::Buffer, x: u16, y: u16, symbol: &str, style: tui::style::Style) {
+    buf.get_mut(x, y).set_symbol(symbol).set_style(style);
+}
+
+fn symbols(lines: config::BorderLines) -> Symbols {
+    let (horizontal, vertical, top_left, top_right, bottom_left, bottom_right) = match lines {
+        config::BorderLines::Plain => ("─", "│", "┌", "┐", "└", "┘"),
+        config::BorderLines::Rounded => ("─", "│", "╭", "╮", "╰", "╯"),
+        config::BorderLines::Double => ("═", "║", "╔", "╗", "╚", "╝"),
+        config::BorderLines::Thick => ("━", "┃", "┏", "┓", "┗", "┛"),
+    };
+    Symbols {
+        horizontal,
+        vertical,
+        top_left,
+        top_right,
+        bottom_left,
+        bottom_right,
+    }
+}
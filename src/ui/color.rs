@@ -0,0 +1,168 @@
+use crate::config;
+
+/// The 16 standard colors in palette order, as `tui` names them
+const ANSI: [tui::style::Color; 16] = [
+    tui::style::Color::Black,
+    tui::style::Color::Red,
+    tui::style::Color::Green,
+    tui::style::Color::Yellow,
+    tui::style::Color::Blue,
+    tui::style::Color::Magenta,
+    tui::style::Color::Cyan,
+    tui::style::Color::Gray,
+    tui::style::Color::DarkGray,
+    tui::style::Color::LightRed,
+    tui::style::Color::LightGreen,
+    tui::style::Color::LightYellow,
+    tui::style::Color::LightBlue,
+    tui::style::Color::LightMagenta,
+    tui::style::Color::LightCyan,
+    tui::style::Color::White,
+];
+
+/// The usual xterm values of the 16 standard colors
+const ANSI_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// The levels of each component in the 6×6×6 color cube of the 256-color palette
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// The colors of mux's own parts of the screen, already downgraded to the terminal's depth.
+#[derive(Clone, Copy, Debug)]
+pub struct Theme {
+    pub depth: config::Depth,
+    pub accent: tui::style::Color,
+    pub focused: tui::style::Color,
+    pub border: tui::style::Color,
+    pub bar: tui::style::Color,
+    pub ok: tui::style::Color,
+    pub failed: tui::style::Color,
+}
+
+impl Theme {
+    pub fn new(theme: &config::Theme, depth: config::Depth) -> Self {
+        let depth = resolve_depth(depth);
+        let color = |color: Option<config::Color>, default| {
+            downgrade(color.map_or(default, |config::Color(color)| color), depth)
+        };
+
+        Theme {
+            depth,
+            accent: color(theme.accent, tui::style::Color::Yellow),
+            focused: color(theme.focused.or(theme.accent), tui::style::Color::Yellow),
+            border: color(theme.border, tui::style::Color::DarkGray),
+            bar: color(theme.bar, tui::style::Color::DarkGray),
+            ok: color(theme.ok, tui::style::Color::Green),
+            failed: color(theme.failed, tui::style::Color::Red),
+        }
+    }
+}
+
+/// Replace `Depth::Auto` with what the environment says the terminal supports.
+fn resolve_depth(depth: config::Depth) -> config::Depth {
+    use std::env;
+
+    if depth != config::Depth::Auto {
+        return depth;
+    }
+
+    let colorterm = env::var("COLORTERM").unwrap_or_default();
+    let term = env::var("TERM").unwrap_or_default();
+    if colorterm == "truecolor" || colorterm == "24bit" {
+        config::Depth::TrueColor
+    } else if term.contains("256color") {
+        config::Depth::Indexed
+    } else {
+        config::Depth::Ansi
+    }
+}
+
+/// Turn `color` into the closest color that a terminal with `depth` can show.
+pub fn downgrade(color: tui::style::Color, depth: config::Depth) -> tui::style::Color {
+    match (color, depth) {
+        (tui::style::Color::Rgb(r, g, b), config::Depth::Indexed) => {
+            tui::style::Color::Indexed(nearest_indexed((r, g, b)))
+        }
+        (tui::style::Color::Rgb(r, g, b), config::Depth::Ansi) => nearest_ansi((r, g, b)),
+        (tui::style::Color::Indexed(index), config::Depth::Ansi) => {
+            if index < 16 {
+                ANSI[usize::from(index)]
+            } else {
+                nearest_ansi(indexed_rgb(index))
+            }
+        }
+        (color, _) => color,
+    }
+}
+
+fn nearest_indexed(rgb: (u8, u8, u8)) -> u8 {
+    let level = |component: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|&i| (i32::from(CUBE_LEVELS[i]) - i32::from(component)).abs())
+            .unwrap()
+    };
+    let (r, g, b) = (level(rgb.0), level(rgb.1), level(rgb.2));
+    #[allow(clippy::cast_possible_truncation)]
+    let cube = (16 + 36 * r + 6 * g + b) as u8;
+
+    let average = (u32::from(rgb.0) + u32::from(rgb.1) + u32::from(rgb.2)) / 3;
+    #[allow(clippy::cast_possible_truncation)]
+    let gray = (232 + (average.saturating_sub(3) / 10).min(23)) as u8;
+
+    if distance(rgb, indexed_rgb(gray)) < distance(rgb, indexed_rgb(cube)) {
+        gray
+    } else {
+        cube
+    }
+}
+
+fn nearest_ansi(rgb: (u8, u8, u8)) -> tui::style::Color {
+    let index = (0..ANSI_RGB.len())
+        .min_by_key(|&i| distance(rgb, ANSI_RGB[i]))
+        .unwrap();
+    ANSI[index]
+}
+
+/// The RGB value of a color in the 256-color palette
+fn indexed_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => ANSI_RGB[usize::from(index)],
+        16..=231 => {
+            let index = usize::from(index - 16);
+            (
+                CUBE_LEVELS[index / 36],
+                CUBE_LEVELS[index / 6 % 6],
+                CUBE_LEVELS[index % 6],
+            )
+        }
+        _ => {
+            let level = 8 + 10 * (index - 232);
+            (level, level, level)
+        }
+    }
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    #[allow(clippy::cast_sign_loss)]
+    let square = |a: u8, b: u8| {
+        let d = i32::from(a) - i32::from(b);
+        (d * d) as u32
+    };
+    square(a.0, b.0) + square(a.1, b.1) + square(a.2, b.2)
+}
@@ -0,0 +1,185 @@
+//! The terminal that the output of a process goes through.  It is shared between a task of its
+//! own that parses the output and the UI that draws it, so that one chatty process can't hold up
+//! typing or the other processes.
+use std::mem;
+use std::sync;
+use std::time;
+
+use super::progress;
+
+/// How much output of a process that isn't on the screen is kept back at most, before it goes
+/// through the process's terminal anyway
+const MAX_DEFERRED: usize = 1 << 20;
+
+/// How many chunks of output a process can be ahead of its terminal before reading from it waits
+const BUFFERED_CHUNKS: usize = 16;
+
+pub struct Emulator {
+    pub term: terminal_emulator::term::Term,
+    processor: terminal_emulator::Processor,
+    /// What the terminal answered to queries from the process, to send back to it
+    replies: Vec<u8>,
+    /// Whether to keep output back until `flush`, for panes that aren't on the screen with
+    /// `Settings::defer_hidden`
+    pub defer: bool,
+    /// Output that hasn't gone through the terminal yet
+    deferred: bytes::BytesMut,
+    /// When the oldest of the deferred output arrived
+    deferred_since: time::Instant,
+    /// The last progress percentage that the process printed
+    pub progress: Option<u8>,
+}
+
+impl Emulator {
+    pub fn new(size: terminal_emulator::term::SizeInfo) -> Self {
+        let mut term = terminal_emulator::term::Term::new(size);
+        term.set_graphics_supported(super::graphics::supported());
+        let processor = terminal_emulator::Processor::new();
+        let replies = Vec::new();
+        let defer = false;
+        let deferred = bytes::BytesMut::new();
+        let deferred_since = time::Instant::now();
+        let progress = None;
+        Self {
+            term,
+            processor,
+            replies,
+            defer,
+            deferred,
+            deferred_since,
+            progress,
+        }
+    }
+
+    /// An empty terminal of the default size, for a process that was just started
+    pub fn shared() -> sync::Arc<sync::Mutex<Self>> {
+        sync::Arc::new(sync::Mutex::new(Self::new(super::size_info(
+            super::DEFAULT_SIZE,
+        ))))
+    }
+
+    /// Run `output` of a process through the terminal of `emulator` on a task of its own, passing
+    /// the output on once the terminal shows it.  The parsing happens on the blocking threads of
+    /// the runtime.
+    pub fn parse_output(
+        emulator: sync::Arc<sync::Mutex<Self>>,
+        output: impl futures::stream::Stream<Item = bytes::BytesMut, Error = failure::Error>
+            + Send
+            + 'static,
+    ) -> impl futures::stream::Stream<Item = bytes::Bytes, Error = failure::Error> {
+        use futures::future::Future;
+        use futures::stream::Stream;
+
+        let parsed = output.and_then(move |data| {
+            let emulator = sync::Arc::clone(&emulator);
+            let data = data.freeze();
+            let fed = data.clone();
+            futures::future::poll_fn(move || {
+                tokio_threadpool::blocking(|| emulator.lock().unwrap().feed(&fed))
+            })
+            .map_err(failure::Error::from)
+            .map(move |()| data)
+        });
+        futures::sync::mpsc::spawn(
+            parsed,
+            &tokio::executor::DefaultExecutor::current(),
+            BUFFERED_CHUNKS,
+        )
+    }
+
+    /// Run output through the terminal, or keep it for later with `defer`.
+    pub fn feed(&mut self, data: &[u8]) {
+        if self.defer && self.deferred.len() + data.len() < MAX_DEFERRED {
+            if self.deferred.is_empty() {
+                self.deferred_since = time::Instant::now();
+            }
+            self.deferred.extend_from_slice(data);
+        } else {
+            self.flush();
+            self.advance(data);
+        }
+    }
+
+    /// Run the deferred output through the terminal.
+    pub fn flush(&mut self) {
+        if !self.deferred.is_empty() {
+            let data = self.deferred.take();
+            self.advance(&data);
+        }
+    }
+
+    /// Run the deferred output through the terminal if the oldest of it arrived at least
+    /// `interval` ago, returning whether there was any.
+    pub fn flush_after(&mut self, interval: time::Duration) -> bool {
+        if self.deferred.is_empty() || self.deferred_since.elapsed() < interval {
+            false
+        } else {
+            self.flush();
+            true
+        }
+    }
+
+    /// Start over with an empty screen of the same size, for a new process.
+    pub fn reset(&mut self, title: &str) {
+        use terminal_emulator::Handler;
+
+        let size = *self.term.size_info();
+        let defer = self.defer;
+        let scrollback_budget = self.term.scrollback_budget();
+        let tabspaces = self.term.tabspaces();
+        let invalid_utf8 = self.processor.invalid_utf8();
+        let c1_controls = self.processor.c1_controls();
+        *self = Self::new(size);
+        self.defer = defer;
+        self.term.set_scrollback_budget(scrollback_budget);
+        self.term.set_tabspaces(tabspaces);
+        self.processor.set_invalid_utf8(invalid_utf8);
+        self.processor.set_c1_controls(c1_controls);
+        self.term.set_title(title);
+    }
+
+    /// How the output of the process that isn't UTF-8 is shown
+    pub fn invalid_utf8(&self) -> terminal_emulator::ansi::InvalidUtf8 {
+        self.processor.invalid_utf8()
+    }
+
+    /// Show the output of the process that isn't UTF-8 differently from now on
+    pub fn set_invalid_utf8(&mut self, policy: terminal_emulator::ansi::InvalidUtf8) {
+        self.processor.set_invalid_utf8(policy);
+    }
+
+    /// Whether 8-bit C1 controls in the output of the process are understood
+    pub fn c1_controls(&self) -> bool {
+        self.processor.c1_controls()
+    }
+
+    /// Understand 8-bit C1 controls in the output of the process from now on, or stop doing so
+    pub fn set_c1_controls(&mut self, enabled: bool) {
+        self.processor.set_c1_controls(enabled);
+    }
+
+    /// Roughly how many bytes the terminal and the output that is kept back take up
+    pub fn memory_usage(&self) -> usize {
+        self.term.memory_usage() + self.deferred.len()
+    }
+
+    /// What the terminal answered to the process since the last call
+    pub fn take_replies(&mut self) -> Vec<u8> {
+        mem::replace(&mut self.replies, Vec::new())
+    }
+
+    fn advance(&mut self, data: &[u8]) {
+        self.processor
+            .advance_bytes(&mut self.term, data, &mut self.replies);
+
+        // Progress bars usually redraw the cursor's line; a figure that is no longer shown stays
+        if let Some(progress) = self
+            .term
+            .recent_lines(progress::LINES)
+            .iter()
+            .find_map(|line| progress::parse(line))
+        {
+            self.progress = Some(progress);
+        }
+    }
+}
@@ -0,0 +1,37 @@
+//! Counting the lines of a process's output that look like errors.
+use crate::config;
+use crate::lines;
+
+/// Splits the output of a process into lines of plain text, without colors and other escape
+/// sequences, and counts the lines that match any of the error patterns.  The lines are kept
+/// until `take_lines`, for the ALL tab.
+#[derive(Default)]
+pub struct Counter {
+    lines: lines::Splitter,
+    count: usize,
+}
+
+impl Counter {
+    /// How many lines have matched so far
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Count the lines that `data` finishes; the last line is only matched once its newline has
+    /// been printed.
+    pub fn feed(&mut self, data: &[u8], patterns: &[config::Pattern]) {
+        let start = self.lines.complete().len();
+        self.lines.feed(data);
+
+        for line in &self.lines.complete()[start..] {
+            if patterns.iter().any(|pattern| pattern.0.is_match(line)) {
+                self.count += 1;
+            }
+        }
+    }
+
+    /// The lines that were finished since the last call
+    pub fn take_lines(&mut self) -> Vec<String> {
+        self.lines.take_lines()
+    }
+}
@@ -0,0 +1,44 @@
+//! Coalescing the draws for bursts of output, so that a process printing megabytes of text
+//! doesn't cause a draw for every read from its terminal.
+use std::time;
+
+/// The shortest time between two draws for output, for about 60 frames per second
+const INTERVAL: time::Duration = time::Duration::from_millis(16);
+
+/// Keeps track of when the screen was last drawn, and whether output changed it since.
+pub struct Scheduler {
+    last_draw: time::Instant,
+    pending: bool,
+}
+
+impl Scheduler {
+    /// Note that output changed the screen, returning whether to draw it right away; otherwise
+    /// the draw waits until it is `due`.
+    pub fn request(&mut self) -> bool {
+        if self.last_draw.elapsed() >= INTERVAL {
+            true
+        } else {
+            self.pending = true;
+            false
+        }
+    }
+
+    /// Whether a draw that was put off should happen now
+    pub fn due(&self) -> bool {
+        self.pending && self.last_draw.elapsed() >= INTERVAL
+    }
+
+    /// Note that the whole screen was just drawn
+    pub fn drawn(&mut self) {
+        self.last_draw = time::Instant::now();
+        self.pending = false;
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        let last_draw = time::Instant::now();
+        let pending = false;
+        Self { last_draw, pending }
+    }
+}
@@ -0,0 +1,132 @@
+//! Images that processes show, with the kitty graphics protocol or iTerm2's inline images, passed
+//! on to the terminal that mux runs in when it can show them too.  Otherwise the panes only show
+//! the placeholders that their terminals put in the cells of the images.
+use std::collections::HashSet;
+use std::env;
+use std::io;
+
+use terminal_emulator::term::{Image, ImageFormat};
+
+/// How many base64 encoded bytes of an image go in one escape sequence, which is the most that
+/// the protocol allows
+const CHUNK_SIZE: usize = 4096;
+
+/// How many images the outer terminal is sent before it is told to forget them all
+const MAX_SENT: usize = 64;
+
+/// Whether the terminal that mux runs in says that it speaks the kitty graphics protocol.
+pub fn supported() -> bool {
+    let term = env::var("TERM").unwrap_or_default();
+    let program = env::var("TERM_PROGRAM").unwrap_or_default();
+    term == "xterm-kitty" || term == "xterm-ghostty" || program == "WezTerm" || program == "ghostty"
+}
+
+/// An image that is on the screen, or the part of it that isn't scrolled out of its pane
+#[derive(Clone, Debug)]
+pub struct Placement {
+    /// The id of the image in the cells of the process's terminal
+    pub id: u32,
+    pub image: Image,
+    /// Where the top left corner of the shown part goes on the screen
+    pub x: u16,
+    pub y: u16,
+    /// How many lines at the top of the image are scrolled out of the pane
+    pub skipped: usize,
+    /// How many lines of the image are shown
+    pub lines: usize,
+}
+
+/// The images that the outer terminal was sent, and where it shows them
+#[derive(Default)]
+pub struct Graphics {
+    /// The ids of the images that the outer terminal has
+    sent: HashSet<u32>,
+    /// The id, position, skipped and shown lines of the placements from the last update
+    shown: Vec<(u32, u16, u16, usize, usize)>,
+}
+
+impl Graphics {
+    /// Show `placements` over what was just drawn, if they changed since the last call.
+    pub fn update(&mut self, out: &mut impl io::Write, placements: &[Placement]) -> io::Result<()> {
+        let shown = placements
+            .iter()
+            .map(|p| (p.id, p.x, p.y, p.skipped, p.lines))
+            .collect::<Vec<_>>();
+        if shown == self.shown {
+            return Ok(());
+        }
+
+        // The old placements would stay on the screen over whatever is drawn there now
+        if self.sent.len() > MAX_SENT {
+            self.forget(out)?;
+        } else {
+            out.write_all(b"\x1b_Ga=d,d=a,q=2\x1b\\")?;
+        }
+        for placement in placements {
+            self.place(out, placement)?;
+        }
+        self.shown = shown;
+        out.flush()
+    }
+
+    /// Take all images off the screen and out of the outer terminal, for a screen that is drawn
+    /// again from scratch.
+    pub fn forget(&mut self, out: &mut impl io::Write) -> io::Result<()> {
+        self.sent.clear();
+        self.shown.clear();
+        out.write_all(b"\x1b_Ga=d,d=A,q=2\x1b\\")
+    }
+
+    fn place(&mut self, out: &mut impl io::Write, placement: &Placement) -> io::Result<()> {
+        let image = &placement.image;
+        let format = match (image.format, image.pixels) {
+            (ImageFormat::File, _) if image.data.starts_with(b"\x89PNG") => "f=100".to_owned(),
+            (ImageFormat::Rgb, Some((width, height))) => format!("f=24,s={},v={}", width, height),
+            (ImageFormat::Rgba, Some((width, height))) => format!("f=32,s={},v={}", width, height),
+            _ => return Ok(()),
+        };
+        // Part of an image can only be cut off when its height in pixels is known
+        let crop = if placement.skipped == 0 && placement.lines == image.height {
+            String::new()
+        } else if let Some((_, height)) = image.pixels {
+            format!(
+                ",y={},h={}",
+                height * placement.skipped / image.height,
+                height * placement.lines / image.height
+            )
+        } else {
+            return Ok(());
+        };
+
+        if self.sent.insert(placement.id) {
+            transmit(out, placement.id, &format, &image.data)?;
+        }
+        write!(
+            out,
+            "\x1b[{};{}H\x1b_Ga=p,i={},c={},r={}{},C=1,q=2\x1b\\",
+            placement.y + 1,
+            placement.x + 1,
+            placement.id,
+            image.width,
+            placement.lines,
+            crop
+        )
+    }
+}
+
+/// Send the outer terminal an image to keep as `id`, in chunks.
+fn transmit(out: &mut impl io::Write, id: u32, format: &str, data: &[u8]) -> io::Result<()> {
+    let encoded = base64::encode(data);
+    let chunks = encoded.as_bytes().chunks(CHUNK_SIZE).collect::<Vec<_>>();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = if i + 1 < chunks.len() { 1 } else { 0 };
+        if i == 0 {
+            write!(out, "\x1b_Ga=t,t=d,i={},{},q=2,m={};", id, format, more)?;
+        } else {
+            write!(out, "\x1b_Gm={};", more)?;
+        }
+        out.write_all(chunk)?;
+        out.write_all(b"\x1b\\")?;
+    }
+    Ok(())
+}
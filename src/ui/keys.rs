@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+
+use crate::config;
+
+/// A command that the user can trigger with a key binding
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Command {
+    ReloadConfig,
+    ToggleMouse,
+    NextTiling,
+    MoveTabUp,
+    MoveTabDown,
+    ToggleZoom,
+    /// Highlight the selected tab when its process prints something, or stop doing so
+    ToggleMonitorActivity,
+    /// Highlight the selected tab when its process has been quiet for a while, or stop doing so
+    ToggleMonitorSilence,
+    /// Scroll the list of tabs without changing the selected tab
+    ScrollTabsUp,
+    ScrollTabsDown,
+    /// Show the ALL tab with the output of all processes together, or go back
+    ToggleAll,
+    Goto,
+    Save,
+    ScrollUp,
+    ScrollDown,
+    ToggleFollow,
+    JumpToLive,
+    /// Clear the scrollback and the screen of the selected process
+    ClearScrollback,
+    /// Go on to the next way of showing bytes that aren't UTF-8 in the selected process
+    CycleInvalidUtf8,
+    /// Understand 8-bit C1 controls in the output of the selected process, or stop doing so
+    ToggleC1Controls,
+    /// Scroll to the prompt above or below, for shells that mark their prompts
+    PreviousPrompt,
+    NextPrompt,
+    /// Copy what the last command printed to the clipboard, for shells that mark their prompts
+    CopyOutput,
+    ToggleTabs,
+    ToggleInputMode,
+    NextTab,
+    PreviousTab,
+    NextFailed,
+    /// Hide or show the tabs of processes that exited successfully
+    ToggleFinished,
+    /// Collapse or expand the group of the selected process
+    ToggleGroup,
+    /// Terminate the running processes in the group of the selected process
+    KillGroup,
+    /// Start the finished processes in the group of the selected process again
+    RestartGroup,
+    /// Mark tabs to run an action on all of their processes at once
+    MarkTabs,
+    /// Start the selected process again, after editing its argument
+    Rerun,
+    /// Give the selected tab a title of its own, or go back to the titles that its process sets
+    Rename,
+    Quit,
+    /// Detach from the session, when running in one
+    Detach,
+    /// Type the prefix key into the processes
+    SendPrefix,
+    /// Removes a default key binding in the config file
+    #[serde(rename = "none")]
+    Unbound,
+}
+
+/// Which commands the keys trigger, either after pressing the prefix key or directly.
+pub struct Keymap {
+    pub prefix: termion::event::Key,
+    prefixed: HashMap<termion::event::Key, Command>,
+    global: HashMap<termion::event::Key, Command>,
+}
+
+/// The commands bound to letters after the prefix key
+const DEFAULT_BINDINGS: &[(char, Command)] = &[
+    ('r', Command::ReloadConfig),
+    ('m', Command::ToggleMouse),
+    ('l', Command::NextTiling),
+    (',', Command::MoveTabUp),
+    ('.', Command::MoveTabDown),
+    ('z', Command::ToggleZoom),
+    ('w', Command::ToggleAll),
+    ('g', Command::Goto),
+    ('s', Command::Save),
+    ('u', Command::ScrollUp),
+    ('d', Command::ScrollDown),
+    ('o', Command::ToggleFollow),
+    ('e', Command::JumpToLive),
+    ('K', Command::ClearScrollback),
+    ('B', Command::CycleInvalidUtf8),
+    ('C', Command::ToggleC1Controls),
+    ('j', Command::PreviousPrompt),
+    ('J', Command::NextPrompt),
+    ('Y', Command::CopyOutput),
+    ('b', Command::ToggleTabs),
+    ('i', Command::ToggleInputMode),
+    ('n', Command::NextTab),
+    ('p', Command::PreviousTab),
+    ('f', Command::NextFailed),
+    ('a', Command::Rerun),
+    ('t', Command::Rename),
+    ('y', Command::ToggleMonitorActivity),
+    ('x', Command::ToggleMonitorSilence),
+    ('h', Command::ToggleFinished),
+    ('c', Command::ToggleGroup),
+    ('k', Command::KillGroup),
+    ('R', Command::RestartGroup),
+    ('v', Command::MarkTabs),
+];
+
+impl Keymap {
+    /// The default key bindings, changed by the ones in the config file.
+    pub fn new(keys: &config::Keys) -> Self {
+        let config::Key(prefix) = keys.prefix;
+
+        let mut prefixed = DEFAULT_BINDINGS
+            .iter()
+            .map(|&(c, command)| (termion::event::Key::Char(c), command))
+            .collect::<HashMap<_, _>>();
+        prefixed.insert(termion::event::Key::Char('q'), Command::Quit);
+        prefixed.insert(termion::event::Key::PageUp, Command::ScrollTabsUp);
+        prefixed.insert(termion::event::Key::PageDown, Command::ScrollTabsDown);
+        // Like in tmux; this scrolls down instead when not running in a session
+        prefixed.insert(termion::event::Key::Char('d'), Command::Detach);
+        prefixed.insert(prefix, Command::SendPrefix);
+
+        // Keys on their own go to the processes unless the config file binds them, since shells
+        // and editors use Alt for moving by words and the like
+        let mut global = HashMap::new();
+
+        apply_overrides(&mut prefixed, &keys.bindings);
+        apply_overrides(&mut global, &keys.global);
+
+        Keymap {
+            prefix,
+            prefixed,
+            global,
+        }
+    }
+
+    /// The command for a key pressed after the prefix key
+    pub fn prefixed(&self, key: termion::event::Key) -> Option<Command> {
+        self.prefixed.get(&key).cloned()
+    }
+
+    /// The command for a key pressed without the prefix key
+    pub fn global(&self, key: termion::event::Key) -> Option<Command> {
+        self.global.get(&key).cloned()
+    }
+}
+
+fn apply_overrides(
+    bindings: &mut HashMap<termion::event::Key, Command>,
+    overrides: &HashMap<config::Key, Command>,
+) {
+    for (&config::Key(key), &command) in overrides {
+        if command == Command::Unbound {
+            bindings.remove(&key);
+        } else {
+            bindings.insert(key, command);
+        }
+    }
+}
+
+/// The bytes to send to a process for an input event, encoded for the process's terminal modes,
+/// or `None` if it should be forwarded the way it was typed.
+///
+/// The outer terminal is kept in application keypad mode, so keypad keys arrive as SS3 sequences;
+/// they are turned back into keypad keys here to be encoded like the process asks for.
+pub fn encode(
+    event: &termion::event::Event,
+    mode: terminal_emulator::term::TermMode,
+) -> Option<Vec<u8>> {
+    use terminal_emulator::input::{self, Key as InputKey, Modifiers};
+    use termion::event::{Event, Key};
+
+    let (key, mods) = match *event {
+        Event::Key(key) => match key {
+            Key::Char('\n') => (InputKey::Enter, Modifiers::empty()),
+            Key::Char('\t') => (InputKey::Tab, Modifiers::empty()),
+            Key::Char(c) => (InputKey::Char(c), Modifiers::empty()),
+            Key::Alt(c) => (InputKey::Char(c), Modifiers::ALT),
+            Key::Ctrl(c) => (InputKey::Char(c), Modifiers::CTRL),
+            Key::Null => (InputKey::Char(' '), Modifiers::CTRL),
+            Key::Esc => (InputKey::Escape, Modifiers::empty()),
+            Key::Backspace => (InputKey::Backspace, Modifiers::empty()),
+            Key::BackTab => (InputKey::BackTab, Modifiers::empty()),
+            Key::Up => (InputKey::Up, Modifiers::empty()),
+            Key::Down => (InputKey::Down, Modifiers::empty()),
+            Key::Right => (InputKey::Right, Modifiers::empty()),
+            Key::Left => (InputKey::Left, Modifiers::empty()),
+            Key::Home => (InputKey::Home, Modifiers::empty()),
+            Key::End => (InputKey::End, Modifiers::empty()),
+            Key::Insert => (InputKey::Insert, Modifiers::empty()),
+            Key::Delete => (InputKey::Delete, Modifiers::empty()),
+            Key::PageUp => (InputKey::PageUp, Modifiers::empty()),
+            Key::PageDown => (InputKey::PageDown, Modifiers::empty()),
+            Key::F(n) => (InputKey::F(n), Modifiers::empty()),
+            _ => return None,
+        },
+        Event::Unsupported(ref raw) => match raw.as_slice() {
+            b"\x1bOM" => (InputKey::KeypadEnter, Modifiers::empty()),
+            [0x1b, b'O', c @ b'p'..=b'y'] => (
+                InputKey::Keypad(char::from(b'0' + (c - b'p'))),
+                Modifiers::empty(),
+            ),
+            [0x1b, b'O', c] => {
+                let c = match c {
+                    b'j' => '*',
+                    b'k' => '+',
+                    b'l' => ',',
+                    b'm' => '-',
+                    b'n' => '.',
+                    b'o' => '/',
+                    b'X' => '=',
+                    _ => return None,
+                };
+                (InputKey::Keypad(c), Modifiers::empty())
+            }
+            _ => return None,
+        },
+        _ => return None,
+    };
+    input::encode(key, mods, mode)
+}
+
+/// The bytes that a terminal sends for `key`, for the keys that can be used as a prefix key.
+pub fn key_bytes(key: termion::event::Key) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut buf = [0; 4];
+    match key {
+        termion::event::Key::Char(c) => bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes()),
+        termion::event::Key::Alt(c) => {
+            bytes.push(0x1b);
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+        }
+        termion::event::Key::Ctrl(c) if c.is_ascii() => bytes.push(c as u8 & 0x1f),
+        _ => {}
+    }
+    bytes
+}
@@ -0,0 +1,116 @@
+//! The output of all processes together, line by line in the order that it arrived, for the ALL
+//! tab that shows the whole run at once.
+use std::collections::VecDeque;
+
+/// How many lines are kept at most; the oldest ones go first
+const MAX_LINES: usize = 10_000;
+
+/// The colors of the prefixes, one per process in turn; red is left out for failures
+const PALETTE: &[tui::style::Color] = &[
+    tui::style::Color::Cyan,
+    tui::style::Color::Magenta,
+    tui::style::Color::Yellow,
+    tui::style::Color::Green,
+    tui::style::Color::Blue,
+    tui::style::Color::LightCyan,
+    tui::style::Color::LightMagenta,
+    tui::style::Color::LightYellow,
+    tui::style::Color::LightGreen,
+    tui::style::Color::LightBlue,
+];
+
+#[derive(Default)]
+pub struct Merged {
+    /// The index of the process that printed each line, and the line without escape sequences
+    lines: VecDeque<(usize, String)>,
+    /// How many lines up from the live output the view is; it stays on the same lines while
+    /// more output arrives
+    scroll: usize,
+}
+
+impl Merged {
+    /// Add the `lines` that the process at `index` finished, returning whether there were any.
+    pub fn push(&mut self, index: usize, lines: Vec<String>) -> bool {
+        if lines.is_empty() {
+            return false;
+        }
+        if self.scroll > 0 {
+            self.scroll += lines.len();
+        }
+        self.lines
+            .extend(lines.into_iter().map(|line| (index, line)));
+        while self.lines.len() > MAX_LINES {
+            self.lines.pop_front();
+        }
+        self.scroll = self.scroll.min(self.lines.len());
+        true
+    }
+
+    /// Scroll `lines` up, or down towards the live output.
+    pub fn scroll(&mut self, up: bool, lines: usize) {
+        self.scroll = if up {
+            (self.scroll + lines).min(self.lines.len())
+        } else {
+            self.scroll.saturating_sub(lines)
+        };
+    }
+
+    /// Go back to the live output.
+    pub fn jump_to_live(&mut self) {
+        self.scroll = 0;
+    }
+
+    /// Draw the lines that fit in `area`, each with the prefix of its process from `prefixes`.
+    pub fn draw(
+        &self,
+        area: tui::layout::Rect,
+        buf: &mut tui::buffer::Buffer,
+        prefixes: &[String],
+        accent: tui::style::Color,
+    ) {
+        for y in area.top()..area.bottom() {
+            for x in area.left()..area.right() {
+                buf.get_mut(x, y).reset();
+            }
+        }
+
+        let height = area.height as usize;
+        let end = self.lines.len() - self.scroll;
+        let start = end.saturating_sub(height);
+        for (y, &(index, ref line)) in (area.y..).zip(self.lines.range(start..end)) {
+            let prefix = prefixes.get(index).map_or("", String::as_str);
+            let style = tui::style::Style::default()
+                .fg(PALETTE[index % PALETTE.len()])
+                .modifier(tui::style::Modifier::BOLD);
+            buf.set_stringn(area.x, y, prefix, area.width as usize, style);
+
+            let width = super::text_width(prefix).min(area.width);
+            buf.set_stringn(
+                area.x + width,
+                y,
+                line,
+                (area.width - width) as usize,
+                tui::style::Style::default(),
+            );
+        }
+
+        if self.scroll > 0 {
+            // Same as for the panes of the processes
+            let text = match self.scroll {
+                1 => "[1 line up]".to_owned(),
+                lines => format!("[{} lines up]", lines),
+            };
+            let width = super::text_width(&text);
+            if width <= area.width && area.height > 0 {
+                buf.set_string(
+                    area.right() - width,
+                    area.y,
+                    &text,
+                    tui::style::Style::default()
+                        .fg(tui::style::Color::Black)
+                        .bg(accent),
+                );
+            }
+        }
+    }
+}
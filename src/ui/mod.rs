@@ -1,21 +1,71 @@
+mod border;
+mod color;
+mod emulator;
+mod errors;
+mod frames;
+mod graphics;
+mod keys;
+mod merged;
+mod progress;
+mod prompt;
+mod status_bar;
+mod tab_strip;
+mod task;
+mod tiling;
 mod vertical_tabs;
+mod viewport;
+
+use crate::config;
+use crate::control;
+use crate::report;
+use crate::snapshot;
+use crate::sys;
+use crate::tag;
+
+pub use self::emulator::Emulator;
+pub use self::keys::Command;
+pub use self::task::Task;
+pub use self::tiling::Tiling;
+pub use self::vertical_tabs::Truncate;
+pub use self::viewport::Height;
+pub use self::viewport::Viewport;
 
 pub struct Ui<B>
 where
-    B: tui::backend::Backend,
+    B: tui::backend::Backend + std::io::Write,
 {
     state: State,
-    terminal: tui::Terminal<B>,
+    terminal: tui::Terminal<Viewport<B>>,
     last_size: tui::layout::Rect,
+    mouse: bool,
+    frames: frames::Scheduler,
+    /// The images that were passed on to the terminal, if it can show them
+    graphics: Option<graphics::Graphics>,
+    /// Whether the terminal was told to blink the cursor
+    cursor_blinks: bool,
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug)]
 pub enum Event {
     UserInput(termion::event::Event, bytes::Bytes),
+    /// Text that the user pasted, without the bracketed paste markers
+    Paste(bytes::Bytes),
     EndOfUserInput,
+    /// Output of a process, which already went through its terminal
     ProcessOutput(usize, bytes::Bytes),
     ProcessExit(usize, std::process::ExitStatus),
+    /// The process was started again, with a new argument, for `Action::Respawn`
+    ProcessStarted {
+        index: usize,
+        pid: u32,
+        arg: String,
+        title: String,
+        tag: Option<String>,
+        command_line: String,
+    },
     Resized,
+    Tick,
+    Control(control::Request, control::Responder),
 }
 
 #[derive(Clone, Debug)]
@@ -27,51 +77,324 @@ pub enum Action {
     ProcessInputAll {
         data: bytes::Bytes,
     },
-    #[allow(dead_code)]
     ProcessTermResize {
         index: usize,
         width: u16,
         height: u16,
     },
+    /// Start the process again with a different argument, in the same pane
+    Respawn {
+        index: usize,
+        arg: String,
+    },
+    /// The user asked to quit
+    Quit,
 }
 
 pub struct ProcessSettings {
+    /// The stable ID of the pane, used to refer to it from scripts
+    pub id: usize,
+    /// The operating system's ID of the process
+    pub pid: u32,
+    /// The argument that the process was started for
+    pub arg: String,
     pub initial_title: String,
+    /// The group that the tab is listed under, if any
+    pub group: Option<String>,
+    /// The complete command line that the process was started with
+    pub command_line: String,
+    /// What to put in front of every line in the log and the saved output, from `--tag`
+    pub tag: Option<String>,
+    /// The terminal that the output of the process goes through, with `Emulator::parse_output`
+    pub emulator: std::sync::Arc<std::sync::Mutex<Emulator>>,
+}
+
+pub struct Settings {
+    /// The command template that the processes were started from
+    pub command: String,
+    pub status: StatusSettings,
+    /// How to shorten tab titles that don't fit in the tab bar
+    pub title_truncate: Truncate,
+    /// Whether mouse reporting is enabled when starting up
+    pub mouse: bool,
+    /// Show the tabs in a single line above the process instead of in a column beside it
+    pub compact_tabs: bool,
+    /// The widest that the list of tabs gets; it is narrower if all titles fit
+    pub tab_width: u16,
+    /// How to arrange the processes when starting up
+    pub tiling: Tiling,
+    /// Where to save the output of each process, if anywhere
+    pub log_dir: Option<std::path::PathBuf>,
+    /// Keep colors and text styles as escape sequences when saving the scrollback of a process
+    pub save_attributes: bool,
+    /// Dim the panes of processes that haven't printed anything for this long
+    pub dim_idle: Option<std::time::Duration>,
+    /// Show a dimmed cursor in the panes of processes that aren't selected
+    pub inactive_cursors: bool,
+    /// Show the command line, working directory and start time of the selected process above it
+    pub pane_header: bool,
+    /// Hide the tabs of processes that exited successfully this long ago, when starting up
+    pub hide_finished: Option<std::time::Duration>,
+    /// Only run the output of processes that aren't on the screen through their terminals this
+    /// often, instead of as soon as it arrives
+    pub defer_hidden: Option<std::time::Duration>,
+    /// Which processes typed keys go to when starting up
+    pub input_mode: InputMode,
+    /// Detaches the client when running in a session
+    pub detach: Option<Box<dyn Fn() + Send>>,
+}
+
+/// Which processes receive the keys that the user types
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum InputMode {
+    /// Every process gets the same input
+    All,
+    /// Only the selected process gets input
+    Selected,
+    /// The processes in the group of the selected process get input
+    Group,
+}
+
+/// Status bar segments requested on the command line; these are enabled in addition to the ones
+/// enabled in the config file.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StatusSettings {
+    /// Whether to show the current wall-clock time
+    pub clock: bool,
+    /// Whether to show the user and host name
+    pub host: bool,
 }
 
 struct State {
+    /// The processes in the order they were started, which is how actions refer to them
     processes: Vec<ProcessState>,
+    /// The indices of `processes` in the order they are shown
+    order: Vec<usize>,
+    /// The index of the selected process
     selected: usize,
     scroll: usize,
+    /// The selected process, and whether the ALL tab was shown, when the list of tabs was last
+    /// scrolled to the selected tab
+    scrolled_to: Option<(usize, bool)>,
+    /// How many rows the list of tabs had room for when it was last drawn
+    tabs_height: usize,
+    settings: Settings,
+    config: config::Source,
+    /// The colors of the chrome, downgraded to what the terminal supports
+    theme: color::Theme,
+    keymap: keys::Keymap,
+    /// Whether the prefix key was pressed, so that the next key is a command
+    prefixed: bool,
+    /// Whether the user is being asked to confirm quitting while processes are still running
+    confirm_quit: bool,
+    /// The processes that the user is being asked to confirm terminating
+    confirm_kill: Option<Vec<usize>>,
+    quit: bool,
+    clock: Option<String>,
+    host: Option<String>,
+    started: std::time::Instant,
+    /// The time since starting, as shown in the status bar
+    elapsed: String,
+    /// The frame of the spinner shown next to running processes
+    spinner: usize,
+    mouse: bool,
+    tiling: Tiling,
+    input_mode: InputMode,
+    goto: Option<Goto>,
+    save: Option<Save>,
+    rerun: Option<Rerun>,
+    rename: Option<Rename>,
+    marking: Option<Marking>,
+    /// Whether the selected process temporarily gets all of the space
+    zoomed: bool,
+    /// Whether the ALL tab is shown instead of the selected process
+    all: bool,
+    /// The lines of all processes together, for the ALL tab
+    merged: merged::Merged,
+    /// Whether the user hid the tabs to make room for the process
+    tabs_hidden: bool,
+    /// Whether the tabs of processes that exited successfully are left out
+    hide_finished: bool,
+    /// The groups whose tabs are folded away under their header
+    collapsed: std::collections::HashSet<String>,
+    /// The index of the process whose tab is being dragged with the mouse
+    dragging: Option<usize>,
+    /// Where the cursor of the selected process was drawn on the screen, if it is shown
+    cursor: Option<(u16, u16)>,
+    /// Whether the selected process asked for a blinking cursor
+    cursor_blinks: bool,
+    /// The images that are on the screen, for terminals that can show them
+    placements: Vec<graphics::Placement>,
+    /// Text to put on the clipboard of the terminal that mux runs in
+    clipboard: Option<String>,
+    /// The working directory that the processes were started in, for the pane header
+    cwd: String,
+}
+
+/// How to show the cursor of a process
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Cursor {
+    Hidden,
+    /// Shown with the cursor of the terminal that mux runs in
+    Active,
+    /// Drawn as a dimmed block
+    Inactive,
+}
+
+/// The prompt for going to a pane by typing (part of) its title or argument
+#[derive(Default)]
+struct Goto {
+    text: String,
+    /// The pane that pressing enter again goes to, after telling the user that several matched
+    confirm: Option<usize>,
+    message: Option<String>,
+}
+
+/// The prompt for the file to save the scrollback of the selected process to
+struct Save {
+    text: String,
+    /// Whether pressing enter again overwrites the file, after telling the user that it exists
+    confirm: bool,
+    message: Option<String>,
+}
+
+/// The prompt for the argument to start the selected process again with
+struct Rerun {
+    text: String,
+    message: Option<String>,
+}
+
+/// The prompt for a new title of the selected tab
+struct Rename {
+    text: String,
+    message: Option<String>,
+}
+
+/// Marking tabs with space to run an action on all of the marked processes at once
+#[derive(Default)]
+struct Marking {
+    /// The signal being typed, after pressing `s`
+    signal: Option<String>,
+    /// What happened with the last action
+    message: Option<String>,
+}
+
+/// A row in the list of tabs
+enum Row {
+    /// The tab with the output of all processes together
+    All,
+    /// The header of a group of tabs, which collapses and expands the group when clicked
+    Group(String),
+    Tab(usize),
+}
+
+struct Layout {
+    banner_area: tui::layout::Rect,
+    tabs_area: tui::layout::Rect,
+    header_area: tui::layout::Rect,
+    process_area: tui::layout::Rect,
+    status_area: tui::layout::Rect,
 }
 
 struct ProcessState {
-    terminal_emulator: terminal_emulator::term::Term,
-    processor: terminal_emulator::Processor,
+    id: usize,
+    pid: u32,
+    arg: String,
+    emulator: std::sync::Arc<std::sync::Mutex<Emulator>>,
     title: String,
+    /// The title that the process set last, which is shown unless the user renamed the tab
+    process_title: String,
+    /// Whether the user renamed the tab, so that titles from the process are ignored
+    renamed: bool,
+    /// The group that the tab is listed under, if any
+    group: Option<String>,
+    /// The complete command line that the process was started with
+    command_line: String,
+    started: chrono::DateTime<chrono::Local>,
+    /// When the process was started, to show how long it has been running
+    running_since: std::time::Instant,
     exit_status: Option<std::process::ExitStatus>,
+    /// Whether the process is stopped, for example because the user pressed Ctrl+Z in it
+    suspended: bool,
+    /// Whether the process printed something since it was last shown
+    unseen: bool,
+    last_output: std::time::Instant,
+    /// Whether the tab is highlighted while it has unseen output
+    monitor_activity: bool,
+    /// How long the process has to be quiet for its tab to be highlighted, if at all
+    monitor_silence: Option<std::time::Duration>,
+    /// When the process exited, so that its tab can be hidden a while later
+    exited: Option<std::time::Instant>,
     input: Vec<u8>,
+    /// The file that all output of the process is copied to
+    log: Option<std::fs::File>,
+    /// What to put in front of every line in the log and the saved output
+    tag: Option<String>,
+    /// Puts the tag in front of the lines that go to the log
+    tagger: Option<tag::Tagger>,
+    /// The (width, height) of the emulated terminal
+    size: (u16, u16),
+    resized: bool,
+    /// Whether the output comes from a snapshot, so that there is no process anymore
+    restored: bool,
+    /// The argument that the user asked to start the process again with
+    respawn: Option<String>,
+    /// The last progress percentage that the process printed
+    progress: Option<u8>,
+    /// The lines of output that look like errors
+    errors: errors::Counter,
+    /// Whether the tab is marked for an action on several processes
+    marked: bool,
+    /// The mouse button that was pressed in the pane, to report drags and the release with
+    mouse_button: Option<terminal_emulator::mouse::Button>,
+    /// Whether the process rang the bell since it was passed on to the terminal
+    bell: bool,
+    /// The notifications that the process sent, to pass on to the terminal; a process that sends
+    /// more than `MAX_NOTIFICATIONS` only keeps its latest ones
+    notifications: std::collections::VecDeque<terminal_emulator::ansi::Notification>,
+    /// When the last notification of the process was passed on to the terminal
+    notification_sent: Option<std::time::Instant>,
+    /// Whether the process sent a notification since it was last shown
+    notified: bool,
 }
 
 impl<B> Ui<B>
 where
-    B: tui::backend::Backend + 'static,
+    B: tui::backend::Backend + std::io::Write + 'static,
 {
     pub fn new(
-        terminal: tui::Terminal<B>,
+        terminal: tui::Terminal<Viewport<B>>,
         processes: impl IntoIterator<Item = ProcessSettings>,
+        settings: Settings,
+        config: config::Source,
     ) -> Result<Self, failure::Error> {
         let processes = processes
             .into_iter()
-            .map(ProcessState::from_settings)
-            .collect();
-        let state = State::new(processes);
+            .map(|p| {
+                ProcessState::from_settings(
+                    p,
+                    settings.log_dir.as_ref().map(std::path::PathBuf::as_path),
+                )
+            })
+            .collect::<Result<_, _>>()?;
+        let mouse = settings.mouse;
+        let state = State::new(processes, settings, config);
         let last_size = terminal.size()?;
+        let frames = frames::Scheduler::default();
+        let graphics = if graphics::supported() {
+            Some(graphics::Graphics::default())
+        } else {
+            None
+        };
 
         Ok(Self {
             state,
             terminal,
             last_size,
+            mouse,
+            frames,
+            graphics,
+            cursor_blinks: false,
         })
     }
 
@@ -85,32 +408,98 @@ where
         }
     }
 
+    /// Whether anything changed with time, including output whose draw was put off.
+    pub fn check_tick(&mut self) -> bool {
+        let changed = self.state.update_clock();
+        let now = std::time::Instant::now();
+        let notifying = self.state.processes.iter().any(|p| p.notification_due(now));
+        changed || notifying || self.frames.due()
+    }
+
     pub fn on_event(&mut self, event: &Event) -> Result<Vec<Action>, failure::Error> {
+        // Output is by far the most frequent event, and most of it goes to processes that are not
+        // on the screen; drawing everything again for each chunk would keep a core busy.  Bursts
+        // of output are drawn at most once per frame, the rest with the next tick
+        if let Event::ProcessOutput(idx, data) = event {
+            if self.state.on_data(*idx, data) && self.frames.request() {
+                self.draw()?;
+            }
+            return Ok(self.take_actions(None));
+        }
+
         let mut process_input_all = None;
         let process_input_all_ref = &mut process_input_all;
+        let top = self.terminal.backend().top()?;
 
         let state_ref = &mut self.state;
         self.terminal.draw(move |mut frame| {
             match event {
-                Event::ProcessOutput(idx, data) => {
-                    state_ref.on_data(*idx, data.clone());
-                }
                 Event::ProcessExit(idx, status) => {
                     state_ref.on_exit(*idx, *status);
                 }
+                Event::ProcessStarted {
+                    index,
+                    pid,
+                    arg,
+                    title,
+                    tag,
+                    command_line,
+                } => {
+                    let process = &mut state_ref.processes[*index];
+                    process.restart(*pid, arg, title, tag.clone(), command_line);
+                }
                 Event::UserInput(event, user_input) => {
-                    let handled_input = state_ref.on_user_input(frame.size(), event);
-                    if !handled_input {
-                        *process_input_all_ref = Some(user_input.clone());
+                    // Mouse rows count from the top of the terminal, but the layout from the top
+                    // of the viewport
+                    if let Some(event) = viewport_event(event, top) {
+                        let handled_input = state_ref.on_user_input(frame.size(), &event);
+                        if !handled_input {
+                            *process_input_all_ref = state_ref.forward_input(&event, user_input);
+                        }
                     }
                 }
+                Event::Paste(data) => {
+                    state_ref.on_paste(data);
+                }
+                Event::Control(request, responder) => {
+                    responder.send(state_ref.on_control(request));
+                }
                 _ => {}
             };
 
             frame.render(state_ref, frame.size());
         })?;
+        self.frames.drawn();
+        self.state.update_deferred();
+
+        self.update_mouse()?;
+        self.update_graphics()?;
+        self.update_cursor()?;
+        self.update_bell()?;
+        self.update_notifications()?;
+        self.update_clipboard()?;
+
+        Ok(self.take_actions(process_input_all))
+    }
 
-        let result = process_input_all
+    /// What the processes need to be told after an event.
+    fn take_actions(&mut self, process_input_all: Option<bytes::Bytes>) -> Vec<Action> {
+        let resizes = self
+            .state
+            .take_resizes()
+            .map(|(index, (width, height))| Action::ProcessTermResize {
+                index,
+                width,
+                height,
+            })
+            .collect::<Vec<_>>();
+        let respawns = self
+            .state
+            .take_respawns()
+            .map(|(index, arg)| Action::Respawn { index, arg })
+            .collect::<Vec<_>>();
+
+        process_input_all
             .into_iter()
             .map(|data| Action::ProcessInputAll { data })
             .chain(
@@ -121,9 +510,14 @@ where
                         data: data.freeze(),
                     }),
             )
-            .collect();
-
-        Ok(result)
+            .chain(resizes)
+            .chain(respawns)
+            .chain(if self.state.quit {
+                Some(Action::Quit)
+            } else {
+                None
+            })
+            .collect()
     }
 
     pub fn draw(&mut self) -> Result<(), failure::Error> {
@@ -131,6 +525,190 @@ where
         self.terminal.draw(|mut f| {
             f.render(state, f.size());
         })?;
+        self.frames.drawn();
+        self.update_graphics()?;
+        self.update_cursor()?;
+        Ok(())
+    }
+
+    /// Draw everything again from scratch, for a client that just attached to the session.
+    pub fn redraw(&mut self) -> Result<(), failure::Error> {
+        use std::io::Write;
+
+        self.terminal.clear()?;
+        if let Some(ref mut graphics) = self.graphics {
+            graphics.forget(self.terminal.backend_mut())?;
+        }
+        // The client's terminal starts with a steady cursor
+        self.cursor_blinks = false;
+        if self.mouse {
+            self.terminal
+                .backend_mut()
+                .write_all(crate::tty::ENABLE_MOUSE)?;
+        }
+        self.draw()
+    }
+
+    /// Save the output of all panes to `path`, for `mux restore`.
+    pub fn save_snapshot(&mut self, path: &std::path::Path) -> Result<(), failure::Error> {
+        self.state.snapshot().save(path)
+    }
+
+    /// Show the output of panes from a snapshot instead of what the processes printed; the panes
+    /// must have been created from the same snapshot.
+    pub fn restore(&mut self, panes: Vec<snapshot::Pane>) -> Result<(), failure::Error> {
+        use std::os::unix::process::ExitStatusExt;
+        use terminal_emulator::Handler;
+
+        for (process, pane) in self.state.processes.iter_mut().zip(panes) {
+            let mut emulator = process.lock();
+            emulator.term.restore_grid(pane.grid, &pane.zero_width);
+            emulator.term.set_tabspaces(pane.tabspaces);
+            emulator.term.set_scrollback_budget(pane.scrollback_budget);
+            // The cursor was wherever the process left it, which says nothing anymore
+            emulator
+                .term
+                .unset_mode(terminal_emulator::ansi::Mode::ShowCursor);
+            drop(emulator);
+            process.exit_status = pane.exit_status.map(std::process::ExitStatus::from_raw);
+            process.restored = true;
+        }
+        self.draw()
+    }
+
+    /// Summarize how the processes are doing, for printing after the UI is closed.
+    pub fn report(&self, log: Option<std::path::PathBuf>) -> report::Report {
+        let processes = self
+            .state
+            .processes
+            .iter()
+            .map(|p| report::Process {
+                title: p.title.clone(),
+                exit_status: p.exit_status,
+            })
+            .collect();
+
+        report::Report { processes, log }
+    }
+
+    /// Move the terminal's cursor to where the selected process's cursor is, or hide it, and make
+    /// it blink if the process asked for that.
+    fn update_cursor(&mut self) -> Result<(), failure::Error> {
+        use std::io::Write;
+
+        if self.state.cursor_blinks != self.cursor_blinks {
+            self.cursor_blinks = self.state.cursor_blinks;
+            self.terminal
+                .backend_mut()
+                .write_all(if self.cursor_blinks {
+                    crate::tty::ENABLE_CURSOR_BLINK
+                } else {
+                    crate::tty::DISABLE_CURSOR_BLINK
+                })?;
+        }
+        match self.state.cursor {
+            Some((x, y)) => {
+                self.terminal.set_cursor(x, y)?;
+                self.terminal.show_cursor()?;
+            }
+            None => self.terminal.hide_cursor()?,
+        }
+        Ok(())
+    }
+
+    /// Ring the terminal's bell if any of the processes rang theirs since the last call.
+    fn update_bell(&mut self) -> Result<(), failure::Error> {
+        use std::io::Write;
+
+        let mut rang = false;
+        for process in &mut self.state.processes {
+            rang |= std::mem::replace(&mut process.bell, false);
+        }
+        if rang {
+            let backend = self.terminal.backend_mut();
+            backend.write_all(b"\x07")?;
+            backend.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Pass the notifications that the processes sent on to the terminal, with the titles of
+    /// their tabs so that it's clear where they come from.  Each process gets one notification
+    /// through every `NOTIFICATION_INTERVAL`, so that one that sends a flood of them can't bury
+    /// the desktop; the others wait for a later draw.
+    fn update_notifications(&mut self) -> Result<(), failure::Error> {
+        use std::io::Write;
+
+        // The text goes into an escape sequence, which control characters would end
+        fn clean(text: &str) -> String {
+            text.chars().filter(|c| !c.is_control()).collect()
+        }
+
+        let now = std::time::Instant::now();
+        let mut sent = false;
+        for process in &mut self.state.processes {
+            if !process.notification_due(now) {
+                continue;
+            }
+            if let Some(notification) = process.notifications.pop_front() {
+                let sequence = match notification.title {
+                    Some(title) => format!(
+                        "\x1b]777;notify;{}: {};{}\x1b\\",
+                        clean(&process.title).replace(';', ","),
+                        clean(&title).replace(';', ","),
+                        clean(&notification.body)
+                    ),
+                    None => format!(
+                        "\x1b]9;{}: {}\x1b\\",
+                        clean(&process.title),
+                        clean(&notification.body)
+                    ),
+                };
+                self.terminal.backend_mut().write_all(sequence.as_bytes())?;
+                process.notification_sent = Some(now);
+                sent = true;
+            }
+        }
+        if sent {
+            self.terminal.backend_mut().flush()?;
+        }
+        Ok(())
+    }
+
+    /// Put the text that the user copied on the clipboard of the terminal (OSC 52).
+    fn update_clipboard(&mut self) -> Result<(), failure::Error> {
+        use std::io::Write;
+
+        if let Some(text) = self.state.clipboard.take() {
+            let backend = self.terminal.backend_mut();
+            write!(backend, "\x1b]52;c;{}\x1b\\", base64::encode(&text))?;
+            backend.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Show the images that are on the screen with the terminal's own graphics, if it has any.
+    fn update_graphics(&mut self) -> Result<(), failure::Error> {
+        if let Some(ref mut graphics) = self.graphics {
+            graphics.update(self.terminal.backend_mut(), &self.state.placements)?;
+        }
+        Ok(())
+    }
+
+    /// Switch mouse reporting on or off if the state asked for it since the last call.
+    fn update_mouse(&mut self) -> Result<(), failure::Error> {
+        use std::io::Write;
+
+        if self.state.mouse != self.mouse {
+            self.mouse = self.state.mouse;
+            let backend = self.terminal.backend_mut();
+            backend.write_all(if self.mouse {
+                crate::tty::ENABLE_MOUSE
+            } else {
+                crate::tty::DISABLE_MOUSE
+            })?;
+            backend.flush()?;
+        }
         Ok(())
     }
 }
@@ -141,23 +719,189 @@ impl Action {
             Action::ProcessInput { index, .. } => index == other_index,
             Action::ProcessInputAll { .. } => true,
             Action::ProcessTermResize { index, .. } => index == other_index,
+            Action::Respawn { .. } | Action::Quit => false,
         }
     }
 }
 
 impl State {
-    fn new(processes: Vec<ProcessState>) -> Self {
+    fn new(mut processes: Vec<ProcessState>, settings: Settings, config: config::Source) -> Self {
+        let monitor = &config.config.monitor;
+        for process in &mut processes {
+            process.monitor_activity = monitor.activity;
+            process.monitor_silence = monitor.silence.map(std::time::Duration::from_secs);
+        }
+        let order = (0..processes.len()).collect();
         let selected = 0;
         let scroll = 0;
-        Self {
+        let scrolled_to = None;
+        let tabs_height = 0;
+        let clock = None;
+        let host = None;
+        let started = std::time::Instant::now();
+        let elapsed = format_duration(std::time::Duration::from_secs(0));
+        let spinner = 0;
+        let mouse = settings.mouse;
+        let tiling = settings.tiling;
+        let input_mode = settings.input_mode;
+        let goto = None;
+        let save = None;
+        let rerun = None;
+        let rename = None;
+        let marking = None;
+        let zoomed = false;
+        let all = false;
+        let merged = merged::Merged::default();
+        let tabs_hidden = false;
+        let hide_finished = settings.hide_finished.is_some();
+        let collapsed = std::collections::HashSet::new();
+        let dragging = None;
+        let cursor = None;
+        let placements = Vec::new();
+        let clipboard = None;
+        let cursor_blinks = false;
+        let cwd = std::env::current_dir()
+            .map(|dir| dir.display().to_string())
+            .unwrap_or_default();
+        let theme = color::Theme::new(&config.config.theme, config.config.colors.depth);
+        let keymap = keys::Keymap::new(&config.config.keys);
+        let prefixed = false;
+        let confirm_quit = false;
+        let confirm_kill = None;
+        let quit = false;
+        let mut state = Self {
             processes,
+            order,
             selected,
             scroll,
+            scrolled_to,
+            tabs_height,
+            settings,
+            config,
+            theme,
+            keymap,
+            prefixed,
+            confirm_quit,
+            confirm_kill,
+            quit,
+            clock,
+            host,
+            started,
+            elapsed,
+            spinner,
+            mouse,
+            tiling,
+            input_mode,
+            goto,
+            save,
+            rerun,
+            rename,
+            marking,
+            zoomed,
+            all,
+            merged,
+            tabs_hidden,
+            hide_finished,
+            collapsed,
+            dragging,
+            cursor,
+            cursor_blinks,
+            placements,
+            clipboard,
+            cwd,
+        };
+        state.apply_config();
+        state
+    }
+
+    fn apply_config(&mut self) {
+        let config = &self.config.config;
+        let status = &self.settings.status;
+
+        self.clock = if status.clock || config.status.clock {
+            Some(current_time())
+        } else {
+            None
+        };
+        self.host = if status.host || config.status.host {
+            host_name()
+        } else {
+            None
+        };
+        self.theme = color::Theme::new(&config.theme, config.colors.depth);
+        self.keymap = keys::Keymap::new(&config.keys);
+    }
+
+    fn update_clock(&mut self) -> bool {
+        let mut changed = false;
+
+        if let Some(ref mut clock) = self.clock {
+            let now = current_time();
+            if *clock != now {
+                *clock = now;
+                changed = true;
+            }
         }
+
+        let elapsed = format_duration(self.started.elapsed());
+        if self.elapsed != elapsed {
+            self.elapsed = elapsed;
+            // Checking is too expensive to do on every tick; once a second is responsive enough
+            self.update_suspended();
+            changed = true;
+        }
+
+        if let Some(interval) = self.settings.defer_hidden {
+            for process in &mut self.processes {
+                let flushed = process.lock().flush_after(interval);
+                if flushed && process.take_changes() {
+                    changed = true;
+                }
+            }
+        }
+
+        let running = self.processes.iter().any(|p| p.exit_status.is_none());
+        #[allow(clippy::cast_possible_truncation)]
+        let spinner = (self.started.elapsed().as_millis() / 100) as usize % SPINNER.len();
+        if running && self.spinner != spinner {
+            self.spinner = spinner;
+            changed = true;
+        }
+
+        changed
     }
 
-    fn on_data(&mut self, index: usize, data: bytes::Bytes) {
-        self.processes[index].on_data(data)
+    /// Check which processes are stopped.
+    fn update_suspended(&mut self) {
+        for process in &mut self.processes {
+            process.suspended = process.exit_status.is_none()
+                && sys::process::is_stopped(process.pid).unwrap_or(false);
+        }
+    }
+
+    /// Take note of output that went to the terminal of a process, returning whether anything on
+    /// the screen changed.
+    fn on_data(&mut self, index: usize, data: &[u8]) -> bool {
+        let process = &mut self.processes[index];
+        let tab_changed = process.on_data(data, &self.config.config.errors.patterns);
+        let merged = self.merged.push(index, process.errors.take_lines());
+        tab_changed || (self.all && merged) || (!self.all && self.is_drawn(index))
+    }
+
+    /// Tell the terminals of the processes that aren't on the screen to keep their output back,
+    /// with `Settings::defer_hidden`.
+    fn update_deferred(&self) {
+        if self.settings.defer_hidden.is_some() {
+            for (index, process) in self.processes.iter().enumerate() {
+                process.lock().defer = !self.is_drawn(index);
+            }
+        }
+    }
+
+    /// Whether the terminal of the process at `index` is on the screen.
+    fn is_drawn(&self, index: usize) -> bool {
+        index == self.selected
+            || (!self.zoomed && self.tiling != Tiling::Tabs && !self.is_hidden(index))
     }
 
     fn on_exit(&mut self, index: usize, status: std::process::ExitStatus) {
@@ -165,24 +909,149 @@ impl State {
     }
 
     fn on_user_input(&mut self, area: tui::layout::Rect, event: &termion::event::Event) -> bool {
-        match *event {
-            termion::event::Event::Key(_) => false,
+        let selected = self.selected;
+        let redraw = match *event {
+            termion::event::Event::Key(key) if self.goto.is_some() => {
+                self.on_goto_key(key);
+                true
+            }
+            termion::event::Event::Key(key) if self.save.is_some() => {
+                self.on_save_key(key);
+                true
+            }
+            termion::event::Event::Key(key) if self.rerun.is_some() => {
+                self.on_rerun_key(key);
+                true
+            }
+            termion::event::Event::Key(key) if self.rename.is_some() => {
+                self.on_rename_key(key);
+                true
+            }
+            termion::event::Event::Key(key) if self.marking.is_some() => {
+                self.on_marking_key(key);
+                true
+            }
+            termion::event::Event::Key(key) if self.confirm_quit => {
+                self.confirm_quit = false;
+                if let termion::event::Key::Char('y') | termion::event::Key::Char('Y') = key {
+                    self.quit = true;
+                }
+                true
+            }
+            termion::event::Event::Key(key) if self.confirm_kill.is_some() => {
+                let indices = self.confirm_kill.take().unwrap_or_default();
+                if let termion::event::Key::Char('y') | termion::event::Key::Char('Y') = key {
+                    for index in indices {
+                        self.processes[index].terminate();
+                    }
+                }
+                true
+            }
+            termion::event::Event::Key(key) if self.prefixed => {
+                self.prefixed = false;
+                match (self.keymap.prefixed(key), key) {
+                    (Some(command), _) => {
+                        self.on_command(command);
+                    }
+                    // Like the window numbers in tmux, unless the digit is bound to a command
+                    (None, termion::event::Key::Char(c @ '1'..='9')) => {
+                        self.select_position(usize::from(c as u8 - b'1'));
+                    }
+                    // Unbound keys after the prefix are dropped rather than typed
+                    (None, _) => {}
+                }
+                true
+            }
+            termion::event::Event::Key(key) if key == self.keymap.prefix => {
+                self.prefixed = true;
+                true
+            }
+            termion::event::Event::Key(key) => match self.keymap.global(key) {
+                Some(command) => self.on_command(command),
+                None => false,
+            },
             termion::event::Event::Mouse(m) => {
-                let (tabs_area, process_area) = self.layout(area);
+                let Layout {
+                    tabs_area,
+                    process_area,
+                    ..
+                } = self.layout(area);
                 let (x, y) = mouse_event_coords(&m);
 
-                if contains_point(tabs_area, x, y) {
-                    match self.tabs().on_mouse_event(tabs_area, &m) {
-                        Some(vertical_tabs::MouseAction::Select(selected)) => {
-                            self.selected = selected;
+                if let termion::event::MouseEvent::Release(..) = m {
+                    self.dragging = None;
+                }
+
+                if self.all && contains_point(process_area, x, y) {
+                    use termion::event::{MouseButton, MouseEvent};
+
+                    match m {
+                        MouseEvent::Press(MouseButton::WheelUp, ..) => self.merged.scroll(true, 3),
+                        MouseEvent::Press(MouseButton::WheelDown, ..) => {
+                            self.merged.scroll(false, 3)
                         }
+                        _ => {}
+                    }
+                    true
+                } else if self.zoomed {
+                    contains_point(process_area, x, y)
+                        && self.processes[self.selected].on_user_input(process_area, event)
+                } else if let Some(panes) = self.tiling.panes(process_area, self.shown().len()) {
+                    match panes.iter().position(|&pane| contains_point(pane, x, y)) {
+                        Some(position) => {
+                            let index = self.shown()[position];
+                            self.selected = index;
+                            let inner = border::Border::inner(panes[position]);
+                            self.processes[index].on_user_input(inner, event)
+                        }
+                        None => false,
+                    }
+                } else if self.settings.compact_tabs && contains_point(tabs_area, x, y) {
+                    match self.tab_strip().on_mouse_event(tabs_area, &m) {
+                        Some(0) => self.all = true,
+                        Some(position) => {
+                            self.all = false;
+                            self.selected = self.shown()[position - 1];
+                        }
+                        None => {}
+                    }
+                    true
+                } else if contains_point(tabs_area, x, y) {
+                    let rows = self.rows();
+                    match self.tabs().on_mouse_event(tabs_area, &m) {
+                        Some(vertical_tabs::MouseAction::Select(row)) => match rows[row] {
+                            Row::All => self.all = true,
+                            Row::Group(ref group) => {
+                                if let termion::event::MouseEvent::Press(..) = m {
+                                    self.toggle_collapsed(group);
+                                }
+                            }
+                            Row::Tab(index) => match m {
+                                termion::event::MouseEvent::Press(button, ..) => {
+                                    self.all = false;
+                                    self.selected = index;
+                                    if button == termion::event::MouseButton::Left {
+                                        self.dragging = Some(self.selected);
+                                    }
+                                }
+                                termion::event::MouseEvent::Hold(..) => {
+                                    let shown = self.shown();
+                                    let position = shown.iter().position(|&i| i == index);
+                                    if let (Some(dragged), Some(position)) =
+                                        (self.dragging, position)
+                                    {
+                                        self.move_tab(dragged, position);
+                                    }
+                                }
+                                termion::event::MouseEvent::Release(..) => {}
+                            },
+                        },
                         Some(vertical_tabs::MouseAction::ScrollUp) => {
                             self.scroll = 0.max(self.scroll as isize - 1) as usize;
                         }
                         Some(vertical_tabs::MouseAction::ScrollDown) => {
-                            self.scroll = ((self.processes.len() as isize - area.height as isize
-                                + 2)
-                            .min(self.scroll as isize)
+                            self.scroll = ((rows.len() as isize - area.height as isize + 2)
+                                .min(self.scroll as isize)
                                 + 1) as usize;
                         }
                         None => {}
@@ -195,30 +1064,1283 @@ impl State {
                 }
             }
             termion::event::Event::Unsupported(_) => false,
+        };
+        // Going to a process, in whichever way, leaves the ALL tab
+        if self.selected != selected {
+            self.all = false;
+        }
+        redraw
+    }
+
+    fn on_control(&mut self, request: &control::Request) -> control::Response {
+        match *request {
+            control::Request::List => Ok(self
+                .order
+                .iter()
+                .map(|&index| &self.processes[index])
+                .map(|p| format!("{}\t{}\t{}\t{}\n", p.id, p.state_name(), p.title, p.arg))
+                .collect()),
+            control::Request::Stats => Ok(self
+                .order
+                .iter()
+                .map(|&index| &self.processes[index])
+                .map(|p| format!("{}\t{}\t{}\n", p.id, p.lock().memory_usage(), p.title))
+                .collect()),
+            control::Request::SelectPane { ref target } => {
+                let matches = self.resolve(target)?;
+                if matches.len() > 1 {
+                    return Err(format!(
+                        "{} panes match ({}); select-pane needs exactly one",
+                        matches.len(),
+                        self.pane_ids(&matches)
+                    ));
+                }
+                self.selected = matches[0];
+                Ok(String::new())
+            }
+            control::Request::SendKeys {
+                ref target,
+                ref keys,
+                all,
+            } => {
+                let matches = self.resolve(target)?;
+                if matches.len() > 1 && !all {
+                    return Err(format!(
+                        "{} panes match ({}); pass -y to send to all of them",
+                        matches.len(),
+                        self.pane_ids(&matches)
+                    ));
+                }
+                for index in matches {
+                    for key in keys {
+                        self.processes[index].input.extend(control::key_bytes(key));
+                    }
+                }
+                Ok(String::new())
+            }
+            control::Request::RenamePane {
+                ref target,
+                ref title,
+            } => {
+                let matches = self.resolve(target)?;
+                if matches.len() > 1 {
+                    return Err(format!(
+                        "{} panes match ({}); rename-pane needs exactly one",
+                        matches.len(),
+                        self.pane_ids(&matches)
+                    ));
+                }
+                self.processes[matches[0]].rename(title.clone());
+                Ok(String::new())
+            }
+            control::Request::GroupPane {
+                ref target,
+                ref group,
+            } => {
+                for index in self.resolve(target)? {
+                    self.processes[index].group = group.clone();
+                }
+                Ok(String::new())
+            }
+            control::Request::Snapshot { ref path } => self
+                .snapshot()
+                .save(path)
+                .map(|()| String::new())
+                .map_err(|err| err.to_string()),
+        }
+    }
+
+    /// The output and state of every pane, to save for `mux restore`.
+    fn snapshot(&mut self) -> snapshot::Snapshot {
+        use std::os::unix::process::ExitStatusExt;
+
+        for process in &self.processes {
+            process.lock().flush();
+        }
+
+        let panes = self
+            .processes
+            .iter()
+            .map(|p| {
+                let emulator = p.lock();
+                snapshot::Pane {
+                    id: p.id,
+                    arg: p.arg.clone(),
+                    title: p.title.clone(),
+                    group: p.group.clone(),
+                    exit_status: p.exit_status.map(ExitStatusExt::into_raw),
+                    grid: emulator.term.grid().clone(),
+                    zero_width: emulator.term.zero_width().clone(),
+                    tabspaces: emulator.term.tabspaces(),
+                    scrollback_budget: emulator.term.scrollback_budget(),
+                }
+            })
+            .collect();
+
+        snapshot::Snapshot {
+            command: self.settings.command.clone(),
+            panes,
+        }
+    }
+
+    /// Find the indices of the processes that a target refers to; there is at least one.
+    fn resolve(&self, target: &control::Target) -> Result<Vec<usize>, String> {
+        let matches = self
+            .processes
+            .iter()
+            .enumerate()
+            // Closed tabs are gone for good
+            .filter(|&(index, _)| self.order.contains(&index))
+            .filter(|(_, p)| {
+                target.matches(p.id, &p.title, &p.arg, p.group.as_ref().map(String::as_str))
+            })
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+
+        if matches.is_empty() {
+            Err("no pane matches the target".to_owned())
+        } else {
+            Ok(matches)
+        }
+    }
+
+    fn pane_ids(&self, indices: &[usize]) -> String {
+        indices
+            .iter()
+            .map(|&index| self.processes[index].id.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Queue input that wasn't handled by mux for the processes that get input, translating keys
+    /// whose encoding depends on the process's terminal modes.  Returns the input if all
+    /// processes get the same bytes.
+    fn forward_input(
+        &mut self,
+        event: &termion::event::Event,
+        raw: &bytes::Bytes,
+    ) -> Option<bytes::Bytes> {
+        match self.input_mode {
+            InputMode::All => {
+                let translated = self.processes.iter().any(|p| {
+                    keys::encode(event, *p.lock().term.mode())
+                        .map_or(false, |data| data[..] != raw[..])
+                });
+                if !translated {
+                    return Some(raw.clone());
+                }
+                for process in &mut self.processes {
+                    process.key_input(event, raw);
+                }
+            }
+            InputMode::Selected => self.processes[self.selected].key_input(event, raw),
+            InputMode::Group => {
+                for index in self.group_members(self.selected) {
+                    self.processes[index].key_input(event, raw);
+                }
+            }
+        }
+        None
+    }
+
+    /// Send pasted text to the processes that get input, or type it into an open prompt.
+    fn on_paste(&mut self, data: &[u8]) {
+        if self.goto.is_some()
+            || self.save.is_some()
+            || self.rerun.is_some()
+            || self.rename.is_some()
+        {
+            for c in String::from_utf8_lossy(data)
+                .chars()
+                .filter(|c| !c.is_control())
+            {
+                let key = termion::event::Key::Char(c);
+                if self.goto.is_some() {
+                    self.on_goto_key(key);
+                } else if self.save.is_some() {
+                    self.on_save_key(key);
+                } else if self.rerun.is_some() {
+                    self.on_rerun_key(key);
+                } else {
+                    self.on_rename_key(key);
+                }
+            }
+            return;
+        }
+
+        match self.input_mode {
+            InputMode::All => {
+                for process in &mut self.processes {
+                    process.paste(data);
+                }
+            }
+            InputMode::Selected => self.processes[self.selected].paste(data),
+            InputMode::Group => {
+                for index in self.group_members(self.selected) {
+                    self.processes[index].paste(data);
+                }
+            }
+        }
+    }
+
+    fn on_goto_key(&mut self, key: termion::event::Key) {
+        let mut goto = match self.goto.take() {
+            Some(goto) => goto,
+            None => return,
+        };
+
+        match key {
+            termion::event::Key::Esc => return,
+            termion::event::Key::Char('\n') if goto.text.parse::<usize>().is_ok() => {
+                // A number is the position of the tab in the list, like after the prefix key
+                let count = self.shown().len();
+                match goto.text.parse::<usize>() {
+                    Ok(number) if number >= 1 && number <= count => {
+                        self.select_position(number - 1);
+                        return;
+                    }
+                    _ => goto.message = Some(format!("there are {} tabs", count)),
+                }
+            }
+            termion::event::Key::Char('\n') => {
+                // Plain text matches anywhere in the title or argument
+                let pattern = if goto.text.contains(|c: char| "*?[/".contains(c)) {
+                    goto.text.clone()
+                } else {
+                    format!("*{}*", goto.text)
+                };
+
+                match control::Target::parse(&pattern).and_then(|t| self.resolve(&t)) {
+                    Ok(ref matches) if matches.len() == 1 || goto.confirm == Some(matches[0]) => {
+                        self.selected = matches[0];
+                        return;
+                    }
+                    Ok(matches) => {
+                        goto.confirm = Some(matches[0]);
+                        goto.message = Some(format!(
+                            "{} panes match, press enter again to go to {}",
+                            matches.len(),
+                            self.processes[matches[0]].title
+                        ));
+                    }
+                    Err(message) => {
+                        goto.confirm = None;
+                        goto.message = Some(message);
+                    }
+                }
+            }
+            termion::event::Key::Char(c) => {
+                goto.text.push(c);
+                goto.confirm = None;
+                goto.message = None;
+            }
+            termion::event::Key::Backspace => {
+                goto.text.pop();
+                goto.confirm = None;
+                goto.message = None;
+            }
+            _ => {}
+        }
+
+        self.goto = Some(goto);
+    }
+
+    fn on_save_key(&mut self, key: termion::event::Key) {
+        let mut save = match self.save.take() {
+            Some(save) => save,
+            None => return,
+        };
+
+        match key {
+            termion::event::Key::Esc => return,
+            termion::event::Key::Char('\n')
+                if !save.confirm && std::path::Path::new(&save.text).exists() =>
+            {
+                save.confirm = true;
+                save.message = Some("already exists, press enter again to overwrite it".to_owned());
+            }
+            termion::event::Key::Char('\n') => {
+                let contents = self.processes[self.selected].history(self.settings.save_attributes);
+                match std::fs::write(&save.text, contents) {
+                    Ok(()) => return,
+                    Err(err) => {
+                        save.confirm = false;
+                        save.message = Some(err.to_string());
+                    }
+                }
+            }
+            termion::event::Key::Char(c) => {
+                save.text.push(c);
+                save.confirm = false;
+                save.message = None;
+            }
+            termion::event::Key::Backspace => {
+                save.text.pop();
+                save.confirm = false;
+                save.message = None;
+            }
+            _ => {}
+        }
+
+        self.save = Some(save);
+    }
+
+    fn on_rerun_key(&mut self, key: termion::event::Key) {
+        let mut rerun = match self.rerun.take() {
+            Some(rerun) => rerun,
+            None => return,
+        };
+
+        match key {
+            termion::event::Key::Esc => return,
+            termion::event::Key::Char('\n') => {
+                let process = &mut self.processes[self.selected];
+                if process.restored {
+                    rerun.message = Some("this pane was restored from a snapshot".to_owned());
+                } else if process.exit_status.is_none() {
+                    rerun.message = Some("still running, wait for it to finish first".to_owned());
+                } else {
+                    process.respawn = Some(rerun.text);
+                    return;
+                }
+            }
+            termion::event::Key::Char(c) => {
+                rerun.text.push(c);
+                rerun.message = None;
+            }
+            termion::event::Key::Backspace => {
+                rerun.text.pop();
+                rerun.message = None;
+            }
+            _ => {}
+        }
+
+        self.rerun = Some(rerun);
+    }
+
+    fn on_rename_key(&mut self, key: termion::event::Key) {
+        let mut rename = match self.rename.take() {
+            Some(rename) => rename,
+            None => return,
+        };
+
+        match key {
+            termion::event::Key::Esc => return,
+            termion::event::Key::Char('\n') => {
+                self.processes[self.selected].rename(rename.text);
+                return;
+            }
+            termion::event::Key::Char(c) => {
+                rename.text.push(c);
+                rename.message = None;
+            }
+            termion::event::Key::Backspace => {
+                rename.text.pop();
+                rename.message = None;
+            }
+            _ => {}
+        }
+
+        self.rename = Some(rename);
+    }
+
+    fn on_marking_key(&mut self, key: termion::event::Key) {
+        use termion::event::Key;
+
+        let mut marking = match self.marking.take() {
+            Some(marking) => marking,
+            None => return,
+        };
+        marking.message = None;
+
+        if let Some(mut signal) = marking.signal.take() {
+            match key {
+                Key::Esc => {}
+                Key::Char('\n') => match sys::process::signal_number(&signal) {
+                    Some(number) => {
+                        let sent = self
+                            .marked()
+                            .into_iter()
+                            .filter(|&index| self.processes[index].signal(number))
+                            .count();
+                        marking.message =
+                            Some(format!("sent {} to {} {}", signal, sent, jobs(sent)));
+                    }
+                    None => {
+                        marking.message = Some(format!("unknown signal {:?}", signal));
+                        marking.signal = Some(signal);
+                    }
+                },
+                Key::Char(c) => {
+                    signal.push(c);
+                    marking.signal = Some(signal);
+                }
+                Key::Backspace => {
+                    signal.pop();
+                    marking.signal = Some(signal);
+                }
+                _ => marking.signal = Some(signal),
+            }
+            self.marking = Some(marking);
+            return;
+        }
+
+        let marked = self.marked();
+        match key {
+            Key::Esc | Key::Char('q') => {
+                for process in &mut self.processes {
+                    process.marked = false;
+                }
+                return;
+            }
+            Key::Char(' ') => {
+                let process = &mut self.processes[self.selected];
+                process.marked = !process.marked;
+            }
+            Key::Up | Key::Char('k') => {
+                if let Some(index) = self.find_tab(true, |index| !self.is_hidden(index)) {
+                    self.selected = index;
+                }
+            }
+            Key::Down | Key::Char('j') => {
+                if let Some(index) = self.find_tab(false, |index| !self.is_hidden(index)) {
+                    self.selected = index;
+                }
+            }
+            Key::Char('a') => {
+                let shown = self.shown();
+                let all = shown.iter().all(|&index| self.processes[index].marked);
+                for index in shown {
+                    self.processes[index].marked = !all;
+                }
+            }
+            Key::Char(_) if marked.is_empty() => {
+                marking.message = Some("no tabs are marked, press space to mark one".to_owned());
+            }
+            Key::Char('s') => marking.signal = Some("TERM".to_owned()),
+            Key::Char('r') => {
+                let mut restarted = 0;
+                for index in marked {
+                    let process = &mut self.processes[index];
+                    if process.exit_status.is_some() && !process.restored {
+                        process.respawn = Some(process.arg.clone());
+                        restarted += 1;
+                    }
+                }
+                marking.message = Some(format!(
+                    "restarted {} finished {}",
+                    restarted,
+                    jobs(restarted)
+                ));
+            }
+            Key::Char('l') => {
+                let result = marked.iter().try_for_each(|&index| {
+                    let process = &self.processes[index];
+                    let path = pane_file_name(process.id, &process.arg, "txt");
+                    let contents = process.history(self.settings.save_attributes);
+                    std::fs::write(&path, contents).map_err(|err| format!("{}: {}", path, err))
+                });
+                marking.message = Some(match result {
+                    Ok(()) => format!(
+                        "saved the output of {} {} to {}",
+                        marked.len(),
+                        jobs(marked.len()),
+                        self.cwd
+                    ),
+                    Err(err) => err,
+                });
+            }
+            Key::Char('x') => {
+                let closed = self.close(&marked);
+                marking.message = Some(format!("closed {} tabs", closed));
+            }
+            _ => {}
+        }
+        self.marking = Some(marking);
+    }
+
+    /// The marked processes, in the displayed order.
+    fn marked(&self) -> Vec<usize> {
+        self.sorted()
+            .into_iter()
+            .filter(|&index| self.processes[index].marked)
+            .collect()
+    }
+
+    /// Remove the tabs of the processes at `indices` from the list, terminating the processes that
+    /// are still running; the last tab is kept.  Returns how many tabs were closed.
+    fn close(&mut self, indices: &[usize]) -> usize {
+        let mut closed = 0;
+        for &index in indices {
+            if self.order.len() > 1 {
+                self.processes[index].terminate();
+                self.processes[index].marked = false;
+                self.order.retain(|&i| i != index);
+                closed += 1;
+            }
         }
+        if !self.order.contains(&self.selected) {
+            self.selected = self.order[0];
+        }
+        closed
     }
 
-    fn layout(&self, area: tui::layout::Rect) -> (tui::layout::Rect, tui::layout::Rect) {
+    /// Run a key-bound command, returning whether the key press was consumed.
+    fn on_command(&mut self, command: keys::Command) -> bool {
+        match command {
+            keys::Command::ReloadConfig => {
+                // Only bound while there is a config problem to fix
+                if self.config.error.is_none() {
+                    return false;
+                }
+                if self.config.reload() {
+                    self.apply_config();
+                }
+                true
+            }
+            keys::Command::ToggleMouse => {
+                self.mouse = !self.mouse;
+                true
+            }
+            keys::Command::NextTiling => {
+                self.tiling = self.tiling.next();
+                true
+            }
+            keys::Command::MoveTabUp => {
+                let position = self.selected_position();
+                self.move_tab(self.selected, position.saturating_sub(1));
+                true
+            }
+            keys::Command::MoveTabDown => {
+                let position = self.selected_position();
+                self.move_tab(self.selected, position + 1);
+                true
+            }
+            keys::Command::ToggleZoom => {
+                self.zoomed = !self.zoomed;
+                true
+            }
+            keys::Command::ScrollTabsUp | keys::Command::ScrollTabsDown => {
+                // Half a page, so that some of the tabs stay in view to keep track
+                let rows = (self.tabs_height / 2).max(1);
+                self.scroll_tabs(command == keys::Command::ScrollTabsUp, rows);
+                true
+            }
+            keys::Command::ToggleMonitorActivity => {
+                let process = &mut self.processes[self.selected];
+                process.monitor_activity = !process.monitor_activity;
+                true
+            }
+            keys::Command::ToggleMonitorSilence => {
+                let silence = self
+                    .config
+                    .config
+                    .monitor
+                    .silence
+                    .map_or(DEFAULT_SILENCE, std::time::Duration::from_secs);
+                let process = &mut self.processes[self.selected];
+                process.monitor_silence = match process.monitor_silence {
+                    Some(_) => None,
+                    None => Some(silence),
+                };
+                true
+            }
+            keys::Command::ToggleAll => {
+                self.all = !self.all;
+                true
+            }
+            keys::Command::Goto => {
+                self.goto = Some(Goto::default());
+                true
+            }
+            keys::Command::ScrollUp | keys::Command::ScrollDown if self.all => {
+                let page = usize::from(self.processes[self.selected].size.1);
+                self.merged.scroll(command == keys::Command::ScrollUp, page);
+                true
+            }
+            keys::Command::ScrollUp => {
+                self.processes[self.selected].scroll_page(true);
+                true
+            }
+            keys::Command::ScrollDown => {
+                self.processes[self.selected].scroll_page(false);
+                true
+            }
+            keys::Command::ToggleFollow => {
+                let mut emulator = self.processes[self.selected].lock();
+                let follow = emulator.term.auto_scroll();
+                emulator.term.set_auto_scroll(!follow);
+                true
+            }
+            keys::Command::JumpToLive if self.all => {
+                self.merged.jump_to_live();
+                true
+            }
+            keys::Command::JumpToLive => {
+                let mut emulator = self.processes[self.selected].lock();
+                emulator.term.set_auto_scroll(true);
+                emulator
+                    .term
+                    .scroll_display(terminal_emulator::Scroll::Bottom);
+                true
+            }
+            keys::Command::ClearScrollback => {
+                self.processes[self.selected]
+                    .lock()
+                    .term
+                    .clear_history_and_screen();
+                true
+            }
+            keys::Command::CycleInvalidUtf8 => {
+                use terminal_emulator::ansi::InvalidUtf8;
+
+                let mut emulator = self.processes[self.selected].lock();
+                let policy = match emulator.invalid_utf8() {
+                    InvalidUtf8::Replace => InvalidUtf8::Latin1,
+                    InvalidUtf8::Latin1 => InvalidUtf8::Hex,
+                    InvalidUtf8::Hex => InvalidUtf8::Replace,
+                };
+                emulator.set_invalid_utf8(policy);
+                true
+            }
+            keys::Command::ToggleC1Controls => {
+                let mut emulator = self.processes[self.selected].lock();
+                let enabled = emulator.c1_controls();
+                emulator.set_c1_controls(!enabled);
+                true
+            }
+            keys::Command::PreviousPrompt | keys::Command::NextPrompt => {
+                self.processes[self.selected]
+                    .jump_to_prompt(command == keys::Command::PreviousPrompt);
+                true
+            }
+            keys::Command::CopyOutput => {
+                self.clipboard = self.processes[self.selected].last_output();
+                true
+            }
+            keys::Command::Save => {
+                let process = &self.processes[self.selected];
+                self.save = Some(Save {
+                    text: pane_file_name(process.id, &process.arg, "txt"),
+                    confirm: false,
+                    message: None,
+                });
+                true
+            }
+            keys::Command::Rename => {
+                self.rename = Some(Rename {
+                    text: self.processes[self.selected].title.clone(),
+                    message: Some("leave empty for the titles that the process sets".to_owned()),
+                });
+                true
+            }
+            keys::Command::Rerun => {
+                self.rerun = Some(Rerun {
+                    text: self.processes[self.selected].arg.clone(),
+                    message: None,
+                });
+                true
+            }
+            keys::Command::ToggleTabs => {
+                self.tabs_hidden = !self.tabs_hidden;
+                true
+            }
+            keys::Command::ToggleFinished => {
+                self.hide_finished = !self.hide_finished;
+                true
+            }
+            keys::Command::ToggleInputMode => {
+                let grouped = self.processes.iter().any(|p| p.group.is_some());
+                self.input_mode = match self.input_mode {
+                    InputMode::All => InputMode::Selected,
+                    InputMode::Selected if grouped => InputMode::Group,
+                    InputMode::Selected | InputMode::Group => InputMode::All,
+                };
+                true
+            }
+            keys::Command::NextTab => {
+                if let Some(index) = self.find_tab(false, |index| !self.is_hidden(index)) {
+                    self.selected = index;
+                }
+                true
+            }
+            keys::Command::PreviousTab => {
+                if let Some(index) = self.find_tab(true, |index| !self.is_hidden(index)) {
+                    self.selected = index;
+                }
+                true
+            }
+            keys::Command::NextFailed => {
+                let failed = self.find_tab(false, |index| {
+                    self.processes[index]
+                        .exit_status
+                        .map_or(false, |s| !s.success())
+                });
+                if let Some(index) = failed {
+                    self.selected = index;
+                }
+                true
+            }
+            keys::Command::ToggleGroup => {
+                if let Some(group) = self.processes[self.selected].group.clone() {
+                    self.toggle_collapsed(&group);
+                }
+                true
+            }
+            keys::Command::KillGroup => {
+                let running = self
+                    .group_members(self.selected)
+                    .into_iter()
+                    .filter(|&index| {
+                        let process = &self.processes[index];
+                        process.exit_status.is_none() && !process.restored
+                    })
+                    .collect::<Vec<_>>();
+                if !running.is_empty() {
+                    self.confirm_kill = Some(running);
+                }
+                true
+            }
+            keys::Command::MarkTabs => {
+                self.marking = Some(Marking::default());
+                true
+            }
+            keys::Command::RestartGroup => {
+                for index in self.group_members(self.selected) {
+                    let process = &mut self.processes[index];
+                    if process.exit_status.is_some() && !process.restored {
+                        process.respawn = Some(process.arg.clone());
+                    }
+                }
+                true
+            }
+            keys::Command::Quit => {
+                if self.running() > 0 {
+                    self.confirm_quit = true;
+                } else {
+                    self.quit = true;
+                }
+                true
+            }
+            keys::Command::Detach => match self.settings.detach {
+                Some(ref detach) => {
+                    detach();
+                    true
+                }
+                None => self.on_command(keys::Command::ScrollDown),
+            },
+            keys::Command::SendPrefix => {
+                let data = keys::key_bytes(self.keymap.prefix);
+                match self.input_mode {
+                    InputMode::All => {
+                        for process in &mut self.processes {
+                            process.input.extend_from_slice(&data);
+                        }
+                    }
+                    InputMode::Selected => {
+                        self.processes[self.selected].input.extend_from_slice(&data);
+                    }
+                    InputMode::Group => {
+                        for index in self.group_members(self.selected) {
+                            self.processes[index].input.extend_from_slice(&data);
+                        }
+                    }
+                }
+                true
+            }
+            keys::Command::Unbound => false,
+        }
+    }
+
+    /// How many processes haven't exited yet.
+    fn running(&self) -> usize {
+        self.processes
+            .iter()
+            .filter(|p| p.exit_status.is_none() && !p.restored)
+            .count()
+    }
+
+    /// The processes in the displayed order, with the tabs of each group moved together to
+    /// where the group first appears; tabs that aren't in a group come first.
+    fn sorted(&self) -> Vec<usize> {
+        let mut groups = Vec::new();
+        for &index in &self.order {
+            if let Some(ref group) = self.processes[index].group {
+                if !groups.contains(&group) {
+                    groups.push(group);
+                }
+            }
+        }
+
+        let mut sorted = self.order.clone();
+        sorted.sort_by_key(|&index| {
+            self.processes[index]
+                .group
+                .as_ref()
+                .and_then(|group| groups.iter().position(|&g| g == group))
+        });
+        sorted
+    }
+
+    /// The indices of the processes whose tabs are shown, in the displayed order.
+    fn shown(&self) -> Vec<usize> {
+        self.sorted()
+            .into_iter()
+            .filter(|&index| !self.is_hidden(index))
+            .collect()
+    }
+
+    /// The rows of the list of tabs: the ALL tab and the shown tabs, with a header before each
+    /// group.
+    fn rows(&self) -> Vec<Row> {
+        let mut rows = vec![Row::All];
+        let mut current = None;
+        for index in self.sorted() {
+            if let Some(ref group) = self.processes[index].group {
+                if current != Some(group) {
+                    current = Some(group);
+                    rows.push(Row::Group(group.clone()));
+                }
+            }
+            if !self.is_hidden(index) {
+                rows.push(Row::Tab(index));
+            }
+        }
+        rows
+    }
+
+    /// Whether the tab of the process at `index` is left out, because its group is collapsed or
+    /// because it finished successfully; the selected process is shown even if it finished.
+    fn is_hidden(&self, index: usize) -> bool {
+        let process = &self.processes[index];
+        let collapsed = process
+            .group
+            .as_ref()
+            .map_or(false, |group| self.collapsed.contains(group));
+        let delay = self.settings.hide_finished.unwrap_or_default();
+        let finished = self.hide_finished
+            && index != self.selected
+            && process.exit_status.map_or(false, |s| s.success())
+            && process
+                .exited
+                .map_or(false, |exited| exited.elapsed() >= delay);
+        collapsed || finished
+    }
+
+    /// The processes in the same group as the one at `index`, or just that one if it isn't in a
+    /// group.
+    fn group_members(&self, index: usize) -> Vec<usize> {
+        match self.processes[index].group {
+            Some(ref group) => self
+                .sorted()
+                .into_iter()
+                .filter(|&i| self.processes[i].group.as_ref() == Some(group))
+                .collect(),
+            None => vec![index],
+        }
+    }
+
+    /// Fold the tabs of `group` away under its header, or show them again.
+    fn toggle_collapsed(&mut self, group: &str) {
+        if !self.collapsed.remove(group) {
+            self.collapsed.insert(group.to_owned());
+        }
+    }
+
+    /// The first process after the selected one in the displayed order (or before it, going
+    /// `back`) that is `accept`ed, wrapping around to the selected one.
+    fn find_tab(&self, back: bool, accept: impl Fn(usize) -> bool) -> Option<usize> {
+        let sorted = self.sorted();
+        let count = sorted.len();
+        let position = sorted
+            .iter()
+            .position(|&index| index == self.selected)
+            .unwrap_or(0);
+        (1..=count)
+            .map(|offset| {
+                if back {
+                    sorted[(position + count - offset) % count]
+                } else {
+                    sorted[(position + offset) % count]
+                }
+            })
+            .find(|&index| accept(index))
+    }
+
+    /// Select the process at `position` among the shown tabs, if there are that many.
+    fn select_position(&mut self, position: usize) {
+        if let Some(&index) = self.shown().get(position) {
+            self.selected = index;
+            self.all = false;
+        }
+    }
+
+    /// Where the selected process is among the shown tabs.
+    fn selected_position(&self) -> usize {
+        self.shown()
+            .iter()
+            .position(|&index| index == self.selected)
+            .unwrap_or(0)
+    }
+
+    /// Show the process at `index` at `position` among the shown tabs, shifting the others.
+    fn move_tab(&mut self, index: usize, position: usize) {
+        let others = self
+            .shown()
+            .into_iter()
+            .filter(|&i| i != index)
+            .collect::<Vec<_>>();
+        if let Some(current) = self.order.iter().position(|&i| i == index) {
+            self.order.remove(current);
+            // Go in front of the tab that is shown at `position` now, or last
+            let position = others
+                .get(position)
+                .and_then(|&other| self.order.iter().position(|&i| i == other))
+                .unwrap_or_else(|| self.order.len());
+            self.order.insert(position, index);
+        }
+    }
+
+    fn layout(&self, area: tui::layout::Rect) -> Layout {
+        let status_height = 1;
+
+        if self.tiling != Tiling::Tabs || self.zoomed || self.tabs_hidden {
+            let rows = tui::layout::Layout::default()
+                .direction(tui::layout::Direction::Vertical)
+                .constraints(
+                    [
+                        tui::layout::Constraint::Length(self.banner_height()),
+                        tui::layout::Constraint::Min(0),
+                        tui::layout::Constraint::Length(status_height),
+                    ]
+                    .as_ref(),
+                )
+                .split(area);
+            let (header_area, process_area) = self.split_header(rows[1]);
+
+            return Layout {
+                banner_area: rows[0],
+                tabs_area: tui::layout::Rect::default(),
+                header_area,
+                process_area,
+                status_area: rows[2],
+            };
+        }
+
+        if self.settings.compact_tabs {
+            let rows = tui::layout::Layout::default()
+                .direction(tui::layout::Direction::Vertical)
+                .constraints(
+                    [
+                        tui::layout::Constraint::Length(self.banner_height()),
+                        tui::layout::Constraint::Length(1),
+                        tui::layout::Constraint::Min(0),
+                        tui::layout::Constraint::Length(status_height),
+                    ]
+                    .as_ref(),
+                )
+                .split(area);
+            let (header_area, process_area) = self.split_header(rows[2]);
+
+            return Layout {
+                banner_area: rows[0],
+                tabs_area: rows[1],
+                header_area,
+                process_area,
+                status_area: rows[3],
+            };
+        }
+
+        let rows = tui::layout::Layout::default()
+            .direction(tui::layout::Direction::Vertical)
+            .constraints(
+                [
+                    tui::layout::Constraint::Length(self.banner_height()),
+                    tui::layout::Constraint::Min(0),
+                    tui::layout::Constraint::Length(status_height),
+                ]
+                .as_ref(),
+            )
+            .split(area);
+
         let parts = tui::layout::Layout::default()
             .direction(tui::layout::Direction::Horizontal)
             .constraints(
                 [
-                    tui::layout::Constraint::Length(40),
+                    tui::layout::Constraint::Length(
+                        self.tabs().width().min(self.settings.tab_width),
+                    ),
                     tui::layout::Constraint::Percentage(100),
                 ]
                 .as_ref(),
             )
-            .split(area);
+            .split(rows[1]);
+        let (header_area, process_area) = self.split_header(parts[1]);
+
+        Layout {
+            banner_area: rows[0],
+            tabs_area: parts[0],
+            header_area,
+            process_area,
+            status_area: rows[2],
+        }
+    }
+
+    /// Take the row of the pane header off the top of `area`, if it is shown.
+    fn split_header(&self, area: tui::layout::Rect) -> (tui::layout::Rect, tui::layout::Rect) {
+        if self.settings.pane_header && area.height > 1 {
+            let header = tui::layout::Rect { height: 1, ..area };
+            let rest = tui::layout::Rect {
+                y: area.y + 1,
+                height: area.height - 1,
+                ..area
+            };
+            (header, rest)
+        } else {
+            (tui::layout::Rect { height: 0, ..area }, area)
+        }
+    }
+
+    /// The line above the selected process with its complete command line, working directory and
+    /// start time.
+    fn pane_header(&self) -> vertical_tabs::Title {
+        let process = &self.processes[self.selected];
+        let dim = tui::style::Style::default().fg(tui::style::Color::Gray);
+        vertical_tabs::Title::default()
+            .text(process.command_line.as_str())
+            .symbols(vec![
+                tui::widgets::Text::styled(
+                    process
+                        .started
+                        .format("started %Y-%m-%d %H:%M:%S")
+                        .to_string(),
+                    dim,
+                ),
+                tui::widgets::Text::styled(format!("in {}", self.cwd), dim),
+            ])
+            .style(tui::style::Style::default().modifier(tui::style::Modifier::BOLD))
+    }
+
+    fn banner_height(&self) -> u16 {
+        match self.config.error {
+            Some(config::Error { line: Some(_), .. }) => 2,
+            Some(_) => 1,
+            None => 0,
+        }
+    }
+
+    fn draw_banner(&self, area: tui::layout::Rect, buf: &mut tui::buffer::Buffer) {
+        use tui::widgets::Widget;
+
+        if let Some(ref error) = self.config.error {
+            let style = tui::style::Style::default()
+                .fg(tui::style::Color::White)
+                .bg(tui::style::Color::Red)
+                .modifier(tui::style::Modifier::BOLD);
+
+            let mut lines = vec![tui::widgets::Text::styled(
+                format!("{} (using defaults, press Ctrl+t r to retry)\n", error),
+                style,
+            )];
+            if let Some((number, ref text)) = error.line {
+                lines.push(tui::widgets::Text::styled(
+                    format!("{:>5} | {}\n", number, text),
+                    style,
+                ));
+            }
+
+            tui::widgets::Paragraph::new(lines.iter())
+                .style(style)
+                .draw(area, buf);
+        }
+    }
+
+    fn status_bar(&self) -> status_bar::StatusBar {
+        let running = self.running();
+        let succeeded = self
+            .processes
+            .iter()
+            .filter(|p| p.exit_status.map_or(false, |s| s.success()))
+            .count();
+        let failed = self.processes.len() - running - succeeded;
+
+        let mut left = vec![
+            tui::widgets::Text::raw(format!(
+                "{} {}",
+                self.processes.len(),
+                jobs(self.processes.len())
+            )),
+            tui::widgets::Text::raw(format!("{} running", running)),
+            tui::widgets::Text::styled(
+                format!("{} ok", succeeded),
+                tui::style::Style::default()
+                    .fg(self.theme.ok)
+                    .bg(self.theme.bar),
+            ),
+            tui::widgets::Text::styled(
+                format!("{} failed", failed),
+                tui::style::Style::default()
+                    .fg(if failed > 0 {
+                        self.theme.failed
+                    } else {
+                        tui::style::Color::White
+                    })
+                    .bg(self.theme.bar),
+            ),
+            tui::widgets::Text::raw(self.elapsed.as_str()),
+            tui::widgets::Text::raw(match self.input_mode {
+                InputMode::All => "input: all",
+                InputMode::Selected => "input: selected",
+                InputMode::Group => "input: group",
+            }),
+        ];
+
+        let hidden = self.processes.len() - self.shown().len();
+        if hidden > 0 {
+            left.push(tui::widgets::Text::raw(format!("{} hidden", hidden)));
+        }
+
+        let (display_offset, scroll_limit, auto_scroll, invalid_utf8, c1_controls) = {
+            let emulator = self.processes[self.selected].lock();
+            let grid = emulator.term.grid();
+            // Full-screen programs have no scrollback to be scrolled or pinned in
+            let alt_screen = emulator.term.alt_screen();
+            (
+                grid.display_offset(),
+                grid.scroll_limit(),
+                alt_screen || emulator.term.auto_scroll(),
+                emulator.invalid_utf8(),
+                emulator.c1_controls(),
+            )
+        };
+        if display_offset > 0 {
+            left.push(tui::widgets::Text::raw(format!(
+                "scroll: {}/{}",
+                display_offset, scroll_limit
+            )));
+        }
+        if self.prefixed {
+            left.push(tui::widgets::Text::styled(
+                "prefix",
+                tui::style::Style::default()
+                    .fg(self.theme.accent)
+                    .bg(self.theme.bar),
+            ));
+        }
+        let selected = &self.processes[self.selected];
+        let monitor = match (selected.monitor_activity, selected.monitor_silence) {
+            (false, None) => None,
+            (true, None) => Some("monitor: activity".to_owned()),
+            (false, Some(silence)) => Some(format!("monitor: silence {}s", silence.as_secs())),
+            (true, Some(silence)) => {
+                Some(format!("monitor: activity, silence {}s", silence.as_secs()))
+            }
+        };
+        if let Some(monitor) = monitor {
+            left.push(tui::widgets::Text::raw(monitor));
+        }
+        // How bytes that aren't UTF-8 are shown, when it isn't the default
+        match invalid_utf8 {
+            terminal_emulator::ansi::InvalidUtf8::Replace => {}
+            terminal_emulator::ansi::InvalidUtf8::Latin1 => {
+                left.push(tui::widgets::Text::raw("utf-8: latin1"))
+            }
+            terminal_emulator::ansi::InvalidUtf8::Hex => {
+                left.push(tui::widgets::Text::raw("utf-8: hex"))
+            }
+        }
+        if c1_controls {
+            left.push(tui::widgets::Text::raw("c1"));
+        }
+        if !auto_scroll {
+            left.push(tui::widgets::Text::styled(
+                "pinned",
+                tui::style::Style::default()
+                    .fg(self.theme.accent)
+                    .bg(self.theme.bar),
+            ));
+        }
+
+        let right = self
+            .clock
+            .iter()
+            .chain(self.host.iter())
+            .map(|s| tui::widgets::Text::raw(s.as_str()))
+            .collect();
+
+        status_bar::StatusBar::default()
+            .left(left)
+            .right(right)
+            .style(
+                tui::style::Style::default()
+                    .fg(tui::style::Color::White)
+                    .bg(self.theme.bar),
+            )
+    }
+
+    /// Which of `rows` is highlighted as the selected one.
+    fn selected_row(&self, rows: &[Row]) -> usize {
+        // The header of a collapsed group stands in for the selected tab in it
+        let selected_group = self.processes[self.selected].group.as_ref();
+        rows.iter()
+            .position(|row| match *row {
+                Row::All => self.all,
+                Row::Group(ref group) => {
+                    !self.all && selected_group == Some(group) && self.collapsed.contains(group)
+                }
+                Row::Tab(index) => !self.all && index == self.selected,
+            })
+            .unwrap_or(0)
+    }
+
+    /// Scroll the list of tabs by `rows`, up or down, keeping at least one tab in view.
+    fn scroll_tabs(&mut self, up: bool, rows: usize) {
+        let last = self.rows().len().saturating_sub(1);
+        self.scroll = if up {
+            self.scroll.saturating_sub(rows)
+        } else {
+            (self.scroll + rows).min(last)
+        };
+    }
 
-        (parts[0], parts[1])
+    /// Scroll the list of tabs so that the selected tab is in view, if it changed since the last
+    /// call; the list has room for `height` rows.
+    fn scroll_to_selected(&mut self, height: usize) {
+        self.tabs_height = height;
+        if self.scrolled_to == Some((self.selected, self.all)) {
+            return;
+        }
+        self.scrolled_to = Some((self.selected, self.all));
+
+        let rows = self.rows();
+        if rows.len() <= height {
+            self.scroll = 0;
+            return;
+        }
+        // Leave room for the arrows that show that there are more tabs above and below
+        let visible = height.saturating_sub(2).max(1);
+        let selected = self.selected_row(&rows);
+        if selected < self.scroll {
+            self.scroll = selected;
+        } else if selected >= self.scroll + visible {
+            self.scroll = selected + 1 - visible;
+        }
     }
 
     fn tabs(&self) -> vertical_tabs::VerticalTabs {
+        let rows = self.rows();
+        let selected = self.selected_row(&rows);
+
         vertical_tabs::VerticalTabs::default()
             .titles(
-                self.processes
-                    .iter()
-                    .map(|p| p.tab_title())
+                rows.iter()
+                    .map(|row| match *row {
+                        Row::All => vertical_tabs::Title::default().text("ALL").header(true),
+                        Row::Group(ref group) => self.group_title(group),
+                        Row::Tab(index) => {
+                            self.processes[index].tab_title(SPINNER[self.spinner], &self.theme)
+                        }
+                    })
                     .collect::<Vec<_>>(),
             )
             .block(tui::widgets::Block::default().borders(tui::widgets::Borders::RIGHT))
@@ -227,8 +2349,168 @@ impl State {
                 tui::style::Style::default()
                     .modifier(tui::style::Modifier::BOLD | tui::style::Modifier::UNDERLINED),
             )
-            .select(self.selected)
+            .select(selected)
             .scroll(self.scroll)
+            .truncate(self.settings.title_truncate)
+    }
+
+    /// The header of a group in the list of tabs, with how many of its processes have finished.
+    fn group_title(&self, group: &str) -> vertical_tabs::Title {
+        let members = self
+            .processes
+            .iter()
+            .filter(|p| p.group.as_ref().map(String::as_str) == Some(group))
+            .collect::<Vec<_>>();
+        let finished = members.iter().filter(|p| p.exit_status.is_some()).count();
+        let failed = members
+            .iter()
+            .filter(|p| p.exit_status.map_or(false, |s| !s.success()))
+            .count();
+
+        let mut symbols = vec![tui::widgets::Text::raw(format!(
+            "{}/{}",
+            finished,
+            members.len()
+        ))];
+        if failed > 0 {
+            symbols.push(tui::widgets::Text::styled(
+                format!("✗ {}", failed),
+                tui::style::Style::default()
+                    .fg(self.theme.failed)
+                    .modifier(tui::style::Modifier::BOLD),
+            ));
+        }
+
+        let marker = if self.collapsed.contains(group) {
+            "▸"
+        } else {
+            "▾"
+        };
+        vertical_tabs::Title::default()
+            .text(format!("{} {}", marker, group))
+            .symbols(symbols)
+            .header(true)
+    }
+
+    fn tab_strip(&self) -> tab_strip::TabStrip {
+        tab_strip::TabStrip::default()
+            .titles(
+                std::iter::once("ALL")
+                    .chain(
+                        self.shown()
+                            .into_iter()
+                            .map(|index| self.processes[index].title.as_str()),
+                    )
+                    .collect(),
+            )
+            .style(
+                tui::style::Style::default()
+                    .fg(tui::style::Color::White)
+                    .bg(self.theme.bar),
+            )
+            .highlight_style(
+                tui::style::Style::default()
+                    .fg(tui::style::Color::Black)
+                    .bg(tui::style::Color::White)
+                    .modifier(tui::style::Modifier::BOLD),
+            )
+            .select(if self.all {
+                0
+            } else {
+                self.selected_position() + 1
+            })
+            .truncate(self.settings.title_truncate)
+    }
+
+    fn draw_panes(&mut self, panes: Vec<tui::layout::Rect>, buf: &mut tui::buffer::Buffer) {
+        use tui::widgets::Widget;
+
+        for (index, pane) in self.shown().into_iter().zip(panes) {
+            let cursor = self.cursor_for(index);
+            let process = &mut self.processes[index];
+            let borders = &self.config.config.borders;
+            let (lines, border_style) = if index == self.selected {
+                let style = tui::style::Style::default()
+                    .fg(self.theme.focused)
+                    .modifier(tui::style::Modifier::BOLD);
+                (borders.focused, style)
+            } else {
+                (
+                    borders.lines,
+                    tui::style::Style::default().fg(self.theme.border),
+                )
+            };
+
+            border::Border::default()
+                .title(&process.title)
+                .lines(lines)
+                .placement(borders.title)
+                .style(border_style)
+                .draw(pane, buf);
+            let inner = border::Border::inner(pane);
+
+            if inner.area() > 0 {
+                process.fit(inner);
+                if let Some(position) = process.draw(
+                    inner,
+                    buf,
+                    &self.config.config.colors,
+                    &self.theme,
+                    cursor,
+                    &mut self.placements,
+                ) {
+                    self.cursor = Some(position);
+                }
+
+                let idle = self
+                    .settings
+                    .dim_idle
+                    .map_or(false, |dim_idle| process.last_output.elapsed() >= dim_idle);
+                if idle && index != self.selected {
+                    for y in inner.top()..inner.bottom() {
+                        for x in inner.left()..inner.right() {
+                            let cell = buf.get_mut(x, y);
+                            cell.style.modifier |= tui::style::Modifier::DIM;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn cursor_for(&self, index: usize) -> Cursor {
+        if index == self.selected {
+            if self.goto.is_none()
+                && self.save.is_none()
+                && self.rerun.is_none()
+                && self.rename.is_none()
+                && self.marking.is_none()
+                && !self.confirm_quit
+                && self.confirm_kill.is_none()
+            {
+                Cursor::Active
+            } else {
+                Cursor::Hidden
+            }
+        } else if self.settings.inactive_cursors {
+            Cursor::Inactive
+        } else {
+            Cursor::Hidden
+        }
+    }
+
+    fn take_resizes<'a>(&'a mut self) -> impl Iterator<Item = (usize, (u16, u16))> + 'a {
+        self.processes
+            .iter_mut()
+            .enumerate()
+            .flat_map(|(idx, process)| process.take_resize().map(|s| (idx, s)))
+    }
+
+    fn take_respawns<'a>(&'a mut self) -> impl Iterator<Item = (usize, String)> + 'a {
+        self.processes
+            .iter_mut()
+            .enumerate()
+            .flat_map(|(idx, process)| process.respawn.take().map(|arg| (idx, arg)))
     }
 
     fn take_process_inputs<'a>(
@@ -243,64 +2525,632 @@ impl State {
 
 impl tui::widgets::Widget for State {
     fn draw(&mut self, area: tui::layout::Rect, buf: &mut tui::buffer::Buffer) {
-        let (tabs_area, process_area) = self.layout(area);
+        let Layout {
+            banner_area,
+            tabs_area,
+            header_area,
+            process_area,
+            status_area,
+        } = self.layout(area);
+
+        if banner_area.area() > 0 {
+            self.draw_banner(banner_area, buf);
+        }
+        if header_area.area() > 0 {
+            self.pane_header().draw(header_area, buf);
+        }
+
+        if !self.settings.compact_tabs && tabs_area.height > 0 {
+            self.scroll_to_selected(usize::from(tabs_area.height));
+        }
+
+        self.cursor = None;
+        self.placements.clear();
+        if self.all {
+            if self.settings.compact_tabs {
+                self.tab_strip().draw(tabs_area, buf);
+            } else {
+                self.tabs().draw(tabs_area, buf);
+            }
+
+            let prefixes = self
+                .processes
+                .iter()
+                .map(ProcessState::merged_prefix)
+                .collect::<Vec<_>>();
+            self.merged
+                .draw(process_area, buf, &prefixes, self.theme.accent);
+        } else if self.zoomed {
+            // The other processes keep their size, so that they don't have to redraw twice
+            let cursor = self.cursor_for(self.selected);
+            let process = &mut self.processes[self.selected];
+            process.fit(process_area);
+            self.cursor = process.draw(
+                process_area,
+                buf,
+                &self.config.config.colors,
+                &self.theme,
+                cursor,
+                &mut self.placements,
+            );
+        } else if let Some(panes) = self.tiling.panes(process_area, self.shown().len()) {
+            self.draw_panes(panes, buf);
+        } else {
+            if self.settings.compact_tabs {
+                self.tab_strip().draw(tabs_area, buf);
+            } else {
+                self.tabs().draw(tabs_area, buf);
+            }
 
-        self.tabs().draw(tabs_area, buf);
+            // Keep the hidden tabs the same size so that they don't have to redraw when switching
+            for process in &mut self.processes {
+                process.fit(process_area);
+            }
+            let cursor = self.cursor_for(self.selected);
+            self.cursor = self.processes[self.selected].draw(
+                process_area,
+                buf,
+                &self.config.config.colors,
+                &self.theme,
+                cursor,
+                &mut self.placements,
+            );
+        }
+        self.cursor_blinks =
+            self.cursor.is_some() && self.processes[self.selected].lock().term.cursor_blinks();
 
-        self.processes[self.selected].draw(process_area, buf);
+        if status_area.area() > 0 {
+            if let Some(ref goto) = self.goto {
+                prompt::Prompt::default()
+                    .label("go to:")
+                    .text(&goto.text)
+                    .message(goto.message.as_ref().map(String::as_str))
+                    .style(
+                        tui::style::Style::default()
+                            .fg(tui::style::Color::Black)
+                            .bg(self.theme.accent),
+                    )
+                    .draw(status_area, buf);
+            } else if let Some(ref save) = self.save {
+                prompt::Prompt::default()
+                    .label("save to:")
+                    .text(&save.text)
+                    .message(save.message.as_ref().map(String::as_str))
+                    .style(
+                        tui::style::Style::default()
+                            .fg(tui::style::Color::Black)
+                            .bg(self.theme.accent),
+                    )
+                    .draw(status_area, buf);
+            } else if let Some(ref rename) = self.rename {
+                prompt::Prompt::default()
+                    .label("rename to:")
+                    .text(&rename.text)
+                    .message(rename.message.as_ref().map(String::as_str))
+                    .style(
+                        tui::style::Style::default()
+                            .fg(tui::style::Color::Black)
+                            .bg(self.theme.accent),
+                    )
+                    .draw(status_area, buf);
+            } else if let Some(ref rerun) = self.rerun {
+                prompt::Prompt::default()
+                    .label("rerun with:")
+                    .text(&rerun.text)
+                    .message(rerun.message.as_ref().map(String::as_str))
+                    .style(
+                        tui::style::Style::default()
+                            .fg(tui::style::Color::Black)
+                            .bg(self.theme.accent),
+                    )
+                    .draw(status_area, buf);
+            } else if self.confirm_quit {
+                let running = self.running();
+                let message = format!(
+                    "{} {} still running and will be hung up (SIGHUP) when mux exits",
+                    running,
+                    if running == 1 { "job is" } else { "jobs are" }
+                );
+                prompt::Prompt::default()
+                    .label("quit? [y/N]")
+                    .message(Some(&message))
+                    .style(
+                        tui::style::Style::default()
+                            .fg(tui::style::Color::Black)
+                            .bg(self.theme.failed),
+                    )
+                    .draw(status_area, buf);
+            } else if let Some(ref marking) = self.marking {
+                let help = format!(
+                    "{} marked - space: mark, a: all, s: signal, r: restart, l: save output, \
+                     x: close, esc: done",
+                    self.marked().len()
+                );
+                let (label, text) = match marking.signal {
+                    Some(ref signal) => ("signal:", signal.as_str()),
+                    None => ("mark:", ""),
+                };
+                prompt::Prompt::default()
+                    .label(label)
+                    .text(text)
+                    .message(Some(
+                        marking
+                            .message
+                            .as_ref()
+                            .map_or(help.as_str(), String::as_str),
+                    ))
+                    .style(
+                        tui::style::Style::default()
+                            .fg(tui::style::Color::Black)
+                            .bg(self.theme.accent),
+                    )
+                    .draw(status_area, buf);
+            } else if let Some(ref indices) = self.confirm_kill {
+                let message = format!(
+                    "{} {} will be terminated (SIGTERM)",
+                    indices.len(),
+                    jobs(indices.len())
+                );
+                prompt::Prompt::default()
+                    .label("kill? [y/N]")
+                    .message(Some(&message))
+                    .style(
+                        tui::style::Style::default()
+                            .fg(tui::style::Color::Black)
+                            .bg(self.theme.failed),
+                    )
+                    .draw(status_area, buf);
+            } else {
+                self.status_bar().draw(status_area, buf);
+            }
+        }
     }
 }
 
 impl ProcessState {
-    fn from_settings(settings: ProcessSettings) -> Self {
+    fn from_settings(
+        settings: ProcessSettings,
+        log_dir: Option<&std::path::Path>,
+    ) -> Result<Self, failure::Error> {
         use terminal_emulator::Handler;
 
-        let mut terminal_emulator =
-            terminal_emulator::term::Term::new(terminal_emulator::term::SizeInfo {
-                width: 80.0,
-                height: 24.0,
-                cell_width: 1.0,
-                cell_height: 1.0,
-                padding_x: 0.0,
-                padding_y: 0.0,
-                dpr: 1.0,
-            });
-        let processor = terminal_emulator::Processor::new();
+        let size = DEFAULT_SIZE;
         let exit_status = None;
+        let suspended = false;
+        let unseen = false;
+        let last_output = std::time::Instant::now();
+        let monitor_activity = false;
+        let monitor_silence = None;
+        let started = chrono::Local::now();
+        let running_since = std::time::Instant::now();
+        let exited = None;
         let input = Vec::new();
+        let resized = false;
+        let restored = false;
+        let respawn = None;
+        let progress = None;
+        let errors = errors::Counter::default();
+        let marked = false;
+        let mouse_button = None;
+        let bell = false;
+        let notifications = std::collections::VecDeque::new();
+        let notification_sent = None;
+        let notified = false;
+        let tagger = settings.tag.as_ref().map(|tag| tag::Tagger::new(tag));
+        let log = match log_dir {
+            Some(dir) => {
+                let path = dir.join(pane_file_name(settings.id, &settings.arg, "log"));
+                let file = std::fs::File::create(&path)
+                    .map_err(|err| failure::err_msg(format!("{}: {}", path.display(), err)))?;
+                Some(file)
+            }
+            None => None,
+        };
 
-        terminal_emulator.set_title(&settings.initial_title);
+        settings
+            .emulator
+            .lock()
+            .unwrap()
+            .term
+            .set_title(&settings.initial_title);
         let title = settings.initial_title;
+        let process_title = title.clone();
+        let renamed = false;
 
-        Self {
-            terminal_emulator,
-            processor,
+        Ok(Self {
+            id: settings.id,
+            pid: settings.pid,
+            arg: settings.arg,
+            emulator: settings.emulator,
             title,
+            process_title,
+            renamed,
+            group: settings.group,
+            command_line: settings.command_line,
+            started,
+            running_since,
             exit_status,
+            suspended,
+            unseen,
+            last_output,
+            monitor_activity,
+            monitor_silence,
+            exited,
             input,
+            log,
+            tag: settings.tag,
+            tagger,
+            size,
+            resized,
+            restored,
+            respawn,
+            progress,
+            errors,
+            marked,
+            mouse_button,
+            bell,
+            notifications,
+            notification_sent,
+            notified,
+        })
+    }
+
+    /// Resize the emulated terminal to fill `area`, if it doesn't already.
+    fn fit(&mut self, area: tui::layout::Rect) {
+        let size = (area.width.max(2), area.height.max(1));
+        if size != self.size {
+            self.size = size;
+            self.lock().term.resize(&size_info(size));
+            self.resized = true;
+        }
+    }
+
+    /// The new (width, height) of the terminal if it changed since the last call.
+    fn take_resize(&mut self) -> Option<(u16, u16)> {
+        if self.resized {
+            self.resized = false;
+            Some(self.size)
+        } else {
+            None
+        }
+    }
+
+    /// The terminal of the process, locked against the task that parses the output
+    fn lock(&self) -> std::sync::MutexGuard<Emulator> {
+        self.emulator.lock().unwrap()
+    }
+
+    /// Take note of output that already went to the terminal, returning whether the tab title
+    /// changed.
+    fn on_data(&mut self, data: &[u8], error_patterns: &[config::Pattern]) -> bool {
+        use std::io::Write;
+
+        let tab = (self.unseen, self.errors.count());
+        self.unseen = true;
+        self.last_output = std::time::Instant::now();
+
+        if let Some(ref mut log) = self.log {
+            let result = match self.tagger {
+                Some(ref mut tagger) => log.write_all(&tagger.tag(data)),
+                None => log.write_all(data),
+            };
+            if let Err(err) = result {
+                warn!("stopped logging output of pane {}: {}", self.id, err);
+                self.log = None;
+            }
+        }
+
+        self.errors.feed(data, error_patterns);
+
+        let changed = self.take_changes();
+        changed || tab != (self.unseen, self.errors.count())
+    }
+
+    /// What goes in front of the lines of the process in the ALL tab: the tag, or the argument.
+    fn merged_prefix(&self) -> String {
+        match self.tag {
+            // Tab characters take up no room on the screen
+            Some(ref tag) => tag.replace('\t', " "),
+            None => format!("{}: ", self.arg),
         }
     }
 
-    fn on_data(&mut self, data: bytes::Bytes) {
-        for byte in data {
-            // TODO: maybe do something smarter than passing sink() here
-            self.processor
-                .advance(&mut self.terminal_emulator, byte, &mut self.input);
+    /// All of the output that is still kept, with the tag in front of every line, for saving it.
+    fn history(&self, attributes: bool) -> String {
+        let history = {
+            let mut emulator = self.lock();
+            emulator.flush();
+            emulator.term.history_to_string(attributes)
+        };
+        match self.tag {
+            Some(ref tag) => tag::lines(tag, &history),
+            None => history,
         }
+    }
+
+    /// Pick up what the terminal says about the process: its title, its progress, whether it
+    /// rang the bell, its notifications and the answers to its queries.  Returns whether the tab
+    /// changed.
+    fn take_changes(&mut self) -> bool {
+        let (title, progress, bell, notifications, replies) = {
+            let mut emulator = self.lock();
+            (
+                emulator.term.get_next_title(),
+                emulator.progress,
+                emulator.term.take_bell(),
+                emulator.term.take_notifications(),
+                emulator.take_replies(),
+            )
+        };
+        self.input.extend_from_slice(&replies);
+        self.bell |= bell;
+        let mut changed = !notifications.is_empty() && !self.notified;
+        self.notified |= !notifications.is_empty();
+        self.notifications.extend(notifications);
+        let excess = self.notifications.len().saturating_sub(MAX_NOTIFICATIONS);
+        self.notifications.drain(..excess);
+
+        changed |= progress != self.progress;
+        self.progress = progress;
+        if let Some(title) = title {
+            if !self.renamed {
+                changed |= title != self.title;
+                self.title = title.clone();
+            }
+            self.process_title = title;
+        }
+        changed
+    }
+
+    /// Whether a notification is waiting that may be passed on to the terminal at `now`.
+    fn notification_due(&self, now: std::time::Instant) -> bool {
+        !self.notifications.is_empty()
+            && self.notification_sent.map_or(true, |sent| {
+                now.duration_since(sent) >= NOTIFICATION_INTERVAL
+            })
+    }
 
-        if let Some(title) = self.terminal_emulator.get_next_title() {
+    /// Show `title` on the tab instead of the titles that the process sets, or go back to those
+    /// for an empty title.
+    fn rename(&mut self, title: String) {
+        if title.is_empty() {
+            self.renamed = false;
+            self.title = self.process_title.clone();
+        } else {
+            self.renamed = true;
             self.title = title;
         }
     }
 
     fn on_exit(&mut self, status: std::process::ExitStatus) {
+        // The last output decides the title and what the tab shows
+        self.lock().flush();
+        self.take_changes();
         self.exit_status = Some(status);
+        self.exited = Some(std::time::Instant::now());
+    }
+
+    /// Start over for a new process, whose terminal was already reset with `Emulator::reset`.
+    fn restart(
+        &mut self,
+        pid: u32,
+        arg: &str,
+        title: &str,
+        tag: Option<String>,
+        command_line: &str,
+    ) {
+        self.pid = pid;
+        self.arg = arg.to_owned();
+        // The tag can say which argument the lines come from
+        self.tagger = tag.as_ref().map(|tag| tag::Tagger::new(tag));
+        self.tag = tag;
+        self.command_line = command_line.to_owned();
+        self.started = chrono::Local::now();
+        self.running_since = std::time::Instant::now();
+        self.process_title = title.to_owned();
+        if !self.renamed {
+            self.title = title.to_owned();
+        }
+        self.exit_status = None;
+        self.exited = None;
+        self.progress = None;
+        self.errors = errors::Counter::default();
+        self.last_output = std::time::Instant::now();
+        // The new pseudo-terminal starts out with the default size
+        self.resized = true;
+    }
+
+    /// Ask the process to exit, if it is still running.
+    fn terminate(&self) {
+        if self.exit_status.is_none() && !self.restored {
+            if let Err(err) = sys::process::terminate(self.pid) {
+                warn!("could not terminate process {}: {}", self.pid, err);
+            }
+        }
+    }
+
+    /// Send `signal` to the process, if it is still running; returns whether it was sent.
+    fn signal(&self, signal: i32) -> bool {
+        if self.exit_status.is_some() || self.restored {
+            return false;
+        }
+        match sys::process::signal(self.pid, signal) {
+            Ok(()) => true,
+            Err(err) => {
+                warn!(
+                    "could not send signal {} to process {}: {}",
+                    signal, self.pid, err
+                );
+                false
+            }
+        }
+    }
+
+    /// A short description of whether the process is running, for `mux ctl list`.
+    fn state_name(&self) -> String {
+        match self.exit_status {
+            None => "running".to_owned(),
+            Some(status) => match status.code() {
+                Some(code) => format!("exited {}", code),
+                None => "killed".to_owned(),
+            },
+        }
+    }
+
+    fn on_user_input(&mut self, area: tui::layout::Rect, event: &termion::event::Event) -> bool {
+        use termion::event::{Event, MouseButton, MouseEvent};
+
+        if let Event::Mouse(m) = *event {
+            if self.report_mouse(area, m) {
+                return true;
+            }
+        }
+
+        let lines = match *event {
+            Event::Mouse(MouseEvent::Press(MouseButton::WheelUp, ..)) => 3,
+            Event::Mouse(MouseEvent::Press(MouseButton::WheelDown, ..)) => -3,
+            _ => return true,
+        };
+
+        // Full-screen programs have no scrollback
+        let mut emulator = self.lock();
+        if !emulator.term.alt_screen() {
+            emulator
+                .term
+                .scroll_display(terminal_emulator::Scroll::Lines(lines));
+        }
+        true
     }
 
-    fn on_user_input(&mut self, _area: tui::layout::Rect, _event: &termion::event::Event) -> bool {
+    /// Pass a mouse event in `area` on to the process, if it asked for mouse reports or is a
+    /// full-screen program that gets the wheel as arrow keys.  Returns whether it did.
+    fn report_mouse(&mut self, area: tui::layout::Rect, event: termion::event::MouseEvent) -> bool {
+        use terminal_emulator::mouse::{self, Action, Button};
+        use terminal_emulator::term::TermMode;
+        use termion::event::{MouseButton, MouseEvent};
+
+        let mode = *self.lock().term.mode();
+        let reporting = mode.intersects(
+            TermMode::MOUSE_REPORT_CLICK | TermMode::MOUSE_DRAG | TermMode::MOUSE_MOTION,
+        );
+        let alternate_scroll = mode.contains(TermMode::ALT_SCREEN | TermMode::ALTERNATE_SCROLL);
+        if area.area() == 0 || !(reporting || alternate_scroll) {
+            self.mouse_button = None;
+            return false;
+        }
+
+        let (x, y) = mouse_event_coords(&event);
+        let action = match event {
+            // Clicks on the border of the pane are not for the process
+            MouseEvent::Press(..) if !contains_point(area, x, y) => return true,
+            MouseEvent::Press(button, ..) => {
+                let button = match button {
+                    MouseButton::Left => Button::Left,
+                    MouseButton::Middle => Button::Middle,
+                    MouseButton::Right => Button::Right,
+                    MouseButton::WheelUp => Button::WheelUp,
+                    MouseButton::WheelDown => Button::WheelDown,
+                };
+                if button != Button::WheelUp && button != Button::WheelDown {
+                    self.mouse_button = Some(button);
+                }
+                Action::Press(button)
+            }
+            MouseEvent::Hold(..) => match self.mouse_button {
+                Some(button) => Action::Drag(button),
+                None => Action::Move,
+            },
+            MouseEvent::Release(..) => match self.mouse_button.take() {
+                Some(button) => Action::Release(button),
+                None => return true,
+            },
+        };
+
+        // Drags and releases outside of the pane end up on its edge
+        let event = mouse::Event {
+            action,
+            line: usize::from(y.max(area.y).min(area.bottom() - 1) - area.y),
+            col: usize::from(x.max(area.x).min(area.right() - 1) - area.x),
+        };
+        if let Some(data) = mouse::encode(&event, mode) {
+            self.input.extend_from_slice(&data);
+        }
         true
     }
 
+    /// Scroll back through the output by a page, or have a full-screen program scroll itself.
+    fn scroll_page(&mut self, up: bool) {
+        use termion::event::{Event, Key};
+
+        let alt_screen = self.lock().term.alt_screen();
+        match (alt_screen, up) {
+            (true, true) => self.key_input(&Event::Key(Key::PageUp), b"\x1b[5~"),
+            (true, false) => self.key_input(&Event::Key(Key::PageDown), b"\x1b[6~"),
+            (false, true) => self
+                .lock()
+                .term
+                .scroll_display(terminal_emulator::Scroll::PageUp),
+            (false, false) => self
+                .lock()
+                .term
+                .scroll_display(terminal_emulator::Scroll::PageDown),
+        }
+    }
+
+    /// Scroll to the closest prompt above the top of the pane, or below it, if the shell marks
+    /// its prompts (OSC 133).
+    fn jump_to_prompt(&self, previous: bool) {
+        let mut emulator = self.lock();
+        let term = &mut emulator.term;
+        if term.alt_screen() {
+            return;
+        }
+
+        // The line at the top of the pane, counted like the lines of the prompts
+        let top = term.grid().scroll_limit() - term.grid().display_offset();
+        let prompts = term.prompts();
+        let prompt = if previous {
+            prompts.iter().rev().find(|prompt| prompt.line < top)
+        } else {
+            prompts.iter().find(|prompt| prompt.line > top)
+        };
+        if let Some(prompt) = prompt {
+            term.scroll_display(terminal_emulator::Scroll::ToLine(prompt.line));
+        }
+    }
+
+    /// What the last command printed, if the shell marks its prompts (OSC 133).
+    fn last_output(&self) -> Option<String> {
+        let emulator = self.lock();
+        let prompts = emulator.term.prompts();
+        let lines = prompts.into_iter().rev().find_map(|prompt| prompt.output)?;
+        Some(emulator.term.lines_to_string(lines))
+    }
+
+    /// Queue typed input, encoded the way the process's terminal modes ask for.
+    fn key_input(&mut self, event: &termion::event::Event, raw: &[u8]) {
+        let mode = *self.lock().term.mode();
+        match keys::encode(event, mode) {
+            Some(data) => self.input.extend_from_slice(&data),
+            None => self.input.extend_from_slice(raw),
+        }
+    }
+
+    /// Queue pasted text as input, bracketed if the process asked for it.
+    fn paste(&mut self, data: &[u8]) {
+        let bracketed = self
+            .lock()
+            .term
+            .mode()
+            .contains(terminal_emulator::term::TermMode::BRACKETED_PASTE);
+
+        if bracketed {
+            self.input.extend(crate::tty::bracketed_paste(data));
+        } else {
+            self.input.extend_from_slice(data);
+        }
+    }
+
     fn take_process_input(&mut self) -> Option<bytes::BytesMut> {
         use std::mem;
 
@@ -312,36 +3162,123 @@ impl ProcessState {
         }
     }
 
-    fn tab_title(&self) -> vertical_tabs::Title {
-        let mut title = vertical_tabs::Title::default()
-            .text(&self.title)
-            .style(tui::style::Style::default());
+    /// The title for the list of tabs, with a symbol showing what state the process is in.
+    fn tab_title<'a>(&'a self, spinner: &'a str, theme: &color::Theme) -> vertical_tabs::Title<'a> {
+        let (symbol, color): (std::borrow::Cow<str>, _) = match self.exit_status {
+            None if self.suspended => ("⏸".into(), tui::style::Color::Yellow),
+            None => (spinner.into(), tui::style::Color::Cyan),
+            Some(status) if status.success() => ("✓".into(), theme.ok),
+            Some(status) => match status.code() {
+                Some(code) => (format!("✗ {}", code).into(), theme.failed),
+                None => ("☇".into(), theme.failed),
+            },
+        };
+        let style = tui::style::Style::default()
+            .fg(color)
+            .modifier(tui::style::Modifier::BOLD);
 
-        if let Some(ref exit_status) = self.exit_status {
-            let style = if exit_status.success() {
+        let mut symbols = vec![tui::widgets::Text::Styled(symbol, style)];
+        if let (None, Some(progress)) = (self.exit_status, self.progress) {
+            symbols.push(tui::widgets::Text::styled(
+                progress::bar(progress),
+                tui::style::Style::default().fg(tui::style::Color::Cyan),
+            ));
+        }
+        if !self.restored {
+            // Only as fresh as the last draw, which happens at least once a second for the clock
+            let runtime = match self.exited {
+                Some(exited) => exited.duration_since(self.running_since),
+                None => self.running_since.elapsed(),
+            };
+            symbols.push(tui::widgets::Text::styled(
+                format_runtime(runtime),
+                tui::style::Style::default().modifier(tui::style::Modifier::DIM),
+            ));
+        }
+        if self.errors.count() > 0 {
+            symbols.push(tui::widgets::Text::styled(
+                format!(" {} ", self.errors.count()),
                 tui::style::Style::default()
-                    .fg(tui::style::Color::Green)
-                    .modifier(tui::style::Modifier::BOLD)
-            } else {
+                    .fg(tui::style::Color::White)
+                    .bg(theme.failed)
+                    .modifier(tui::style::Modifier::BOLD),
+            ));
+        }
+        if self.unseen {
+            symbols.push(tui::widgets::Text::styled(
+                "●",
+                tui::style::Style::default().fg(tui::style::Color::Blue),
+            ));
+        }
+        // The same marks as tmux uses in its list of windows, where `!` is for the bell
+        let alert = if self.notified {
+            Some("!")
+        } else if self.monitor_activity && self.unseen {
+            Some("#")
+        } else if self.is_silent() {
+            Some("~")
+        } else {
+            None
+        };
+        if let Some(alert) = alert {
+            symbols.push(tui::widgets::Text::styled(
+                alert,
                 tui::style::Style::default()
-                    .fg(tui::style::Color::Red)
-                    .modifier(tui::style::Modifier::BOLD)
-            };
-            let symbol = if let Some(code) = exit_status.code() {
-                format!("🗙 {}", code).into()
-            } else {
-                "☇".into()
-            };
-
-            title = title.symbols(vec![tui::widgets::Text::Styled(symbol, style)])
+                    .fg(theme.accent)
+                    .modifier(tui::style::Modifier::BOLD),
+            ));
         }
+        if self.marked {
+            symbols.push(tui::widgets::Text::styled(
+                "◆",
+                tui::style::Style::default()
+                    .fg(theme.accent)
+                    .modifier(tui::style::Modifier::BOLD),
+            ));
+        }
+
+        let style = if alert.is_some() {
+            tui::style::Style::default().modifier(tui::style::Modifier::REVERSED)
+        } else {
+            tui::style::Style::default()
+        };
+        vertical_tabs::Title::default()
+            .text(&self.title)
+            .style(style)
+            .symbols(symbols)
+    }
 
-        title
+    /// Whether the process is still running but has been quiet for longer than
+    /// `monitor_silence`; this is only as fresh as the last draw, like the runtime.
+    fn is_silent(&self) -> bool {
+        self.exit_status.is_none()
+            && !self.restored
+            && self
+                .monitor_silence
+                .map_or(false, |silence| self.last_output.elapsed() >= silence)
     }
 }
 
-impl tui::widgets::Widget for ProcessState {
-    fn draw(&mut self, area: tui::layout::Rect, buf: &mut tui::buffer::Buffer) {
+impl ProcessState {
+    /// Draw the process's terminal, resolving its colors through `colors`; this is not a
+    /// `Widget` because the palette comes from the config.  The images on the screen are added to
+    /// `placements`.  Returns where the outer terminal's cursor should go for an active cursor.
+    fn draw(
+        &mut self,
+        area: tui::layout::Rect,
+        buf: &mut tui::buffer::Buffer,
+        colors: &config::Colors,
+        theme: &color::Theme,
+        cursor: Cursor,
+        placements: &mut Vec<graphics::Placement>,
+    ) -> Option<(u16, u16)> {
+        use tui::widgets::Widget;
+
+        self.lock().flush();
+        self.take_changes();
+        self.unseen = false;
+        self.notified = false;
+
         let chunks = tui::layout::Layout::default()
             .direction(tui::layout::Direction::Vertical)
             .constraints(vec![
@@ -352,19 +3289,98 @@ impl tui::widgets::Widget for ProcessState {
         let main_chunk = chunks[0];
         let status_chunk = chunks[1];
 
-        for cell in self.terminal_emulator.renderable_cells() {
+        let emulator = self.lock();
+        for cell in emulator.term.renderable_cells_without_cursor() {
+            use terminal_emulator::term::cell::Flags;
+
+            // Characters on lines of double size are spread over two columns each
+            let doubled = cell.line_size != terminal_emulator::ansi::LineSize::Single;
             #[allow(clippy::cast_possible_truncation)]
             let x = cell.column.0 as u16;
+            let x = if doubled { x.saturating_mul(2) } else { x };
             #[allow(clippy::cast_possible_truncation)]
             let y = cell.line.0 as u16;
             if x < main_chunk.width && y < main_chunk.height {
+                let fits = !cell.flags.contains(Flags::WIDE_CHAR) || x + 1 < main_chunk.width;
                 let x = main_chunk.x + x;
                 let y = main_chunk.y + y;
                 let buf_cell = buf.get_mut(x, y);
-                buf_cell.set_char(cell.chars[0]);
-                buf_cell.set_bg(convert_color(cell.bg));
-                buf_cell.set_fg(convert_color(cell.fg));
-                buf_cell.set_modifier(convert_flags(cell.flags));
+                if cell.flags.contains(Flags::WIDE_CHAR_SPACER) {
+                    // Covered by the wide character before it, which `tui` draws over two cells
+                    buf_cell.set_symbol(" ");
+                } else if fits {
+                    buf_cell.set_symbol(&cell_symbol(&cell.chars));
+                } else {
+                    // Half of a wide character can't be shown at the edge of the pane
+                    buf_cell.set_symbol(" ");
+                }
+                buf_cell.set_bg(convert_color(cell.bg, &emulator.term, colors, theme.depth));
+                buf_cell.set_fg(convert_color(cell.fg, &emulator.term, colors, theme.depth));
+                let mut modifier = convert_flags(cell.flags);
+                if has_dim_color(cell.fg, &emulator.term, colors) {
+                    modifier.remove(tui::style::Modifier::DIM);
+                }
+                buf_cell.set_modifier(modifier);
+                if doubled && !cell.flags.contains(Flags::WIDE_CHAR) && x + 1 < main_chunk.right() {
+                    let style = buf_cell.style;
+                    let next = buf.get_mut(x + 1, y);
+                    next.set_symbol(" ");
+                    next.style = style;
+                }
+
+                // The top left corner of the part of an image that is in the pane
+                if let Some(image) = cell
+                    .image
+                    .filter(|image| image.col == 0 && (image.line == 0 || y == main_chunk.y))
+                {
+                    if let Some(shown) = emulator.term.image(image.id) {
+                        let skipped = usize::from(image.line);
+                        let lines =
+                            (shown.height - skipped).min(usize::from(main_chunk.bottom() - y));
+                        placements.push(graphics::Placement {
+                            id: image.id,
+                            image: shown.clone(),
+                            x,
+                            y,
+                            skipped,
+                            lines,
+                        });
+                    }
+                }
+            }
+        }
+
+        #[allow(clippy::cast_possible_truncation)]
+        let position = emulator
+            .term
+            .cursor_position()
+            .map(|(x, y)| match emulator.term.line_size(y) {
+                terminal_emulator::ansi::LineSize::Single => (x, y),
+                _ => (x * 2, y),
+            })
+            .map(|(x, y)| (x as u16, y as u16))
+            .filter(|&(x, y)| x < main_chunk.width && y < main_chunk.height)
+            .map(|(x, y)| (main_chunk.x + x, main_chunk.y + y));
+        let scrolled = emulator.term.grid().display_offset();
+        drop(emulator);
+
+        if scrolled > 0 {
+            // Otherwise output from a while ago looks just like the live output
+            let text = match scrolled {
+                1 => "[1 line up]".to_owned(),
+                lines => format!("[{} lines up]", lines),
+            };
+            #[allow(clippy::cast_possible_truncation)]
+            let width = text.len() as u16;
+            if width <= main_chunk.width && main_chunk.height > 0 {
+                buf.set_string(
+                    main_chunk.right() - width,
+                    main_chunk.y,
+                    &text,
+                    tui::style::Style::default()
+                        .fg(tui::style::Color::Black)
+                        .bg(theme.accent),
+                );
             }
         }
 
@@ -372,12 +3388,12 @@ impl tui::widgets::Widget for ProcessState {
             let style = if exit_status.success() {
                 tui::style::Style::default()
                     .fg(tui::style::Color::Black)
-                    .bg(tui::style::Color::Green)
+                    .bg(theme.ok)
                     .modifier(tui::style::Modifier::BOLD | tui::style::Modifier::DIM)
             } else {
                 tui::style::Style::default()
                     .fg(tui::style::Color::Black)
-                    .bg(tui::style::Color::Red)
+                    .bg(theme.failed)
                     .modifier(tui::style::Modifier::BOLD | tui::style::Modifier::DIM)
             };
             tui::widgets::Paragraph::new(
@@ -391,13 +3407,141 @@ impl tui::widgets::Widget for ProcessState {
             .style(style)
             .draw(status_chunk, buf);
         }
+
+        match (cursor, position) {
+            (Cursor::Active, position) => position,
+            (Cursor::Inactive, Some((x, y))) => {
+                let cell = buf.get_mut(x, y);
+                cell.style.modifier |= tui::style::Modifier::REVERSED | tui::style::Modifier::DIM;
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+fn size_info((width, height): (u16, u16)) -> terminal_emulator::term::SizeInfo {
+    terminal_emulator::term::SizeInfo {
+        width: f32::from(width),
+        height: f32::from(height),
+        cell_width: 1.0,
+        cell_height: 1.0,
+        padding_x: 0.0,
+        padding_y: 0.0,
+        dpr: 1.0,
+    }
+}
+
+fn host_name() -> Option<String> {
+    match sys::host::name() {
+        Ok(host) => Some(match sys::host::user() {
+            Some(user) => format!("{}@{}", user, host),
+            None => host,
+        }),
+        Err(err) => {
+            warn!("could not determine host name: {}", err);
+            None
+        }
+    }
+}
+
+/// A file name for the output of the pane with ID `id`; the argument is included to make it easy
+/// to find, but shortened and stripped of characters that could cause trouble in file names.
+fn pane_file_name(id: usize, arg: &str, extension: &str) -> String {
+    let arg = arg
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(64)
+        .collect::<String>();
+    format!("{}-{}.{}", id, arg, extension)
+}
+
+/// The (width, height) of the terminal of a process before its pane is drawn
+const DEFAULT_SIZE: (u16, u16) = (80, 24);
+
+/// How long a process has to be quiet to be highlighted, when turning on silence monitoring for
+/// its tab without a `silence` in the config
+const DEFAULT_SILENCE: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// How many notifications of a process wait to be passed on to the terminal
+const MAX_NOTIFICATIONS: usize = 16;
+
+/// How long a process has to wait between notifications that are passed on to the terminal
+const NOTIFICATION_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// The frames of the spinner shown next to running processes
+const SPINNER: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+
+/// The noun for `count` jobs
+fn jobs(count: usize) -> &'static str {
+    if count == 1 {
+        "job"
+    } else {
+        "jobs"
+    }
+}
+
+fn current_time() -> String {
+    chrono::Local::now().format("%H:%M").to_string()
+}
+
+fn format_duration(duration: std::time::Duration) -> String {
+    let seconds = duration.as_secs();
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// How long a process ran, as `mm:ss` or, after an hour, `h:mm:ss`.
+fn format_runtime(duration: std::time::Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds < 3600 {
+        format!("{:02}:{:02}", seconds / 60, seconds % 60)
+    } else {
+        format_duration(duration)
     }
 }
 
+/// How many columns `text` takes up in the chrome, counting every grapheme as one
+fn text_width(text: &str) -> u16 {
+    #[allow(clippy::cast_possible_truncation)]
+    let width = unicode_segmentation::UnicodeSegmentation::graphemes(text, true).count() as u16;
+    width
+}
+
 fn contains_point(rect: tui::layout::Rect, x: u16, y: u16) -> bool {
     rect.x <= x && rect.y <= y && rect.right() > x && rect.bottom() > y
 }
 
+/// Move a mouse event from the rows of the terminal to those of a viewport that starts at row
+/// `top`, or `None` if it happened above the viewport.
+fn viewport_event(event: &termion::event::Event, top: u16) -> Option<termion::event::Event> {
+    use termion::event::{Event, MouseEvent};
+
+    let row = |y: u16| if y > top { Some(y - top) } else { None };
+    match *event {
+        Event::Mouse(MouseEvent::Press(button, x, y)) => {
+            row(y).map(|y| Event::Mouse(MouseEvent::Press(button, x, y)))
+        }
+        Event::Mouse(MouseEvent::Release(x, y)) => {
+            row(y).map(|y| Event::Mouse(MouseEvent::Release(x, y)))
+        }
+        Event::Mouse(MouseEvent::Hold(x, y)) => {
+            row(y).map(|y| Event::Mouse(MouseEvent::Hold(x, y)))
+        }
+        ref event => Some(event.clone()),
+    }
+}
+
 fn mouse_event_coords(event: &termion::event::MouseEvent) -> (u16, u16) {
     match event {
         termion::event::MouseEvent::Press(_, x, y) => (x - 1, y - 1),
@@ -406,8 +3550,54 @@ fn mouse_event_coords(event: &termion::event::MouseEvent) -> (u16, u16) {
     }
 }
 
-fn convert_color(color: terminal_emulator::ansi::Color) -> tui::style::Color {
-    match color {
+/// Resolve a terminal color through the colors that the process of `term` set, then through the
+/// configured palette, and downgrade it to `depth`.
+fn convert_color(
+    color: terminal_emulator::ansi::Color,
+    term: &terminal_emulator::term::Term,
+    colors: &config::Colors,
+    depth: config::Depth,
+) -> tui::style::Color {
+    use terminal_emulator::ansi::NamedColor;
+
+    if let Some(rgb) = term.dynamic_color(color) {
+        return color::downgrade(tui::style::Color::Rgb(rgb.r, rgb.g, rgb.b), depth);
+    }
+
+    let configured = match color {
+        terminal_emulator::ansi::Color::Named(NamedColor::Foreground)
+        | terminal_emulator::ansi::Color::Named(NamedColor::BrightForeground)
+        | terminal_emulator::ansi::Color::Named(NamedColor::DimForeground) => colors.foreground,
+        terminal_emulator::ansi::Color::Named(NamedColor::Background) => colors.background,
+        terminal_emulator::ansi::Color::Named(NamedColor::Cursor) => colors.cursor,
+        terminal_emulator::ansi::Color::Named(NamedColor::CursorText) => colors.cursor_text,
+        terminal_emulator::ansi::Color::Named(named) => {
+            let index = named as usize;
+            if index < 8 {
+                colors.normal.get(index)
+            } else if index < 16 {
+                colors.bright.get(index - 8)
+            } else if index >= NamedColor::DimBlack as usize {
+                // Without a dim color, the normal one is dimmed by the terminal
+                let index = index - NamedColor::DimBlack as usize;
+                colors.dim.get(index).or_else(|| colors.normal.get(index))
+            } else {
+                None
+            }
+        }
+        terminal_emulator::ansi::Color::Indexed(index) if index < 8 => {
+            colors.normal.get(usize::from(index))
+        }
+        terminal_emulator::ansi::Color::Indexed(index) if index < 16 => {
+            colors.bright.get(usize::from(index) - 8)
+        }
+        _ => None,
+    };
+    if let Some(config::Color(color)) = configured {
+        return color::downgrade(color, depth);
+    }
+
+    let color = match color {
         terminal_emulator::ansi::Color::Named(named) => match named {
             terminal_emulator::ansi::NamedColor::Black => tui::style::Color::Black,
             terminal_emulator::ansi::NamedColor::Red => tui::style::Color::Red,
@@ -444,9 +3634,41 @@ fn convert_color(color: terminal_emulator::ansi::Color) -> tui::style::Color {
             tui::style::Color::Rgb(color.r, color.g, color.b)
         }
         terminal_emulator::ansi::Color::Indexed(i) => tui::style::Color::Indexed(i),
+    };
+    color::downgrade(color, depth)
+}
+
+/// Whether `color` is a dim color that resolves to a color of its own, set by the process of
+/// `term` or in `[colors.dim]`, which the terminal that mux runs in shouldn't dim again.
+fn has_dim_color(
+    color: terminal_emulator::ansi::Color,
+    term: &terminal_emulator::term::Term,
+    colors: &config::Colors,
+) -> bool {
+    use terminal_emulator::ansi::NamedColor;
+
+    match color {
+        terminal_emulator::ansi::Color::Named(named) if named.is_dim() => {
+            term.dynamic_color(color).is_some()
+                || (named != NamedColor::DimForeground
+                    && colors
+                        .dim
+                        .get(named as usize - NamedColor::DimBlack as usize)
+                        .is_some())
+        }
+        _ => false,
     }
 }
 
+/// The character of a cell together with the zero-width characters combined with it
+fn cell_symbol(chars: &[char]) -> String {
+    let mut symbol = String::new();
+    symbol.push(chars[0]);
+    // Unused slots for zero-width characters are filled with spaces
+    symbol.extend(chars[1..].iter().take_while(|&&c| c != ' '));
+    symbol
+}
+
 fn convert_flags(flags: terminal_emulator::term::cell::Flags) -> tui::style::Modifier {
     let mut result = tui::style::Modifier::empty();
 
@@ -459,7 +3681,8 @@ fn convert_flags(flags: terminal_emulator::term::cell::Flags) -> tui::style::Mod
     if flags.contains(terminal_emulator::term::cell::Flags::ITALIC) {
         result.insert(tui::style::Modifier::ITALIC);
     }
-    if flags.contains(terminal_emulator::term::cell::Flags::UNDERLINE) {
+    // The terminal that mux runs in gets plain underlines, whatever their style and color
+    if flags.intersects(terminal_emulator::term::cell::Flags::ALL_UNDERLINES) {
         result.insert(tui::style::Modifier::UNDERLINED);
     }
     if flags.contains(terminal_emulator::term::cell::Flags::DIM) {
@@ -471,6 +3694,10 @@ fn convert_flags(flags: terminal_emulator::term::cell::Flags) -> tui::style::Mod
     if flags.contains(terminal_emulator::term::cell::Flags::STRIKEOUT) {
         result.insert(tui::style::Modifier::CROSSED_OUT);
     }
+    // The terminal that mux runs in blinks the text itself
+    if flags.contains(terminal_emulator::term::cell::Flags::BLINK) {
+        result.insert(tui::style::Modifier::SLOW_BLINK);
+    }
 
     result
 }
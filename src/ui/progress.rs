@@ -0,0 +1,120 @@
+/// How many of the most recent lines of a process's output are searched for progress
+pub const LINES: usize = 2;
+
+/// The width of the bar drawn in the tab titles
+const BAR_WIDTH: usize = 5;
+
+/// The last progress figure in `text` as a percentage, from either a percentage like `42%` or
+/// a count like `3/10`.
+pub fn parse(text: &str) -> Option<u8> {
+    let bytes = text.as_bytes();
+    let number_end = |start: usize| {
+        (start..bytes.len())
+            .find(|&i| !bytes[i].is_ascii_digit())
+            .unwrap_or_else(|| bytes.len())
+    };
+
+    let mut progress = None;
+    let mut i = 0;
+    while i < bytes.len() {
+        if !bytes[i].is_ascii_digit() {
+            i += 1;
+            continue;
+        }
+        if i > 0 && bytes[i - 1].is_ascii_alphabetic() {
+            // Part of a word like `x86`
+            i = number_end(i);
+            continue;
+        }
+
+        let start = i;
+        i = number_end(i);
+        // Allow a fraction like `42.5%`
+        if bytes.get(i) == Some(&b'.') && bytes.get(i + 1).map_or(false, u8::is_ascii_digit) {
+            i = number_end(i + 1);
+        }
+        let number = &text[start..i];
+
+        match bytes.get(i) {
+            Some(b'%') => {
+                if let Ok(percent) = number.parse::<f64>() {
+                    if percent <= 100.0 {
+                        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                        let percent = percent as u8;
+                        progress = Some(percent);
+                    }
+                }
+            }
+            Some(b'/') => {
+                let end = number_end(i + 1);
+                if let (Ok(done), Ok(total)) =
+                    (number.parse::<u64>(), text[i + 1..end].parse::<u64>())
+                {
+                    if total > 0 && done <= total {
+                        // Counts can be large enough to overflow when multiplied in a u64
+                        #[allow(clippy::cast_possible_truncation)]
+                        let percent = (u128::from(done) * 100 / u128::from(total)) as u8;
+                        progress = Some(percent);
+                    }
+                }
+                i = end;
+            }
+            _ => {}
+        }
+    }
+    progress
+}
+
+/// A small bar and figure for `percent`, like `██▌   42%`.
+pub fn bar(percent: u8) -> String {
+    const PARTS: [char; 8] = [' ', '▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+
+    let eighths = usize::from(percent) * BAR_WIDTH * 8 / 100;
+    let mut bar = String::with_capacity(BAR_WIDTH * 3 + 5);
+    for cell in 0..BAR_WIDTH {
+        let filled = eighths.saturating_sub(cell * 8).min(8);
+        bar.push(if filled == 8 { '█' } else { PARTS[filled] });
+    }
+    bar.push_str(&format!(" {:>3}%", percent));
+    bar
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_percentages() {
+        assert_eq!(parse("downloading 42% done"), Some(42));
+        assert_eq!(parse("100%"), Some(100));
+        assert_eq!(parse("142%"), None);
+    }
+
+    #[test]
+    fn parses_fractional_percentages() {
+        assert_eq!(parse("42.5%"), Some(42));
+    }
+
+    #[test]
+    fn parses_counts() {
+        assert_eq!(parse("[3/10] compiling"), Some(30));
+        assert_eq!(parse("0/0"), None);
+        assert_eq!(parse("11/10"), None);
+    }
+
+    #[test]
+    fn parses_the_last_figure() {
+        assert_eq!(parse("step 1/4: 75%"), Some(75));
+    }
+
+    #[test]
+    fn ignores_numbers_in_words() {
+        assert_eq!(parse("building for x86"), None);
+        assert_eq!(parse("x86/x64 50%"), Some(50));
+    }
+
+    #[test]
+    fn parses_counts_too_large_to_multiply() {
+        assert_eq!(parse("180000000000000000/200000000000000000"), Some(90));
+    }
+}
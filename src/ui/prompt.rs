@@ -0,0 +1,47 @@
+/// A line where the user types some text, like the pattern to go to a pane.
+#[derive(Default)]
+pub struct Prompt<'a> {
+    label: &'a str,
+    text: &'a str,
+    message: Option<&'a str>,
+    style: tui::style::Style,
+}
+
+impl<'a> Prompt<'a> {
+    pub fn label(mut self, label: &'a str) -> Self {
+        self.label = label;
+        self
+    }
+
+    pub fn text(mut self, text: &'a str) -> Self {
+        self.text = text;
+        self
+    }
+
+    pub fn message(mut self, message: Option<&'a str>) -> Self {
+        self.message = message;
+        self
+    }
+
+    pub fn style(mut self, style: tui::style::Style) -> Self {
+        self.style = style;
+        self
+    }
+}
+
+impl<'a> tui::widgets::Widget for Prompt<'a> {
+    fn draw(&mut self, area: tui::layout::Rect, buf: &mut tui::buffer::Buffer) {
+        if area.area() == 0 {
+            return;
+        }
+
+        self.background(area, buf, self.style.bg);
+
+        let mut line = format!("{} {}█", self.label, self.text);
+        if let Some(message) = self.message {
+            line.push_str("  ");
+            line.push_str(message);
+        }
+        buf.set_stringn(area.x, area.y, line, area.width as usize, self.style);
+    }
+}
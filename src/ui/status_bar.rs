@@ -0,0 +1,65 @@
+#[derive(Default)]
+pub struct StatusBar<'a> {
+    left: Vec<tui::widgets::Text<'a>>,
+    right: Vec<tui::widgets::Text<'a>>,
+    style: tui::style::Style,
+}
+
+impl<'a> StatusBar<'a> {
+    pub fn left(mut self, segments: Vec<tui::widgets::Text<'a>>) -> Self {
+        self.left = segments;
+        self
+    }
+
+    pub fn right(mut self, segments: Vec<tui::widgets::Text<'a>>) -> Self {
+        self.right = segments;
+        self
+    }
+
+    pub fn style(mut self, style: tui::style::Style) -> Self {
+        self.style = style;
+        self
+    }
+
+    fn segment<'b>(&self, segment: &'b tui::widgets::Text<'a>) -> (&'b str, tui::style::Style) {
+        match *segment {
+            tui::widgets::Text::Raw(ref string) => (&**string, self.style),
+            tui::widgets::Text::Styled(ref string, style) => (&**string, style),
+        }
+    }
+}
+
+impl<'a> tui::widgets::Widget for StatusBar<'a> {
+    fn draw(&mut self, area: tui::layout::Rect, buf: &mut tui::buffer::Buffer) {
+        if area.area() == 0 {
+            return;
+        }
+
+        self.background(area, buf, self.style.bg);
+
+        let mut left = area.left();
+        for segment in &self.left {
+            let (string, style) = self.segment(segment);
+            let width = super::text_width(string);
+
+            if left >= area.right() {
+                break;
+            }
+            buf.set_stringn(left, area.y, string, (area.right() - left) as usize, style);
+            left = left.saturating_add(width + 1);
+        }
+
+        let mut right = area.right();
+        for segment in &self.right {
+            let (string, style) = self.segment(segment);
+            let width = super::text_width(string);
+
+            if right < left + width {
+                break;
+            }
+            right -= width;
+            buf.set_string(right, area.y, string, style);
+            right = right.saturating_sub(1);
+        }
+    }
+}
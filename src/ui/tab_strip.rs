@@ -0,0 +1,134 @@
+/// A single line of tabs, used instead of `VerticalTabs` when there is little room.
+#[derive(Default)]
+pub struct TabStrip<'a> {
+    titles: Vec<&'a str>,
+    selected: usize,
+    style: tui::style::Style,
+    highlight_style: tui::style::Style,
+    truncate: super::Truncate,
+}
+
+/// The widest a single tab is allowed to be, including padding
+const MAX_TAB_WIDTH: u16 = 24;
+
+impl<'a> TabStrip<'a> {
+    pub fn titles(mut self, titles: Vec<&'a str>) -> Self {
+        self.titles = titles;
+        self
+    }
+
+    pub fn select(mut self, selected: usize) -> Self {
+        self.selected = selected;
+        self
+    }
+
+    pub fn style(mut self, style: tui::style::Style) -> Self {
+        self.style = style;
+        self
+    }
+
+    pub fn highlight_style(mut self, style: tui::style::Style) -> Self {
+        self.highlight_style = style;
+        self
+    }
+
+    pub fn truncate(mut self, truncate: super::Truncate) -> Self {
+        self.truncate = truncate;
+        self
+    }
+
+    /// Returns the index of the tab that was clicked, if any.
+    pub fn on_mouse_event(
+        &self,
+        area: tui::layout::Rect,
+        event: &termion::event::MouseEvent,
+    ) -> Option<usize> {
+        let (x, y) = super::mouse_event_coords(event);
+
+        self.layout(area)
+            .into_iter()
+            .find(|&(_, tab_area)| super::contains_point(tab_area, x, y))
+            .map(|(i, _)| i)
+    }
+
+    fn tab_width(&self, index: usize) -> u16 {
+        super::text_width(self.titles[index])
+            .saturating_add(2)
+            .min(MAX_TAB_WIDTH)
+    }
+
+    /// The visible tabs and where they are drawn; tabs are scrolled so that the selected one is
+    /// always visible.
+    fn layout(&self, area: tui::layout::Rect) -> Vec<(usize, tui::layout::Rect)> {
+        if self.titles.is_empty() || area.area() == 0 {
+            return Vec::new();
+        }
+
+        let selected = self.selected.min(self.titles.len() - 1);
+
+        // Walk backwards from the selected tab to find the first one that still fits
+        let mut first = selected;
+        let mut used = self.tab_width(selected);
+        while first > 0 && used + 1 + self.tab_width(first - 1) <= area.width {
+            first -= 1;
+            used += 1 + self.tab_width(first);
+        }
+
+        let mut result = Vec::new();
+        let mut x = area.left();
+        for index in first..self.titles.len() {
+            if x >= area.right() {
+                break;
+            }
+            let width = self.tab_width(index).min(area.right() - x);
+            result.push((
+                index,
+                tui::layout::Rect {
+                    x,
+                    y: area.y,
+                    width,
+                    height: 1,
+                },
+            ));
+            x = x.saturating_add(width + 1);
+        }
+        result
+    }
+}
+
+impl<'a> tui::widgets::Widget for TabStrip<'a> {
+    fn draw(&mut self, area: tui::layout::Rect, buf: &mut tui::buffer::Buffer) {
+        if area.area() == 0 {
+            return;
+        }
+
+        self.background(area, buf, self.style.bg);
+
+        for (index, tab_area) in self.layout(area) {
+            let style = if index == self.selected {
+                self.highlight_style
+            } else {
+                self.style
+            };
+
+            if tab_area.width > 2 {
+                let text_area = tui::layout::Rect {
+                    x: tab_area.x + 1,
+                    width: tab_area.width - 2,
+                    ..tab_area
+                };
+                super::vertical_tabs::Title::default()
+                    .text(self.titles[index])
+                    .style(style)
+                    .truncate(self.truncate)
+                    .draw(text_area, buf);
+            }
+
+            if tab_area.right() < area.right() {
+                buf.get_mut(tab_area.right(), area.y)
+                    .set_symbol("│")
+                    .set_style(self.style);
+            }
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! Running the UI as the only owner of its state: one task takes the events one at a time and
+//! hands out the actions for the processes, so that nothing has to lock the UI.
+use std::collections;
+use std::time;
+
+use super::{Action, Event, Ui};
+
+/// How often to check whether the terminal was resized or the clock moved on
+const TIMER_INTERVAL: time::Duration = time::Duration::from_millis(10);
+
+/// How many events to handle before giving the other tasks on the thread a turn
+const EVENTS_PER_POLL: usize = 64;
+
+/// A stream of the actions that the events cause; the UI is handed back through the receiver
+/// from `new` once the stream is dropped, for the report at the end.
+pub struct Task<B, S>
+where
+    B: tui::backend::Backend + std::io::Write,
+{
+    ui: Option<Ui<B>>,
+    events: S,
+    timer: tokio::timer::Interval,
+    /// Whether a client attached to the session since the last call
+    attached: Box<dyn Fn() -> bool + Send>,
+    actions: collections::VecDeque<Action>,
+    done: Option<futures::sync::oneshot::Sender<Ui<B>>>,
+}
+
+impl<B, S> Task<B, S>
+where
+    B: tui::backend::Backend + std::io::Write + 'static,
+    S: futures::stream::Stream<Item = Event, Error = failure::Error>,
+{
+    pub fn new(
+        ui: Ui<B>,
+        events: S,
+        attached: Box<dyn Fn() -> bool + Send>,
+    ) -> (Self, futures::sync::oneshot::Receiver<Ui<B>>) {
+        let (done, receiver) = futures::sync::oneshot::channel();
+        let task = Self {
+            ui: Some(ui),
+            events,
+            timer: tokio::timer::Interval::new_interval(TIMER_INTERVAL),
+            attached,
+            actions: collections::VecDeque::new(),
+            done: Some(done),
+        };
+        (task, receiver)
+    }
+
+    /// The event that the passing time causes, if any.
+    fn timer_event(ui: &mut Ui<B>, attached: &dyn Fn() -> bool) -> Option<Event> {
+        if attached() {
+            // The new client's screen starts out empty
+            if let Err(err) = ui.redraw() {
+                warn!("could not draw for the attached client: {}", err);
+            }
+            Some(Event::Resized)
+        } else if ui.check_resized() {
+            Some(Event::Resized)
+        } else if ui.check_tick() {
+            Some(Event::Tick)
+        } else {
+            None
+        }
+    }
+}
+
+impl<B, S> futures::stream::Stream for Task<B, S>
+where
+    B: tui::backend::Backend + std::io::Write + 'static,
+    S: futures::stream::Stream<Item = Event, Error = failure::Error>,
+{
+    type Item = Action;
+    type Error = failure::Error;
+
+    fn poll(&mut self) -> futures::Poll<Option<Action>, failure::Error> {
+        use futures::Async;
+
+        for _ in 0..EVENTS_PER_POLL {
+            if let Some(action) = self.actions.pop_front() {
+                return Ok(Async::Ready(Some(action)));
+            }
+
+            let ui = match self.ui {
+                Some(ref mut ui) => ui,
+                None => return Ok(Async::Ready(None)),
+            };
+            let event = match self.timer.poll()? {
+                Async::Ready(_) => match Self::timer_event(ui, &*self.attached) {
+                    Some(event) => event,
+                    None => continue,
+                },
+                Async::NotReady => match self.events.poll()? {
+                    Async::Ready(Some(event)) => event,
+                    Async::Ready(None) => return Ok(Async::Ready(None)),
+                    Async::NotReady => return Ok(Async::NotReady),
+                },
+            };
+            self.actions.extend(ui.on_event(&event)?);
+        }
+
+        // Busy processes would otherwise keep the thread to themselves
+        futures::task::current().notify();
+        Ok(Async::NotReady)
+    }
+}
+
+impl<B, S> Drop for Task<B, S>
+where
+    B: tui::backend::Backend + std::io::Write,
+{
+    fn drop(&mut self) {
+        if let (Some(ui), Some(done)) = (self.ui.take(), self.done.take()) {
+            // Nobody waiting for the UI is fine, it restores the terminal when dropped
+            let _ = done.send(ui);
+        }
+    }
+}
@@ -0,0 +1,92 @@
+/// How the processes are arranged on screen
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Tiling {
+    /// One process at a time, with a list of tabs to switch between them
+    Tabs,
+    /// All processes at once, side by side
+    EvenHorizontal,
+    /// All processes at once, stacked on top of each other
+    EvenVertical,
+    /// All processes at once, in a grid of panes
+    Grid,
+}
+
+impl Tiling {
+    /// The tiling to switch to when the user cycles through them
+    pub fn next(self) -> Self {
+        match self {
+            Tiling::Tabs => Tiling::EvenHorizontal,
+            Tiling::EvenHorizontal => Tiling::EvenVertical,
+            Tiling::EvenVertical => Tiling::Grid,
+            Tiling::Grid => Tiling::Tabs,
+        }
+    }
+
+    /// Split `area` into one pane per process, or `None` if only one process is shown at a time.
+    pub fn panes(self, area: tui::layout::Rect, count: usize) -> Option<Vec<tui::layout::Rect>> {
+        match self {
+            Tiling::Tabs => None,
+            Tiling::EvenHorizontal => Some(
+                (0..count)
+                    .map(|i| {
+                        let (x, width) = split(area.x, area.width, i, count);
+                        tui::layout::Rect { x, width, ..area }
+                    })
+                    .collect(),
+            ),
+            Tiling::EvenVertical => Some(
+                (0..count)
+                    .map(|i| {
+                        let (y, height) = split(area.y, area.height, i, count);
+                        tui::layout::Rect { y, height, ..area }
+                    })
+                    .collect(),
+            ),
+            Tiling::Grid => Some(grid(area, count)),
+        }
+    }
+}
+
+impl Default for Tiling {
+    fn default() -> Self {
+        Tiling::Tabs
+    }
+}
+
+/// Split `area` into `count` panes in a grid that is roughly as wide as it is tall.
+fn grid(area: tui::layout::Rect, count: usize) -> Vec<tui::layout::Rect> {
+    if count == 0 {
+        return Vec::new();
+    }
+
+    let columns = (1..=count).find(|c| c * c >= count).unwrap_or(count);
+    let rows = (count + columns - 1) / columns;
+
+    (0..rows)
+        .flat_map(|row| {
+            let (y, height) = split(area.y, area.height, row, rows);
+            // The last row might not be full; let its panes share the full width
+            let in_row = columns.min(count - row * columns);
+            (0..in_row).map(move |column| {
+                let (x, width) = split(area.x, area.width, column, in_row);
+                tui::layout::Rect {
+                    x,
+                    y,
+                    width,
+                    height,
+                }
+            })
+        })
+        .collect()
+}
+
+/// The offset and length of part `index` when splitting `length` cells starting at `start` into
+/// `parts` roughly equal parts.
+fn split(start: u16, length: u16, index: usize, parts: usize) -> (u16, u16) {
+    let length = length as usize;
+    let from = length * index / parts;
+    let to = length * (index + 1) / parts;
+    #[allow(clippy::cast_possible_truncation)]
+    let result = (start + from as u16, (to - from) as u16);
+    result
+}
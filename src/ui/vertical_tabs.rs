@@ -6,13 +6,25 @@ pub struct VerticalTabs<'a> {
     scroll: usize,
     style: tui::style::Style,
     highlight_style: tui::style::Style,
+    truncate: Truncate,
 }
 
 #[derive(Default)]
 pub struct Title<'a> {
-    text: &'a str,
+    text: std::borrow::Cow<'a, str>,
     symbols: Vec<tui::widgets::Text<'a>>,
     style: tui::style::Style,
+    truncate: Truncate,
+    /// Whether this is the header of a group of tabs, which is drawn in bold
+    header: bool,
+}
+
+/// Which part of a title to cut off when it doesn't fit
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Truncate {
+    Start,
+    Middle,
+    End,
 }
 
 pub enum MouseAction {
@@ -59,6 +71,21 @@ impl<'a> VerticalTabs<'a> {
         self
     }
 
+    pub fn truncate(mut self, truncate: Truncate) -> Self {
+        self.truncate = truncate;
+        self
+    }
+
+    /// The width needed to show the longest title in full
+    pub fn width(&self) -> u16 {
+        let area = tui::layout::Rect::new(0, 0, 100, 3);
+        let border = match self.block {
+            Some(ref b) => area.width - b.inner(area).width,
+            None => 0,
+        };
+        self.titles.iter().map(Title::width).max().unwrap_or(0) + border
+    }
+
     fn has_scroll_up(&self, _area: tui::layout::Rect) -> bool {
         self.scroll > 0
     }
@@ -172,11 +199,14 @@ impl<'a> tui::widgets::Widget for VerticalTabs<'a> {
         self.background(select_area, buf, self.style.bg);
 
         for (i, title) in self.titles.iter_mut().enumerate() {
-            let style = if i == self.selected {
+            let mut style = if i == self.selected {
                 self.highlight_style
             } else {
                 self.style
             };
+            if title.header {
+                style.modifier |= tui::style::Modifier::BOLD;
+            }
             let title_area = tui::layout::Rect {
                 x: select_area.x,
                 y: select_area.y + (i as isize - self.scroll as isize).max(0) as u16,
@@ -184,6 +214,7 @@ impl<'a> tui::widgets::Widget for VerticalTabs<'a> {
                 height: 1,
             };
             title.style = style;
+            title.truncate = self.truncate;
             if select_area.intersects(title_area) {
                 title.draw(title_area, buf);
             }
@@ -192,8 +223,8 @@ impl<'a> tui::widgets::Widget for VerticalTabs<'a> {
 }
 
 impl<'a> Title<'a> {
-    pub fn text(mut self, text: &'a str) -> Self {
-        self.text = text;
+    pub fn text(mut self, text: impl Into<std::borrow::Cow<'a, str>>) -> Self {
+        self.text = text.into();
         self
     }
 
@@ -206,6 +237,29 @@ impl<'a> Title<'a> {
         self.style = style;
         self
     }
+
+    pub fn truncate(mut self, truncate: Truncate) -> Self {
+        self.truncate = truncate;
+        self
+    }
+
+    pub fn header(mut self, header: bool) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// The width of the text and symbols, including the space before each symbol
+    fn width(&self) -> u16 {
+        let symbols =
+            self.symbols
+                .iter()
+                .map(|symbol| match *symbol {
+                    tui::widgets::Text::Raw(ref string)
+                    | tui::widgets::Text::Styled(ref string, _) => super::text_width(string) + 1,
+                })
+                .sum::<u16>();
+        super::text_width(&self.text) + symbols
+    }
 }
 
 impl<'a> tui::widgets::Widget for Title<'a> {
@@ -231,19 +285,35 @@ impl<'a> tui::widgets::Widget for Title<'a> {
             area.width -= char_count as u16 + 1;
         }
 
-        if unicode_segmentation::UnicodeSegmentation::graphemes(self.text, true).count()
-            <= area.width as usize
-        {
-            buf.set_stringn(area.x, area.y, self.text, area.width as usize, self.style);
+        if area.width == 0 {
+            return;
+        }
+
+        let graphemes = unicode_segmentation::UnicodeSegmentation::graphemes(&*self.text, true)
+            .collect::<Vec<_>>();
+        let width = area.width as usize;
+
+        if graphemes.len() <= width {
+            buf.set_stringn(area.x, area.y, &self.text, width, self.style);
         } else {
-            buf.set_stringn(
-                area.x,
-                area.y,
-                self.text,
-                area.width as usize - 1,
-                self.style,
+            let keep = width - 1;
+            let (head, tail) = match self.truncate {
+                Truncate::Start => (0, keep),
+                Truncate::Middle => (keep - keep / 2, keep / 2),
+                Truncate::End => (keep, 0),
+            };
+            let text = format!(
+                "{}…{}",
+                graphemes[..head].concat(),
+                graphemes[graphemes.len() - tail..].concat()
             );
-            buf.set_string(area.right() - 1, area.y, "…", self.style);
+            buf.set_stringn(area.x, area.y, text, width, self.style);
         }
     }
 }
+
+impl Default for Truncate {
+    fn default() -> Self {
+        Truncate::End
+    }
+}
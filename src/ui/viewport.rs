@@ -0,0 +1,207 @@
+use std::io;
+use std::sync;
+
+/// A backend that either draws to the whole terminal, or to an inline region of the normal
+/// screen, leaving whatever is above it untouched.
+pub struct Viewport<B>
+where
+    B: tui::backend::Backend + io::Write,
+{
+    backend: B,
+    inline: Option<Inline>,
+    /// The size of a terminal that isn't the one `backend` would ask, like a session's client
+    remote: Option<sync::Arc<sync::Mutex<tui::layout::Rect>>>,
+}
+
+/// How many rows an inline viewport takes up
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Height {
+    Lines(u16),
+    /// A percentage of the terminal height
+    Percent(u16),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Inline {
+    /// The terminal row where the viewport starts
+    top: u16,
+    height: u16,
+}
+
+impl<B> Viewport<B>
+where
+    B: tui::backend::Backend + io::Write,
+{
+    pub fn fullscreen(backend: B) -> Self {
+        let inline = None;
+        let remote = None;
+        Self {
+            backend,
+            inline,
+            remote,
+        }
+    }
+
+    /// Draw to the whole terminal of an attached client, whose size is kept up to date in `size`.
+    pub fn remote(backend: B, size: sync::Arc<sync::Mutex<tui::layout::Rect>>) -> Self {
+        let inline = None;
+        let remote = Some(size);
+        Self {
+            backend,
+            inline,
+            remote,
+        }
+    }
+
+    /// Draw to the `height` rows starting at the cursor row, scrolling the terminal up if there
+    /// isn't enough room below the cursor.
+    pub fn inline(mut backend: B, cursor_row: u16, height: Height) -> io::Result<Self> {
+        let size = backend.size()?;
+        let height = height.rows(size.height);
+
+        // Make room by moving the cursor down, which scrolls the screen once at the bottom
+        write!(backend, "\r")?;
+        for _ in 1..height {
+            writeln!(backend)?;
+        }
+        io::Write::flush(&mut backend)?;
+
+        let top = cursor_row.min(size.height.saturating_sub(height));
+        let inline = Some(Inline { top, height });
+        let remote = None;
+        Ok(Self {
+            backend,
+            inline,
+            remote,
+        })
+    }
+
+    /// The terminal row where the viewport starts, which is 0 unless it is drawn inline.
+    pub fn top(&self) -> io::Result<u16> {
+        self.area().map(|(top, _)| top)
+    }
+
+    /// The row offset and size of the viewport, accounting for the terminal having been resized.
+    fn area(&self) -> io::Result<(u16, tui::layout::Rect)> {
+        let mut size = match self.remote {
+            Some(ref size) => *size.lock().unwrap(),
+            None => self.backend.size()?,
+        };
+        match self.inline {
+            Some(Inline { top, height }) => {
+                let height = height.min(size.height);
+                let top = top.min(size.height.saturating_sub(height));
+                size.height = height;
+                Ok((top, size))
+            }
+            None => Ok((0, size)),
+        }
+    }
+}
+
+impl Height {
+    /// The number of rows to use in a terminal with `total` rows
+    pub fn rows(self, total: u16) -> u16 {
+        let rows = match self {
+            Height::Lines(lines) => lines,
+            #[allow(clippy::cast_possible_truncation)]
+            Height::Percent(percent) => (u32::from(total) * u32::from(percent) / 100) as u16,
+        };
+        rows.min(total).max(1)
+    }
+}
+
+impl<B> tui::backend::Backend for Viewport<B>
+where
+    B: tui::backend::Backend + io::Write,
+{
+    fn draw<'a, I>(&mut self, content: I) -> io::Result<()>
+    where
+        I: Iterator<Item = (u16, u16, &'a tui::buffer::Cell)>,
+    {
+        let (top, area) = self.area()?;
+        self.backend.draw(
+            content
+                .filter(move |&(_, y, _)| y < area.height)
+                .map(move |(x, y, cell)| (x, y + top, cell)),
+        )
+    }
+
+    fn hide_cursor(&mut self) -> io::Result<()> {
+        self.backend.hide_cursor()
+    }
+
+    fn show_cursor(&mut self) -> io::Result<()> {
+        self.backend.show_cursor()
+    }
+
+    fn get_cursor(&mut self) -> io::Result<(u16, u16)> {
+        let (top, _) = self.area()?;
+        let (x, y) = self.backend.get_cursor()?;
+        Ok((x, y.saturating_sub(top)))
+    }
+
+    fn set_cursor(&mut self, x: u16, y: u16) -> io::Result<()> {
+        let (top, _) = self.area()?;
+        self.backend.set_cursor(x, y + top)
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        if self.inline.is_some() {
+            // Only clear our own rows, not the shell history above them
+            let (top, area) = self.area()?;
+            for y in top..top + area.height {
+                write!(
+                    self.backend,
+                    "{}{}",
+                    termion::cursor::Goto(1, y + 1),
+                    termion::clear::CurrentLine
+                )?;
+            }
+            Ok(())
+        } else {
+            self.backend.clear()
+        }
+    }
+
+    fn size(&self) -> io::Result<tui::layout::Rect> {
+        self.area().map(|(_, area)| area)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        tui::backend::Backend::flush(&mut self.backend)
+    }
+}
+
+impl<B> io::Write for Viewport<B>
+where
+    B: tui::backend::Backend + io::Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.backend.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::Write::flush(&mut self.backend)
+    }
+}
+
+impl<B> Drop for Viewport<B>
+where
+    B: tui::backend::Backend + io::Write,
+{
+    fn drop(&mut self) {
+        if self.inline.is_some() {
+            // Leave the cursor where the viewport was so that the shell prompt continues there
+            if let Ok((top, _)) = self.area() {
+                let _ = write!(
+                    self.backend,
+                    "{}{}",
+                    termion::cursor::Goto(1, top + 1),
+                    termion::clear::AfterCursor
+                );
+                let _ = io::Write::flush(&mut self.backend);
+            }
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! Posting events about the run as JSON to HTTP endpoints, so that bots and dashboards can follow
+//! along.
+//!
+//! Requests are made from a background thread so that slow endpoints never hold up the UI; each
+//! one is retried a few times with increasing delays before giving up on it.
+use std::process;
+use std::sync;
+use std::sync::mpsc;
+use std::thread;
+use std::time;
+
+/// How many times to try delivering an event before giving up
+const ATTEMPTS: u32 = 4;
+
+pub struct Webhook {
+    sender: mpsc::Sender<serde_json::Value>,
+    thread: thread::JoinHandle<()>,
+    progress: sync::Mutex<Progress>,
+    failure_threshold: Option<usize>,
+}
+
+#[derive(Default)]
+struct Progress {
+    total: usize,
+    succeeded: usize,
+    failed: usize,
+    threshold_reached: bool,
+    completed: bool,
+}
+
+impl Webhook {
+    pub fn new(
+        urls: Vec<String>,
+        failure_threshold: Option<usize>,
+    ) -> Result<Self, failure::Error> {
+        let client = reqwest::Client::builder()
+            .timeout(time::Duration::from_secs(10))
+            .build()?;
+        let (sender, receiver) = mpsc::channel();
+        let thread = thread::Builder::new()
+            .name("webhook".to_owned())
+            .spawn(move || {
+                for event in receiver {
+                    for url in &urls {
+                        deliver(&client, url, &event);
+                    }
+                }
+            })?;
+        let progress = sync::Mutex::new(Progress::default());
+
+        Ok(Self {
+            sender,
+            thread,
+            progress,
+            failure_threshold,
+        })
+    }
+
+    pub fn run_started(&self, command: &str, args: &[String]) {
+        self.progress.lock().unwrap().total = args.len();
+        self.post(json!({
+            "event": "run_started",
+            "command": command,
+            "args": args,
+        }));
+    }
+
+    pub fn item_finished(&self, pane: usize, arg: &str, status: process::ExitStatus) {
+        let mut progress = self.progress.lock().unwrap();
+        if status.success() {
+            progress.succeeded += 1;
+        } else {
+            progress.failed += 1;
+        }
+
+        self.post(json!({
+            "event": "item_finished",
+            "pane": pane,
+            "arg": arg,
+            "success": status.success(),
+            "exit_code": status.code(),
+            "finished": progress.succeeded + progress.failed,
+            "total": progress.total,
+        }));
+
+        if let Some(threshold) = self.failure_threshold {
+            if progress.failed >= threshold && !progress.threshold_reached {
+                progress.threshold_reached = true;
+                self.post(json!({
+                    "event": "failure_threshold_reached",
+                    "failed": progress.failed,
+                    "threshold": threshold,
+                }));
+            }
+        }
+
+        if progress.succeeded + progress.failed == progress.total {
+            self.complete(&mut progress);
+        }
+    }
+
+    /// Report that the run is over, even if the user quit before all processes were done, and
+    /// wait for the remaining events to be delivered.
+    pub fn finish(self) {
+        self.complete(&mut self.progress.lock().unwrap());
+
+        let Self { sender, thread, .. } = self;
+        drop(sender);
+        if thread.join().is_err() {
+            warn!("the webhook thread panicked");
+        }
+    }
+
+    fn complete(&self, progress: &mut Progress) {
+        if progress.completed {
+            return;
+        }
+        progress.completed = true;
+
+        self.post(json!({
+            "event": "run_completed",
+            "total": progress.total,
+            "succeeded": progress.succeeded,
+            "failed": progress.failed,
+            "running": progress.total - progress.succeeded - progress.failed,
+        }));
+    }
+
+    fn post(&self, event: serde_json::Value) {
+        if self.sender.send(event).is_err() {
+            warn!("the webhook thread is gone, dropping event");
+        }
+    }
+}
+
+fn deliver(client: &reqwest::Client, url: &str, event: &serde_json::Value) {
+    let mut delay = time::Duration::from_millis(500);
+
+    for attempt in 1..=ATTEMPTS {
+        match client.post(url).json(event).send() {
+            Ok(ref response) if response.status().is_success() => return,
+            Ok(response) => warn!(
+                "webhook {} answered {} (attempt {} of {})",
+                url,
+                response.status(),
+                attempt,
+                ATTEMPTS
+            ),
+            Err(err) => warn!(
+                "webhook {} failed: {} (attempt {} of {})",
+                url, err, attempt, ATTEMPTS
+            ),
+        }
+
+        if attempt < ATTEMPTS {
+            thread::sleep(delay);
+            delay *= 2;
+        }
+    }
+
+    error!(
+        "giving up on delivering {} to webhook {}",
+        event["event"], url
+    );
+}
@@ -0,0 +1,56 @@
+//! Record a ref test for `tests/ref.rs` from the output of a program, captured for example with
+//! `script`, by running it through a terminal of the given size and saving the grid it leaves:
+//!
+//! ```text
+//! cargo run --features serde --example record -- OUTPUT COLUMNS LINES tests/ref/NAME
+//! ```
+//!
+//! The test then needs to be added to the list in `tests/ref.rs`.
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process;
+
+use terminal_emulator::term::{SizeInfo, Term};
+use terminal_emulator::Processor;
+
+fn main() -> io::Result<()> {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    if args.len() != 4 {
+        eprintln!("usage: record OUTPUT COLUMNS LINES DIR");
+        process::exit(2);
+    }
+    let dimension = |arg: &str| match arg.parse::<u16>() {
+        Ok(value) if value > 0 => f32::from(value),
+        _ => {
+            eprintln!("not a number of cells: {}", arg);
+            process::exit(2);
+        }
+    };
+
+    let recording = fs::read(&args[0])?;
+    let size = SizeInfo {
+        width: dimension(&args[1]),
+        height: dimension(&args[2]),
+        cell_width: 1.0,
+        cell_height: 1.0,
+        padding_x: 0.0,
+        padding_y: 0.0,
+        dpr: 1.0,
+    };
+
+    let mut term = Term::new(size);
+    Processor::new().advance_bytes(&mut term, &recording, &mut io::sink());
+
+    let dir = Path::new(&args[3]);
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join("recording"), &recording)?;
+    fs::write(dir.join("size.json"), serde_json::to_string(&size)?)?;
+    fs::write(dir.join("grid.json"), serde_json::to_string(term.grid())?)?;
+    fs::write(
+        dir.join("zero_width.json"),
+        serde_json::to_string(term.zero_width())?,
+    )?;
+    Ok(())
+}
@@ -14,14 +14,17 @@
 //
 //! ANSI Terminal Stream Parsing
 use std::io;
+use std::mem;
 use std::ops::Range;
 use std::str;
 
 use crate::index::{Column, Contains, Line};
 use base64;
+use bitflags::bitflags;
 use vte;
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Rgb {
     pub r: u8,
     pub g: u8,
@@ -114,13 +117,224 @@ fn parse_number(input: &[u8]) -> Option<u8> {
 pub struct Processor {
     state: ProcessorState,
     parser: vte::Parser,
+
+    /// Whether 8-bit C1 controls, like `0x9b` for CSI, are understood as their `ESC` sequences
+    c1_controls: bool,
+
+    /// How many more continuation bytes the UTF-8 character that is being received has, since
+    /// those can look like C1 controls
+    utf8_remaining: u8,
+
+    /// What is printed for bytes of text that aren't UTF-8
+    invalid_utf8: InvalidUtf8,
+
+    /// The bytes so far of the UTF-8 character that is being printed, `utf8_len` of them
+    utf8: [u8; 4],
+    utf8_len: usize,
+}
+
+/// What the processor prints for bytes of text that aren't UTF-8
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum InvalidUtf8 {
+    /// Print a `\u{fffd}` for each broken character, like `vte` does
+    Replace,
+
+    /// Print each byte as the Latin-1 character with its value, for programs that still speak it
+    Latin1,
+
+    /// Print each byte as its value in hex like `\xff`, to see what a program sent
+    Hex,
+}
+
+impl Default for InvalidUtf8 {
+    fn default() -> InvalidUtf8 {
+        InvalidUtf8::Replace
+    }
 }
 
 /// Internal state for VTE processor
 pub struct ProcessorState {
     preceding_char: Option<char>,
+
+    /// The name of the setting that a DECRQSS request asks for, while it is being received
+    requested_setting: Option<Vec<u8>>,
+
+    /// Where the processor is in an escape sequence, to find the subparameters of CSI sequences
+    sequence: Sequence,
+
+    /// The index of the CSI parameter that is being received
+    param: usize,
+
+    /// The CSI parameters that follow a colon instead of a semicolon, one bit per parameter
+    subparams: u32,
+
+    /// The arguments and data of an inline image, while it is being received
+    image: Option<Vec<u8>>,
+
+    /// An inline image that has been received completely and is waiting to be handled
+    finished_image: Option<Vec<u8>>,
+
+    /// A kitty graphics command that has been received completely and is waiting to be handled
+    finished_graphics: Option<Vec<u8>>,
+
+    /// Where the processor is in a VT52 escape sequence, in VT52 mode
+    vt52: Option<Vt52>,
+}
+
+/// The start of an iTerm2 inline image, after `OSC`
+const IMAGE_PREFIX: &[u8] = b"1337;File=";
+
+/// The most bytes of an inline image that are kept, with the image encoded as base64
+const MAX_IMAGE_LEN: usize = 16 * 1024 * 1024;
+
+/// How far along an escape sequence the processor is, as far as subparameters are concerned
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Sequence {
+    Ground,
+    Escape,
+    /// After the intermediate bytes of an escape sequence, like the `(` in `ESC ( B`
+    EscapeIntermediate,
+    Csi,
+    /// With how much of `IMAGE_PREFIX` the sequence has matched so far, if it still matches
+    Osc(Option<usize>),
+    /// Receiving an inline image, which is too big for `vte` to keep
+    Image,
+    /// At the start of an APC string, which `vte` ignores
+    Apc,
+    /// In a DCS, SOS, PM or APC string, until the string terminator
+    String,
+    /// Receiving a kitty graphics command, which is an APC string starting with `G`
+    Graphics,
+}
+
+/// How far along a VT52 escape sequence the processor is; `vte` can't parse them, as the
+/// cursor position in `ESC Y` is sent as two bytes of any kind
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Vt52 {
+    Ground,
+    Escape,
+    /// After `ESC Y`, waiting for the line
+    Line,
+    /// After `ESC Y` and the line, waiting for the column
+    Column(u8),
+}
+
+impl ProcessorState {
+    /// Keep track of the subparameters in CSI sequences, like the `3` in `CSI 4:3 m`, and return
+    /// the byte to hand on to `vte`; it doesn't know about colons and would drop the sequence.
+    ///
+    /// Inline images and kitty graphics commands are taken out here too, and `None` is returned
+    /// for their bytes.
+    fn scan(&mut self, byte: u8) -> Option<u8> {
+        match (self.sequence, byte) {
+            (Sequence::Graphics, 0x1b) => {
+                self.sequence = Sequence::Escape;
+                self.finished_graphics = self.image.take();
+            }
+            // The string terminator, which is left to `vte` to end its truncated OSC sequence
+            (Sequence::Image, 0x07) | (Sequence::Image, 0x1b) => {
+                self.sequence = if byte == 0x07 {
+                    Sequence::Ground
+                } else {
+                    Sequence::Escape
+                };
+                self.finished_image = self.image.take();
+            }
+            (Sequence::Image, _) | (Sequence::Graphics, _) => {
+                if let Some(image) = &mut self.image {
+                    if image.len() < MAX_IMAGE_LEN {
+                        image.push(byte);
+                    } else {
+                        debug!("Image is too big");
+                        self.image = None;
+                    }
+                }
+                return None;
+            }
+            (_, 0x1b) => self.sequence = Sequence::Escape,
+            // CAN and SUB cancel any sequence
+            (_, 0x18) | (_, 0x1a) => self.sequence = Sequence::Ground,
+            (Sequence::Escape, b']') => self.sequence = Sequence::Osc(Some(0)),
+            (Sequence::Escape, b'_') => self.sequence = Sequence::Apc,
+            (Sequence::Escape, b'P') | (Sequence::Escape, b'X') | (Sequence::Escape, b'^') => {
+                self.sequence = Sequence::String
+            }
+            (Sequence::Apc, b'G') => {
+                self.sequence = Sequence::Graphics;
+                self.image = Some(Vec::new());
+            }
+            (Sequence::Apc, _) => self.sequence = Sequence::String,
+            (Sequence::Escape, b'[') => {
+                self.sequence = Sequence::Csi;
+                self.param = 0;
+                self.subparams = 0;
+            }
+            (Sequence::Escape, 0x20..=0x2f) => self.sequence = Sequence::EscapeIntermediate,
+            (Sequence::Escape, 0x30..=0x7e) | (Sequence::EscapeIntermediate, 0x30..=0x7e) => {
+                self.sequence = Sequence::Ground
+            }
+            (Sequence::Csi, b';') => self.param += 1,
+            (Sequence::Csi, b':') => {
+                self.param += 1;
+                if self.param < 32 {
+                    self.subparams |= 1 << self.param;
+                }
+                return Some(b';');
+            }
+            // The final byte
+            (Sequence::Csi, 0x40..=0x7e) => self.sequence = Sequence::Ground,
+            (Sequence::Osc(Some(matched)), _) if IMAGE_PREFIX[matched] == byte => {
+                if matched + 1 == IMAGE_PREFIX.len() {
+                    self.sequence = Sequence::Image;
+                    self.image = Some(Vec::new());
+                } else {
+                    self.sequence = Sequence::Osc(Some(matched + 1));
+                }
+            }
+            (Sequence::Osc(_), 0x07) => self.sequence = Sequence::Ground,
+            (Sequence::Osc(_), _) => self.sequence = Sequence::Osc(None),
+            // Controls and other bytes that `vte` executes or ignores without leaving the state.
+            // An 8-bit ST ends a string in `vte` only after its first byte of data, and a UTF-8
+            // character can end with the same byte, so strings are only taken to end with `ESC`;
+            // until then text goes through `vte` like the rest of the string.
+            (Sequence::Escape, _)
+            | (Sequence::EscapeIntermediate, _)
+            | (Sequence::Csi, _)
+            | (Sequence::String, _)
+            | (Sequence::Ground, _) => (),
+        }
+        Some(byte)
+    }
+
+    /// Take the inline image that was just received, if there is one
+    fn take_image(&mut self) -> Option<InlineImage> {
+        let bytes = self.finished_image.take()?;
+        let image = parse_inline_image(&bytes);
+        if image.is_none() {
+            debug!("Ignoring inline image that isn't shown or can't be decoded");
+        }
+        image
+    }
+
+    /// Take the kitty graphics command that was just received, if there is one
+    fn take_graphics(&mut self) -> Option<GraphicsCommand> {
+        let bytes = self.finished_graphics.take()?;
+        let command = parse_graphics(&bytes);
+        if command.is_none() {
+            debug!("Ignoring kitty graphics command that can't be parsed");
+        }
+        command
+    }
+
+    /// Whether the CSI parameter at `index` followed a colon.
+    fn is_subparam(&self, index: usize) -> bool {
+        index < 32 && self.subparams & (1 << index) != 0
+    }
 }
 
+/// The longest setting name in a DECRQSS request that is kept
+const MAX_SETTING_LEN: usize = 8;
+
 /// Helper type that implements `vte::Perform`.
 ///
 /// Processor creates a Performer when running advance and passes the Performer
@@ -145,6 +359,76 @@ impl<'a, H: Handler + TermInfo + 'a, W: io::Write> Performer<'a, H, W> {
             writer,
         }
     }
+
+    /// Handle `byte` in VT52 mode, which has a small set of escape sequences of its own
+    fn vt52(&mut self, byte: u8) {
+        use vte::Perform;
+
+        let state = match self.state.vt52 {
+            Some(state) => state,
+            None => return,
+        };
+        let next = match (state, byte) {
+            (_, 0x1b) => Vt52::Escape,
+            (_, 0x18) | (_, 0x1a) => Vt52::Ground,
+            // Other controls are executed even in the middle of a sequence
+            (_, 0x00..=0x1f) => {
+                self.execute(byte);
+                state
+            }
+            (Vt52::Ground, 0x20..=0x7e) => {
+                self.print(byte as char);
+                Vt52::Ground
+            }
+            (Vt52::Ground, _) => Vt52::Ground,
+            (Vt52::Escape, b'Y') => Vt52::Line,
+            (Vt52::Escape, b'<') => {
+                self.state.vt52 = None;
+                self.handler.set_mode(Mode::Ansi);
+                return;
+            }
+            (Vt52::Escape, _) => {
+                self.vt52_escape(byte);
+                Vt52::Ground
+            }
+            (Vt52::Line, _) => Vt52::Column(byte),
+            (Vt52::Column(line), _) => {
+                self.handler.goto(
+                    Line(usize::from(line.saturating_sub(32))),
+                    Column(usize::from(byte.saturating_sub(32))),
+                );
+                Vt52::Ground
+            }
+        };
+        self.state.vt52 = Some(next);
+    }
+
+    fn vt52_escape(&mut self, byte: u8) {
+        match byte {
+            b'A' => self.handler.move_up(Line(1)),
+            b'B' => self.handler.move_down(Line(1)),
+            b'C' => self.handler.move_forward(Column(1)),
+            b'D' => self.handler.move_backward(Column(1)),
+            b'F' => self.handler.configure_charset(
+                CharsetIndex::G0,
+                StandardCharset::SpecialCharacterAndLineDrawing,
+            ),
+            b'G' => self
+                .handler
+                .configure_charset(CharsetIndex::G0, StandardCharset::Ascii),
+            b'H' => self.handler.goto(Line(0), Column(0)),
+            b'I' => self.handler.reverse_index(),
+            b'J' => self.handler.clear_screen(ClearMode::Below),
+            b'K' => self.handler.clear_line(LineClearMode::Right),
+            // The VT52 answer to identify, which says that there is no printer
+            b'Z' => {
+                let _ = self.writer.write_all(b"\x1b/Z");
+            }
+            b'=' => self.handler.set_keypad_application_mode(),
+            b'>' => self.handler.unset_keypad_application_mode(),
+            _ => debug!("[unhandled] VT52 escape byte={:?}", byte as char),
+        }
+    }
 }
 
 impl Default for Processor {
@@ -152,8 +436,21 @@ impl Default for Processor {
         Processor {
             state: ProcessorState {
                 preceding_char: None,
+                requested_setting: None,
+                sequence: Sequence::Ground,
+                param: 0,
+                subparams: 0,
+                image: None,
+                finished_image: None,
+                finished_graphics: None,
+                vt52: None,
             },
             parser: vte::Parser::new(),
+            c1_controls: false,
+            utf8_remaining: 0,
+            invalid_utf8: InvalidUtf8::default(),
+            utf8: [0; 4],
+            utf8_len: 0,
         }
     }
 }
@@ -163,14 +460,218 @@ impl Processor {
         Default::default()
     }
 
+    /// Whether 8-bit C1 controls are understood
+    pub fn c1_controls(&self) -> bool {
+        self.c1_controls
+    }
+
+    /// Say whether 8-bit C1 controls are understood, which they are not by default: a stray byte
+    /// of binary or Latin-1 output would start a string that hides everything after it.  When they
+    /// are not, `vte` executes or drops them like any other byte that isn't UTF-8.
+    pub fn set_c1_controls(&mut self, enabled: bool) {
+        self.c1_controls = enabled;
+    }
+
+    /// What is printed for bytes of text that aren't UTF-8
+    pub fn invalid_utf8(&self) -> InvalidUtf8 {
+        self.invalid_utf8
+    }
+
+    /// Choose what is printed for bytes of text that aren't UTF-8, which is a replacement
+    /// character by default
+    pub fn set_invalid_utf8(&mut self, policy: InvalidUtf8) {
+        self.invalid_utf8 = policy;
+    }
+
     #[inline]
     pub fn advance<H, W>(&mut self, handler: &mut H, byte: u8, writer: &mut W)
     where
         H: Handler + TermInfo,
         W: io::Write,
     {
-        let mut performer = Performer::new(&mut self.state, handler, writer);
-        self.parser.advance(&mut performer, byte);
+        if self.state.vt52.is_some() {
+            Performer::new(&mut self.state, handler, writer).vt52(byte);
+            return;
+        }
+
+        if self.state.sequence == Sequence::Ground && self.print_utf8(handler, byte, writer) {
+            return;
+        }
+
+        let c1_control = self.is_c1_control(byte);
+        if self.c1_controls && c1_control {
+            // A C1 control is the same as `ESC` followed by the byte 0x40 lower
+            self.advance_byte(handler, 0x1b, writer);
+            self.advance_byte(handler, byte - 0x40, writer);
+        } else {
+            self.advance_byte(handler, byte, writer);
+        }
+    }
+
+    /// Process all of `bytes`, like `advance` does one by one.  Runs of plain text are handed to
+    /// the handler directly, without going through `vte`, which makes bulky output much cheaper.
+    pub fn advance_bytes<H, W>(&mut self, handler: &mut H, bytes: &[u8], writer: &mut W)
+    where
+        H: Handler + TermInfo,
+        W: io::Write,
+    {
+        let mut i = 0;
+        while i < bytes.len() {
+            if self.in_ground() {
+                let text = bytes[i..]
+                    .iter()
+                    .position(|&byte| byte < 0x20 || byte >= 0x7f)
+                    .unwrap_or(bytes.len() - i);
+                if text > 0 {
+                    for &byte in &bytes[i..i + text] {
+                        handler.input(byte as char);
+                    }
+                    self.state.preceding_char = Some(bytes[i + text - 1] as char);
+                    i += text;
+                    continue;
+                }
+            }
+            self.advance(handler, bytes[i], writer);
+            i += 1;
+        }
+    }
+
+    /// Whether the next printable ASCII byte would be printed, so that `vte` is in its ground
+    /// state and not in the middle of a UTF-8 character
+    fn in_ground(&self) -> bool {
+        self.state.sequence == Sequence::Ground
+            && self.state.vt52.is_none()
+            && self.utf8_remaining == 0
+            && self.utf8_len == 0
+    }
+
+    /// Decode the UTF-8 of text instead of `vte`, which drops broken characters together with the
+    /// byte after them, so that they are printed as `invalid_utf8` says.  Returns whether `byte`
+    /// was taken, which C1 controls and other bytes outside of characters are not.
+    fn print_utf8<H, W>(&mut self, handler: &mut H, byte: u8, writer: &mut W) -> bool
+    where
+        H: Handler + TermInfo,
+        W: io::Write,
+    {
+        if self.utf8_len == 0 {
+            match byte {
+                0x00..=0x9f => return false,
+                0xc2..=0xf4 => {
+                    self.utf8[0] = byte;
+                    self.utf8_len = 1;
+                }
+                _ => self.print_invalid_utf8(handler, &[byte]),
+            }
+            return true;
+        }
+
+        if let 0x80..=0xbf = byte {
+            self.utf8[self.utf8_len] = byte;
+            self.utf8_len += 1;
+            let len = match self.utf8[0] {
+                0xc2..=0xdf => 2,
+                0xe0..=0xef => 3,
+                _ => 4,
+            };
+            if self.utf8_len == len {
+                let bytes = self.utf8;
+                self.utf8_len = 0;
+                match str::from_utf8(&bytes[..len]) {
+                    Ok(text) => {
+                        for c in text.chars() {
+                            handler.input(c);
+                            self.state.preceding_char = Some(c);
+                        }
+                    }
+                    Err(_) => self.print_invalid_utf8(handler, &bytes[..len]),
+                }
+            }
+            true
+        } else {
+            // The character is cut short, but the byte that did it still counts
+            let bytes = self.utf8;
+            let len = mem::replace(&mut self.utf8_len, 0);
+            self.print_invalid_utf8(handler, &bytes[..len]);
+            self.advance(handler, byte, writer);
+            true
+        }
+    }
+
+    fn print_invalid_utf8<H: Handler>(&mut self, handler: &mut H, bytes: &[u8]) {
+        let policy = self.invalid_utf8;
+        let state = &mut self.state;
+        let mut print = |c| {
+            handler.input(c);
+            state.preceding_char = Some(c);
+        };
+        match policy {
+            InvalidUtf8::Replace => print('\u{fffd}'),
+            InvalidUtf8::Latin1 => {
+                for &byte in bytes {
+                    // Latin-1 has C1 controls there, which aren't printed
+                    print(if byte >= 0xa0 {
+                        byte as char
+                    } else {
+                        '\u{fffd}'
+                    });
+                }
+            }
+            InvalidUtf8::Hex => {
+                for byte in bytes {
+                    format!("\\x{:02x}", byte).chars().for_each(&mut print);
+                }
+            }
+        }
+    }
+
+    /// Whether `byte` is a C1 control rather than part of a UTF-8 character, keeping track of
+    /// the UTF-8 characters on the way
+    fn is_c1_control(&mut self, byte: u8) -> bool {
+        match byte {
+            0x80..=0xbf if self.utf8_remaining > 0 => {
+                self.utf8_remaining -= 1;
+                false
+            }
+            0x80..=0x9f => true,
+            0xc2..=0xdf => {
+                self.utf8_remaining = 1;
+                false
+            }
+            0xe0..=0xef => {
+                self.utf8_remaining = 2;
+                false
+            }
+            0xf0..=0xf4 => {
+                self.utf8_remaining = 3;
+                false
+            }
+            _ => {
+                self.utf8_remaining = 0;
+                false
+            }
+        }
+    }
+
+    #[inline]
+    fn advance_byte<H, W>(&mut self, handler: &mut H, byte: u8, writer: &mut W)
+    where
+        H: Handler + TermInfo,
+        W: io::Write,
+    {
+        let byte = match self.state.scan(byte) {
+            Some(byte) => byte,
+            None => return,
+        };
+        {
+            let mut performer = Performer::new(&mut self.state, handler, writer);
+            self.parser.advance(&mut performer, byte);
+        }
+        if let Some(image) = self.state.take_image() {
+            handler.inline_image(image);
+        }
+        if let Some(command) = self.state.take_graphics() {
+            handler.graphics_command(writer, command);
+        }
     }
 }
 
@@ -178,6 +679,11 @@ impl Processor {
 pub trait TermInfo {
     fn lines(&self) -> Line;
     fn cols(&self) -> Column;
+
+    /// Whether `CSI s` sets the left and right margins rather than saving the cursor
+    fn left_right_margin_mode(&self) -> bool {
+        false
+    }
 }
 
 /// Facade around [winit's `MouseCursor`](glutin::MouseCursor)
@@ -224,8 +730,17 @@ pub trait Handler {
 
     /// Identify the terminal (should write back to the pty stream)
     ///
+    /// The intermediate is `>` for the secondary and `=` for the tertiary device attributes.
+    ///
     /// TODO this should probably return an io::Result
-    fn identify_terminal<W: io::Write>(&mut self, _: &mut W) {}
+    fn identify_terminal<W: io::Write>(&mut self, _: &mut W, _: Option<char>) {}
+
+    /// ENQ - Send the answerback message (should write back to the pty stream)
+    fn answerback<W: io::Write>(&mut self, _: &mut W) {}
+
+    /// DECRQSS - Report the value of the setting that `name` is the final bytes of the sequence
+    /// for (should write back to the pty stream)
+    fn request_setting<W: io::Write>(&mut self, _: &mut W, _name: &[u8]) {}
 
     // Report device status
     fn device_status<W: io::Write>(&mut self, _: &mut W, _: usize) {}
@@ -259,10 +774,10 @@ pub trait Handler {
     /// Hopefully this is never implemented
     fn bell(&mut self) {}
 
-    /// Substitute char under cursor
+    /// SUB - Show that a character was replaced because of an error
     fn substitute(&mut self) {}
 
-    /// Newline
+    /// Line feed, with a carriage return in new line mode
     fn newline(&mut self) {}
 
     /// Set current position as a tabstop
@@ -292,6 +807,17 @@ pub trait Handler {
     /// to the right of the deleted things is shifted left.
     fn delete_chars(&mut self, _: Column) {}
 
+    /// DECCRA - Copy the cells in `area` to the same sized area with its top left corner at
+    /// `destination`
+    fn copy_area(&mut self, _area: Area, _destination: (Line, Column)) {}
+
+    /// DECFRA - Fill `area` with `c` in the current attributes
+    fn fill_area(&mut self, _c: char, _area: Area) {}
+
+    /// DECERA and DECSERA - Erase `area`; a selective erase only erases the characters and keeps
+    /// the attributes
+    fn erase_area(&mut self, _area: Area, _selective: bool) {}
+
     /// Move backward `count` tabs
     fn move_backward_tabs(&mut self, _count: i64) {}
 
@@ -335,6 +861,9 @@ pub trait Handler {
     /// DECSTBM - Set the terminal scrolling region
     fn set_scrolling_region(&mut self, _: Range<Line>) {}
 
+    /// DECSLRM - Set the left and right margins
+    fn set_left_right_margins(&mut self, _: Range<Column>) {}
+
     /// DECKPAM - Set keypad to applications mode (ESCape instead of digits)
     fn set_keypad_application_mode(&mut self) {}
 
@@ -362,10 +891,188 @@ pub trait Handler {
     /// Set the clipboard
     fn set_clipboard(&mut self, _: &str) {}
 
+    /// Show a desktop notification
+    fn notify(&mut self, _: Notification) {}
+
+    /// Start the hyperlink that the following text is part of, or end it with `None`
+    fn set_hyperlink(&mut self, _: Option<Hyperlink>) {}
+
+    /// Show an image at the cursor, which was sent with the iTerm2 inline image protocol
+    fn inline_image(&mut self, _: InlineImage) {}
+
+    /// Run a command of the kitty graphics protocol, answering it on `writer`
+    fn graphics_command<W: io::Write>(&mut self, _: &mut W, _: GraphicsCommand) {}
+
+    /// DECSWL, DECDWL and DECDHL - Set the size of the characters on the cursor's line
+    fn set_line_size(&mut self, _: LineSize) {}
+
+    /// Mark where a part of a shell command starts on the cursor's line, with OSC 133
+    fn prompt_mark(&mut self, _: PromptMarks) {}
+
+    /// Push keyboard enhancements onto the stack of the active screen (`CSI > flags u`)
+    fn push_keyboard_modes(&mut self, _: KeyboardModes) {}
+
+    /// Pop `count` keyboard enhancements off the stack of the active screen (`CSI < count u`)
+    fn pop_keyboard_modes(&mut self, _count: usize) {}
+
+    /// Change the current keyboard enhancements (`CSI = flags ; mode u`)
+    fn set_keyboard_modes(&mut self, _: KeyboardModes, _: KeyboardModesApplyBehavior) {}
+
+    /// Report the current keyboard enhancements (`CSI ? u`, should write back to the pty stream)
+    fn report_keyboard_modes<W: io::Write>(&mut self, _: &mut W) {}
+
     /// Run the dectest routine
     fn dectest(&mut self) {}
 }
 
+/// A hyperlink that a program started with OSC 8
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Hyperlink {
+    /// Ties together the parts of one link, like when a text editor draws it on several lines
+    pub id: Option<String>,
+    pub uri: String,
+}
+
+/// A desktop notification that a program sent with OSC 9 (like iTerm2) or OSC 777 (like rxvt)
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Notification {
+    /// Only OSC 777 has a title
+    pub title: Option<String>,
+    pub body: String,
+}
+
+/// An image that a program sent with OSC 1337, like `imgcat` does
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InlineImage {
+    pub name: Option<String>,
+    pub width: ImageSize,
+    pub height: ImageSize,
+    pub preserve_aspect_ratio: bool,
+    /// The contents of the image file, in whatever format it was sent in
+    pub data: Vec<u8>,
+}
+
+/// How big an inline image should be along one side
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ImageSize {
+    /// As big as the image itself
+    Auto,
+    Cells(usize),
+    Pixels(usize),
+    /// A percentage of the size of the terminal
+    Percent(usize),
+}
+
+impl ImageSize {
+    fn parse(value: &str) -> Option<ImageSize> {
+        if value == "auto" {
+            Some(ImageSize::Auto)
+        } else if value.ends_with("px") {
+            value[..value.len() - 2].parse().ok().map(ImageSize::Pixels)
+        } else if value.ends_with('%') {
+            value[..value.len() - 1]
+                .parse()
+                .ok()
+                .map(ImageSize::Percent)
+        } else {
+            value.parse().ok().map(ImageSize::Cells)
+        }
+    }
+}
+
+/// Parse what follows `File=` in an inline image sequence: arguments, a colon and the base64
+/// encoded file. Files that are only meant to be downloaded are left out.
+fn parse_inline_image(bytes: &[u8]) -> Option<InlineImage> {
+    let colon = bytes.iter().position(|&b| b == b':')?;
+    let args = str::from_utf8(&bytes[..colon]).ok()?;
+
+    let mut image = InlineImage {
+        name: None,
+        width: ImageSize::Auto,
+        height: ImageSize::Auto,
+        preserve_aspect_ratio: true,
+        data: Vec::new(),
+    };
+    let mut inline = false;
+    for arg in args.split(';') {
+        let mut parts = arg.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some("name"), Some(name)) => {
+                image.name = base64::decode(name)
+                    .ok()
+                    .and_then(|name| String::from_utf8(name).ok());
+            }
+            (Some("width"), Some(width)) => image.width = ImageSize::parse(width)?,
+            (Some("height"), Some(height)) => image.height = ImageSize::parse(height)?,
+            (Some("preserveAspectRatio"), Some(value)) => {
+                image.preserve_aspect_ratio = value != "0"
+            }
+            (Some("inline"), Some(value)) => inline = value == "1",
+            _ => (),
+        }
+    }
+    if !inline {
+        return None;
+    }
+
+    let data = bytes[colon + 1..]
+        .iter()
+        .cloned()
+        .filter(|byte| !byte.is_ascii_whitespace())
+        .collect::<Vec<_>>();
+    image.data = base64::decode(&data).ok()?;
+    Some(image)
+}
+
+/// A command of the kitty graphics protocol, sent with `APC G`
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct GraphicsCommand {
+    /// The keys and values before the payload, like `a` for the action and `f` for the format
+    pub control: Vec<(char, String)>,
+    /// The payload, still encoded as base64
+    pub payload: Vec<u8>,
+}
+
+impl GraphicsCommand {
+    /// The value of `key`, if the command has one.
+    pub fn value(&self, key: char) -> Option<&str> {
+        self.control
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The value of `key` as a number, which is 0 when it is left out like the protocol says.
+    pub fn number(&self, key: char) -> u32 {
+        self.value(key)
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0)
+    }
+}
+
+/// Parse what follows `APC G`: keys and values separated by commas, and the payload after a
+/// semicolon.
+fn parse_graphics(bytes: &[u8]) -> Option<GraphicsCommand> {
+    let semicolon = bytes.iter().position(|&b| b == b';').unwrap_or(bytes.len());
+    let control = str::from_utf8(&bytes[..semicolon]).ok()?;
+
+    let mut command = GraphicsCommand::default();
+    for pair in control.split(',').filter(|pair| !pair.is_empty()) {
+        let mut parts = pair.splitn(2, '=');
+        let key = parts.next()?;
+        let value = parts.next()?;
+        let mut chars = key.chars();
+        match (chars.next(), chars.next()) {
+            (Some(key), None) => command.control.push((key, value.to_owned())),
+            _ => return None,
+        }
+    }
+    if semicolon < bytes.len() {
+        command.payload = bytes[semicolon + 1..].to_vec();
+    }
+    Some(command)
+}
+
 /// Describes shape of cursor
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub enum CursorStyle {
@@ -388,11 +1095,78 @@ impl Default for CursorStyle {
     }
 }
 
+/// How big the characters on a line are, from DECSWL, DECDWL and DECDHL
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum LineSize {
+    Single,
+
+    /// Every character is twice as wide, so only half of the columns fit on the line
+    DoubleWidth,
+
+    /// The top half of characters that are twice as wide and twice as high
+    DoubleHeightTop,
+
+    /// The bottom half of characters that are twice as wide and twice as high
+    DoubleHeightBottom,
+}
+
+impl Default for LineSize {
+    fn default() -> LineSize {
+        LineSize::Single
+    }
+}
+
+bitflags! {
+    /// The parts of a shell command that start or end on a line, as marked with OSC 133
+    #[derive(Default)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct PromptMarks: u8 {
+        /// The prompt starts (`A`)
+        const PROMPT  = 0b0001;
+        /// The command that the user types starts (`B`)
+        const COMMAND = 0b0010;
+        /// The output of the command starts (`C`)
+        const OUTPUT  = 0b0100;
+        /// The command finished (`D`)
+        const END     = 0b1000;
+    }
+}
+
+bitflags! {
+    /// The enhancements of kitty's keyboard protocol that a program can ask for with `CSI > u`
+    #[derive(Default)]
+    pub struct KeyboardModes: u8 {
+        /// Send keys that are ambiguous otherwise, like Escape and Alt+key, as `CSI u`
+        const DISAMBIGUATE_ESC_CODES  = 0b0_0001;
+        const REPORT_EVENT_TYPES      = 0b0_0010;
+        const REPORT_ALTERNATE_KEYS   = 0b0_0100;
+        /// Send all keys as `CSI u`, also the ones that type text
+        const REPORT_ALL_KEYS_AS_ESC  = 0b0_1000;
+        const REPORT_ASSOCIATED_TEXT  = 0b1_0000;
+    }
+}
+
+/// How `CSI = flags ; mode u` changes the current keyboard enhancements
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum KeyboardModesApplyBehavior {
+    /// Use exactly the given ones
+    Replace,
+    /// Add the given ones
+    Union,
+    /// Remove the given ones
+    Difference,
+}
+
 /// Terminal modes
 #[derive(Debug, Eq, PartialEq)]
 pub enum Mode {
     /// ?1
     CursorKeys = 1,
+    /// ?2
+    ///
+    /// DECANM - resetting it switches to VT52 mode, which `ESC <` leaves again
+    Ansi = 2,
     /// Select 80 or 132 columns per page
     ///
     /// CSI ? 3 h -> set 132 column font
@@ -418,6 +1192,10 @@ pub enum Mode {
     LineWrap = 7,
     /// ?12
     BlinkingCursor = 12,
+    /// ?69
+    ///
+    /// DECLRMM - makes `CSI s` set the left and right margins
+    LeftRightMargin = 69,
     /// 20
     ///
     /// NB This is actually a private mode. We should consider adding a second
@@ -433,8 +1211,12 @@ pub enum Mode {
     ReportAllMouseMotion = 1003,
     /// ?1004
     ReportFocusInOut = 1004,
+    /// ?1005
+    Utf8Mouse = 1005,
     /// ?1006
     SgrMouse = 1006,
+    /// ?1007
+    AlternateScroll = 1007,
     /// ?1049
     SwapScreenAndSetRestoreCursor = 1049,
     /// ?2004
@@ -449,16 +1231,20 @@ impl Mode {
         if private {
             Some(match num {
                 1 => Mode::CursorKeys,
+                2 => Mode::Ansi,
                 3 => Mode::DECCOLM,
                 6 => Mode::Origin,
                 7 => Mode::LineWrap,
                 12 => Mode::BlinkingCursor,
                 25 => Mode::ShowCursor,
+                69 => Mode::LeftRightMargin,
                 1000 => Mode::ReportMouseClicks,
                 1002 => Mode::ReportCellMouseMotion,
                 1003 => Mode::ReportAllMouseMotion,
                 1004 => Mode::ReportFocusInOut,
+                1005 => Mode::Utf8Mouse,
                 1006 => Mode::SgrMouse,
+                1007 => Mode::AlternateScroll,
                 1049 => Mode::SwapScreenAndSetRestoreCursor,
                 2004 => Mode::BracketedPaste,
                 _ => {
@@ -489,6 +1275,16 @@ pub enum LineClearMode {
     All,
 }
 
+/// A rectangle of cells for the VT420 rectangular area operations, including its bottom line and
+/// right column; like the cursor, it is relative to the scrolling region in origin mode
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct Area {
+    pub top: Line,
+    pub left: Column,
+    pub bottom: Line,
+    pub right: Column,
+}
+
 /// Mode for clearing terminal
 ///
 /// Relative to cursor
@@ -518,6 +1314,7 @@ pub enum TabulationClearMode {
 /// The order here matters since the enum should be castable to a `usize` for
 /// indexing a color list.
 #[derive(Debug, Copy, Clone, Eq, PartialEq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum NamedColor {
     /// Black
     Black = 0,
@@ -606,6 +1403,22 @@ impl NamedColor {
         }
     }
 
+    /// Whether this is one of the dim colors
+    pub fn is_dim(self) -> bool {
+        match self {
+            NamedColor::DimBlack
+            | NamedColor::DimRed
+            | NamedColor::DimGreen
+            | NamedColor::DimYellow
+            | NamedColor::DimBlue
+            | NamedColor::DimMagenta
+            | NamedColor::DimCyan
+            | NamedColor::DimWhite
+            | NamedColor::DimForeground => true,
+            _ => false,
+        }
+    }
+
     pub fn to_dim(self) -> Self {
         match self {
             NamedColor::Black => NamedColor::DimBlack,
@@ -632,6 +1445,7 @@ impl NamedColor {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Color {
     Named(NamedColor),
     Spec(Rgb),
@@ -667,8 +1481,16 @@ pub enum Attr {
     CancelBoldDim,
     /// Cancel italic
     CancelItalic,
-    /// Cancel underline
+    /// Cancel underline, of any style
     CancelUnderline,
+    /// Double underline
+    DoubleUnderline,
+    /// Curly underline
+    Undercurl,
+    /// Dotted underline
+    DottedUnderline,
+    /// Dashed underline
+    DashedUnderline,
     /// Cancel blink
     CancelBlink,
     /// Cancel inversion
@@ -681,6 +1503,8 @@ pub enum Attr {
     Foreground(Color),
     /// Set indexed background color
     Background(Color),
+    /// Set the underline color, or go back to the foreground color
+    UnderlineColor(Option<Color>),
 }
 
 /// Identifiers which can be assigned to a graphic character set
@@ -701,6 +1525,7 @@ impl Default for CharsetIndex {
 
 /// Standard or common character sets which can be designated as G0-G3
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum StandardCharset {
     Ascii,
     SpecialCharacterAndLineDrawing,
@@ -719,6 +1544,10 @@ where
 {
     #[inline]
     fn print(&mut self, c: char) {
+        // `vte` prints DEL, which terminals ignore
+        if c == '\x7f' {
+            return;
+        }
         self.handler.input(c);
         self.state.preceding_char = Some(c);
     }
@@ -729,34 +1558,55 @@ where
             C0::HT => self.handler.put_tab(1),
             C0::BS => self.handler.backspace(),
             C0::CR => self.handler.carriage_return(),
-            C0::LF | C0::VT | C0::FF => self.handler.linefeed(),
+            C0::LF | C0::VT | C0::FF => self.handler.newline(),
             C0::BEL => self.handler.bell(),
             C0::SUB => self.handler.substitute(),
             C0::SI => self.handler.set_active_charset(CharsetIndex::G0),
             C0::SO => self.handler.set_active_charset(CharsetIndex::G1),
-            C1::NEL => self.handler.newline(),
+            C0::ENQ => self.handler.answerback(self.writer),
+            // Padding
+            C0::NUL => (),
+            C1::IND => self.handler.linefeed(),
+            C1::NEL => {
+                self.handler.linefeed();
+                self.handler.carriage_return();
+            }
             C1::HTS => self.handler.set_horizontal_tabstop(),
-            C1::DECID => self.handler.identify_terminal(self.writer),
+            C1::RI => self.handler.reverse_index(),
+            C1::DECID => self.handler.identify_terminal(self.writer, None),
+            // A string terminator without a string
+            C1::ST => (),
             _ => debug!("[unhandled] execute byte={:02x}", byte),
         }
     }
 
     #[inline]
     fn hook(&mut self, params: &[i64], intermediates: &[u8], ignore: bool) {
-        debug!(
-            "[unhandled hook] params={:?}, ints: {:?}, ignore: {:?}",
-            params, intermediates, ignore
-        );
+        match intermediates {
+            // DECRQSS; the `q` that ends the sequence doesn't make it here
+            b"$" if !ignore => self.state.requested_setting = Some(Vec::new()),
+            _ => debug!(
+                "[unhandled hook] params={:?}, ints: {:?}, ignore: {:?}",
+                params, intermediates, ignore
+            ),
+        }
     }
 
     #[inline]
     fn put(&mut self, byte: u8) {
-        debug!("[unhandled put] byte={:?}", byte);
+        match self.state.requested_setting {
+            Some(ref mut name) if name.len() < MAX_SETTING_LEN => name.push(byte),
+            Some(_) => (),
+            None => debug!("[unhandled put] byte={:?}", byte),
+        }
     }
 
     #[inline]
     fn unhook(&mut self) {
-        debug!("[unhandled unhook]");
+        match self.state.requested_setting.take() {
+            Some(name) => self.handler.request_setting(self.writer, &name),
+            None => debug!("[unhandled unhook]"),
+        }
     }
 
     // TODO replace OSC parsing with parser combinators
@@ -794,17 +1644,47 @@ where
             // This is ignored, since alacritty has no concept of tabs
             b"1" => return,
 
+            // Start or end a hyperlink
+            b"8" if params.len() > 2 => {
+                let id = params[1]
+                    .split(|&b| b == b':')
+                    .find(|param| param.starts_with(b"id="))
+                    .and_then(|param| str::from_utf8(&param[3..]).ok())
+                    .map(str::to_owned);
+                // The URI can contain semicolons, which `vte` splits it at
+                let uri = params[2..].join(&b';');
+                match str::from_utf8(&uri) {
+                    Ok("") => self.handler.set_hyperlink(None),
+                    Ok(uri) => self.handler.set_hyperlink(Some(Hyperlink {
+                        id,
+                        uri: uri.to_owned(),
+                    })),
+                    Err(_) => unhandled(params),
+                }
+            }
+
+            // Desktop notification; ConEmu uses OSC 9 with a number for other things, like progress
+            b"9" if params.len() > 1 && !params[1].iter().all(u8::is_ascii_digit) => {
+                // The text can contain semicolons, which `vte` splits it at
+                match String::from_utf8(params[1..].join(&b';')) {
+                    Ok(body) => self.handler.notify(Notification { title: None, body }),
+                    Err(_) => unhandled(params),
+                }
+            }
+
             // Set color index
             b"4" => {
                 if params.len() > 1 && params.len() % 2 != 0 {
+                    // Any number of index and color pairs
                     for chunk in params[1..].chunks(2) {
                         let index = parse_number(chunk[0]);
                         let color = parse_rgb_color(chunk[1]);
-                        if let (Some(i), Some(c)) = (index, color) {
-                            self.handler.set_color(i as usize, c);
-                            return;
+                        match (index, color) {
+                            (Some(i), Some(c)) => self.handler.set_color(i as usize, c),
+                            _ => unhandled(params),
                         }
                     }
+                    return;
                 }
                 unhandled(params);
             }
@@ -908,6 +1788,34 @@ where
             // Reset text cursor color
             b"112" => self.handler.reset_color(NamedColor::Cursor as usize),
 
+            // Semantic prompts; the options after the mark, like the exit status, are ignored
+            b"133" if params.len() > 1 => {
+                let mark = match params[1].get(0) {
+                    Some(b'A') => PromptMarks::PROMPT,
+                    Some(b'B') => PromptMarks::COMMAND,
+                    Some(b'C') => PromptMarks::OUTPUT,
+                    Some(b'D') => PromptMarks::END,
+                    _ => return unhandled(params),
+                };
+                self.handler.prompt_mark(mark);
+            }
+
+            // Desktop notification with a title, as `notify;TITLE;BODY`
+            b"777" if params.len() > 2 && params[1] == b"notify" => {
+                let title = str::from_utf8(params[2]);
+                let body = String::from_utf8(params[3..].join(&b';'));
+                match (title, body) {
+                    (Ok(title), Ok(body)) => self.handler.notify(Notification {
+                        title: Some(title.to_owned()),
+                        body,
+                    }),
+                    _ => unhandled(params),
+                }
+            }
+
+            // Inline images, which `Processor` takes out before they get here
+            b"1337" if params.len() > 1 && params[1].starts_with(b"File=") => (),
+
             _ => unhandled(params),
         }
     }
@@ -936,6 +1844,24 @@ where
             };
         }
 
+        // The top, left, bottom and right parameters of a rectangular area operation
+        macro_rules! area {
+            (idx: $idx:expr) => {
+                Area {
+                    top: Line(arg_or_default!(idx: $idx, default: 1) as usize - 1),
+                    left: Column(arg_or_default!(idx: $idx + 1, default: 1) as usize - 1),
+                    bottom: Line(
+                        arg_or_default!(idx: $idx + 2, default: handler.lines().0 as _) as usize
+                            - 1,
+                    ),
+                    right: Column(
+                        arg_or_default!(idx: $idx + 3, default: handler.cols().0 as _) as usize
+                            - 1,
+                    ),
+                }
+            };
+        }
+
         match action {
             '@' => handler.insert_blank(Column(arg_or_default!(idx: 0, default: 1) as usize)),
             'A' => {
@@ -951,7 +1877,7 @@ where
                 }
             }
             'B' | 'e' => handler.move_down(Line(arg_or_default!(idx: 0, default: 1) as usize)),
-            'c' => handler.identify_terminal(writer),
+            'c' => handler.identify_terminal(writer, intermediates.get(0).map(|&b| b as char)),
             'C' | 'a' => handler.move_forward(Column(arg_or_default!(idx: 0, default: 1) as usize)),
             'D' => handler.move_backward(Column(arg_or_default!(idx: 0, default: 1) as usize)),
             'E' => handler.move_down_and_cr(Line(arg_or_default!(idx: 0, default: 1) as usize)),
@@ -1000,6 +1926,10 @@ where
                 for arg in args {
                     let mode = Mode::from_primitive(private, *arg);
                     match mode {
+                        Some(Mode::Ansi) => {
+                            handler.unset_mode(Mode::Ansi);
+                            self.state.vt52 = Some(Vt52::Ground);
+                        }
                         Some(mode) => handler.unset_mode(mode),
                         None => unhandled!(),
                     }
@@ -1037,6 +1967,18 @@ where
                         1 => Attr::Bold,
                         2 => Attr::Dim,
                         3 => Attr::Italic,
+                        4 if self.state.is_subparam(i + 1) && i + 1 < args.len() => {
+                            i += 1;
+                            match args[i] {
+                                0 => Attr::CancelUnderline,
+                                1 => Attr::Underscore,
+                                2 => Attr::DoubleUnderline,
+                                3 => Attr::Undercurl,
+                                4 => Attr::DottedUnderline,
+                                5 => Attr::DashedUnderline,
+                                _ => unhandled!(),
+                            }
+                        }
                         4 => Attr::Underscore,
                         5 => Attr::BlinkSlow,
                         6 => Attr::BlinkFast,
@@ -1060,9 +2002,7 @@ where
                         36 => Attr::Foreground(Color::Named(NamedColor::Cyan)),
                         37 => Attr::Foreground(Color::Named(NamedColor::White)),
                         38 => {
-                            let mut start = 0;
-                            if let Some(color) = parse_color(&args[i..], &mut start) {
-                                i += start;
+                            if let Some(color) = parse_sgr_color(args, &mut i, self.state) {
                                 Attr::Foreground(color)
                             } else {
                                 break;
@@ -1078,15 +2018,21 @@ where
                         46 => Attr::Background(Color::Named(NamedColor::Cyan)),
                         47 => Attr::Background(Color::Named(NamedColor::White)),
                         48 => {
-                            let mut start = 0;
-                            if let Some(color) = parse_color(&args[i..], &mut start) {
-                                i += start;
+                            if let Some(color) = parse_sgr_color(args, &mut i, self.state) {
                                 Attr::Background(color)
                             } else {
                                 break;
                             }
                         }
                         49 => Attr::Background(Color::Named(NamedColor::Background)),
+                        58 => {
+                            if let Some(color) = parse_sgr_color(args, &mut i, self.state) {
+                                Attr::UnderlineColor(Some(color))
+                            } else {
+                                break;
+                            }
+                        }
+                        59 => Attr::UnderlineColor(None),
                         90 => Attr::Foreground(Color::Named(NamedColor::BrightBlack)),
                         91 => Attr::Foreground(Color::Named(NamedColor::BrightRed)),
                         92 => Attr::Foreground(Color::Named(NamedColor::BrightGreen)),
@@ -1127,10 +2073,53 @@ where
 
                 handler.set_scrolling_region(top..bottom);
             }
+            's' if handler.left_right_margin_mode() => {
+                let left = Column(arg_or_default!(idx: 0, default: 1) as usize - 1);
+                let right = arg_or_default!(idx: 1, default: handler.cols().0 as _) as usize;
+
+                handler.set_left_right_margins(left..Column(right));
+            }
             's' => handler.save_cursor_position(),
-            'u' => handler.restore_cursor_position(),
+            'u' if intermediates.is_empty() => handler.restore_cursor_position(),
+            'u' => {
+                let flags = args.get(0).cloned().unwrap_or(0);
+                let modes = KeyboardModes::from_bits_truncate(flags as u8);
+                match intermediates {
+                    b">" => handler.push_keyboard_modes(modes),
+                    b"<" => {
+                        handler.pop_keyboard_modes(arg_or_default!(idx: 0, default: 1) as usize)
+                    }
+                    b"=" => {
+                        let behavior = match arg_or_default!(idx: 1, default: 1) {
+                            1 => KeyboardModesApplyBehavior::Replace,
+                            2 => KeyboardModesApplyBehavior::Union,
+                            3 => KeyboardModesApplyBehavior::Difference,
+                            _ => unhandled!(),
+                        };
+                        handler.set_keyboard_modes(modes, behavior);
+                    }
+                    b"?" => handler.report_keyboard_modes(writer),
+                    _ => unhandled!(),
+                }
+            }
+            'v' if intermediates == b"$" => {
+                // The page numbers are ignored, since there is only one page
+                let line = Line(arg_or_default!(idx: 5, default: 1) as usize - 1);
+                let col = Column(arg_or_default!(idx: 6, default: 1) as usize - 1);
+                handler.copy_area(area!(idx: 0), (line, col));
+            }
+            'x' if intermediates == b"$" => {
+                let c = match args.get(0).map(|&c| c as u32).and_then(std::char::from_u32) {
+                    Some(c @ ' '..='~') | Some(c @ '\u{a0}'..='\u{ff}') => c,
+                    _ => unhandled!(),
+                };
+                handler.fill_area(c, area!(idx: 1));
+            }
+            'z' if intermediates == b"$" => handler.erase_area(area!(idx: 0), false),
+            '{' if intermediates == b"$" => handler.erase_area(area!(idx: 0), true),
             'q' => {
-                let style = match arg_or_default!(idx: 0, default: 0) {
+                let ps = arg_or_default!(idx: 0, default: 0);
+                let style = match ps {
                     0 => None,
                     1 | 2 => Some(CursorStyle::Block),
                     3 | 4 => Some(CursorStyle::Underline),
@@ -1139,6 +2128,12 @@ where
                 };
 
                 handler.set_cursor_style(style);
+                // The odd styles blink, and the default one is steady like the default cursor
+                if ps % 2 == 1 {
+                    handler.set_mode(Mode::BlinkingCursor);
+                } else {
+                    handler.unset_mode(Mode::BlinkingCursor);
+                }
             }
             _ => unhandled!(),
         }
@@ -1178,10 +2173,16 @@ where
             }
             b'H' => self.handler.set_horizontal_tabstop(),
             b'M' => self.handler.reverse_index(),
-            b'Z' => self.handler.identify_terminal(self.writer),
+            b'Z' => self.handler.identify_terminal(self.writer, None),
             b'c' => self.handler.reset_state(),
             b'0' => configure_charset!(StandardCharset::SpecialCharacterAndLineDrawing),
             b'7' => self.handler.save_cursor_position(),
+            b'3' if intermediates == b"#" => self.handler.set_line_size(LineSize::DoubleHeightTop),
+            b'4' if intermediates == b"#" => {
+                self.handler.set_line_size(LineSize::DoubleHeightBottom)
+            }
+            b'5' if intermediates == b"#" => self.handler.set_line_size(LineSize::Single),
+            b'6' if intermediates == b"#" => self.handler.set_line_size(LineSize::DoubleWidth),
             b'8' => {
                 if !intermediates.is_empty() && intermediates[0] == b'#' {
                     self.handler.dectest();
@@ -1198,6 +2199,25 @@ where
 }
 
 /// Parse a color specifier from list of attributes
+/// Parse the color of the `38`, `48` or `58` SGR parameter at `args[*i]` and move `i` to its last
+/// parameter.  With colons, an RGB color can have a color space ID before its components.
+fn parse_sgr_color(args: &[i64], i: &mut usize, state: &ProcessorState) -> Option<Color> {
+    let mut start = 0;
+    let color = if args.len() > *i + 5
+        && args[*i + 1] == 2
+        && (*i + 1..*i + 6).all(|j| state.is_subparam(j))
+    {
+        let rgb = [args[*i], 2, args[*i + 3], args[*i + 4], args[*i + 5]];
+        let color = parse_color(&rgb, &mut start);
+        start += 1;
+        color
+    } else {
+        parse_color(&args[*i..], &mut start)
+    };
+    *i += start;
+    color
+}
+
 fn parse_color(attrs: &[i64], i: &mut usize) -> Option<Color> {
     if attrs.len() < 2 {
         return None;
@@ -15,9 +15,13 @@
 //! A specialized 2d grid implementation optimized for use in a terminal.
 
 use std::cmp::{max, min, Ordering};
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
 use std::iter;
+use std::mem;
 use std::ops::{Deref, Index, IndexMut, Range, RangeFrom, RangeFull, RangeTo};
 
+use crate::ansi::PromptMarks;
 use crate::index;
 use crate::selection::Selection;
 
@@ -32,6 +36,17 @@ use self::storage::Storage;
 
 const MIN_INIT_SIZE: usize = 1_000;
 
+/// A cell that knows whether its row wraps onto the next one, so that rows can be rewrapped
+pub trait GridCell {
+    /// Whether the cell is blank, so that it can be dropped at the end of a line
+    fn is_empty(&self) -> bool;
+    /// Whether the row continues on the next one after this cell
+    fn is_wrap(&self) -> bool;
+    fn set_wrap(&mut self, wrap: bool);
+    /// Whether the cell holds a char that takes up this cell and the next
+    fn is_wide(&self) -> bool;
+}
+
 /// Bidirection iterator
 pub trait BidirectionalIterator: iter::Iterator {
     fn prev(&mut self) -> Option<Self::Item>;
@@ -55,6 +70,8 @@ impl<T> Deref for Indexed<T> {
 
 /// Represents the terminal display contents
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "UncheckedGrid<T>"))]
 pub struct Grid<T> {
     /// Lines in the grid. Each row holds a list of cells corresponding to the
     /// columns in that row.
@@ -79,9 +96,77 @@ pub struct Grid<T> {
     scroll_limit: usize,
 
     /// Selected region
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub selection: Option<Selection>,
 
     max_scroll_limit: usize,
+
+    /// Lines that changed since the damage was last taken
+    #[cfg_attr(feature = "serde", serde(skip))]
+    damage: Damage,
+}
+
+/// A grid that was deserialized, before it is known to be consistent
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct UncheckedGrid<T> {
+    raw: Storage<T>,
+    cols: index::Column,
+    lines: index::Line,
+    display_offset: usize,
+    scroll_limit: usize,
+    max_scroll_limit: usize,
+}
+
+#[cfg(feature = "serde")]
+impl<T> TryFrom<UncheckedGrid<T>> for Grid<T> {
+    type Error = &'static str;
+
+    fn try_from(grid: UncheckedGrid<T>) -> Result<Self, Self::Error> {
+        if grid.lines.0 == 0 || grid.cols.0 == 0 || grid.raw.screen_lines() != grid.lines {
+            return Err("the size of the grid doesn't match its lines");
+        }
+        if grid
+            .raw
+            .rows()
+            .any(|row| row.len() != grid.cols.0 || row.occ > grid.cols.0)
+        {
+            return Err("the size of the grid doesn't match its columns");
+        }
+        if grid.scroll_limit + grid.lines.0 > grid.raw.len()
+            || grid.display_offset > grid.scroll_limit
+        {
+            return Err("the scrollback is outside of the lines");
+        }
+        Ok(Grid {
+            raw: grid.raw,
+            cols: grid.cols,
+            lines: grid.lines,
+            display_offset: grid.display_offset,
+            scroll_limit: grid.scroll_limit,
+            selection: None,
+            max_scroll_limit: grid.max_scroll_limit,
+            damage: Damage::default(),
+        })
+    }
+}
+
+/// The lines of a grid that changed since `Grid::take_damage` was last called
+///
+/// This isn't part of what the grid holds, so grids compare equal whatever their damage.
+#[derive(Clone, Debug, Default)]
+struct Damage {
+    /// Whether all of the screen changed, like when the display scrolls
+    all: bool,
+    /// Whether each line of the active region changed; damage for a different number of lines
+    /// than the grid has counts as all of it
+    lines: Vec<bool>,
+}
+
+impl PartialEq for Damage {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
 }
 
 pub struct Iterator<'a, T> {
@@ -92,12 +177,21 @@ pub struct Iterator<'a, T> {
     pub cur: index::Point<usize>,
 }
 
+/// How to move the view of the history with `scroll_display`
 #[derive(Copy, Clone)]
 pub enum Scroll {
+    /// Up by a number of lines, or down for negative numbers
     Lines(isize),
+    /// Up by a screenful
     PageUp,
+    /// Down by a screenful
     PageDown,
+    /// So that a line, counted from the oldest line in the history, is at the top of the screen,
+    /// or as close to it as possible
+    ToLine(usize),
+    /// To the oldest line in the history
     Top,
+    /// Back to the live screen
     Bottom,
 }
 
@@ -119,6 +213,7 @@ impl<T: Copy + Clone> Grid<T> {
             scroll_limit: 0,
             selection: None,
             max_scroll_limit: scrollback,
+            damage: Damage::default(),
         }
     }
 
@@ -149,9 +244,27 @@ impl<T: Copy + Clone> Grid<T> {
         self.raw
             .update_history(history_size, Row::new(self.cols, &template));
         self.scroll_limit = min(self.scroll_limit, history_size);
+        self.damage_all();
+    }
+
+    /// Keep at most `limit` lines of history, dropping the oldest ones beyond it
+    pub fn set_max_scroll_limit(&mut self, limit: usize, template: &T) {
+        self.max_scroll_limit = limit;
+        if self.history_size() > limit {
+            self.raw
+                .update_history(limit, Row::new(self.cols, template));
+            // Free the dropped lines right away
+            self.raw.truncate();
+        }
+        self.scroll_limit = min(self.scroll_limit, limit);
+        if self.display_offset > self.scroll_limit {
+            self.display_offset = self.scroll_limit;
+            self.damage_all();
+        }
     }
 
     pub fn scroll_display(&mut self, scroll: Scroll) {
+        let offset = self.display_offset;
         match scroll {
             Scroll::Lines(count) => {
                 self.display_offset = min(
@@ -165,9 +278,15 @@ impl<T: Copy + Clone> Grid<T> {
             Scroll::PageDown => {
                 self.display_offset -= min(self.display_offset, self.lines.0);
             }
+            Scroll::ToLine(line) => {
+                self.display_offset = self.scroll_limit.saturating_sub(line);
+            }
             Scroll::Top => self.display_offset = self.scroll_limit,
             Scroll::Bottom => self.display_offset = 0,
         }
+        if self.display_offset != offset {
+            self.damage_all();
+        }
     }
 
     pub fn resize(&mut self, lines: index::Line, cols: index::Column, template: &T) {
@@ -175,6 +294,7 @@ impl<T: Copy + Clone> Grid<T> {
         if lines == self.lines && cols == self.cols {
             return;
         }
+        self.damage_all();
 
         match self.lines.cmp(&lines) {
             Ordering::Less => self.grow_lines(lines, template),
@@ -280,6 +400,8 @@ impl<T: Copy + Clone> Grid<T> {
         positions: index::Line,
         template: &T,
     ) {
+        self.damage_lines(region.clone());
+
         // Whether or not there is a scrolling region active, as long as it
         // starts at the top, we can do a full rotation which just involves
         // changing the start index.
@@ -321,6 +443,8 @@ impl<T: Copy + Clone> Grid<T> {
     ///
     /// This is the performance-sensitive part of scrolling.
     pub fn scroll_up(&mut self, region: &Range<index::Line>, positions: index::Line, template: &T) {
+        self.damage_lines(region.clone());
+
         if region.start == index::Line(0) {
             // Update display offset when not pinned to active area
             if self.display_offset != 0 {
@@ -387,6 +511,7 @@ impl<T> Grid<T> {
 
     pub fn clear_history(&mut self) {
         self.scroll_limit = 0;
+        self.damage_all();
     }
 
     #[inline]
@@ -438,6 +563,169 @@ impl<T> Grid<T> {
     pub fn display_offset(&self) -> usize {
         self.display_offset
     }
+
+    /// Mark `line` of the active region as changed, for `take_damage`.
+    #[inline]
+    pub fn damage_line(&mut self, line: index::Line) {
+        if let Some(damaged) = self.damage.lines.get_mut(line.0) {
+            *damaged = true;
+        }
+    }
+
+    /// Mark `lines` of the active region as changed, for `take_damage`.
+    pub fn damage_lines(&mut self, lines: Range<index::Line>) {
+        for line in index::Range(lines) {
+            self.damage_line(line);
+        }
+    }
+
+    /// Mark the whole screen as changed, for `take_damage`.
+    #[inline]
+    pub fn damage_all(&mut self) {
+        self.damage.all = true;
+    }
+
+    /// The ranges of displayed lines that changed since the last call, in order, or `None` if
+    /// all of them may have.  Changes to `selection` are not tracked.
+    pub fn take_damage(&mut self) -> Option<Vec<Range<usize>>> {
+        let lines = self.lines.0;
+        let damage = mem::replace(
+            &mut self.damage,
+            Damage {
+                all: false,
+                lines: vec![false; lines],
+            },
+        );
+        if damage.all || damage.lines.len() != lines {
+            return None;
+        }
+
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        for line in (0..lines).filter(|&line| damage.lines[line]) {
+            // Lines move down the screen when the display is scrolled back into the history
+            let shown = line + self.display_offset;
+            if shown >= lines {
+                break;
+            }
+            match ranges.last_mut() {
+                Some(range) if range.end == shown => range.end += 1,
+                _ => ranges.push(shown..shown + 1),
+            }
+        }
+        Some(ranges)
+    }
+}
+
+impl<T: GridCell + Copy + Clone> Grid<T> {
+    /// Change the number of columns, rewrapping the lines that were wrapped before to the new
+    /// width, history included.
+    ///
+    /// The `cursor` moves along with the text that it is on and stays on the same line of the
+    /// screen, with history moving in or out above it; `past_end` is whether the cursor is past
+    /// the last column, so that the next char wraps.  Lines below the cursor that no longer fit
+    /// on the screen are dropped.
+    pub fn reflow(
+        &mut self,
+        cols: index::Column,
+        cursor: &mut index::Point,
+        past_end: &mut bool,
+        template: &T,
+    ) {
+        if cols == self.cols {
+            return;
+        }
+        self.damage_all();
+
+        // The rows joined into the lines they were before wrapping, top to bottom, with the
+        // cursor as a line and an offset into it, and the prompt marks of each line
+        let cursor_row = self.line_to_offset(cursor.line);
+        let mut cursor_at = (0, 0);
+        let mut lines = Vec::new();
+        let mut line = Vec::new();
+        let mut marks = Vec::new();
+        let mut line_marks = PromptMarks::empty();
+        for i in (0..self.scroll_limit + *self.lines).rev() {
+            if i == cursor_row {
+                cursor_at = (
+                    lines.len(),
+                    line.len() + *cursor.col + usize::from(*past_end),
+                );
+            }
+            line.extend(self.raw[i].iter().cloned());
+            line_marks |= self.raw[i].marks;
+            match line.last_mut() {
+                Some(last) if last.is_wrap() => last.set_wrap(false),
+                _ => {
+                    lines.push(mem::take(&mut line));
+                    marks.push(mem::replace(&mut line_marks, PromptMarks::empty()));
+                }
+            }
+        }
+        if !line.is_empty() {
+            lines.push(line);
+            marks.push(line_marks);
+        }
+
+        // Blanks at the end of a line only fill up the row, except for the ones before the cursor
+        for (index, line) in lines.iter_mut().enumerate() {
+            let mut len = line
+                .iter()
+                .rposition(|cell| !cell.is_empty())
+                .map_or(0, |i| i + 1);
+            if index == cursor_at.0 {
+                len = max(len, cursor_at.1);
+            }
+            line.truncate(len);
+        }
+
+        let mut rows = Vec::new();
+        let mut cursor_pos = (0, 0);
+        for (index, line) in lines.iter().enumerate() {
+            let mut row = Row::new(cols, template);
+            row.marks = marks[index];
+            let mut col = 0;
+            for (offset, cell) in line.iter().enumerate() {
+                // A wide char moves to the next row as a whole
+                if col == *cols || (cell.is_wide() && col + 1 == *cols) {
+                    row[cols - 1].set_wrap(true);
+                    rows.push(mem::replace(&mut row, Row::new(cols, template)));
+                    col = 0;
+                }
+                if (index, offset) == cursor_at {
+                    cursor_pos = (rows.len(), col);
+                }
+                row[index::Column(col)] = *cell;
+                col += 1;
+            }
+            if (index, line.len()) == cursor_at {
+                cursor_pos = (rows.len(), col);
+            }
+            rows.push(row);
+        }
+
+        let (cursor_row, cursor_col) = cursor_pos;
+        let below = *self.lines - 1 - *cursor.line;
+        rows.resize(cursor_row + 1 + below, Row::new(cols, template));
+        while rows.len() < *self.lines {
+            rows.push(Row::new(cols, template));
+        }
+
+        // The oldest lines go if there is too much history now
+        let history = min(rows.len() - *self.lines, self.max_scroll_limit);
+        let dropped = rows.len() - *self.lines - history;
+        rows.drain(..dropped);
+
+        *past_end = cursor_col == *cols;
+        cursor.col = index::Column(min(cursor_col, *cols - 1));
+        cursor.line = index::Line(cursor_row - dropped - history);
+
+        rows.reverse();
+        self.raw = Storage::from_rows(rows, self.lines);
+        self.cols = cols;
+        self.scroll_limit = history;
+        self.display_offset = min(self.display_offset, history);
+        self.selection = None;
+    }
 }
 
 impl<'a, T> iter::Iterator for Iterator<'a, T> {
@@ -505,9 +793,17 @@ impl<T> Index<usize> for Grid<T> {
     }
 }
 
+impl<T> IndexMut<usize> for Grid<T> {
+    #[inline]
+    fn index_mut(&mut self, index: usize) -> &mut Row<T> {
+        &mut self.raw[index]
+    }
+}
+
 impl<T> IndexMut<index::Line> for Grid<T> {
     #[inline]
     fn index_mut(&mut self, index: index::Line) -> &mut Row<T> {
+        self.damage_line(index);
         &mut self.raw[index]
     }
 }
@@ -584,6 +880,7 @@ impl<T> IndexRegion<Range<index::Line>, T> for Grid<T> {
         assert!(index.start < self.num_lines());
         assert!(index.end <= self.num_lines());
         assert!(index.start <= index.end);
+        self.damage_lines(index.clone());
         RegionMut {
             start: index.start,
             end: index.end,
@@ -603,6 +900,7 @@ impl<T> IndexRegion<RangeTo<index::Line>, T> for Grid<T> {
     }
     fn region_mut(&mut self, index: RangeTo<index::Line>) -> RegionMut<'_, T> {
         assert!(index.end <= self.num_lines());
+        self.damage_lines(index::Line(0)..index.end);
         RegionMut {
             start: index::Line(0),
             end: index.end,
@@ -622,6 +920,8 @@ impl<T> IndexRegion<RangeFrom<index::Line>, T> for Grid<T> {
     }
     fn region_mut(&mut self, index: RangeFrom<index::Line>) -> RegionMut<'_, T> {
         assert!(index.start < self.num_lines());
+        let end = self.num_lines();
+        self.damage_lines(index.start..end);
         RegionMut {
             start: index.start,
             end: self.num_lines(),
@@ -640,6 +940,7 @@ impl<T> IndexRegion<RangeFull, T> for Grid<T> {
     }
 
     fn region_mut(&mut self, _: RangeFull) -> RegionMut<'_, T> {
+        self.damage_all();
         RegionMut {
             start: index::Line(0),
             end: self.num_lines(),
@@ -19,10 +19,12 @@ use std::ops::{Index, IndexMut};
 use std::ops::{Range, RangeFrom, RangeFull, RangeTo, RangeToInclusive};
 use std::slice;
 
+use crate::ansi::{LineSize, PromptMarks};
 use crate::index::Column;
 
 /// A row in the grid
 #[derive(Default, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Row<T> {
     inner: Vec<T>,
 
@@ -35,6 +37,14 @@ pub struct Row<T> {
     /// - 1 means there is a value at index zero, but nowhere else
     /// - `occ == inner.len` means every value is occupied
     pub(crate) occ: usize,
+
+    /// How big the characters on the line are
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub size: LineSize,
+
+    /// The parts of shell commands that start or end on the line
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub marks: PromptMarks,
 }
 
 impl<T: PartialEq> PartialEq for Row<T> {
@@ -48,6 +58,8 @@ impl<T: Copy + Clone> Row<T> {
         Self {
             inner: vec![*template; *columns],
             occ: 0,
+            size: LineSize::Single,
+            marks: PromptMarks::empty(),
         }
     }
 
@@ -68,6 +80,8 @@ impl<T: Copy + Clone> Row<T> {
         }
 
         self.occ = 0;
+        self.size = LineSize::Single;
+        self.marks = PromptMarks::empty();
     }
 }
 
@@ -11,11 +11,11 @@
 /// Because certain Vec operations are no longer valid on this type, no Deref
 /// implementation is provided. Anything from Vec that should be exposed must be
 /// done so manually.
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
 use std::ops::{Index, IndexMut};
 use std::slice;
 
-use static_assertions::assert_eq_size;
-
 use super::Row;
 use crate::index::Line;
 
@@ -23,6 +23,8 @@ use crate::index::Line;
 const TRUNCATE_STEP: usize = 100;
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "UncheckedStorage<T>"))]
 pub struct Storage<T> {
     inner: Vec<Row<T>>,
     zero: usize,
@@ -37,6 +39,36 @@ pub struct Storage<T> {
     len: usize,
 }
 
+/// A storage that was deserialized, before it is known to be consistent
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct UncheckedStorage<T> {
+    inner: Vec<Row<T>>,
+    zero: usize,
+    visible_lines: Line,
+    len: usize,
+}
+
+#[cfg(feature = "serde")]
+impl<T> TryFrom<UncheckedStorage<T>> for Storage<T> {
+    type Error = &'static str;
+
+    fn try_from(storage: UncheckedStorage<T>) -> Result<Self, Self::Error> {
+        if storage.zero >= storage.inner.len() {
+            return Err("the first line is outside of the lines");
+        }
+        if storage.len > storage.inner.len() || storage.visible_lines.0 >= storage.len {
+            return Err("the number of lines doesn't match the lines");
+        }
+        Ok(Storage {
+            inner: storage.inner,
+            zero: storage.zero,
+            visible_lines: storage.visible_lines,
+            len: storage.len,
+        })
+    }
+}
+
 impl<T: PartialEq> ::std::cmp::PartialEq for Storage<T> {
     fn eq(&self, other: &Self) -> bool {
         // Make sure length is equal
@@ -95,6 +127,17 @@ impl<T> Storage<T> {
         }
     }
 
+    /// Storage of `rows`, the bottom one first, of which the first `lines` are visible
+    pub fn from_rows(rows: Vec<Row<T>>, lines: Line) -> Self {
+        let len = rows.len();
+        Self {
+            inner: rows,
+            zero: 0,
+            visible_lines: lines - 1,
+            len,
+        }
+    }
+
     /// Update the size of the scrollback history
     pub fn update_history(&mut self, history_size: usize, template_row: Row<T>)
     where
@@ -196,6 +239,18 @@ impl<T> Storage<T> {
         self.len
     }
 
+    /// How many of the lines are on the screen
+    #[cfg(feature = "serde")]
+    pub fn screen_lines(&self) -> Line {
+        self.visible_lines + 1
+    }
+
+    /// Iterate over the rows that are in use, from the bottom one up
+    #[cfg(feature = "serde")]
+    pub fn rows(&self) -> impl Iterator<Item = &Row<T>> {
+        (0..self.len).map(move |index| &self.inner[self.compute_index(index)])
+    }
+
     /// Compute actual index in underlying storage given the requested index.
     fn compute_index(&self, requested: usize) -> usize {
         debug_assert!(requested < self.len);
@@ -217,37 +272,11 @@ impl<T> Storage<T> {
         self.inner.swap(a, b);
     }
 
-    /// Swap implementation for Row<T>.
-    ///
-    /// Exploits the known size of Row<T> to produce a slightly more efficient
-    /// swap than going through slice::swap.
-    ///
-    /// The default implementation from swap generates 8 movups and 4 movaps
-    /// instructions. This implementation achieves the swap in only 8 movups
-    /// instructions.
+    /// Swap two rows, by their index from the top of the visible lines.
     pub fn swap(&mut self, a: usize, b: usize) {
-        assert_eq_size!(Row<T>, [usize; 4]);
-
         let a = self.compute_index(a);
         let b = self.compute_index(b);
-
-        unsafe {
-            // Cast to a qword array to opt out of copy restrictions and avoid
-            // drop hazards. Byte array is no good here since for whatever
-            // reason LLVM won't optimized it.
-            let a_ptr = self.inner.as_mut_ptr().add(a) as *mut usize;
-            let b_ptr = self.inner.as_mut_ptr().add(b) as *mut usize;
-
-            // Copy 1 qword at a time
-            //
-            // The optimizer unrolls this loop and vectorizes it.
-            let mut tmp: usize;
-            for i in 0..4 {
-                tmp = *a_ptr.offset(i);
-                *a_ptr.offset(i) = *b_ptr.offset(i);
-                *b_ptr.offset(i) = tmp;
-            }
-        }
+        self.inner.swap(a, b);
     }
 
     /// Iterate over *all* entries in the underlying buffer
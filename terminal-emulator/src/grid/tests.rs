@@ -122,3 +122,25 @@ fn test_iter() {
     assert_eq!(None, final_iter.next());
     assert_eq!(Some(&23), final_iter.prev());
 }
+
+// Snapshots whose lines don't hold together are rejected
+#[cfg(feature = "serde")]
+#[test]
+fn deserialize_checks_the_lines() {
+    let grid = Grid::new(Line(3), Column(2), 5, 0usize);
+    let value = serde_json::to_value(&grid).unwrap();
+    assert_eq!(grid, serde_json::from_value(value.clone()).unwrap());
+
+    let corrupt = |pointer: &str, bad: serde_json::Value| {
+        let mut value = value.clone();
+        *value.pointer_mut(pointer).unwrap() = bad;
+        serde_json::from_value::<Grid<usize>>(value).is_err()
+    };
+    assert!(corrupt("/raw/zero", 1000.into()));
+    assert!(corrupt("/raw/len", 1000.into()));
+    assert!(corrupt("/raw/visible_lines", 7.into()));
+    assert!(corrupt("/lines", 7.into()));
+    assert!(corrupt("/cols", 7.into()));
+    assert!(corrupt("/scroll_limit", 1000.into()));
+    assert!(corrupt("/display_offset", 1.into()));
+}
@@ -0,0 +1,317 @@
+//! The bytes that a terminal sends to the program in it for the keys that are typed.
+//!
+//! How some keys are sent depends on modes that the program set: the cursor keys send SS3
+//! instead of CSI sequences in application cursor mode (`?1`), the keypad sends SS3 sequences
+//! instead of its characters in application keypad mode (`ESC =`), and Enter sends a line feed
+//! after the carriage return in new line mode (`20`).  In VT52 mode (`?2` reset) the cursor and
+//! PF keys send `ESC` and a letter, and the application keypad `ESC ?` and a letter.
+//!
+//! Programs can also ask for the keys that are ambiguous otherwise, or for all keys, to be sent
+//! as `CSI u` sequences with kitty's keyboard protocol.
+use bitflags::bitflags;
+
+use crate::term::TermMode;
+
+/// A key on the keyboard
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Key {
+    /// A key that types a character, already shifted if Shift is held
+    Char(char),
+    Enter,
+    Tab,
+    /// Shift+Tab
+    BackTab,
+    Backspace,
+    Escape,
+    Up,
+    Down,
+    Right,
+    Left,
+    Home,
+    End,
+    Insert,
+    Delete,
+    PageUp,
+    PageDown,
+    /// A function key, from F1 to F12
+    F(u8),
+    /// A key on the keypad that types a character, one of `0` to `9` and `*+,-./=`
+    Keypad(char),
+    KeypadEnter,
+}
+
+bitflags! {
+    /// The modifier keys that are held down
+    #[derive(Default)]
+    pub struct Modifiers: u8 {
+        const SHIFT = 0b001;
+        const ALT   = 0b010;
+        const CTRL  = 0b100;
+    }
+}
+
+/// The bytes to send for `key` with `mods` held down to a program in `mode`, or `None` if a
+/// terminal has nothing to send for it.
+pub fn encode(key: Key, mods: Modifiers, mode: TermMode) -> Option<Vec<u8>> {
+    let app_cursor = mode.contains(TermMode::APP_CURSOR);
+    let app_keypad = mode.contains(TermMode::APP_KEYPAD);
+    let new_line = mode.contains(TermMode::LINE_FEED_NEW_LINE);
+    // Modified keys other than characters say which modifiers are held, as xterm does
+    let modifier = if mods.is_empty() {
+        None
+    } else {
+        Some(1 + mods.bits())
+    };
+
+    let all_keys = mode.contains(TermMode::REPORT_ALL_KEYS);
+    if all_keys || mode.contains(TermMode::DISAMBIGUATE_KEYS) {
+        if let Some(bytes) = csi_u(key, mods, all_keys) {
+            return Some(bytes);
+        }
+    }
+
+    if mode.contains(TermMode::VT52) {
+        let letter = match key {
+            Key::Up => Some(b'A'),
+            Key::Down => Some(b'B'),
+            Key::Right => Some(b'C'),
+            Key::Left => Some(b'D'),
+            Key::F(n @ 1..=4) => Some(b'P' + n - 1),
+            _ => None,
+        };
+        if let Some(letter) = letter {
+            return Some(vec![0x1b, letter]);
+        }
+        match key {
+            Key::KeypadEnter if app_keypad => return Some(b"\x1b?M".to_vec()),
+            Key::Keypad(c) if app_keypad => return Some(vec![0x1b, b'?', keypad_letter(c)?]),
+            _ => (),
+        }
+    }
+
+    let mut bytes = Vec::new();
+    let mut buf = [0; 4];
+    match key {
+        Key::Char(c) => {
+            if mods.contains(Modifiers::ALT) {
+                bytes.push(0x1b);
+            }
+            match c {
+                ' ' | '@' | '2' if mods.contains(Modifiers::CTRL) => bytes.push(0),
+                '?' | '8' if mods.contains(Modifiers::CTRL) => bytes.push(0x7f),
+                // Ctrl+3 to Ctrl+7 are the control characters that have no letter
+                '3'..='7' if mods.contains(Modifiers::CTRL) => bytes.push(c as u8 - b'3' + 0x1b),
+                'a'..='z' | 'A'..='Z' | '[' | '\\' | ']' | '^' | '_'
+                    if mods.contains(Modifiers::CTRL) =>
+                {
+                    bytes.push(c as u8 & 0x1f)
+                }
+                _ => bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes()),
+            }
+        }
+        Key::KeypadEnter if app_keypad => bytes.extend_from_slice(b"\x1bOM"),
+        Key::Enter | Key::KeypadEnter => {
+            if mods.contains(Modifiers::ALT) {
+                bytes.push(0x1b);
+            }
+            bytes.extend_from_slice(if new_line { b"\r\n" } else { b"\r" });
+        }
+        Key::Tab => bytes.push(b'\t'),
+        Key::BackTab => bytes.extend_from_slice(b"\x1b[Z"),
+        Key::Backspace => {
+            if mods.contains(Modifiers::ALT) {
+                bytes.push(0x1b);
+            }
+            bytes.push(if mods.contains(Modifiers::CTRL) {
+                0x08
+            } else {
+                0x7f
+            });
+        }
+        Key::Escape => bytes.push(0x1b),
+        Key::Up => cursor(&mut bytes, b'A', modifier, app_cursor),
+        Key::Down => cursor(&mut bytes, b'B', modifier, app_cursor),
+        Key::Right => cursor(&mut bytes, b'C', modifier, app_cursor),
+        Key::Left => cursor(&mut bytes, b'D', modifier, app_cursor),
+        Key::Home => cursor(&mut bytes, b'H', modifier, app_cursor),
+        Key::End => cursor(&mut bytes, b'F', modifier, app_cursor),
+        Key::Insert => tilde(&mut bytes, 2, modifier),
+        Key::Delete => tilde(&mut bytes, 3, modifier),
+        Key::PageUp => tilde(&mut bytes, 5, modifier),
+        Key::PageDown => tilde(&mut bytes, 6, modifier),
+        Key::F(n @ 1..=4) => cursor(&mut bytes, b'P' + n - 1, modifier, true),
+        Key::F(n @ 5..=12) => {
+            // The numbers skip 16 and 22, like on the VT220
+            let number = match n {
+                5 => 15,
+                6..=10 => n + 11,
+                _ => n + 12,
+            };
+            tilde(&mut bytes, number, modifier);
+        }
+        Key::F(_) => return None,
+        Key::Keypad(c) if app_keypad => bytes.extend_from_slice(&[0x1b, b'O', keypad_letter(c)?]),
+        Key::Keypad(c) => bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes()),
+    }
+    Some(bytes)
+}
+
+/// The `CSI u` sequence of kitty's keyboard protocol for `key`, or `None` if it is sent the
+/// usual way.  Unless `all_keys` is set, keys that type text and Enter, Tab and Backspace only
+/// use it with modifiers, so that a shell stays usable after a program forgot to turn it off.
+fn csi_u(key: Key, mut mods: Modifiers, all_keys: bool) -> Option<Vec<u8>> {
+    let modified = all_keys || !mods.is_empty();
+    let code = match key {
+        // Shift only changes which character is typed
+        Key::Char(c) if all_keys || !(mods - Modifiers::SHIFT).is_empty() => {
+            if c.is_ascii_uppercase() {
+                mods |= Modifiers::SHIFT;
+            }
+            u32::from(c.to_ascii_lowercase())
+        }
+        Key::Escape => 27,
+        Key::Enter if modified => 13,
+        Key::Tab if modified => 9,
+        Key::BackTab if all_keys => {
+            mods |= Modifiers::SHIFT;
+            9
+        }
+        Key::Backspace if modified => 127,
+        // The keypad has its own keys in the private use area
+        Key::Keypad(c) => match c {
+            '0'..='9' => 57399 + (u32::from(c) - u32::from('0')),
+            '.' => 57409,
+            '/' => 57410,
+            '*' => 57411,
+            '-' => 57412,
+            '+' => 57413,
+            '=' => 57415,
+            ',' => 57416,
+            _ => return None,
+        },
+        Key::KeypadEnter => 57414,
+        _ => return None,
+    };
+
+    let sequence = if mods.is_empty() {
+        format!("\x1b[{}u", code)
+    } else {
+        format!("\x1b[{};{}u", code, 1 + mods.bits())
+    };
+    Some(sequence.into_bytes())
+}
+
+/// The letter that a key on the keypad sends in application keypad mode
+fn keypad_letter(c: char) -> Option<u8> {
+    Some(match c {
+        '0'..='9' => b'p' + (c as u8 - b'0'),
+        '*' => b'j',
+        '+' => b'k',
+        ',' => b'l',
+        '-' => b'm',
+        '.' => b'n',
+        '/' => b'o',
+        '=' => b'X',
+        _ => return None,
+    })
+}
+
+/// A cursor key, or another key that is sent like one, ending in `letter`
+fn cursor(bytes: &mut Vec<u8>, letter: u8, modifier: Option<u8>, ss3: bool) {
+    match modifier {
+        Some(modifier) => bytes.extend_from_slice(format!("\x1b[1;{}", modifier).as_bytes()),
+        None if ss3 => bytes.extend_from_slice(b"\x1bO"),
+        None => bytes.extend_from_slice(b"\x1b["),
+    }
+    bytes.push(letter);
+}
+
+/// A key that is sent as a number followed by a tilde
+fn tilde(bytes: &mut Vec<u8>, number: u8, modifier: Option<u8>) {
+    let sequence = match modifier {
+        Some(modifier) => format!("\x1b[{};{}~", number, modifier),
+        None => format!("\x1b[{}~", number),
+    };
+    bytes.extend_from_slice(sequence.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode, Key, Modifiers};
+    use crate::term::TermMode;
+
+    fn plain(key: Key, mode: TermMode) -> Vec<u8> {
+        encode(key, Modifiers::empty(), mode).unwrap()
+    }
+
+    #[test]
+    fn keys_follow_the_terminal_modes() {
+        let normal = TermMode::default();
+
+        assert_eq!(plain(Key::Up, normal), b"\x1b[A");
+        assert_eq!(plain(Key::Up, TermMode::APP_CURSOR), b"\x1bOA");
+        assert_eq!(plain(Key::Keypad('7'), normal), b"7");
+        assert_eq!(plain(Key::Keypad('7'), TermMode::APP_KEYPAD), b"\x1bOw");
+        assert_eq!(plain(Key::KeypadEnter, normal), b"\r");
+        assert_eq!(plain(Key::KeypadEnter, TermMode::APP_KEYPAD), b"\x1bOM");
+        assert_eq!(plain(Key::Enter, TermMode::LINE_FEED_NEW_LINE), b"\r\n");
+        assert_eq!(plain(Key::Up, TermMode::VT52), b"\x1bA");
+        assert_eq!(
+            plain(Key::Keypad('7'), TermMode::VT52 | TermMode::APP_KEYPAD),
+            b"\x1b?w"
+        );
+    }
+
+    #[test]
+    fn modifiers_are_encoded() {
+        let normal = TermMode::default();
+
+        assert_eq!(
+            encode(Key::Char('c'), Modifiers::CTRL, normal).unwrap(),
+            b"\x03"
+        );
+        assert_eq!(
+            encode(Key::Char('4'), Modifiers::CTRL, normal).unwrap(),
+            b"\x1c"
+        );
+        assert_eq!(
+            encode(Key::Char('x'), Modifiers::ALT, normal).unwrap(),
+            b"\x1bx"
+        );
+        // Modified cursor keys are the same in application cursor mode
+        assert_eq!(
+            encode(Key::Left, Modifiers::CTRL, TermMode::APP_CURSOR).unwrap(),
+            b"\x1b[1;5D"
+        );
+        assert_eq!(
+            encode(Key::F(5), Modifiers::SHIFT, normal).unwrap(),
+            b"\x1b[15;2~"
+        );
+        assert_eq!(plain(Key::F(1), normal), b"\x1bOP");
+        assert_eq!(plain(Key::F(12), normal), b"\x1b[24~");
+        assert_eq!(encode(Key::F(13), Modifiers::empty(), normal), None);
+    }
+
+    #[test]
+    fn kitty_keyboard_protocol_disambiguates_keys() {
+        let disambiguate = TermMode::DISAMBIGUATE_KEYS;
+        let all = TermMode::REPORT_ALL_KEYS;
+
+        assert_eq!(plain(Key::Escape, disambiguate), b"\x1b[27u");
+        assert_eq!(plain(Key::Char('a'), disambiguate), b"a");
+        assert_eq!(plain(Key::Enter, disambiguate), b"\r");
+        assert_eq!(
+            encode(Key::Char('i'), Modifiers::CTRL, disambiguate).unwrap(),
+            b"\x1b[105;5u"
+        );
+        assert_eq!(
+            encode(Key::Char('A'), Modifiers::ALT, disambiguate).unwrap(),
+            b"\x1b[97;4u"
+        );
+        assert_eq!(plain(Key::Keypad('1'), disambiguate), b"\x1b[57400u");
+        assert_eq!(plain(Key::Up, disambiguate), b"\x1b[A");
+
+        assert_eq!(plain(Key::Char('a'), all), b"\x1b[97u");
+        assert_eq!(plain(Key::Enter, all), b"\x1b[13u");
+    }
+}
@@ -1,13 +1,20 @@
 #[macro_use]
 extern crate log;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
 
 mod grid;
 mod index;
 
 pub mod ansi;
+pub mod input;
 pub mod mode;
+pub mod mouse;
 pub mod selection;
 pub mod term;
 
 pub use ansi::Handler;
 pub use ansi::Processor;
+pub use grid::Grid;
+pub use grid::Scroll;
@@ -0,0 +1,208 @@
+//! Mouse reports for programs that asked for them with the mouse tracking modes.
+//!
+//! Which events are reported depends on the tracking mode (`?1000` for clicks, `?1002` for drags
+//! as well and `?1003` for all motion), and how they are encoded on the extended coordinates mode:
+//! the X10 bytes by default, coordinates as UTF-8 characters with `?1005`, or the SGR sequences
+//! with `?1006`.  Programs on the alternate screen that don't ask for mouse reports get the wheel
+//! as arrow keys in alternate scroll mode (`?1007`).
+use crate::input::{self, Key, Modifiers};
+use crate::term::TermMode;
+
+/// The highest coordinate that the X10 encoding can express
+const X10_MAX: usize = 255 - 32;
+/// The highest coordinate that the UTF-8 encoding can express, with two bytes
+const UTF8_MAX: usize = 0x7ff - 32;
+
+/// How many lines a step of the mouse wheel scrolls in alternate scroll mode
+const ALTERNATE_SCROLL_LINES: usize = 3;
+
+/// A mouse button, where the wheel counts as two
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Button {
+    Left,
+    Middle,
+    Right,
+    WheelUp,
+    WheelDown,
+}
+
+/// What happened to the mouse
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    Press(Button),
+    Release(Button),
+    /// Moved while the button was held down
+    Drag(Button),
+    /// Moved while no button was held down
+    Move,
+}
+
+/// A mouse event, at a cell of the screen counted from the top left
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub action: Action,
+    pub line: usize,
+    pub col: usize,
+}
+
+/// The bytes that report `event` to a program in `mode`, or `None` if the program didn't ask for
+/// it or it is too far from the top left for the encoding.
+pub fn encode(event: &Event, mode: TermMode) -> Option<Vec<u8>> {
+    let motion = mode.contains(TermMode::MOUSE_MOTION);
+    let drag = motion || mode.contains(TermMode::MOUSE_DRAG);
+    let click = drag || mode.contains(TermMode::MOUSE_REPORT_CLICK);
+    let sgr = mode.contains(TermMode::SGR_MOUSE);
+
+    if !click {
+        if !mode.contains(TermMode::ALT_SCREEN | TermMode::ALTERNATE_SCROLL) {
+            return None;
+        }
+        let key = match event.action {
+            Action::Press(Button::WheelUp) => Key::Up,
+            Action::Press(Button::WheelDown) => Key::Down,
+            _ => return None,
+        };
+        let arrow = input::encode(key, Modifiers::empty(), mode)?;
+        let mut bytes = Vec::with_capacity(arrow.len() * ALTERNATE_SCROLL_LINES);
+        for _ in 0..ALTERNATE_SCROLL_LINES {
+            bytes.extend_from_slice(&arrow);
+        }
+        return Some(bytes);
+    }
+
+    let code = match event.action {
+        Action::Press(button) => button_code(button),
+        // The wheel is never released, and only SGR tells which button was
+        Action::Release(Button::WheelUp) | Action::Release(Button::WheelDown) => return None,
+        Action::Release(button) => {
+            if sgr {
+                button_code(button)
+            } else {
+                3
+            }
+        }
+        Action::Drag(button) if drag => button_code(button) + 32,
+        Action::Move if motion => 3 + 32,
+        _ => return None,
+    };
+
+    let (col, line) = (event.col + 1, event.line + 1);
+    if sgr {
+        let end = if let Action::Release(_) = event.action {
+            'm'
+        } else {
+            'M'
+        };
+        return Some(format!("\x1b[<{};{};{}{}", code, col, line, end).into_bytes());
+    }
+
+    let mut bytes = b"\x1b[M".to_vec();
+    bytes.push(32 + code);
+    if mode.contains(TermMode::UTF8_MOUSE) {
+        if col > UTF8_MAX || line > UTF8_MAX {
+            return None;
+        }
+        for &value in &[col, line] {
+            let mut buf = [0; 4];
+            let c = std::char::from_u32(32 + value as u32)?;
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+        }
+    } else {
+        if col > X10_MAX || line > X10_MAX {
+            return None;
+        }
+        #[allow(clippy::cast_possible_truncation)]
+        bytes.extend_from_slice(&[32 + col as u8, 32 + line as u8]);
+    }
+    Some(bytes)
+}
+
+fn button_code(button: Button) -> u8 {
+    match button {
+        Button::Left => 0,
+        Button::Middle => 1,
+        Button::Right => 2,
+        Button::WheelUp => 64,
+        Button::WheelDown => 65,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode, Action, Button, Event};
+    use crate::term::TermMode;
+
+    fn at(action: Action, col: usize, line: usize) -> Event {
+        Event { action, line, col }
+    }
+
+    #[test]
+    fn only_requested_events_are_reported() {
+        let press = at(Action::Press(Button::Left), 0, 0);
+        let drag = at(Action::Drag(Button::Left), 0, 0);
+        let motion = at(Action::Move, 0, 0);
+
+        assert_eq!(encode(&press, TermMode::default()), None);
+        assert_eq!(
+            encode(&press, TermMode::MOUSE_REPORT_CLICK),
+            Some(b"\x1b[M !!".to_vec())
+        );
+        assert_eq!(encode(&drag, TermMode::MOUSE_REPORT_CLICK), None);
+        assert_eq!(
+            encode(&drag, TermMode::MOUSE_DRAG),
+            Some(b"\x1b[M@!!".to_vec())
+        );
+        assert_eq!(encode(&motion, TermMode::MOUSE_DRAG), None);
+        assert_eq!(
+            encode(&motion, TermMode::MOUSE_MOTION),
+            Some(b"\x1b[MC!!".to_vec())
+        );
+    }
+
+    #[test]
+    fn wheel_scrolls_with_arrow_keys_on_the_alternate_screen() {
+        let wheel = at(Action::Press(Button::WheelDown), 0, 0);
+        let alt_screen = TermMode::default() | TermMode::ALT_SCREEN;
+
+        assert_eq!(encode(&wheel, TermMode::default()), None);
+        assert_eq!(
+            encode(&wheel, alt_screen),
+            Some(b"\x1b[B\x1b[B\x1b[B".to_vec())
+        );
+        assert_eq!(
+            encode(&wheel, alt_screen | TermMode::APP_CURSOR),
+            Some(b"\x1bOB\x1bOB\x1bOB".to_vec())
+        );
+        assert_eq!(
+            encode(&wheel, alt_screen - TermMode::ALTERNATE_SCROLL),
+            None
+        );
+        // Programs that ask for the mouse get the wheel itself
+        assert_eq!(
+            encode(&wheel, alt_screen | TermMode::MOUSE_REPORT_CLICK),
+            Some(b"\x1b[Ma!!".to_vec())
+        );
+    }
+
+    #[test]
+    fn events_are_encoded_like_the_program_asked() {
+        let click = TermMode::MOUSE_REPORT_CLICK;
+        let release = at(Action::Release(Button::Right), 9, 4);
+        let far = at(Action::Press(Button::WheelUp), 299, 0);
+
+        assert_eq!(encode(&release, click), Some(b"\x1b[M#*%".to_vec()));
+        assert_eq!(
+            encode(&release, click | TermMode::SGR_MOUSE),
+            Some(b"\x1b[<2;10;5m".to_vec())
+        );
+        assert_eq!(encode(&far, click), None);
+        assert_eq!(
+            encode(&far, click | TermMode::UTF8_MOUSE),
+            Some("\x1b[M`\u{14c}!".as_bytes().to_vec())
+        );
+        assert_eq!(
+            encode(&far, click | TermMode::SGR_MOUSE),
+            Some(b"\x1b[<64;300;1M".to_vec())
+        );
+    }
+}
@@ -460,7 +460,7 @@ mod test {
         fn semantic_search_right(&self, point: Point<usize>) -> Point<usize> {
             point
         }
-        fn url_search(&self, _: Point<usize>) -> Option<String> {
+        fn url_search(&self, _: Point<usize>) -> Option<crate::term::Url> {
             None
         }
     }
@@ -11,7 +11,12 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+
 use bitflags::bitflags;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::ansi::{Color, NamedColor};
 use crate::grid;
@@ -20,7 +25,15 @@ use crate::index::Column;
 // Maximum number of zerowidth characters which will be stored per cell.
 pub const MAX_ZEROWIDTH_CHARS: usize = 5;
 
+/// How many different runs of zero-width characters a terminal keeps; when there are more, the
+/// ones that no cell has anymore make room, and after that new ones are left out of cells
+const MAX_ZEROWIDTH_RUNS: usize = 65_536;
+
+/// The zero-width characters of a cell, padded with spaces
+type ZeroWidth = [char; MAX_ZEROWIDTH_CHARS];
+
 bitflags! {
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
     pub struct Flags: u16 {
         const INVERSE           = 0b00_0000_0001;
         const BOLD              = 0b00_0000_0010;
@@ -33,16 +46,206 @@ bitflags! {
         const DIM_BOLD          = 0b00_1000_0010;
         const HIDDEN            = 0b01_0000_0000;
         const STRIKEOUT         = 0b10_0000_0000;
+        const DOUBLE_UNDERLINE  = 0b00_0100_0000_0000;
+        const UNDERCURL         = 0b00_1000_0000_0000;
+        const DOTTED_UNDERLINE  = 0b01_0000_0000_0000;
+        const DASHED_UNDERLINE  = 0b10_0000_0000_0000;
+        const ALL_UNDERLINES    = 0b11_1100_0000_1000;
+        const BLINK             = 0b100_0000_0000_0000;
     }
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Cell {
     pub c: char,
     pub fg: Color,
     pub bg: Color,
     pub flags: Flags,
-    pub extra: [char; MAX_ZEROWIDTH_CHARS],
+    /// The number of the zero-width characters combined with `c`, which few cells have, in the
+    /// `ZeroWidthRuns` of the terminal
+    #[cfg_attr(feature = "serde", serde(default))]
+    extra: Option<NonZeroU32>,
+    /// The color of the underline, if not the foreground color
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub underline_color: Option<Color>,
+    /// The hyperlink that the cell is part of, for [`Term::hyperlink`]; links are lost when the
+    /// grid is serialized
+    ///
+    /// [`Term::hyperlink`]: ../struct.Term.html#method.hyperlink
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub hyperlink: Option<u32>,
+    /// The part of an inline image that the cell shows, for [`Term::image`]; images are lost
+    /// when the grid is serialized
+    ///
+    /// [`Term::image`]: ../struct.Term.html#method.image
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub image: Option<ImageCell>,
+}
+
+/// The runs of zero-width characters that the cells of a terminal have, so that a cell only needs
+/// the number of its run
+#[derive(Clone, Debug, Default)]
+pub struct ZeroWidthRuns {
+    /// The runs by their number minus one, with spaces for the numbers that are free
+    runs: Vec<ZeroWidth>,
+    numbers: HashMap<ZeroWidth, NonZeroU32>,
+    /// The numbers of runs that no cell had anymore
+    free: Vec<NonZeroU32>,
+}
+
+impl ZeroWidthRuns {
+    /// The character of `cell` followed by the zero-width characters combined with it, padded
+    /// with spaces
+    #[inline]
+    pub fn chars(&self, cell: &Cell) -> [char; MAX_ZEROWIDTH_CHARS + 1] {
+        let mut chars = [' '; MAX_ZEROWIDTH_CHARS + 1];
+        chars[0] = cell.c;
+        chars[1..].copy_from_slice(&self.run(cell.extra));
+        chars
+    }
+
+    /// Roughly how many bytes the runs take up
+    pub fn memory_usage(&self) -> usize {
+        // Each run is in the map too
+        self.runs.capacity() * std::mem::size_of::<ZeroWidth>() * 2
+    }
+
+    /// Combine the zero-width character `c` with the character of `cell`, unless it has as many
+    /// as it can hold already.  Returns `false` if there is no room for another run.
+    pub(crate) fn push(&mut self, cell: &mut Cell, c: char) -> bool {
+        let mut run = self.run(cell.extra);
+        match run.iter_mut().find(|elem| **elem == ' ') {
+            Some(elem) => *elem = c,
+            None => return true,
+        }
+        match self.number(run) {
+            Some(number) => {
+                cell.extra = Some(number);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Give `cell` from a terminal with `other` runs the same characters with these runs.
+    /// Returns `false` if there is no room for another run.
+    pub(crate) fn import(&mut self, cell: &mut Cell, other: &ZeroWidthRuns) -> bool {
+        if cell.extra.is_none() {
+            return true;
+        }
+        let number = self.number(other.run(cell.extra));
+        cell.extra = number;
+        number.is_some()
+    }
+
+    /// Free the runs that none of `cells` have, for new ones to take their numbers.
+    pub(crate) fn retain<'a>(&mut self, cells: impl Iterator<Item = &'a Cell>) {
+        let mut used = vec![false; self.runs.len()];
+        for number in cells.filter_map(|cell| cell.extra) {
+            if let Some(used) = used.get_mut(number.get() as usize - 1) {
+                *used = true;
+            }
+        }
+        for (index, used) in used.into_iter().enumerate() {
+            let run = &mut self.runs[index];
+            if !used && run[0] != ' ' {
+                if let Some(number) = self.numbers.remove(run) {
+                    self.free.push(number);
+                }
+                *run = [' '; MAX_ZEROWIDTH_CHARS];
+            }
+        }
+    }
+
+    /// The number of `run`, or `None` if there are too many runs already
+    fn number(&mut self, run: ZeroWidth) -> Option<NonZeroU32> {
+        if let Some(&number) = self.numbers.get(&run) {
+            return Some(number);
+        }
+        let number = match self.free.pop() {
+            Some(number) => number,
+            None if self.runs.len() < MAX_ZEROWIDTH_RUNS => {
+                self.runs.push(run);
+                #[allow(clippy::cast_possible_truncation)]
+                NonZeroU32::new(self.runs.len() as u32)?
+            }
+            None => {
+                debug!("Too many runs of zero-width characters, ignoring {:?}", run);
+                return None;
+            }
+        };
+        self.runs[number.get() as usize - 1] = run;
+        self.numbers.insert(run, number);
+        Some(number)
+    }
+
+    fn run(&self, number: Option<NonZeroU32>) -> ZeroWidth {
+        number
+            .and_then(|number| self.runs.get(number.get() as usize - 1))
+            .cloned()
+            .unwrap_or([' '; MAX_ZEROWIDTH_CHARS])
+    }
+}
+
+impl PartialEq for ZeroWidthRuns {
+    fn eq(&self, other: &ZeroWidthRuns) -> bool {
+        self.runs == other.runs
+    }
+}
+
+impl Eq for ZeroWidthRuns {}
+
+/// The runs are saved as a list, with spaces for free numbers
+#[cfg(feature = "serde")]
+impl Serialize for ZeroWidthRuns {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.runs.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for ZeroWidthRuns {
+    fn deserialize<D>(deserializer: D) -> Result<ZeroWidthRuns, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let runs = Vec::<ZeroWidth>::deserialize(deserializer)?;
+        if runs.len() > MAX_ZEROWIDTH_RUNS {
+            return Err(serde::de::Error::invalid_length(
+                runs.len(),
+                &"at most 65536 runs",
+            ));
+        }
+        let mut zero_width = ZeroWidthRuns::default();
+        for (index, &run) in runs.iter().enumerate() {
+            #[allow(clippy::cast_possible_truncation)]
+            let number = NonZeroU32::new(index as u32 + 1).unwrap();
+            if run[0] == ' ' {
+                zero_width.free.push(number);
+            } else {
+                zero_width.numbers.insert(run, number);
+            }
+        }
+        zero_width.runs = runs;
+        Ok(zero_width)
+    }
+}
+
+/// Where a cell is in an inline image
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ImageCell {
+    /// The image, for [`Term::image`]
+    ///
+    /// [`Term::image`]: ../struct.Term.html#method.image
+    pub id: u32,
+    /// The column of the image that the cell shows, counted from its left side
+    pub col: u16,
+    /// The line of the image that the cell shows, counted from its top
+    pub line: u16,
 }
 
 impl Default for Cell {
@@ -70,7 +273,7 @@ impl LineLength for grid::Row<Cell> {
         }
 
         for (index, cell) in self[..].iter().rev().enumerate() {
-            if cell.c != ' ' || cell.extra[0] != ' ' {
+            if cell.c != ' ' || cell.extra.is_some() {
                 length = Column(self.len() - index);
                 break;
             }
@@ -80,6 +283,28 @@ impl LineLength for grid::Row<Cell> {
     }
 }
 
+impl grid::GridCell for Cell {
+    #[inline]
+    fn is_empty(&self) -> bool {
+        Cell::is_empty(self)
+    }
+
+    #[inline]
+    fn is_wrap(&self) -> bool {
+        self.flags.contains(Flags::WRAPLINE)
+    }
+
+    #[inline]
+    fn set_wrap(&mut self, wrap: bool) {
+        self.flags.set(Flags::WRAPLINE, wrap);
+    }
+
+    #[inline]
+    fn is_wide(&self) -> bool {
+        self.flags.contains(Flags::WIDE_CHAR)
+    }
+}
+
 impl Cell {
     #[inline]
     pub fn bold(&self) -> bool {
@@ -98,22 +323,26 @@ impl Cell {
 
     pub fn new(c: char, fg: Color, bg: Color) -> Cell {
         Cell {
-            extra: [' '; MAX_ZEROWIDTH_CHARS],
+            extra: None,
             c,
             bg,
             fg,
             flags: Flags::empty(),
+            underline_color: None,
+            hyperlink: None,
+            image: None,
         }
     }
 
     #[inline]
     pub fn is_empty(&self) -> bool {
         (self.c == ' ' || self.c == '\t')
-            && self.extra[0] == ' '
+            && self.extra.is_none()
             && self.bg == Color::Named(NamedColor::Background)
+            && self.image.is_none()
             && !self
                 .flags
-                .intersects(Flags::INVERSE | Flags::UNDERLINE | Flags::STRIKEOUT)
+                .intersects(Flags::INVERSE | Flags::ALL_UNDERLINES | Flags::STRIKEOUT)
     }
 
     #[inline]
@@ -123,33 +352,14 @@ impl Cell {
     }
 
     #[inline]
-    pub fn chars(&self) -> [char; MAX_ZEROWIDTH_CHARS + 1] {
-        unsafe {
-            let mut chars = [std::mem::uninitialized(); MAX_ZEROWIDTH_CHARS + 1];
-            std::ptr::write(&mut chars[0], self.c);
-            std::ptr::copy_nonoverlapping(
-                self.extra.as_ptr(),
-                chars.as_mut_ptr().offset(1),
-                self.extra.len(),
-            );
-            chars
-        }
-    }
-
-    #[inline]
-    pub fn push_extra(&mut self, c: char) {
-        for elem in self.extra.iter_mut() {
-            if elem == &' ' {
-                *elem = c;
-                break;
-            }
-        }
+    pub fn clear_extra(&mut self) {
+        self.extra = None;
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Cell, LineLength};
+    use super::{Cell, LineLength, ZeroWidthRuns};
 
     use crate::grid::Row;
     use crate::index::Column;
@@ -171,6 +381,48 @@ mod tests {
 
         assert_eq!(row.line_length(), Column(10));
     }
+
+    #[test]
+    fn zero_width_characters_are_kept_outside_of_cells() {
+        let mut runs = ZeroWidthRuns::default();
+        let mut cell = Cell::default();
+        cell.c = 'e';
+        assert!(runs.push(&mut cell, '\u{301}'));
+        let mut other = cell;
+        assert!(runs.push(&mut other, '\u{302}'));
+
+        assert_eq!(runs.chars(&cell), ['e', '\u{301}', ' ', ' ', ' ', ' ']);
+        assert_eq!(
+            runs.chars(&other),
+            ['e', '\u{301}', '\u{302}', ' ', ' ', ' ']
+        );
+        other.clear_extra();
+        assert!(runs.push(&mut other, '\u{301}'));
+        assert_eq!(other, cell);
+        assert!(std::mem::size_of::<Cell>() <= 48);
+
+        // The run that no cell has anymore makes room for a new one
+        runs.retain(std::iter::once(&cell));
+        let mut other = Cell::default();
+        assert!(runs.push(&mut other, '\u{20e3}'));
+        assert_eq!(runs.chars(&other), [' ', '\u{20e3}', ' ', ' ', ' ', ' ']);
+        assert_eq!(runs.chars(&cell), ['e', '\u{301}', ' ', ' ', ' ', ' ']);
+        assert_eq!(runs.runs.len(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn zero_width_runs_are_serialized() {
+        let mut runs = ZeroWidthRuns::default();
+        let mut cell = Cell::default();
+        runs.push(&mut cell, '\u{200d}');
+        let serialized = serde_json::to_string(&runs).unwrap();
+
+        let value: serde_json::Value = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(value[0][0], "\u{200d}");
+        let runs = serde_json::from_str::<ZeroWidthRuns>(&serialized).unwrap();
+        assert_eq!(runs.chars(&cell)[1], '\u{200d}');
+    }
 }
 
 #[cfg(all(test, feature = "bench"))]
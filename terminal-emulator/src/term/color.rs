@@ -0,0 +1,157 @@
+//! The RGB values that cells are drawn in, for embedders that don't leave colors to another
+//! terminal.
+use std::ops::{Index, IndexMut};
+
+use crate::ansi::{NamedColor, Rgb};
+
+use super::COLOR_COUNT;
+
+/// The usual xterm values of the 16 standard colors
+const ANSI: [Rgb; 16] = [
+    Rgb { r: 0, g: 0, b: 0 },
+    Rgb { r: 205, g: 0, b: 0 },
+    Rgb { r: 0, g: 205, b: 0 },
+    Rgb {
+        r: 205,
+        g: 205,
+        b: 0,
+    },
+    Rgb { r: 0, g: 0, b: 238 },
+    Rgb {
+        r: 205,
+        g: 0,
+        b: 205,
+    },
+    Rgb {
+        r: 0,
+        g: 205,
+        b: 205,
+    },
+    Rgb {
+        r: 229,
+        g: 229,
+        b: 229,
+    },
+    Rgb {
+        r: 127,
+        g: 127,
+        b: 127,
+    },
+    Rgb { r: 255, g: 0, b: 0 },
+    Rgb { r: 0, g: 255, b: 0 },
+    Rgb {
+        r: 255,
+        g: 255,
+        b: 0,
+    },
+    Rgb {
+        r: 92,
+        g: 92,
+        b: 255,
+    },
+    Rgb {
+        r: 255,
+        g: 0,
+        b: 255,
+    },
+    Rgb {
+        r: 0,
+        g: 255,
+        b: 255,
+    },
+    Rgb {
+        r: 255,
+        g: 255,
+        b: 255,
+    },
+];
+
+/// The levels of each component in the 6×6×6 color cube of the 256-color palette
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// The RGB value of every color that a cell can have, indexed like `Term`'s dynamic colors: the
+/// 256 indexed colors, and then the named colors from `NamedColor::Foreground` on.
+#[derive(Copy, Clone)]
+pub struct List([Rgb; COLOR_COUNT]);
+
+impl Default for List {
+    fn default() -> List {
+        let mut list = [Rgb::default(); COLOR_COUNT];
+        list[..16].copy_from_slice(&ANSI);
+        for (i, rgb) in list[16..232].iter_mut().enumerate() {
+            *rgb = Rgb {
+                r: CUBE_LEVELS[i / 36],
+                g: CUBE_LEVELS[i / 6 % 6],
+                b: CUBE_LEVELS[i % 6],
+            };
+        }
+        for (i, rgb) in list[232..256].iter_mut().enumerate() {
+            #[allow(clippy::cast_possible_truncation)]
+            let level = 8 + 10 * i as u8;
+            *rgb = Rgb {
+                r: level,
+                g: level,
+                b: level,
+            };
+        }
+
+        let foreground = ANSI[7];
+        let background = ANSI[0];
+        list[NamedColor::Foreground as usize] = foreground;
+        list[NamedColor::Background as usize] = background;
+        list[NamedColor::CursorText as usize] = background;
+        list[NamedColor::Cursor as usize] = foreground;
+        list[NamedColor::BrightForeground as usize] = ANSI[15];
+        list[NamedColor::DimForeground as usize] = dim(foreground);
+
+        let dim_start = NamedColor::DimBlack as usize;
+        for (rgb, &normal) in list[dim_start..dim_start + 8].iter_mut().zip(&ANSI[..8]) {
+            *rgb = dim(normal);
+        }
+
+        List(list)
+    }
+}
+
+impl Index<usize> for List {
+    type Output = Rgb;
+
+    #[inline]
+    fn index(&self, index: usize) -> &Rgb {
+        &self.0[index]
+    }
+}
+
+impl IndexMut<usize> for List {
+    #[inline]
+    fn index_mut(&mut self, index: usize) -> &mut Rgb {
+        &mut self.0[index]
+    }
+}
+
+impl Index<NamedColor> for List {
+    type Output = Rgb;
+
+    #[inline]
+    fn index(&self, index: NamedColor) -> &Rgb {
+        &self.0[index as usize]
+    }
+}
+
+impl IndexMut<NamedColor> for List {
+    #[inline]
+    fn index_mut(&mut self, index: NamedColor) -> &mut Rgb {
+        &mut self.0[index as usize]
+    }
+}
+
+/// `rgb` at two thirds of its brightness, the default for the dim colors
+pub fn dim(rgb: Rgb) -> Rgb {
+    #[allow(clippy::cast_possible_truncation)]
+    let scale = |c: u8| (u16::from(c) * 2 / 3) as u8;
+    Rgb {
+        r: scale(rgb.r),
+        g: scale(rgb.g),
+        b: scale(rgb.b),
+    }
+}
@@ -13,8 +13,11 @@
 // limitations under the License.
 //
 //! Exports the `Term` type which is a high-level API for the Grid
-use std::cmp::min;
-use std::ops::{Index, IndexMut, Range};
+use std::cmp::{max, min, Reverse};
+use std::collections::{HashMap, VecDeque};
+use std::ops::{Index, IndexMut, Range, RangeInclusive};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 use std::{io, ptr};
 
@@ -22,16 +25,62 @@ use arraydeque::ArrayDeque;
 use unicode_width::UnicodeWidthChar;
 
 use crate::ansi::{
-    self, Attr, CharsetIndex, Color, CursorStyle, Handler, MouseCursor, NamedColor, StandardCharset,
+    self, Attr, CharsetIndex, Color, CursorStyle, Handler, ImageSize, KeyboardModes,
+    KeyboardModesApplyBehavior, LineSize, MouseCursor, NamedColor, PromptMarks, StandardCharset,
 };
 use crate::grid::{
-    BidirectionalIterator, DisplayIter, Grid, IndexRegion, Indexed, Scroll, ViewportPosition,
+    BidirectionalIterator, DisplayIter, Grid, IndexRegion, Indexed, Row, Scroll, ViewportPosition,
 };
 use crate::index;
 use crate::selection::{self, Locations, Selection};
-use crate::term::cell::{Cell, LineLength};
+use crate::term::cell::{Cell, ImageCell, LineLength, ZeroWidthRuns};
 
 pub mod cell;
+pub mod color;
+
+/// How many different hyperlinks cells can have at once; later ones are shown as plain text
+const MAX_HYPERLINKS: usize = 65_536;
+
+/// How many notifications are kept until they are taken; a program that sends more only keeps
+/// its latest ones
+const MAX_NOTIFICATIONS: usize = 16;
+
+/// How many inline images are kept; cells that show older ones only keep their placeholder
+const MAX_IMAGES: usize = 16;
+
+/// How many bytes of inline images are kept at most, as with `MAX_IMAGES`
+const MAX_IMAGE_BYTES: usize = 32 * 1024 * 1024;
+
+/// The most base64 encoded bytes of a kitty graphics image that are kept
+const MAX_GRAPHICS_UPLOAD: usize = 16 * 1024 * 1024;
+
+/// The size of a cell in pixels that inline images are measured with, since the terminal doesn't
+/// know how big cells really are
+const CELL_PIXELS: (usize, usize) = (8, 16);
+
+/// How many keyboard enhancements a screen keeps; pushing more drops the oldest
+const KEYBOARD_MODES_DEPTH: usize = 16;
+
+/// How many lines of history the normal screen keeps at most
+pub const MAX_HISTORY_LINES: usize = 1024;
+
+/// How long blinking text and cursors stay shown, and then hidden
+pub const BLINK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How many colors programs can change: the 256 indexed colors and the named ones after them
+const COLOR_COUNT: usize = NamedColor::DimForeground as usize + 1;
+
+/// The dim versions of the first 8 indexed colors
+const DIM_COLORS: [NamedColor; 8] = [
+    NamedColor::DimBlack,
+    NamedColor::DimRed,
+    NamedColor::DimGreen,
+    NamedColor::DimYellow,
+    NamedColor::DimBlue,
+    NamedColor::DimMagenta,
+    NamedColor::DimCyan,
+    NamedColor::DimWhite,
+];
 
 /// A type that can expand a given point to a region
 ///
@@ -42,8 +91,26 @@ pub trait Search {
     fn semantic_search_left(&self, _: index::Point<usize>) -> index::Point<usize>;
     /// Find the nearest semantic boundary _to the point_ of provided point.
     fn semantic_search_right(&self, _: index::Point<usize>) -> index::Point<usize>;
-    /// Find the nearest URL boundary in both directions.
-    fn url_search(&self, _: index::Point<usize>) -> Option<String>;
+    /// Find the URL that the provided point is part of, if any.
+    fn url_search(&self, _: index::Point<usize>) -> Option<Url>;
+}
+
+/// The schemes that text has to start with to be taken for a URL
+const URL_SCHEMES: &[&str] = &[
+    "http", "https", "ftp", "file", "git", "ssh", "mailto", "news",
+];
+
+/// A URL in the grid, as found by [`Search::url_search`]
+///
+/// [`Search::url_search`]: trait.Search.html#tymethod.url_search
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Url {
+    /// The text of the URL
+    pub text: String,
+    /// The cell that the URL starts in
+    pub start: index::Point<usize>,
+    /// The cell that the URL ends in, inclusive
+    pub end: index::Point<usize>,
 }
 
 impl Search for Term {
@@ -91,8 +158,116 @@ impl Search for Term {
         point
     }
 
-    fn url_search(&self, _: index::Point<usize>) -> Option<String> {
-        None // TODO
+    fn url_search(&self, mut point: index::Point<usize>) -> Option<Url> {
+        // Limit the starting point to the last line in the history
+        point.line = min(point.line, self.grid.len() - 1);
+
+        // The right half of a wide char belongs to the cell on its left
+        if point.col > index::Column(0)
+            && self.grid[point.line][point.col]
+                .flags
+                .contains(cell::Flags::WIDE_CHAR_SPACER)
+        {
+            point.col -= 1;
+        }
+
+        let start = &self.grid[point.line][point.col];
+        if is_url_separator(start.c) {
+            return None;
+        }
+        let last_col = self.grid.num_cols() - index::Column(1);
+
+        // The chars around the point that a URL could be made of, with their cells
+        let mut cells = vec![(start.c, point)];
+
+        let mut iter = self.grid.iter_from(point);
+        while let Some(cell) = iter.prev() {
+            if iter.cur.col == last_col && !cell.flags.contains(cell::Flags::WRAPLINE) {
+                break; // cut off if on new line
+            }
+            if cell.flags.contains(cell::Flags::WIDE_CHAR_SPACER) {
+                continue;
+            }
+            if is_url_separator(cell.c) {
+                break;
+            }
+            cells.push((cell.c, iter.cur));
+        }
+        cells.reverse();
+        let offset = cells.len() - 1;
+
+        let mut iter = self.grid.iter_from(point);
+        let mut cell = start;
+        while iter.cur.col != last_col || cell.flags.contains(cell::Flags::WRAPLINE) {
+            cell = match iter.next() {
+                Some(cell) => cell,
+                None => break,
+            };
+            if cell.flags.contains(cell::Flags::WIDE_CHAR_SPACER) {
+                continue;
+            }
+            if is_url_separator(cell.c) {
+                break;
+            }
+            cells.push((cell.c, iter.cur));
+        }
+
+        let chars = cells.iter().map(|&(c, _)| c).collect::<Vec<_>>();
+
+        // The scheme closest to the left of the point, that isn't the end of some other word
+        let (begin, scheme_len) = (0..=offset).rev().find_map(|i| {
+            if i > 0 && chars[i - 1].is_alphanumeric() {
+                return None;
+            }
+            url_scheme_len(&chars[i..]).map(|len| (i, len))
+        })?;
+
+        let mut end = chars.len();
+        while end > begin && has_url_trailer(&chars[begin..end]) {
+            end -= 1;
+        }
+        if end <= begin + scheme_len || offset >= end {
+            return None;
+        }
+
+        Some(Url {
+            text: chars[begin..end].iter().collect(),
+            start: cells[begin].1,
+            end: cells[end - 1].1,
+        })
+    }
+}
+
+/// Whether `c` can't be part of a URL, so that a URL stops there.
+fn is_url_separator(c: char) -> bool {
+    match c {
+        '<' | '>' | '"' | '\'' | '`' | '{' | '}' | '|' | '\\' | '^' => true,
+        c => c.is_whitespace() || c.is_control(),
+    }
+}
+
+/// The length of the scheme that `chars` starts with, including its `:`.
+fn url_scheme_len(chars: &[char]) -> Option<usize> {
+    URL_SCHEMES.iter().find_map(|scheme| {
+        let len = scheme.len();
+        if chars.len() > len && chars[len] == ':' && scheme.chars().eq(chars[..len].iter().cloned())
+        {
+            Some(len + 1)
+        } else {
+            None
+        }
+    })
+}
+
+/// Whether the last char of `url` more likely belongs to the text around it, like the period at
+/// the end of a sentence or the closing parenthesis around the URL.
+fn has_url_trailer(url: &[char]) -> bool {
+    let count = |c: char| url.iter().filter(|&&u| u == c).count();
+    match url.last() {
+        Some('.') | Some(',') | Some(':') | Some(';') | Some('?') | Some('!') => true,
+        Some(')') => count('(') < count(')'),
+        Some(']') => count('[') < count(']'),
+        _ => false,
     }
 }
 
@@ -116,6 +291,7 @@ impl selection::Dimensions for Term {
 pub struct RenderableCellsIter<'a> {
     inner: DisplayIter<'a, Cell>,
     grid: &'a Grid<Cell>,
+    zero_width: &'a ZeroWidthRuns,
     cursor: &'a index::Point,
     cursor_offset: usize,
     mode: TermMode,
@@ -130,6 +306,7 @@ impl<'a> RenderableCellsIter<'a> {
     /// cursor.
     fn new<'b>(
         grid: &'b Grid<Cell>,
+        zero_width: &'b ZeroWidthRuns,
         cursor: &'b index::Point,
         mode: TermMode,
         selection: Option<Locations>,
@@ -193,6 +370,7 @@ impl<'a> RenderableCellsIter<'a> {
             cursor,
             cursor_offset,
             grid,
+            zero_width,
             inner,
             mode,
             selection: selection_range,
@@ -313,6 +491,18 @@ impl<'a> RenderableCellsIter<'a> {
         self
     }
 
+    /// Check if the cell at a display position is part of the selection
+    #[inline]
+    fn is_selected(&self, line: index::Line, column: index::Column) -> bool {
+        use crate::index::Contains;
+
+        let index = index::Linear(line.0 * self.grid.num_cols().0 + column.0);
+        self.selection
+            .as_ref()
+            .map(|range| range.contains_(index))
+            .unwrap_or(false)
+    }
+
     /// Check if the cursor should be rendered.
     #[inline]
     fn cursor_is_visible(&self) -> bool {
@@ -339,9 +529,13 @@ impl<'a> RenderableCellsIter<'a> {
             Color::Indexed(idx) => {
                 let idx = match (cell.flags & Flags::DIM_BOLD, idx) {
                     (self::cell::Flags::BOLD, 0..=7) => idx + 8,
-                    (self::cell::Flags::DIM, 8..=15) => idx - 8,
-                    // TODO
-                    // (self::cell::Flags::DIM, 0..=7) => idx as usize + 260,
+                    // Like the named colors, dim wins over bold
+                    (self::cell::Flags::DIM, 8..=15) | (self::cell::Flags::DIM_BOLD, 8..=15) => {
+                        idx - 8
+                    }
+                    (self::cell::Flags::DIM, 0..=7) | (self::cell::Flags::DIM_BOLD, 0..=7) => {
+                        return Color::Named(DIM_COLORS[usize::from(idx)]);
+                    }
                     _ => idx,
                 };
 
@@ -360,6 +554,27 @@ pub struct RenderableCell {
     pub fg: Color,
     pub bg: Color,
     pub flags: cell::Flags,
+    /// The color of the underline, if not `fg`
+    pub underline_color: Option<Color>,
+    /// The hyperlink that the cell is part of, for `Term::hyperlink`
+    pub hyperlink: Option<u32>,
+    /// The part of an inline image that the cell shows, for `Term::image`
+    pub image: Option<ImageCell>,
+    /// How big the characters on the cell's line are
+    pub line_size: LineSize,
+    /// Whether the cell is part of the selection, which is usually drawn highlighted
+    pub selected: bool,
+}
+
+/// A `RenderableCell` with its colors resolved to RGB, from `Term::renderable_cells_rgb`
+#[derive(Copy, Clone, Debug)]
+pub struct RgbCell {
+    pub cell: RenderableCell,
+    /// The colors that the cell is drawn in, already swapped if it is `INVERSE`
+    pub fg: ansi::Rgb,
+    pub bg: ansi::Rgb,
+    /// The color of the underline, which is `fg` unless the program chose another one
+    pub underline: ansi::Rgb,
 }
 
 impl<'a> Iterator for RenderableCellsIter<'a> {
@@ -373,7 +588,7 @@ impl<'a> Iterator for RenderableCellsIter<'a> {
     fn next(&mut self) -> Option<Self::Item> {
         loop {
             // Handle cursor
-            let cell = if self.cursor_offset == self.inner.offset()
+            let (cell, selected) = if self.cursor_offset == self.inner.offset()
                 && self.inner.column() == self.cursor.col
             {
                 // Cursor cell
@@ -386,39 +601,39 @@ impl<'a> Iterator for RenderableCellsIter<'a> {
                 if self.cursor_cells.is_empty() {
                     self.inner.next();
                 }
-                cell
+                let selected = self.is_selected(cell.line, cell.column);
+                (cell, selected)
             } else {
-                use crate::index::Contains;
-
                 let cell = self.inner.next()?;
-
-                let index = index::Linear(cell.line.0 * self.grid.num_cols().0 + cell.column.0);
-
-                let selected = self
-                    .selection
-                    .as_ref()
-                    .map(|range| range.contains_(index))
-                    .unwrap_or(false);
+                let selected = self.is_selected(cell.line, cell.column);
 
                 // Skip empty cells
                 if cell.is_empty() && !selected {
                     continue;
                 }
 
-                cell
+                (cell, selected)
             };
 
             // Apply inversion and lookup RGB values
             let fg = self.compute_fg(cell.fg, &cell);
             let bg = cell.bg;
+            let line_size = self.grid
+                [self.grid.display_offset() + *self.grid.num_lines() - 1 - cell.line.0]
+                .size;
 
             return Some(RenderableCell {
                 line: cell.line,
                 column: cell.column,
                 flags: cell.flags,
-                chars: cell.chars(),
+                chars: self.zero_width.chars(&cell),
                 fg,
                 bg,
+                underline_color: cell.underline_color,
+                hyperlink: cell.hyperlink,
+                image: cell.image,
+                line_size,
+                selected,
             });
         }
     }
@@ -428,7 +643,8 @@ pub mod mode {
     use bitflags::bitflags;
 
     bitflags! {
-        pub struct TermMode: u16 {
+        #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+        pub struct TermMode: u32 {
             const SHOW_CURSOR         = 0b00_0000_0000_0001;
             const APP_CURSOR          = 0b00_0000_0000_0010;
             const APP_KEYPAD          = 0b00_0000_0000_0100;
@@ -442,15 +658,22 @@ pub mod mode {
             const INSERT              = 0b00_0100_0000_0000;
             const FOCUS_IN_OUT        = 0b00_1000_0000_0000;
             const ALT_SCREEN          = 0b01_0000_0000_0000;
-            const MOUSE_DRAG          = 0b10_0000_0000_0000;
-            const ANY                 = 0b11_1111_1111_1111;
+            const MOUSE_DRAG          = 0b010_0000_0000_0000;
+            const BLINKING_CURSOR     = 0b100_0000_0000_0000;
+            const UTF8_MOUSE          = 0b1000_0000_0000_0000;
+            const DISAMBIGUATE_KEYS   = 0b0001_0000_0000_0000_0000;
+            const REPORT_ALL_KEYS     = 0b0010_0000_0000_0000_0000;
+            const ALTERNATE_SCROLL    = 0b0100_0000_0000_0000_0000;
+            const LEFT_RIGHT_MARGIN   = 0b0_1000_0000_0000_0000_0000;
+            const VT52                = 0b1_0000_0000_0000_0000_0000;
+            const ANY                 = 0b1_1111_1111_1111_1111_1111;
             const NONE                = 0;
         }
     }
 
     impl Default for TermMode {
         fn default() -> TermMode {
-            TermMode::SHOW_CURSOR | TermMode::LINE_WRAP
+            TermMode::SHOW_CURSOR | TermMode::LINE_WRAP | TermMode::ALTERNATE_SCROLL
         }
     }
 }
@@ -509,6 +732,7 @@ impl CharsetMapping for StandardCharset {
 }
 
 #[derive(Default, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 struct Charsets([StandardCharset; 4]);
 
 impl Index<CharsetIndex> for Charsets {
@@ -525,6 +749,7 @@ impl IndexMut<CharsetIndex> for Charsets {
 }
 
 #[derive(Default, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Cursor {
     /// The location of this cursor
     pub point: index::Point,
@@ -540,7 +765,31 @@ impl Cursor {
     pub fn attributes(&self) -> Cell { self.template }
 }
 
+/// How the intensity of the visual bell ramps down, named after the CSS easing functions
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum VisualBellAnimation {
+    Ease,
+    EaseOut,
+    EaseOutSine,
+    EaseOutQuad,
+    EaseOutCubic,
+    EaseOutQuart,
+    EaseOutQuint,
+    EaseOutExpo,
+    EaseOutCirc,
+    Linear,
+}
+
+impl Default for VisualBellAnimation {
+    fn default() -> Self {
+        VisualBellAnimation::EaseOutExpo
+    }
+}
+
 pub struct VisualBell {
+    /// Visual bell animation
+    animation: VisualBellAnimation,
+
     /// Visual bell duration
     duration: Duration,
 
@@ -551,22 +800,57 @@ pub struct VisualBell {
 impl VisualBell {
     pub fn new() -> VisualBell {
         VisualBell {
+            animation: VisualBellAnimation::default(),
             duration: Duration::from_secs(1),
             start_time: None,
         }
     }
 
+    /// Change how the visual bell ramps down, and for how long; a zero `duration` disables it.
+    pub fn configure(&mut self, animation: VisualBellAnimation, duration: Duration) {
+        self.animation = animation;
+        self.duration = duration;
+    }
+
     /// Ring the visual bell, and return its intensity.
     pub fn ring(&mut self) -> f64 {
         let now = Instant::now();
         self.start_time = Some(now);
-        0.0
+        self.intensity_at_instant(now)
     }
 
     /// Get the currently intensity of the visual bell. The bell's intensity
     /// ramps down from 1.0 to 0.0 at a rate determined by the bell's duration.
     pub fn intensity(&self) -> f64 {
-        0.0
+        self.intensity_at_instant(Instant::now())
+    }
+
+    fn intensity_at_instant(&self, instant: Instant) -> f64 {
+        // A bell that is disabled, hasn't rung or rings later is off
+        let earlier = match self.start_time {
+            Some(earlier) if self.duration > Duration::from_secs(0) && instant >= earlier => {
+                earlier
+            }
+            _ => return 0.0,
+        };
+
+        // How far along the ramp the bell is, from 0.0 to 1.0
+        let time = (seconds(instant.duration_since(earlier)) / seconds(self.duration)).min(1.0);
+        let progress = match self.animation {
+            VisualBellAnimation::Ease => cubic_bezier(0.25, 0.1, 0.25, 1.0, time),
+            VisualBellAnimation::EaseOut => cubic_bezier(0.0, 0.0, 0.58, 1.0, time),
+            VisualBellAnimation::EaseOutSine => cubic_bezier(0.39, 0.575, 0.565, 1.0, time),
+            VisualBellAnimation::EaseOutQuad => cubic_bezier(0.25, 0.46, 0.45, 0.94, time),
+            VisualBellAnimation::EaseOutCubic => cubic_bezier(0.215, 0.61, 0.355, 1.0, time),
+            VisualBellAnimation::EaseOutQuart => cubic_bezier(0.165, 0.84, 0.44, 1.0, time),
+            VisualBellAnimation::EaseOutQuint => cubic_bezier(0.23, 1.0, 0.32, 1.0, time),
+            VisualBellAnimation::EaseOutExpo => cubic_bezier(0.19, 1.0, 0.22, 1.0, time),
+            VisualBellAnimation::EaseOutCirc => cubic_bezier(0.075, 0.82, 0.165, 1.0, time),
+            VisualBellAnimation::Linear => time,
+        };
+
+        // The intensity decays as the animation progresses
+        1.0 - progress
     }
 
     /// Check whether or not the visual bell has completed "ringing".
@@ -583,6 +867,83 @@ impl VisualBell {
     }
 }
 
+/// The value at `x`, from 0.0 to 1.0, of the easing curve with the control points `(x1, y1)` and
+/// `(x2, y2)`, like CSS's `cubic-bezier(x1, y1, x2, y2)`; the curve runs from (0, 0) to (1, 1).
+fn cubic_bezier(x1: f64, y1: f64, x2: f64, y2: f64, x: f64) -> f64 {
+    let bezier = |p1: f64, p2: f64, t: f64| {
+        3.0 * (1.0 - t).powi(2) * t * p1 + 3.0 * (1.0 - t) * t.powi(2) * p2 + t.powi(3)
+    };
+
+    // With both control points between 0 and 1 the curve only moves right, so the point on it
+    // that is at `x` can be found by halving
+    let (mut low, mut high) = (0.0, 1.0);
+    for _ in 0..64 {
+        let t = (low + high) / 2.0;
+        if bezier(x1, x2, t) < x {
+            low = t;
+        } else {
+            high = t;
+        }
+    }
+    bezier(y1, y2, (low + high) / 2.0)
+}
+
+/// The keyboard enhancements out of `modes` that the key encoder implements, so that programs
+/// that ask which ones are on know what to expect
+fn supported_keyboard_modes(modes: KeyboardModes) -> KeyboardModes {
+    modes & (KeyboardModes::DISAMBIGUATE_ESC_CODES | KeyboardModes::REPORT_ALL_KEYS_AS_ESC)
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1e9
+}
+
+/// The rows of `grid`, the history included.
+fn grid_rows(grid: &Grid<Cell>) -> impl Iterator<Item = &Row<Cell>> {
+    (0..grid.len()).map(move |line| &grid[line])
+}
+
+/// A prompt that the shell marked with OSC 133, from `Term::prompts`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Prompt {
+    /// The line that the prompt starts on, counted from the oldest line in the history
+    pub line: usize,
+    /// The lines that the command printed, counted the same way, once it was run
+    pub output: Option<Range<usize>>,
+}
+
+/// Where the cursor is and how it looks, for renderers that draw the cursor themselves and for
+/// placing the window of an input method; see `Term::cursor_state`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CursorState {
+    /// The column and line of the cursor on the screen, or `None` if it is scrolled out of view
+    pub position: Option<(usize, usize)>,
+    /// Whether the program shows the cursor, which it can hide with `CSI ?25l`
+    pub visible: bool,
+    /// The shape that the program asked for with DECSCUSR, or else the default one
+    pub style: CursorStyle,
+    pub blinking: bool,
+}
+
+/// Which way `Term::search` goes from where it starts
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// Towards the bottom of the screen
+    Forward,
+    /// Towards the oldest line in the history
+    Backward,
+}
+
+/// What changed on the screen since the last call to `Term::take_damage`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Damage {
+    /// All of it may have, like after a resize or when the display scrolls
+    Full,
+    /// The ranges of displayed lines that changed, in order
+    Lines(Vec<Range<usize>>),
+}
+
 pub struct Term {
     /// The grid
     grid: Grid<Cell>,
@@ -599,6 +960,45 @@ pub struct Term {
     /// Would be nice to avoid the allocation...
     next_title: Option<String>,
 
+    /// Got a request to set the clipboard; it's buffered here until the embedder takes it
+    next_clipboard: Option<String>,
+
+    /// The notifications that the program sent since the last call to `take_notifications`
+    notifications: VecDeque<ansi::Notification>,
+
+    /// The colors that the program set with OSC 4, 10 and 11 instead of the palette's, by index
+    colors: [Option<ansi::Rgb>; COLOR_COUNT],
+
+    /// The hyperlinks that cells are part of, by the index in the cells
+    hyperlinks: Vec<Option<ansi::Hyperlink>>,
+
+    /// The index of each hyperlink, so that a link that is started again keeps its index
+    hyperlink_indices: HashMap<ansi::Hyperlink, u32>,
+
+    /// The indices of hyperlinks that no cell had anymore, to use again for new ones
+    unused_hyperlinks: Vec<u32>,
+
+    /// The zero-width characters that cells of both grids have, by the number in the cells
+    zero_width: ZeroWidthRuns,
+
+    /// The latest images that were shown, by the id in the cells that show them
+    images: VecDeque<(u32, Image)>,
+
+    /// The images that the program transmitted with the kitty graphics protocol, by its number
+    graphics_images: VecDeque<(u32, Image)>,
+
+    /// The numbers of the kitty graphics images that were shown, with the ids in their cells
+    graphics_placements: VecDeque<(u32, u32)>,
+
+    /// The first chunks of a kitty graphics image, while the rest of it is being received
+    graphics_upload: Option<ansi::GraphicsCommand>,
+
+    /// Whether to tell programs that images can be shown, for `set_graphics_supported`
+    graphics_supported: bool,
+
+    /// What to send programs that ask with ENQ, for `set_answerback`
+    answerback: String,
+
     /// Got a request to set the mouse cursor; it's buffered here until the next draw
     next_mouse_cursor: Option<MouseCursor>,
 
@@ -624,6 +1024,10 @@ pub struct Term {
     /// Scroll region
     scroll_region: Range<index::Line>,
 
+    /// Left and right margins, which are the whole width unless they were set in left and right
+    /// margin mode
+    margins: Range<index::Column>,
+
     /// Size
     size_info: SizeInfo,
 
@@ -632,12 +1036,25 @@ pub struct Term {
     pub visual_bell: VisualBell,
     pub next_is_urgent: Option<bool>,
 
+    /// Whether the bell rang since the last call to `take_bell`
+    next_bell: bool,
+
+    /// The line that the cursor was on when the damage was last taken
+    damage_cursor: index::Line,
+
     /// Saved cursor from main grid
     cursor_save: Cursor,
 
     /// Saved cursor from alt grid
     cursor_save_alt: Cursor,
 
+    /// The keyboard enhancements that programs pushed on the active screen, the current ones
+    /// last
+    keyboard_modes: Vec<KeyboardModes>,
+
+    /// The keyboard enhancements of the inactive screen, which has its own stack
+    keyboard_modes_inactive: Vec<KeyboardModes>,
+
     semantic_escape_chars: String,
 
     /// Current style of the cursor
@@ -646,11 +1063,18 @@ pub struct Term {
     /// Default style for resetting the cursor
     default_cursor_style: CursorStyle,
 
+    /// When the cursor and text last started blinking, so that a cursor that starts blinking is
+    /// shown first
+    blink_start: Instant,
+
     dynamic_title: bool,
 
     /// Number of spaces in one tab
     tabspaces: usize,
 
+    /// Roughly how many bytes the screens and the history may take up together
+    scrollback_budget: Option<usize>,
+
     /// Automatically scroll to bottom when new lines are added
     auto_scroll: bool,
 
@@ -660,6 +1084,7 @@ pub struct Term {
 
 /// Terminal size info
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SizeInfo {
     /// Terminal window width
     pub width: f32,
@@ -728,125 +1153,597 @@ impl Term {
         self.next_title.take()
     }
 
-    pub fn scroll_display(&mut self, scroll: Scroll) {
-        self.grid.scroll_display(scroll);
-        self.dirty = true;
+    #[inline]
+    pub fn get_next_clipboard(&mut self) -> Option<String> {
+        self.next_clipboard.take()
     }
 
+    /// Whether the bell rang since the last call, for embedders that pass it on or show it
+    /// somewhere other than with the visual bell.
     #[inline]
-    pub fn get_next_mouse_cursor(&mut self) -> Option<MouseCursor> {
-        self.next_mouse_cursor.take()
+    pub fn take_bell(&mut self) -> bool {
+        ::std::mem::replace(&mut self.next_bell, false)
     }
 
-    pub fn new(size: SizeInfo) -> Term {
-        let num_cols = size.cols();
-        let num_lines = size.lines();
+    /// The desktop notifications that the program sent since the last call, oldest first
+    pub fn take_notifications(&mut self) -> Vec<ansi::Notification> {
+        self.notifications.drain(..).collect()
+    }
 
-        let semantic_escape_chars = "".to_owned();
-        let history_size = 1024; // TODO
-        let default_cursor_style = ansi::CursorStyle::Block;
-        let dynamic_title = true;
-        let auto_scroll = true;
-        let grid = Grid::new(num_lines, num_cols, history_size, Cell::default());
-        let alt = Grid::new(
-            num_lines,
-            num_cols,
-            0, /* scroll history */
-            Cell::default(),
-        );
+    /// The hyperlink with the `index` that cells have.
+    pub fn hyperlink(&self, index: u32) -> Option<&ansi::Hyperlink> {
+        self.hyperlinks.get(index as usize).and_then(Option::as_ref)
+    }
 
-        let tabspaces = 4;
-        let tabs = TabStops::new(grid.num_cols(), tabspaces);
+    /// The index of `hyperlink` for the cells, if there is room for it.
+    fn hyperlink_index(&mut self, hyperlink: ansi::Hyperlink) -> Option<u32> {
+        if let Some(&index) = self.hyperlink_indices.get(&hyperlink) {
+            return Some(index);
+        }
+        if self.unused_hyperlinks.is_empty() && self.hyperlinks.len() >= MAX_HYPERLINKS {
+            self.free_hyperlinks();
+        }
 
-        let scroll_region = index::Line(0)..grid.num_lines();
+        let index = match self.unused_hyperlinks.pop() {
+            Some(index) => {
+                self.hyperlinks[index as usize] = Some(hyperlink.clone());
+                index
+            }
+            None if self.hyperlinks.len() < MAX_HYPERLINKS => {
+                self.hyperlinks.push(Some(hyperlink.clone()));
+                #[allow(clippy::cast_possible_truncation)]
+                let index = (self.hyperlinks.len() - 1) as u32;
+                index
+            }
+            None => {
+                debug!("Too many hyperlinks, ignoring {:?}", hyperlink);
+                return None;
+            }
+        };
+        self.hyperlink_indices.insert(hyperlink, index);
+        Some(index)
+    }
 
-        Term {
-            next_title: None,
-            next_mouse_cursor: None,
-            dirty: false,
-            visual_bell: VisualBell::new(),
-            next_is_urgent: None,
-            input_needs_wrap: false,
-            grid,
-            alt_grid: alt,
-            alt: false,
-            active_charset: Default::default(),
-            cursor: Default::default(),
-            cursor_save: Default::default(),
-            cursor_save_alt: Default::default(),
-            tabs,
-            mode: Default::default(),
-            scroll_region,
-            size_info: size,
-            semantic_escape_chars,
-            cursor_style: None,
-            default_cursor_style,
-            dynamic_title,
-            tabspaces,
-            auto_scroll,
-            should_exit: false,
+    /// Forget the hyperlinks that no cell has anymore, so that their indices can be used again.
+    fn free_hyperlinks(&mut self) {
+        let mut used = vec![false; self.hyperlinks.len()];
+        let templates = [
+            &self.cursor.template,
+            &self.cursor_save.template,
+            &self.cursor_save_alt.template,
+        ];
+        let cells = grid_rows(&self.grid)
+            .chain(grid_rows(&self.alt_grid))
+            .flat_map(|row| row[..].iter())
+            .chain(templates.iter().cloned());
+        for index in cells.filter_map(|cell| cell.hyperlink) {
+            if let Some(used) = used.get_mut(index as usize) {
+                *used = true;
+            }
+        }
+
+        for (index, _) in used.iter().enumerate().filter(|(_, used)| !**used) {
+            if let Some(hyperlink) = self.hyperlinks[index].take() {
+                self.hyperlink_indices.remove(&hyperlink);
+                #[allow(clippy::cast_possible_truncation)]
+                self.unused_hyperlinks.push(index as u32);
+            }
         }
     }
 
-    #[inline]
-    pub fn needs_draw(&self) -> bool {
-        self.dirty
+    /// The image with the `id` that cells have, if it is still kept.
+    pub fn image(&self, id: u32) -> Option<&Image> {
+        self.images
+            .iter()
+            .find(|(image_id, _)| *image_id == id)
+            .map(|(_, image)| image)
     }
 
-    pub fn selection_to_string(&self) -> Option<String> {
-        /// Need a generic push() for the Append trait
-        trait PushChar {
-            fn push_char(&mut self, c: char);
-            fn maybe_newline(&mut self, grid: &Grid<Cell>, line: usize, ending: index::Column) {
-                if ending != index::Column(0)
-                    && !grid[line][ending - 1].flags.contains(cell::Flags::WRAPLINE)
-                {
-                    self.push_char('\n');
-                }
-            }
+    /// How big the characters are on the shown `line`, counted from the top like in
+    /// `cursor_position`.
+    pub fn line_size(&self, line: usize) -> LineSize {
+        self.grid[self.grid.display_offset() + *self.grid.num_lines() - 1 - line].size
+    }
+
+    /// The lines and columns of `area` that are on the screen; in origin mode the area is relative
+    /// to the scrolling region and margins and can't leave them.
+    fn area_cells(&self, area: ansi::Area) -> Option<(Range<index::Line>, Range<index::Column>)> {
+        let (offset, end, col_offset, col_end) = if self.mode.contains(mode::TermMode::ORIGIN) {
+            (
+                self.scroll_region.start,
+                self.scroll_region.end,
+                self.margins.start,
+                self.margins.end,
+            )
+        } else {
+            (
+                index::Line(0),
+                self.grid.num_lines(),
+                index::Column(0),
+                self.grid.num_cols(),
+            )
+        };
+        let lines = min(area.top + offset, end)..min(area.bottom + offset + 1, end);
+        let cols = min(area.left + col_offset, col_end)..min(area.right + col_offset + 1, col_end);
+        if lines.start < lines.end && cols.start < cols.end {
+            Some((lines, cols))
+        } else {
+            None
         }
+    }
 
-        impl PushChar for String {
-            #[inline]
-            fn push_char(&mut self, c: char) {
-                self.push(c);
-            }
+    /// How many columns fit on the cursor's line, which is half of them on lines with double
+    /// width characters.
+    fn line_cols(&self) -> index::Column {
+        match self.grid[self.cursor.point.line].size {
+            LineSize::Single => self.grid.num_cols(),
+            _ => index::Column(max(self.grid.num_cols().0 / 2, 1)),
         }
+    }
 
-        use std::ops::Range;
+    /// Whether the cursor is between the left and right margins
+    fn cursor_in_margins(&self) -> bool {
+        self.margins.start <= self.cursor.point.col && self.cursor.point.col < self.margins.end
+    }
 
-        trait Append: PushChar {
-            fn append(
-                &mut self,
-                grid: &Grid<Cell>,
-                tabs: &TabStops,
-                line: usize,
-                cols: Range<index::Column>,
-            );
+    /// The first column that the cursor can move to on its line, which is the left margin unless
+    /// the cursor is left of it.
+    fn left_col(&self) -> index::Column {
+        if self.cursor.point.col >= self.margins.start {
+            self.margins.start
+        } else {
+            index::Column(0)
         }
+    }
 
-        impl Append for String {
-            fn append(
-                &mut self,
-                grid: &Grid<Cell>,
-                tabs: &TabStops,
-                mut line: usize,
-                cols: Range<index::Column>,
-            ) {
-                // Select until last line still within the buffer
-                line = min(line, grid.len() - 1);
+    /// The last column that the cursor can move to on its line, which is the right margin unless
+    /// the cursor is right of it.
+    fn right_col(&self) -> index::Column {
+        let cols = self.line_cols();
+        if self.cursor.point.col < self.margins.end {
+            min(self.margins.end, cols) - 1
+        } else {
+            cols - 1
+        }
+    }
 
-                let grid_line = &grid[line];
-                let line_length = grid_line.line_length();
-                let line_end = min(line_length, cols.end + 1);
+    /// Say whether the terminal that shows this one can show images itself, so that programs
+    /// that ask if images work with the kitty graphics protocol get an answer.
+    pub fn set_graphics_supported(&mut self, supported: bool) {
+        self.graphics_supported = supported;
+    }
 
-                if line_end.0 == 0 && cols.end >= grid.num_cols() - 1 {
-                    self.push('\n');
-                } else if cols.start < line_end {
-                    let mut tab_mode = false;
+    /// Put the tab stops every `tabspaces` columns, or at least every column, in place of the
+    /// ones that programs set.
+    pub fn set_tabspaces(&mut self, tabspaces: usize) {
+        self.tabspaces = max(tabspaces, 1);
+        self.tabs = TabStops::new(self.grid.num_cols(), self.tabspaces);
+    }
 
-                    for col in index::Range::from(cols.start..line_end) {
-                        let cell = grid_line[col];
+    #[inline]
+    pub fn tabspaces(&self) -> usize {
+        self.tabspaces
+    }
+
+    /// Keep the history within roughly `bytes` of memory together with the screens, dropping
+    /// its oldest lines beyond that; with `None`, it keeps up to `MAX_HISTORY_LINES` lines.
+    pub fn set_scrollback_budget(&mut self, bytes: Option<usize>) {
+        self.scrollback_budget = bytes;
+        self.apply_scrollback_budget();
+    }
+
+    #[inline]
+    pub fn scrollback_budget(&self) -> Option<usize> {
+        self.scrollback_budget
+    }
+
+    /// Roughly how many bytes the terminal takes up: the cells of the screens and the history,
+    /// counting the lines that are set aside for the history to grow into, and the images,
+    /// hyperlinks and zero-width characters that the cells have.
+    pub fn memory_usage(&self) -> usize {
+        let cells = (self.grid.len() + self.alt_grid.len()) * self.line_bytes();
+
+        // The pixels of kitty graphics are shared with the images that show them
+        let mut images = self
+            .images
+            .iter()
+            .chain(&self.graphics_images)
+            .map(|(_, image)| &image.data)
+            .collect::<Vec<_>>();
+        images.sort_by_key(|data| Arc::as_ptr(data));
+        images.dedup_by_key(|data| Arc::as_ptr(data));
+        let images = images.iter().map(|data| data.len()).sum::<usize>();
+
+        // Each hyperlink is in the map too
+        let hyperlinks = self
+            .hyperlinks
+            .iter()
+            .flatten()
+            .map(|link| {
+                let text = link.uri.len() + link.id.as_ref().map_or(0, String::len);
+                2 * (std::mem::size_of::<ansi::Hyperlink>() + text)
+            })
+            .sum::<usize>();
+
+        cells + images + hyperlinks + self.zero_width.memory_usage()
+    }
+
+    /// Roughly how many bytes a line of cells takes up
+    fn line_bytes(&self) -> usize {
+        std::mem::size_of::<Row<Cell>>() + self.grid.num_cols().0 * std::mem::size_of::<Cell>()
+    }
+
+    fn apply_scrollback_budget(&mut self) {
+        let mut lines = MAX_HISTORY_LINES;
+        if let Some(bytes) = self.scrollback_budget {
+            let screens = self.grid.num_lines().0 + self.alt_grid.num_lines().0;
+            lines = min(lines, (bytes / self.line_bytes()).saturating_sub(screens));
+        }
+        // The alternate screen has no history, and swaps places with the normal one
+        let grid = if self.mode.contains(TermMode::ALT_SCREEN) {
+            &mut self.alt_grid
+        } else {
+            &mut self.grid
+        };
+        grid.set_max_scroll_limit(lines, &Cell::default());
+    }
+
+    /// Set the message that programs get when they send ENQ, which is nothing by default since
+    /// whatever it is gets typed into the program.
+    pub fn set_answerback(&mut self, answerback: &str) {
+        self.answerback = answerback.to_owned();
+    }
+
+    /// Show `image` at the cursor, taking up cells like text would; the cells have a placeholder
+    /// in them for renderers that can't draw it.  Unless `move_cursor` is off, the cursor ends up
+    /// after the last line of the image like in iTerm2.  Returns the id of the image in the cells.
+    #[allow(clippy::cast_possible_truncation)]
+    fn place_image(
+        &mut self,
+        mut image: Image,
+        (width, height): (ImageSize, ImageSize),
+        preserve_aspect_ratio: bool,
+        move_cursor: bool,
+    ) -> u32 {
+        let point = self.cursor.point;
+        let (width, height) = image_size(
+            (width, height),
+            preserve_aspect_ratio,
+            image.pixels,
+            (self.grid.num_cols() - point.col).0,
+            self.grid.num_lines().0,
+        );
+        // The image is cut off at the bottom when it can't scroll the screen
+        let height = if move_cursor {
+            height
+        } else {
+            min(height, (self.grid.num_lines() - point.line).0)
+        };
+        image.width = width;
+        image.height = height;
+
+        let id = next_image_id();
+        let label = format!("[{}]", image.name.as_ref().map_or("image", String::as_str));
+        let mut label = label.chars().filter(|c| c.width() == Some(1));
+        for line in 0..height {
+            let row = if !move_cursor {
+                point.line + line
+            } else {
+                if line > 0 {
+                    self.linefeed();
+                }
+                self.cursor.point.line
+            };
+            let row = &mut self.grid[row];
+            for (i, cell) in row[point.col..point.col + width].iter_mut().enumerate() {
+                *cell = self.cursor.template;
+                if line == 0 {
+                    cell.c = label.next().unwrap_or(' ');
+                }
+                cell.image = Some(ImageCell {
+                    id,
+                    col: i as u16,
+                    line: line as u16,
+                });
+            }
+        }
+
+        if move_cursor {
+            self.cursor.point.col = point.col + width;
+            self.input_needs_wrap = self.cursor.point.col >= self.grid.num_cols();
+            if self.input_needs_wrap {
+                self.cursor.point.col = self.grid.num_cols() - 1;
+            }
+        }
+
+        self.images.push_back((id, image));
+        limit_images(&mut self.images);
+        id
+    }
+
+    /// Decode the image of a kitty graphics command, and keep it by its id if it has one.
+    fn transmit_graphics(
+        &mut self,
+        command: &ansi::GraphicsCommand,
+    ) -> Result<Image, &'static str> {
+        let image = decode_graphics(command)?;
+        let number = command.number('i');
+        if number != 0 {
+            self.graphics_images.retain(|(n, _)| *n != number);
+            self.graphics_images.push_back((number, image.clone()));
+            limit_images(&mut self.graphics_images);
+        }
+        Ok(image)
+    }
+
+    /// The image that a program transmitted with the kitty graphics protocol as `number`.
+    fn graphics_image(&self, number: u32) -> Option<Image> {
+        self.graphics_images
+            .iter()
+            .find(|(n, _)| *n == number)
+            .map(|(_, image)| image.clone())
+    }
+
+    /// Place an image at the cursor for a kitty graphics command, in the number of columns and
+    /// lines that it asks for or as big as the image is.
+    fn place_graphics(&mut self, command: &ansi::GraphicsCommand, image: Image) {
+        let side = |key| match command.number(key) {
+            0 => ImageSize::Auto,
+            cells => ImageSize::Cells(cells as usize),
+        };
+        let move_cursor = command.number('C') == 0;
+        let id = self.place_image(image, (side('c'), side('r')), true, move_cursor);
+        self.graphics_placements
+            .push_back((command.number('i'), id));
+        if self.graphics_placements.len() > MAX_IMAGES {
+            self.graphics_placements.pop_front();
+        }
+    }
+
+    /// Take placements of kitty graphics images off the screen; with an upper case `d` key the
+    /// images themselves are forgotten too.
+    fn delete_graphics(&mut self, command: &ansi::GraphicsCommand) {
+        let number = command.number('i');
+        match command.value('d').unwrap_or("a") {
+            "a" => self.delete_graphics_where(false, |_| true),
+            "A" => self.delete_graphics_where(true, |_| true),
+            "i" => self.delete_graphics_where(false, |n| n == number),
+            "I" => self.delete_graphics_where(true, |n| n == number),
+            which => debug!("Deleting kitty graphics by {:?} is not supported", which),
+        }
+    }
+
+    /// Take the kitty graphics images with a number that is `deleted` off the screen, and forget
+    /// them too if asked to.
+    fn delete_graphics_where(&mut self, forget: bool, deleted: impl Fn(u32) -> bool) {
+        let ids = self
+            .graphics_placements
+            .iter()
+            .filter(|(number, _)| deleted(*number))
+            .map(|&(_, id)| id)
+            .collect::<Vec<_>>();
+        self.graphics_placements
+            .retain(|(number, _)| !deleted(*number));
+        self.images.retain(|(id, _)| !ids.contains(id));
+        if forget {
+            self.graphics_images.retain(|(number, _)| !deleted(*number));
+        }
+
+        let template = self.cursor.template;
+        self.grid.region_mut(..).each(|cell| {
+            if cell.image.map_or(false, |image| ids.contains(&image.id)) {
+                cell.reset(&template);
+            }
+        });
+        self.dirty = true;
+    }
+
+    /// The color that the program set for `color` instead of the palette's, if any.
+    pub fn dynamic_color(&self, color: Color) -> Option<ansi::Rgb> {
+        let index = match color {
+            Color::Named(NamedColor::BrightForeground) => NamedColor::Foreground as usize,
+            // Programs can't set the dim colors, which follow the normal ones that they set
+            Color::Named(named) if named.is_dim() => {
+                return self.colors[named.to_bright() as usize].map(color::dim);
+            }
+            Color::Named(named) => named as usize,
+            Color::Indexed(index) => usize::from(index),
+            Color::Spec(_) => return None,
+        };
+        self.colors[index]
+    }
+
+    pub fn scroll_display(&mut self, scroll: Scroll) {
+        self.grid.scroll_display(scroll);
+        self.dirty = true;
+    }
+
+    /// Clear the scrollback and the screen and move the cursor to the top left, like `CSI 3 J`
+    /// and `CSI 2 J` together, for the user to get rid of old output; unlike a reset, the modes
+    /// that the program set are kept.  On the alternate screen, only the scrollback of the normal
+    /// screen is cleared, and the full-screen program keeps what it drew.
+    pub fn clear_history_and_screen(&mut self) {
+        use crate::ansi::Handler;
+
+        if self.alt_screen() {
+            self.alt_grid.clear_history();
+            return;
+        }
+        self.scroll_display(Scroll::Bottom);
+        self.clear_screen(ansi::ClearMode::Saved);
+        self.clear_screen(ansi::ClearMode::All);
+        self.goto(index::Line(0), index::Column(0));
+    }
+
+    /// Whether a full-screen program switched to the alternate screen, which has no scrollback;
+    /// the view of the normal screen comes back as it was once the program switches back
+    #[inline]
+    pub fn alt_screen(&self) -> bool {
+        self.mode.contains(TermMode::ALT_SCREEN)
+    }
+
+    /// Whether new output scrolls the view back down to the bottom
+    #[inline]
+    pub fn auto_scroll(&self) -> bool {
+        self.auto_scroll
+    }
+
+    #[inline]
+    pub fn set_auto_scroll(&mut self, auto_scroll: bool) {
+        self.auto_scroll = auto_scroll;
+    }
+
+    #[inline]
+    pub fn get_next_mouse_cursor(&mut self) -> Option<MouseCursor> {
+        self.next_mouse_cursor.take()
+    }
+
+    pub fn new(size: SizeInfo) -> Term {
+        let num_cols = size.cols();
+        let num_lines = size.lines();
+
+        let semantic_escape_chars = "".to_owned();
+        let history_size = MAX_HISTORY_LINES;
+        let default_cursor_style = ansi::CursorStyle::Block;
+        let dynamic_title = true;
+        let auto_scroll = true;
+        let grid = Grid::new(num_lines, num_cols, history_size, Cell::default());
+        let alt = Grid::new(
+            num_lines,
+            num_cols,
+            0, /* scroll history */
+            Cell::default(),
+        );
+
+        let tabspaces = 8;
+        let tabs = TabStops::new(grid.num_cols(), tabspaces);
+
+        let scroll_region = index::Line(0)..grid.num_lines();
+        let margins = index::Column(0)..grid.num_cols();
+
+        Term {
+            next_title: None,
+            next_clipboard: None,
+            notifications: VecDeque::new(),
+            colors: [None; COLOR_COUNT],
+            hyperlinks: Vec::new(),
+            hyperlink_indices: HashMap::new(),
+            unused_hyperlinks: Vec::new(),
+            zero_width: ZeroWidthRuns::default(),
+            images: VecDeque::new(),
+            graphics_images: VecDeque::new(),
+            graphics_placements: VecDeque::new(),
+            graphics_upload: None,
+            graphics_supported: false,
+            answerback: String::new(),
+            next_mouse_cursor: None,
+            dirty: false,
+            visual_bell: VisualBell::new(),
+            next_is_urgent: None,
+            next_bell: false,
+            input_needs_wrap: false,
+            grid,
+            alt_grid: alt,
+            alt: false,
+            active_charset: Default::default(),
+            cursor: Default::default(),
+            damage_cursor: index::Line(0),
+            cursor_save: Default::default(),
+            cursor_save_alt: Default::default(),
+            keyboard_modes: Vec::new(),
+            keyboard_modes_inactive: Vec::new(),
+            tabs,
+            mode: Default::default(),
+            scroll_region,
+            margins,
+            size_info: size,
+            semantic_escape_chars,
+            cursor_style: None,
+            default_cursor_style,
+            blink_start: Instant::now(),
+            dynamic_title,
+            tabspaces,
+            scrollback_budget: None,
+            auto_scroll,
+            should_exit: false,
+        }
+    }
+
+    #[inline]
+    pub fn needs_draw(&self) -> bool {
+        self.dirty
+    }
+
+    /// What changed on the screen since the last call, so that only those lines have to be
+    /// drawn again.  Changes to the selection are left out, since they are made from outside.
+    pub fn take_damage(&mut self) -> Damage {
+        // The cursor is drawn on the line that it is on now, and no longer on the one it left
+        let line = self.cursor.point.line;
+        let left = ::std::mem::replace(&mut self.damage_cursor, line);
+        self.grid.damage_line(left);
+        self.grid.damage_line(line);
+        match self.grid.take_damage() {
+            Some(lines) => Damage::Lines(lines),
+            None => Damage::Full,
+        }
+    }
+
+    pub fn selection_to_string(&self) -> Option<String> {
+        /// Need a generic push() for the Append trait
+        trait PushChar {
+            fn push_char(&mut self, c: char);
+            fn maybe_newline(&mut self, grid: &Grid<Cell>, line: usize, ending: index::Column) {
+                if ending != index::Column(0)
+                    && !grid[line][ending - 1].flags.contains(cell::Flags::WRAPLINE)
+                {
+                    self.push_char('\n');
+                }
+            }
+        }
+
+        impl PushChar for String {
+            #[inline]
+            fn push_char(&mut self, c: char) {
+                self.push(c);
+            }
+        }
+
+        use std::ops::Range;
+
+        trait Append: PushChar {
+            fn append(
+                &mut self,
+                grid: &Grid<Cell>,
+                zero_width: &ZeroWidthRuns,
+                tabs: &TabStops,
+                line: usize,
+                cols: Range<index::Column>,
+            );
+        }
+
+        impl Append for String {
+            fn append(
+                &mut self,
+                grid: &Grid<Cell>,
+                zero_width: &ZeroWidthRuns,
+                tabs: &TabStops,
+                mut line: usize,
+                cols: Range<index::Column>,
+            ) {
+                // Select until last line still within the buffer
+                line = min(line, grid.len() - 1);
+
+                let grid_line = &grid[line];
+                let line_length = grid_line.line_length();
+                let line_end = min(line_length, cols.end + 1);
+
+                if line_end.0 == 0 && cols.end >= grid.num_cols() - 1 {
+                    self.push('\n');
+                } else if cols.start < line_end {
+                    let mut tab_mode = false;
+
+                    for col in index::Range::from(cols.start..line_end) {
+                        let cell = grid_line[col];
 
                         if tab_mode {
                             // Skip over whitespace until next tab-stop once a tab was found
@@ -859,7 +1756,10 @@ impl Term {
 
                         if !cell.flags.contains(cell::Flags::WIDE_CHAR_SPACER) {
                             self.push(cell.c);
-                            for c in (&cell.chars()[1..]).iter().filter(|c| **c != ' ') {
+                            for c in (&zero_width.chars(&cell)[1..])
+                                .iter()
+                                .filter(|c| **c != ' ')
+                            {
                                 self.push(*c);
                             }
                         }
@@ -894,17 +1794,30 @@ impl Term {
         match line_count {
             // Selection within single line
             0 => {
-                res.append(&self.grid, &self.tabs, start.line, start.col..end.col);
+                res.append(
+                    &self.grid,
+                    &self.zero_width,
+                    &self.tabs,
+                    start.line,
+                    start.col..end.col,
+                );
             }
 
             // Selection ends on line following start
             1 => {
                 // Ending line
-                res.append(&self.grid, &self.tabs, end.line, end.col..max_col);
+                res.append(
+                    &self.grid,
+                    &self.zero_width,
+                    &self.tabs,
+                    end.line,
+                    end.col..max_col,
+                );
 
                 // Starting line
                 res.append(
                     &self.grid,
+                    &self.zero_width,
                     &self.tabs,
                     start.line,
                     index::Column(0)..start.col,
@@ -914,16 +1827,29 @@ impl Term {
             // Multi line selection
             _ => {
                 // Ending line
-                res.append(&self.grid, &self.tabs, end.line, end.col..max_col);
+                res.append(
+                    &self.grid,
+                    &self.zero_width,
+                    &self.tabs,
+                    end.line,
+                    end.col..max_col,
+                );
 
                 let middle_range = (start.line + 1)..(end.line);
                 for line in middle_range.rev() {
-                    res.append(&self.grid, &self.tabs, line, index::Column(0)..max_col);
+                    res.append(
+                        &self.grid,
+                        &self.zero_width,
+                        &self.tabs,
+                        line,
+                        index::Column(0)..max_col,
+                    );
                 }
 
                 // Starting line
                 res.append(
                     &self.grid,
+                    &self.zero_width,
                     &self.tabs,
                     start.line,
                     index::Column(0)..start.col,
@@ -934,6 +1860,80 @@ impl Term {
         Some(res)
     }
 
+    /// The text of the whole scrollback history and the screen, oldest line first
+    ///
+    /// Empty lines at the bottom of the screen are left out. With `attributes`, colors and text
+    /// styles are kept as SGR escape sequences.
+    pub fn history_to_string(&self, attributes: bool) -> String {
+        let total = self.grid.scroll_limit() + self.grid.num_lines().0;
+        let last = (0..total)
+            .find(|&line| self.grid[line].line_length() > index::Column(0))
+            .unwrap_or(total);
+
+        self.rows_to_string((last..total).rev(), attributes)
+    }
+
+    /// The text of `lines`, counted from the top of the history like the lines of `Prompt`
+    pub fn lines_to_string(&self, lines: Range<usize>) -> String {
+        let total = self.grid.scroll_limit() + self.grid.num_lines().0;
+        let rows = lines.start.min(total)..lines.end.min(total);
+        self.rows_to_string(rows.map(|line| total - 1 - line), false)
+    }
+
+    /// The text of the rows at the buffer offsets `rows`, in that order, with wrapped rows joined
+    fn rows_to_string(&self, rows: impl Iterator<Item = usize>, attributes: bool) -> String {
+        // These affect how text is laid out rather than how it looks
+        let layout_flags =
+            cell::Flags::WRAPLINE | cell::Flags::WIDE_CHAR | cell::Flags::WIDE_CHAR_SPACER;
+
+        let mut res = String::new();
+        let mut style = None;
+
+        for line in rows {
+            let row = &self.grid[line];
+            let length = row.line_length();
+
+            for col in index::Range::from(index::Column(0)..length) {
+                let cell = &row[col];
+                if cell.flags.contains(cell::Flags::WIDE_CHAR_SPACER) {
+                    continue;
+                }
+
+                if attributes {
+                    let cell_style = (
+                        cell.fg,
+                        cell.bg,
+                        cell.underline_color,
+                        cell.flags - layout_flags,
+                    );
+                    if style != Some(cell_style) {
+                        res.push_str(&sgr(cell));
+                        style = Some(cell_style);
+                    }
+                }
+
+                res.push(cell.c);
+                for c in self.zero_width.chars(&cell)[1..]
+                    .iter()
+                    .filter(|c| **c != ' ')
+                {
+                    res.push(*c);
+                }
+            }
+
+            if length == index::Column(0) || !row[length - 1].flags.contains(cell::Flags::WRAPLINE)
+            {
+                res.push('\n');
+            }
+        }
+
+        if style.is_some() {
+            res.push_str("\x1b[0m");
+        }
+
+        res
+    }
+
     /// Convert the given pixel values to a grid coordinate
     ///
     /// The mouse coordinates are expected to be relative to the top left. The
@@ -956,30 +1956,307 @@ impl Term {
         &self.grid
     }
 
-    // Mutable access for swapping out the grid during tests
-    #[cfg(test)]
-    pub fn grid_mut(&mut self) -> &mut Grid<Cell> {
-        &mut self.grid
+    /// The text of the line that the cursor is on, followed by up to `count - 1` of the lines
+    /// above it, without zero-width characters.
+    pub fn recent_lines(&self, count: usize) -> Vec<String> {
+        let cursor = self.cursor.point.line.0;
+        (0..count)
+            .take_while(|&offset| offset <= cursor)
+            .map(|offset| {
+                let row = &self.grid[index::Line(cursor - offset)];
+                index::Range::from(index::Column(0)..row.line_length())
+                    .map(|col| &row[col])
+                    .filter(|cell| !cell.flags.contains(cell::Flags::WIDE_CHAR_SPACER))
+                    .map(|cell| cell.c)
+                    .collect()
+            })
+            .collect()
     }
 
-    /// Iterate over the *renderable* cells in the terminal
-    ///
-    /// A renderable cell is any cell which has content other than the default
-    /// background color.  Cells with an alternate background color are
-    /// considered renderable as are cells with any text content.
-    pub fn renderable_cells(&self) -> RenderableCellsIter {
-        let alt_screen = self.mode.contains(TermMode::ALT_SCREEN);
-        let selection = self
-            .grid
-            .selection
-            .as_ref()
-            .and_then(|s| s.to_span(self, alt_screen))
-            .map(|span| span.to_locations());
-
-        let cursor = self.cursor_style.unwrap_or(self.default_cursor_style);
+    /// The prompts that the shell marked with OSC 133, oldest first.
+    pub fn prompts(&self) -> Vec<Prompt> {
+        /// End the output of the last prompt, if it started
+        fn finish(prompts: &mut [Prompt], output: &mut Option<usize>, end: usize) {
+            if let (Some(start), Some(prompt)) = (output.take(), prompts.last_mut()) {
+                prompt.output = Some(start..max(start, end));
+            }
+        }
 
-        RenderableCellsIter::new(&self.grid, &self.cursor.point, self.mode, selection, cursor)
-    }
+        let total = self.grid.scroll_limit() + self.grid.num_lines().0;
+        let mut prompts: Vec<Prompt> = Vec::new();
+        let mut output = None;
+
+        for line in 0..total {
+            let marks = self.grid[total - 1 - line].marks;
+            if marks.contains(PromptMarks::PROMPT)
+                || (marks.contains(PromptMarks::END) && !marks.contains(PromptMarks::OUTPUT))
+            {
+                finish(&mut prompts, &mut output, line);
+            }
+            if marks.contains(PromptMarks::PROMPT) {
+                prompts.push(Prompt { line, output: None });
+            }
+            if marks.contains(PromptMarks::OUTPUT) && !prompts.is_empty() {
+                // Shells that mark the output before the newline after the command mark it on
+                // the command's line
+                let start = if marks.contains(PromptMarks::COMMAND) {
+                    line + 1
+                } else {
+                    line
+                };
+                output = Some(start);
+                if marks.contains(PromptMarks::END) {
+                    finish(&mut prompts, &mut output, line);
+                }
+            }
+        }
+
+        // A command that is still running has printed up to the cursor so far
+        let cursor = self.grid.scroll_limit() + self.cursor.point.line.0;
+        finish(&mut prompts, &mut output, cursor + 1);
+        prompts
+    }
+
+    /// Find `pattern` in the history and on the screen, where lines that wrapped count as one.
+    ///
+    /// Going forward, the matches that start at or after `start` are returned in order; going
+    /// backward, the ones that start before it, closest first.  Each match covers the cells from
+    /// its first to its last character, both wide halves included.  Like with `Search`, the lines
+    /// of the points count up from the bottom line of the screen.
+    pub fn search(
+        &self,
+        pattern: &str,
+        direction: Direction,
+        start: index::Point<usize>,
+    ) -> Vec<RangeInclusive<index::Point<usize>>> {
+        let pattern: Vec<char> = pattern.chars().collect();
+        if pattern.is_empty() {
+            return Vec::new();
+        }
+
+        let total = self.grid.scroll_limit() + self.grid.num_lines().0;
+        let mut matches = Vec::new();
+        // The characters of the current logical line, with the cells they start and end on
+        let mut text: Vec<(char, index::Point<usize>, index::Point<usize>)> = Vec::new();
+
+        for line in (0..total).rev() {
+            let row = &self.grid[line];
+            let length = row.line_length();
+
+            for col in index::Range::from(index::Column(0)..length) {
+                let cell = &row[col];
+                if cell.flags.contains(cell::Flags::WIDE_CHAR_SPACER) {
+                    continue;
+                }
+                let first = index::Point::new(line, col);
+                let last = if cell.flags.contains(cell::Flags::WIDE_CHAR) {
+                    index::Point::new(line, col + 1)
+                } else {
+                    first
+                };
+                text.push((cell.c, first, last));
+            }
+
+            let wraps =
+                length != index::Column(0) && row[length - 1].flags.contains(cell::Flags::WRAPLINE);
+            if wraps && line > 0 {
+                continue;
+            }
+
+            let mut i = 0;
+            while i + pattern.len() <= text.len() {
+                if text[i..i + pattern.len()]
+                    .iter()
+                    .zip(&pattern)
+                    .all(|(&(c, _, _), &p)| c == p)
+                {
+                    matches.push(text[i].1..=text[i + pattern.len() - 1].2);
+                    i += pattern.len();
+                } else {
+                    i += 1;
+                }
+            }
+            text.clear();
+        }
+
+        // The lines count up, so text is read with the lines going down
+        let order = |point: &index::Point<usize>| (Reverse(point.line), point.col);
+        match direction {
+            Direction::Forward => {
+                matches.retain(|found| order(found.start()) >= order(&start));
+            }
+            Direction::Backward => {
+                matches.retain(|found| order(found.start()) < order(&start));
+                matches.reverse();
+            }
+        }
+        matches
+    }
+
+    /// The zero-width characters that the cells have, which are saved together with the grid.
+    pub fn zero_width(&self) -> &ZeroWidthRuns {
+        &self.zero_width
+    }
+
+    /// Free the runs of zero-width characters that no cell has anymore.
+    fn free_zero_width(&mut self) {
+        let cells = grid_rows(&self.grid)
+            .chain(grid_rows(&self.alt_grid))
+            .flat_map(|row| row[..].iter());
+        self.zero_width.retain(cells);
+    }
+
+    /// Replace the contents of the terminal with a grid that was saved earlier together with its
+    /// `zero_width` characters, resized to fit the terminal.
+    pub fn restore_grid(&mut self, mut grid: Grid<Cell>, zero_width: &ZeroWidthRuns) {
+        // The runs of the grid that is replaced make room for the ones of the new grid
+        for line in 0..self.grid.len() {
+            for cell in &mut self.grid[line][..] {
+                cell.clear_extra();
+            }
+        }
+        self.free_zero_width();
+        for line in 0..grid.len() {
+            for cell in &mut grid[line][..] {
+                self.zero_width.import(cell, zero_width);
+            }
+        }
+        grid.selection = None;
+        grid.resize(
+            self.grid.num_lines(),
+            self.grid.num_cols(),
+            &self.cursor.template,
+        );
+        self.grid = grid;
+        self.dirty = true;
+    }
+
+    // Mutable access for swapping out the grid during tests
+    #[cfg(test)]
+    pub fn grid_mut(&mut self) -> &mut Grid<Cell> {
+        &mut self.grid
+    }
+
+    /// Iterate over the *renderable* cells in the terminal
+    ///
+    /// A renderable cell is any cell which has content other than the default
+    /// background color.  Cells with an alternate background color are
+    /// considered renderable as are cells with any text content.
+    pub fn renderable_cells(&self) -> RenderableCellsIter {
+        self.renderable_cells_with_mode(self.mode)
+    }
+
+    /// Like `renderable_cells`, but without the cursor composed into the cells, for renderers
+    /// that show the cursor themselves (see `cursor_position`).
+    pub fn renderable_cells_without_cursor(&self) -> RenderableCellsIter<'_> {
+        self.renderable_cells_with_mode(self.mode - TermMode::SHOW_CURSOR)
+    }
+
+    /// Like `renderable_cells`, with the colors of the cells resolved to RGB: those that the
+    /// program changed as it set them, the rest from `colors`.
+    pub fn renderable_cells_rgb<'a>(
+        &'a self,
+        colors: &'a color::List,
+    ) -> impl Iterator<Item = RgbCell> + 'a {
+        self.renderable_cells().map(move |cell| {
+            let mut fg = self.rgb(cell.fg, colors);
+            // Colors that have no dim variant are dimmed here
+            if cell.flags.contains(self::cell::Flags::DIM) {
+                if let Color::Spec(_) | Color::Indexed(16..=255) = cell.fg {
+                    fg = color::dim(fg);
+                }
+            }
+            let bg = self.rgb(cell.bg, colors);
+            let (fg, bg) = if cell.flags.contains(self::cell::Flags::INVERSE) {
+                (bg, fg)
+            } else {
+                (fg, bg)
+            };
+            let underline = cell
+                .underline_color
+                .map_or(fg, |color| self.rgb(color, colors));
+            RgbCell {
+                cell,
+                fg,
+                bg,
+                underline,
+            }
+        })
+    }
+
+    /// The RGB value of `color`: the one that the program set for it, or else the one in
+    /// `colors`.
+    pub fn rgb(&self, color: Color, colors: &color::List) -> ansi::Rgb {
+        if let Some(rgb) = self.dynamic_color(color) {
+            return rgb;
+        }
+        match color {
+            Color::Spec(rgb) => rgb,
+            Color::Named(named) => colors[named],
+            Color::Indexed(index) => colors[usize::from(index)],
+        }
+    }
+
+    fn renderable_cells_with_mode(&self, mode: TermMode) -> RenderableCellsIter<'_> {
+        let alt_screen = self.mode.contains(TermMode::ALT_SCREEN);
+        let selection = self
+            .grid
+            .selection
+            .as_ref()
+            .and_then(|s| s.to_span(self, alt_screen))
+            .map(|span| span.to_locations());
+
+        let cursor = self.cursor_style.unwrap_or(self.default_cursor_style);
+
+        RenderableCellsIter::new(
+            &self.grid,
+            &self.zero_width,
+            &self.cursor.point,
+            mode,
+            selection,
+            cursor,
+        )
+    }
+
+    /// The position of the cursor on the screen as `(column, line)`, or `None` if it is hidden
+    /// or scrolled out of view.
+    pub fn cursor_position(&self) -> Option<(usize, usize)> {
+        let state = self.cursor_state();
+        if state.visible {
+            state.position
+        } else {
+            None
+        }
+    }
+
+    /// The position, visibility and shape of the cursor, together.
+    pub fn cursor_state(&self) -> CursorState {
+        let point = self.cursor.point;
+        let line = point.line.0 + self.grid.display_offset();
+        let position = if line < self.grid.num_lines().0 && point.col < self.grid.num_cols() {
+            Some((point.col.0, line))
+        } else {
+            None
+        };
+        CursorState {
+            position,
+            visible: self.mode.contains(TermMode::SHOW_CURSOR),
+            style: self.cursor_style.unwrap_or(self.default_cursor_style),
+            blinking: self.cursor_blinks(),
+        }
+    }
+
+    /// Whether the process asked for a blinking cursor, with DECSCUSR or mode 12.
+    pub fn cursor_blinks(&self) -> bool {
+        self.mode.contains(TermMode::BLINKING_CURSOR)
+    }
+
+    /// Whether blinking text, and the cursor if it blinks, are shown at `now` rather than hidden.
+    /// They blink together, `BLINK_INTERVAL` on and then off.
+    pub fn blink_visible(&self, now: Instant) -> bool {
+        let elapsed = now.duration_since(self.blink_start);
+        elapsed.as_millis() / BLINK_INTERVAL.as_millis() % 2 == 0
+    }
 
     /// Resize terminal to new dimensions
     pub fn resize(&mut self, size: &SizeInfo) {
@@ -1017,6 +2294,26 @@ impl Term {
             num_lines = index::Line(2);
         }
 
+        // Rewrap the lines of the normal screen; programs redraw the alternate one themselves
+        if num_cols != old_cols {
+            if self.alt {
+                let mut past_end = false;
+                self.alt_grid.reflow(
+                    num_cols,
+                    &mut self.cursor_save.point,
+                    &mut past_end,
+                    &Cell::default(),
+                );
+            } else {
+                self.grid.reflow(
+                    num_cols,
+                    &mut self.cursor.point,
+                    &mut self.input_needs_wrap,
+                    &Cell::default(),
+                );
+            }
+        }
+
         // Scroll up to keep cursor in terminal
         if self.cursor.point.line >= num_lines {
             let lines = self.cursor.point.line - num_lines + 1;
@@ -1057,8 +2354,9 @@ impl Term {
         self.grid.resize(num_lines, num_cols, &Cell::default());
         self.alt_grid.resize(num_lines, num_cols, &Cell::default());
 
-        // Reset scrolling region to new size
+        // Reset scrolling region and margins to new size
         self.scroll_region = index::Line(0)..self.grid.num_lines();
+        self.margins = index::Column(0)..self.grid.num_cols();
 
         // Ensure cursors are in-bounds.
         self.cursor.point.col = min(self.cursor.point.col, num_cols - 1);
@@ -1070,6 +2368,9 @@ impl Term {
 
         // Recreate tabs list
         self.tabs = TabStops::new(self.grid.num_cols(), self.tabspaces);
+
+        // Lines of another width take up another amount of memory
+        self.apply_scrollback_budget();
     }
 
     #[inline]
@@ -1087,6 +2388,13 @@ impl Term {
         &self.cursor
     }
 
+    /// Replace the underline of the template, of whatever style, with `style`.
+    fn set_underline(&mut self, style: cell::Flags) {
+        let flags = &mut self.cursor.template.flags;
+        flags.remove(cell::Flags::ALL_UNDERLINES);
+        flags.insert(style);
+    }
+
     pub fn swap_alt(&mut self) {
         if self.alt {
             let template = &self.cursor.template;
@@ -1095,6 +2403,24 @@ impl Term {
 
         self.alt = !self.alt;
         ::std::mem::swap(&mut self.grid, &mut self.alt_grid);
+        self.grid.damage_all();
+
+        ::std::mem::swap(&mut self.keyboard_modes, &mut self.keyboard_modes_inactive);
+        self.update_keyboard_mode();
+    }
+
+    /// Set the modes that the key encoder looks at from the current keyboard enhancements.
+    /// Only the enhancements that it implements are kept on the stack.
+    fn update_keyboard_mode(&mut self) {
+        let modes = self.keyboard_modes.last().cloned().unwrap_or_default();
+        self.mode.set(
+            TermMode::DISAMBIGUATE_KEYS,
+            modes.contains(KeyboardModes::DISAMBIGUATE_ESC_CODES),
+        );
+        self.mode.set(
+            TermMode::REPORT_ALL_KEYS,
+            modes.contains(KeyboardModes::REPORT_ALL_KEYS_AS_ESC),
+        );
     }
 
     /// Scroll screen down
@@ -1112,11 +2438,15 @@ impl Term {
         lines = min(lines, self.scroll_region.end - origin);
 
         // Scroll between origin and bottom
-        self.grid.scroll_down(
-            &(origin..self.scroll_region.end),
-            lines,
-            &self.cursor.template,
-        );
+        if self.margins.start == index::Column(0) && self.margins.end == self.grid.num_cols() {
+            self.grid.scroll_down(
+                &(origin..self.scroll_region.end),
+                lines,
+                &self.cursor.template,
+            );
+        } else {
+            self.scroll_between_margins(origin..self.scroll_region.end, lines, false);
+        }
     }
 
     /// Scroll screen up
@@ -1129,11 +2459,41 @@ impl Term {
         let lines = min(lines, self.scroll_region.end - self.scroll_region.start);
 
         // Scroll from origin to bottom less number of lines
-        self.grid.scroll_up(
-            &(origin..self.scroll_region.end),
-            lines,
-            &self.cursor.template,
-        );
+        if self.margins.start == index::Column(0) && self.margins.end == self.grid.num_cols() {
+            self.grid.scroll_up(
+                &(origin..self.scroll_region.end),
+                lines,
+                &self.cursor.template,
+            );
+        } else {
+            self.scroll_between_margins(origin..self.scroll_region.end, lines, true);
+        }
+    }
+
+    /// Scroll the part of `region` between the left and right margins by `lines`, moving the
+    /// cells cell by cell since the grid can only scroll whole lines.  Nothing goes into the
+    /// history.
+    fn scroll_between_margins(&mut self, region: Range<index::Line>, lines: index::Line, up: bool) {
+        let (start, end) = (region.start.0, region.end.0);
+        let lines = min(lines.0, end - start);
+        let cols = self.margins.clone();
+        let template = self.cursor.template;
+
+        for i in 0..end - start {
+            let (line, source) = if up {
+                (start + i, start + i + lines)
+            } else {
+                (end - 1 - i, (end - 1 - i).wrapping_sub(lines))
+            };
+            if start <= source && source < end {
+                let row = self.grid[index::Line(source)][cols.clone()].to_vec();
+                self.grid[index::Line(line)][cols.clone()].copy_from_slice(&row);
+            } else {
+                for cell in &mut self.grid[index::Line(line)][cols.clone()] {
+                    cell.reset(&template);
+                }
+            }
+        }
     }
 
     fn deccolm(&mut self) {
@@ -1141,6 +2501,7 @@ impl Term {
         // Clear scrolling region
         let scroll_region = index::Line(0)..self.grid.num_lines();
         self.set_scrolling_region(scroll_region);
+        self.margins = index::Column(0)..self.grid.num_cols();
 
         // Clear grid
         let template = self.cursor.template;
@@ -1158,6 +2519,84 @@ impl Term {
     }
 }
 
+/// The SGR escape sequence that switches to the colors and style of `cell`
+fn sgr(cell: &Cell) -> String {
+    format!("\x1b[{}m", sgr_params(cell))
+}
+
+/// The parameters of `sgr`, separated by semicolons
+fn sgr_params(cell: &Cell) -> String {
+    let flags = [
+        (cell::Flags::BOLD, "1"),
+        (cell::Flags::DIM, "2"),
+        (cell::Flags::ITALIC, "3"),
+        (cell::Flags::UNDERLINE, "4"),
+        (cell::Flags::DOUBLE_UNDERLINE, "4:2"),
+        (cell::Flags::UNDERCURL, "4:3"),
+        (cell::Flags::DOTTED_UNDERLINE, "4:4"),
+        (cell::Flags::DASHED_UNDERLINE, "4:5"),
+        (cell::Flags::BLINK, "5"),
+        (cell::Flags::INVERSE, "7"),
+        (cell::Flags::HIDDEN, "8"),
+        (cell::Flags::STRIKEOUT, "9"),
+    ];
+
+    let mut params = vec!["0".to_owned()];
+    params.extend(
+        flags
+            .iter()
+            .filter(|(flag, _)| cell.flags.contains(*flag))
+            .map(|(_, param)| (*param).to_owned()),
+    );
+    params.extend(sgr_color(cell.fg, 30));
+    params.extend(sgr_color(cell.bg, 40));
+    params.extend(cell.underline_color.and_then(sgr_underline_color));
+
+    params.join(";")
+}
+
+/// The version of this crate as one number, like `xterm` reports its patch level
+fn version() -> u32 {
+    let part = |part: &str| part.parse::<u32>().unwrap_or(0);
+    part(env!("CARGO_PKG_VERSION_MAJOR")) * 10_000
+        + part(env!("CARGO_PKG_VERSION_MINOR")) * 100
+        + part(env!("CARGO_PKG_VERSION_PATCH"))
+}
+
+/// The SGR parameter for a foreground (`base` 30) or background (`base` 40) color, or `None` for
+/// the default color
+fn sgr_color(color: Color, base: usize) -> Option<String> {
+    match color {
+        Color::Named(name) => {
+            let index = name as usize;
+            if index < 8 {
+                Some((base + index).to_string())
+            } else if index < 16 {
+                Some((base + 60 + index - 8).to_string())
+            } else if index >= NamedColor::DimBlack as usize
+                && index <= NamedColor::DimWhite as usize
+            {
+                Some((base + index - NamedColor::DimBlack as usize).to_string())
+            } else {
+                None
+            }
+        }
+        Color::Indexed(index) => Some(format!("{};5;{}", base + 8, index)),
+        Color::Spec(rgb) => Some(format!("{};2;{};{};{}", base + 8, rgb.r, rgb.g, rgb.b)),
+    }
+}
+
+/// The SGR parameter for an underline color
+fn sgr_underline_color(color: Color) -> Option<String> {
+    match color {
+        // Only the palette has the named colors
+        Color::Named(name) if (name as usize) < 16 => Some(format!("58;5;{}", name as usize)),
+        Color::Named(_) => None,
+        Color::Indexed(index) => Some(format!("58;5;{}", index)),
+        Color::Spec(rgb) => Some(format!("58;2;{};{};{}", rgb.r, rgb.g, rgb.b)),
+    }
+}
+
 impl ansi::TermInfo for Term {
     #[inline]
     fn lines(&self) -> index::Line {
@@ -1168,6 +2607,11 @@ impl ansi::TermInfo for Term {
     fn cols(&self) -> index::Column {
         self.grid.num_cols()
     }
+
+    #[inline]
+    fn left_right_margin_mode(&self) -> bool {
+        self.mode.contains(mode::TermMode::LEFT_RIGHT_MARGIN)
+    }
 }
 
 impl ansi::Handler for Term {
@@ -1222,13 +2666,13 @@ impl ansi::Handler for Term {
                 self.cursor.point.line += 1;
             }
 
-            self.cursor.point.col = index::Column(0);
+            self.cursor.point.col = self.left_col();
             self.input_needs_wrap = false;
         }
 
         // Number of cells the char will occupy
         if let Some(width) = c.width() {
-            let num_cols = self.grid.num_cols();
+            let num_cols = self.right_col() + 1;
 
             // If in insert mode, first shift cells to the right.
             if self.mode.contains(mode::TermMode::INSERT)
@@ -1256,7 +2700,12 @@ impl ansi::Handler for Term {
                 {
                     col.saturating_sub(1);
                 }
-                self.grid[line][index::Column(col)].push_extra(c);
+                let cell = &mut self.grid[line][index::Column(col)];
+                if !self.zero_width.push(cell, c) {
+                    self.free_zero_width();
+                    let cell = &mut self.grid[line][index::Column(col)];
+                    self.zero_width.push(cell, c);
+                }
                 return;
             }
 
@@ -1277,7 +2726,7 @@ impl ansi::Handler for Term {
             }
         }
 
-        if (self.cursor.point.col + 1) < self.grid.num_cols() {
+        if self.cursor.point.col < self.right_col() {
             self.cursor.point.col += 1;
         } else {
             self.input_needs_wrap = true;
@@ -1287,14 +2736,24 @@ impl ansi::Handler for Term {
     #[inline]
     fn goto(&mut self, line: index::Line, col: index::Column) {
         trace!("Going to: line={}, col={}", line, col);
-        let (y_offset, max_y) = if self.mode.contains(mode::TermMode::ORIGIN) {
-            (self.scroll_region.start, self.scroll_region.end - 1)
+        let (y_offset, max_y, x_offset, max_x) = if self.mode.contains(mode::TermMode::ORIGIN) {
+            (
+                self.scroll_region.start,
+                self.scroll_region.end - 1,
+                self.margins.start,
+                self.margins.end - 1,
+            )
         } else {
-            (index::Line(0), self.grid.num_lines() - 1)
+            (
+                index::Line(0),
+                self.grid.num_lines() - 1,
+                index::Column(0),
+                self.grid.num_cols() - 1,
+            )
         };
 
         self.cursor.point.line = min(line + y_offset, max_y);
-        self.cursor.point.col = min(col, self.grid.num_cols() - 1);
+        self.cursor.point.col = min(min(col + x_offset, max_x), self.line_cols() - 1);
         self.input_needs_wrap = false;
     }
 
@@ -1312,13 +2771,16 @@ impl ansi::Handler for Term {
 
     #[inline]
     fn insert_blank(&mut self, count: index::Column) {
-        // Ensure inserting within terminal bounds
-
-        let count = min(count, self.size_info.cols() - self.cursor.point.col);
+        // Ensure inserting within the margins
+        if !self.cursor_in_margins() {
+            return;
+        }
+        let right = self.margins.end;
+        let count = min(count, right - self.cursor.point.col);
 
         let source = self.cursor.point.col;
         let destination = self.cursor.point.col + count;
-        let num_cells = (self.size_info.cols() - destination).0;
+        let num_cells = (right - destination).0;
 
         let line = &mut self.grid[self.cursor.point.line];
 
@@ -1352,8 +2814,62 @@ impl ansi::Handler for Term {
     }
 
     #[inline]
-    fn identify_terminal<W: io::Write>(&mut self, writer: &mut W) {
-        let _ = writer.write_all(b"\x1b[?6c");
+    fn answerback<W: io::Write>(&mut self, writer: &mut W) {
+        trace!("Sending the answerback message");
+        let _ = writer.write_all(self.answerback.as_bytes());
+    }
+
+    #[inline]
+    fn identify_terminal<W: io::Write>(&mut self, writer: &mut W, intermediate: Option<char>) {
+        match intermediate {
+            None => {
+                let _ = writer.write_all(b"\x1b[?6c");
+            }
+            // A VT220 at the version of this crate, like `xterm` answers
+            Some('>') => {
+                let _ = write!(writer, "\x1b[>1;{};0c", version());
+            }
+            // A unit ID of zeros
+            Some('=') => {
+                let _ = writer.write_all(b"\x1bP!|00000000\x1b\\");
+            }
+            Some(intermediate) => debug!("unknown device attributes query: {}", intermediate),
+        }
+    }
+
+    #[inline]
+    fn request_setting<W: io::Write>(&mut self, writer: &mut W, name: &[u8]) {
+        trace!("Reporting setting: {:?}", name);
+        let value = match name {
+            b" q" => {
+                let style = self.cursor_style.unwrap_or(self.default_cursor_style);
+                let steady = match style {
+                    CursorStyle::Block | CursorStyle::HollowBlock => 2,
+                    CursorStyle::Underline => 4,
+                    CursorStyle::Beam => 6,
+                };
+                let ps = if self.cursor_blinks() {
+                    steady - 1
+                } else {
+                    steady
+                };
+                Some(format!("{} q", ps))
+            }
+            b"r" => Some(format!(
+                "{};{}r",
+                self.scroll_region.start + 1,
+                self.scroll_region.end
+            )),
+            b"s" if self.mode.contains(mode::TermMode::LEFT_RIGHT_MARGIN) => {
+                Some(format!("{};{}s", self.margins.start + 1, self.margins.end))
+            }
+            b"m" => Some(format!("{}m", sgr_params(&self.cursor.template))),
+            _ => None,
+        };
+        let _ = match value {
+            Some(value) => write!(writer, "\x1bP1$r{}\x1b\\", value),
+            None => writer.write_all(b"\x1bP0$r\x1b\\"),
+        };
     }
 
     #[inline]
@@ -1374,14 +2890,15 @@ impl ansi::Handler for Term {
     #[inline]
     fn move_forward(&mut self, cols: index::Column) {
         trace!("Moving forward: {}", cols);
-        self.cursor.point.col = min(self.cursor.point.col + cols, self.grid.num_cols() - 1);
+        self.cursor.point.col = min(self.cursor.point.col + cols, self.right_col());
         self.input_needs_wrap = false;
     }
 
     #[inline]
     fn move_backward(&mut self, cols: index::Column) {
         trace!("Moving backward: {}", cols);
-        self.cursor.point.col -= min(self.cursor.point.col, cols);
+        let left = self.left_col();
+        self.cursor.point.col -= min(self.cursor.point.col - left, cols);
         self.input_needs_wrap = false;
     }
 
@@ -1389,14 +2906,16 @@ impl ansi::Handler for Term {
     fn move_down_and_cr(&mut self, lines: index::Line) {
         trace!("Moving down and cr: {}", lines);
         let move_to = self.cursor.point.line + lines;
-        self.goto(move_to, index::Column(0))
+        self.goto_line(move_to);
+        self.carriage_return();
     }
 
     #[inline]
     fn move_up_and_cr(&mut self, lines: index::Line) {
         trace!("Moving up and cr: {}", lines);
         let move_to = index::Line(self.cursor.point.line.0.saturating_sub(lines.0));
-        self.goto(move_to, index::Column(0))
+        self.goto_line(move_to);
+        self.carriage_return();
     }
 
     #[inline]
@@ -1405,6 +2924,7 @@ impl ansi::Handler for Term {
 
         while self.cursor.point.col < self.grid.num_cols() && count != 0 {
             count -= 1;
+            let start = self.cursor.point.col;
 
             let cell = &mut self.grid[&self.cursor.point];
             if cell.c == ' ' {
@@ -1412,7 +2932,7 @@ impl ansi::Handler for Term {
             }
 
             loop {
-                if (self.cursor.point.col + 1) == self.grid.num_cols() {
+                if self.cursor.point.col >= self.right_col() {
                     break;
                 }
 
@@ -1422,6 +2942,11 @@ impl ansi::Handler for Term {
                     break;
                 }
             }
+
+            // At the margin already, where any further tabs would leave it
+            if self.cursor.point.col == start {
+                break;
+            }
         }
 
         self.input_needs_wrap = false;
@@ -1431,7 +2956,7 @@ impl ansi::Handler for Term {
     #[inline]
     fn backspace(&mut self) {
         trace!("Backspace");
-        if self.cursor.point.col > index::Column(0) {
+        if self.cursor.point.col > self.left_col() {
             self.cursor.point.col -= 1;
             self.input_needs_wrap = false;
         }
@@ -1441,7 +2966,7 @@ impl ansi::Handler for Term {
     #[inline]
     fn carriage_return(&mut self) {
         trace!("Carriage return");
-        self.cursor.point.col = index::Column(0);
+        self.cursor.point.col = self.left_col();
         self.input_needs_wrap = false;
     }
 
@@ -1463,11 +2988,13 @@ impl ansi::Handler for Term {
         trace!("Bell");
         self.visual_bell.ring();
         self.next_is_urgent = Some(true);
+        self.next_bell = true;
     }
 
     #[inline]
     fn substitute(&mut self) {
-        trace!("[unimplemented] Substitute");
+        trace!("Substitute");
+        self.input('\u{fffd}');
     }
 
     /// Run LF/NL
@@ -1524,7 +3051,7 @@ impl ansi::Handler for Term {
     fn insert_blank_lines(&mut self, lines: index::Line) {
         use crate::index::Contains;
         trace!("Inserting blank {} lines", lines);
-        if self.scroll_region.contains_(self.cursor.point.line) {
+        if self.scroll_region.contains_(self.cursor.point.line) && self.cursor_in_margins() {
             let origin = self.cursor.point.line;
             self.scroll_down_relative(origin, lines);
         }
@@ -1534,12 +3061,75 @@ impl ansi::Handler for Term {
     fn delete_lines(&mut self, lines: index::Line) {
         use crate::index::Contains;
         trace!("Deleting {} lines", lines);
-        if self.scroll_region.contains_(self.cursor.point.line) {
+        if self.scroll_region.contains_(self.cursor.point.line) && self.cursor_in_margins() {
             let origin = self.cursor.point.line;
             self.scroll_up_relative(origin, lines);
         }
     }
 
+    #[inline]
+    fn copy_area(&mut self, area: ansi::Area, (line, col): (index::Line, index::Column)) {
+        trace!("Copying {:?} to line={}, col={}", area, line, col);
+        let (lines, cols) = match self.area_cells(area) {
+            Some(cells) => cells,
+            None => return,
+        };
+        let destination = ansi::Area {
+            top: line,
+            left: col,
+            bottom: line + (lines.end - lines.start) - 1,
+            right: col + (cols.end - cols.start) - 1,
+        };
+        let (to_lines, to_cols) = match self.area_cells(destination) {
+            Some(cells) => cells,
+            None => return,
+        };
+
+        // The areas can overlap, so everything is read before anything is written
+        let cells = index::Range(lines)
+            .map(|line| self.grid[line][cols.clone()].to_vec())
+            .collect::<Vec<_>>();
+        for (line, row) in index::Range(to_lines).zip(cells) {
+            let len = min(row.len(), (to_cols.end - to_cols.start).0);
+            self.grid[line][to_cols.start..to_cols.start + len].copy_from_slice(&row[..len]);
+        }
+        self.dirty = true;
+    }
+
+    #[inline]
+    fn fill_area(&mut self, c: char, area: ansi::Area) {
+        trace!("Filling {:?} with {:?}", area, c);
+        if let Some((lines, cols)) = self.area_cells(area) {
+            let mut template = self.cursor.template;
+            template.c = c;
+            for line in index::Range(lines) {
+                for cell in &mut self.grid[line][cols.clone()] {
+                    cell.reset(&template);
+                }
+            }
+            self.dirty = true;
+        }
+    }
+
+    #[inline]
+    fn erase_area(&mut self, area: ansi::Area, selective: bool) {
+        trace!("Erasing {:?}, selective={}", area, selective);
+        if let Some((lines, cols)) = self.area_cells(area) {
+            let template = self.cursor.template;
+            for line in index::Range(lines) {
+                for cell in &mut self.grid[line][cols.clone()] {
+                    if selective {
+                        cell.c = ' ';
+                        cell.clear_extra();
+                    } else {
+                        cell.reset(&template);
+                    }
+                }
+            }
+            self.dirty = true;
+        }
+    }
+
     #[inline]
     fn erase_chars(&mut self, count: index::Column) {
         trace!(
@@ -1559,12 +3149,15 @@ impl ansi::Handler for Term {
 
     #[inline]
     fn delete_chars(&mut self, count: index::Column) {
-        // Ensure deleting within terminal bounds
-        let count = min(count, self.size_info.cols());
-
+        // Ensure deleting within the margins
+        if !self.cursor_in_margins() {
+            return;
+        }
+        let right = self.margins.end;
         let start = self.cursor.point.col;
-        let end = min(start + count, self.grid.num_cols() - 1);
-        let n = (self.size_info.cols() - end).0;
+        let count = min(count, right - start);
+        let end = start + count;
+        let n = (right - end).0;
 
         let line = &mut self.grid[self.cursor.point.line];
 
@@ -1578,8 +3171,7 @@ impl ansi::Handler for Term {
         // Clear last `count` cells in line. If deleting 1 char, need to delete
         // 1 cell.
         let template = self.cursor.template;
-        let end = self.size_info.cols() - count;
-        for c in &mut line[end..] {
+        for c in &mut line[(right - count)..right] {
             c.reset(&template);
         }
     }
@@ -1588,21 +3180,42 @@ impl ansi::Handler for Term {
     fn move_backward_tabs(&mut self, count: i64) {
         trace!("Moving backward {} tabs", count);
 
+        let left = self.left_col();
         for _ in 0..count {
             let mut col = self.cursor.point.col;
-            for i in (0..(col.0)).rev() {
+            for i in (left.0..(col.0)).rev() {
                 if self.tabs[index::Column(i)] {
                     col = index::Column(i);
                     break;
                 }
             }
+            // At the margin already, where any further tabs would leave it
+            if col == self.cursor.point.col {
+                break;
+            }
             self.cursor.point.col = col;
         }
     }
 
     #[inline]
     fn move_forward_tabs(&mut self, count: i64) {
-        trace!("[unimplemented] Moving forward {} tabs", count);
+        trace!("Moving forward {} tabs", count);
+
+        let last = self.right_col();
+        for _ in 0..count {
+            let mut col = last;
+            for i in (self.cursor.point.col.0 + 1)..last.0 {
+                if self.tabs[index::Column(i)] {
+                    col = index::Column(i);
+                    break;
+                }
+            }
+            if col == self.cursor.point.col {
+                break;
+            }
+            self.cursor.point.col = col;
+        }
+        self.input_needs_wrap = false;
     }
 
     #[inline]
@@ -1720,14 +3333,21 @@ impl ansi::Handler for Term {
     fn reset_state(&mut self) {
         self.input_needs_wrap = false;
         self.next_title = None;
+        self.next_clipboard = None;
+        self.colors = [None; COLOR_COUNT];
         self.next_mouse_cursor = None;
         self.alt = false;
         self.cursor = Default::default();
         self.active_charset = Default::default();
         self.mode = Default::default();
         self.next_is_urgent = None;
+        self.next_bell = false;
+        self.notifications.clear();
         self.cursor_save = Default::default();
         self.cursor_save_alt = Default::default();
+        self.keyboard_modes.clear();
+        self.keyboard_modes_inactive.clear();
+        self.margins = index::Column(0)..self.grid.num_cols();
         self.cursor_style = None;
         self.grid.clear_history();
         self.grid.region_mut(..).each(|c| c.reset(&Cell::default()));
@@ -1755,6 +3375,7 @@ impl ansi::Handler for Term {
                 self.cursor.template.fg = Color::Named(NamedColor::Foreground);
                 self.cursor.template.bg = Color::Named(NamedColor::Background);
                 self.cursor.template.flags = cell::Flags::empty();
+                self.cursor.template.underline_color = None;
             }
             Attr::Reverse => self.cursor.template.flags.insert(cell::Flags::INVERSE),
             Attr::CancelReverse => self.cursor.template.flags.remove(cell::Flags::INVERSE),
@@ -1768,15 +3389,21 @@ impl ansi::Handler for Term {
                 .remove(cell::Flags::BOLD | cell::Flags::DIM),
             Attr::Italic => self.cursor.template.flags.insert(cell::Flags::ITALIC),
             Attr::CancelItalic => self.cursor.template.flags.remove(cell::Flags::ITALIC),
-            Attr::Underscore => self.cursor.template.flags.insert(cell::Flags::UNDERLINE),
-            Attr::CancelUnderline => self.cursor.template.flags.remove(cell::Flags::UNDERLINE),
+            Attr::Underscore => self.set_underline(cell::Flags::UNDERLINE),
+            Attr::DoubleUnderline => self.set_underline(cell::Flags::DOUBLE_UNDERLINE),
+            Attr::Undercurl => self.set_underline(cell::Flags::UNDERCURL),
+            Attr::DottedUnderline => self.set_underline(cell::Flags::DOTTED_UNDERLINE),
+            Attr::DashedUnderline => self.set_underline(cell::Flags::DASHED_UNDERLINE),
+            Attr::CancelUnderline => self.set_underline(cell::Flags::empty()),
+            Attr::UnderlineColor(color) => self.cursor.template.underline_color = color,
             Attr::Hidden => self.cursor.template.flags.insert(cell::Flags::HIDDEN),
             Attr::CancelHidden => self.cursor.template.flags.remove(cell::Flags::HIDDEN),
             Attr::Strike => self.cursor.template.flags.insert(cell::Flags::STRIKEOUT),
             Attr::CancelStrike => self.cursor.template.flags.remove(cell::Flags::STRIKEOUT),
-            _ => {
-                debug!("Term got unhandled attr: {:?}", attr);
+            Attr::BlinkSlow | Attr::BlinkFast => {
+                self.cursor.template.flags.insert(cell::Flags::BLINK)
             }
+            Attr::CancelBlink => self.cursor.template.flags.remove(cell::Flags::BLINK),
         }
     }
 
@@ -1794,6 +3421,7 @@ impl ansi::Handler for Term {
             }
             ansi::Mode::ShowCursor => self.mode.insert(mode::TermMode::SHOW_CURSOR),
             ansi::Mode::CursorKeys => self.mode.insert(mode::TermMode::APP_CURSOR),
+            ansi::Mode::Ansi => self.mode.remove(mode::TermMode::VT52),
             ansi::Mode::ReportMouseClicks => {
                 self.mode.insert(mode::TermMode::MOUSE_REPORT_CLICK);
                 self.set_mouse_cursor(MouseCursor::Arrow);
@@ -1808,14 +3436,18 @@ impl ansi::Handler for Term {
             }
             ansi::Mode::ReportFocusInOut => self.mode.insert(mode::TermMode::FOCUS_IN_OUT),
             ansi::Mode::BracketedPaste => self.mode.insert(mode::TermMode::BRACKETED_PASTE),
+            ansi::Mode::Utf8Mouse => self.mode.insert(mode::TermMode::UTF8_MOUSE),
             ansi::Mode::SgrMouse => self.mode.insert(mode::TermMode::SGR_MOUSE),
+            ansi::Mode::AlternateScroll => self.mode.insert(mode::TermMode::ALTERNATE_SCROLL),
+            ansi::Mode::LeftRightMargin => self.mode.insert(mode::TermMode::LEFT_RIGHT_MARGIN),
             ansi::Mode::LineWrap => self.mode.insert(mode::TermMode::LINE_WRAP),
             ansi::Mode::LineFeedNewLine => self.mode.insert(mode::TermMode::LINE_FEED_NEW_LINE),
             ansi::Mode::Origin => self.mode.insert(mode::TermMode::ORIGIN),
             ansi::Mode::DECCOLM => self.deccolm(),
             ansi::Mode::Insert => self.mode.insert(mode::TermMode::INSERT), // heh
             ansi::Mode::BlinkingCursor => {
-                trace!("... unimplemented mode");
+                self.mode.insert(mode::TermMode::BLINKING_CURSOR);
+                self.blink_start = Instant::now();
             }
         }
     }
@@ -1834,6 +3466,7 @@ impl ansi::Handler for Term {
             }
             ansi::Mode::ShowCursor => self.mode.remove(mode::TermMode::SHOW_CURSOR),
             ansi::Mode::CursorKeys => self.mode.remove(mode::TermMode::APP_CURSOR),
+            ansi::Mode::Ansi => self.mode.insert(mode::TermMode::VT52),
             ansi::Mode::ReportMouseClicks => {
                 self.mode.remove(mode::TermMode::MOUSE_REPORT_CLICK);
                 self.set_mouse_cursor(MouseCursor::Text);
@@ -1848,15 +3481,19 @@ impl ansi::Handler for Term {
             }
             ansi::Mode::ReportFocusInOut => self.mode.remove(mode::TermMode::FOCUS_IN_OUT),
             ansi::Mode::BracketedPaste => self.mode.remove(mode::TermMode::BRACKETED_PASTE),
+            ansi::Mode::Utf8Mouse => self.mode.remove(mode::TermMode::UTF8_MOUSE),
             ansi::Mode::SgrMouse => self.mode.remove(mode::TermMode::SGR_MOUSE),
+            ansi::Mode::AlternateScroll => self.mode.remove(mode::TermMode::ALTERNATE_SCROLL),
+            ansi::Mode::LeftRightMargin => {
+                self.mode.remove(mode::TermMode::LEFT_RIGHT_MARGIN);
+                self.margins = index::Column(0)..self.grid.num_cols();
+            }
             ansi::Mode::LineWrap => self.mode.remove(mode::TermMode::LINE_WRAP),
             ansi::Mode::LineFeedNewLine => self.mode.remove(mode::TermMode::LINE_FEED_NEW_LINE),
             ansi::Mode::Origin => self.mode.remove(mode::TermMode::ORIGIN),
             ansi::Mode::DECCOLM => self.deccolm(),
             ansi::Mode::Insert => self.mode.remove(mode::TermMode::INSERT),
-            ansi::Mode::BlinkingCursor => {
-                trace!("... unimplemented mode");
-            }
+            ansi::Mode::BlinkingCursor => self.mode.remove(mode::TermMode::BLINKING_CURSOR),
         }
     }
 
@@ -1868,6 +3505,18 @@ impl ansi::Handler for Term {
         self.goto(index::Line(0), index::Column(0));
     }
 
+    #[inline]
+    fn set_left_right_margins(&mut self, margins: Range<index::Column>) {
+        trace!("Setting left and right margins: {:?}", margins);
+        let end = min(margins.end, self.grid.num_cols());
+        // The margins have to be at least two columns apart
+        if margins.start + 1 >= end {
+            return;
+        }
+        self.margins = margins.start..end;
+        self.goto(index::Line(0), index::Column(0));
+    }
+
     #[inline]
     fn set_keypad_application_mode(&mut self) {
         trace!("Setting keypad application mode");
@@ -1892,36 +3541,361 @@ impl ansi::Handler for Term {
         self.cursor.charsets[index] = charset;
     }
 
-    /// Set the clipboard
+    /// Set an indexed color value
     #[inline]
-    fn set_clipboard(&mut self, _string: &str) {
-        // TODO
+    fn set_color(&mut self, index: usize, color: ansi::Rgb) {
+        trace!("Setting color[{}] = {:?}", index, color);
+        if let Some(slot) = self.colors.get_mut(index) {
+            *slot = Some(color);
+            self.grid.damage_all();
+            self.dirty = true;
+        }
     }
 
+    /// Reset an indexed color to original value
     #[inline]
-    fn dectest(&mut self) {
-        trace!("Dectesting");
-        let mut template = self.cursor.template;
-        template.c = 'E';
-
-        self.grid.region_mut(..).each(|c| c.reset(&template));
+    fn reset_color(&mut self, index: usize) {
+        trace!("Resetting color[{}]", index);
+        if let Some(slot) = self.colors.get_mut(index) {
+            *slot = None;
+            self.grid.damage_all();
+            self.dirty = true;
+        }
     }
-}
-
-struct TabStops {
-    tabs: Vec<bool>,
-}
 
-impl TabStops {
-    fn new(num_cols: index::Column, tabspaces: usize) -> TabStops {
-        TabStops {
-            tabs: index::Range::from(index::Column(0)..num_cols)
-                .map(|i| (*i as usize) % tabspaces == 0)
-                .collect::<Vec<bool>>(),
-        }
+    #[inline]
+    fn set_hyperlink(&mut self, hyperlink: Option<ansi::Hyperlink>) {
+        trace!("Setting hyperlink: {:?}", hyperlink);
+        let index = hyperlink.and_then(|hyperlink| self.hyperlink_index(hyperlink));
+        self.cursor.template.hyperlink = index;
     }
 
-    fn clear_all(&mut self) {
+    /// Show an inline image at the cursor, like iTerm2 does
+    #[inline]
+    fn inline_image(&mut self, image: ansi::InlineImage) {
+        trace!("Showing an inline image of {} bytes", image.data.len());
+        let size = (image.width, image.height);
+        let preserve_aspect_ratio = image.preserve_aspect_ratio;
+        let image = Image {
+            name: image.name,
+            width: 0,
+            height: 0,
+            pixels: image_pixels(&image.data),
+            format: ImageFormat::File,
+            data: Arc::new(image.data),
+        };
+        self.place_image(image, size, preserve_aspect_ratio, true);
+    }
+
+    /// Run a command of the kitty graphics protocol
+    ///
+    /// Images that are placed show up like inline images do; the ones that are only transmitted
+    /// are kept by the id that the program gave them, for placing them later.
+    #[inline]
+    fn graphics_command<W: io::Write>(&mut self, writer: &mut W, command: ansi::GraphicsCommand) {
+        trace!("Running kitty graphics command {:?}", command.control);
+        // Only the first chunk of an image has the keys, the others just say if more follow
+        let command = match self.graphics_upload.take() {
+            Some(mut upload) => {
+                if upload.payload.len() + command.payload.len() > MAX_GRAPHICS_UPLOAD {
+                    debug!("Kitty graphics image is too big");
+                    return;
+                }
+                let more = command.number('m') == 1;
+                upload.payload.extend(command.payload);
+                if more {
+                    self.graphics_upload = Some(upload);
+                    return;
+                }
+                upload
+            }
+            None if command.number('m') == 1 => {
+                self.graphics_upload = Some(command);
+                return;
+            }
+            None => command,
+        };
+
+        let result = match command.value('a').unwrap_or("t") {
+            "t" => self.transmit_graphics(&command).map(|_| ()),
+            "T" => self.transmit_graphics(&command).map(|image| {
+                self.place_graphics(&command, image);
+            }),
+            "p" => match self.graphics_image(command.number('i')) {
+                Some(image) => {
+                    self.place_graphics(&command, image);
+                    Ok(())
+                }
+                None => Err("ENOENT:No such image"),
+            },
+            "d" => {
+                self.delete_graphics(&command);
+                return;
+            }
+            // Without an answer, programs take it that the terminal can't show images
+            "q" if self.graphics_supported => decode_graphics(&command).map(|_| ()),
+            "q" => return,
+            _ => Err("EINVAL:Unknown action"),
+        };
+
+        // Answers only go to commands with an id, and the `q` key can silence them
+        let id = command.number('i');
+        let quiet = command.number('q');
+        let answer = match result {
+            Ok(()) if quiet == 0 => "OK",
+            Err(error) if quiet < 2 => error,
+            _ => return,
+        };
+        if id != 0 {
+            let _ = write!(writer, "\x1b_Gi={};{}\x1b\\", id, answer);
+        }
+    }
+
+    /// Set the size of the characters on the cursor's line; the columns that don't fit anymore
+    /// keep their contents, but the cursor can't get to them
+    #[inline]
+    fn set_line_size(&mut self, size: LineSize) {
+        trace!("Setting the line size to {:?}", size);
+        self.grid[self.cursor.point.line].size = size;
+        self.cursor.point.col = min(self.cursor.point.col, self.line_cols() - 1);
+        self.dirty = true;
+    }
+
+    #[inline]
+    fn prompt_mark(&mut self, mark: PromptMarks) {
+        trace!("Marking {:?}", mark);
+        self.grid[self.cursor.point.line].marks.insert(mark);
+    }
+
+    #[inline]
+    fn push_keyboard_modes(&mut self, modes: KeyboardModes) {
+        trace!("Pushing keyboard modes {:?}", modes);
+        if self.keyboard_modes.len() >= KEYBOARD_MODES_DEPTH {
+            self.keyboard_modes.remove(0);
+        }
+        self.keyboard_modes.push(supported_keyboard_modes(modes));
+        self.update_keyboard_mode();
+    }
+
+    #[inline]
+    fn pop_keyboard_modes(&mut self, count: usize) {
+        trace!("Popping {} keyboard modes", count);
+        let len = self.keyboard_modes.len();
+        self.keyboard_modes.truncate(len.saturating_sub(count));
+        self.update_keyboard_mode();
+    }
+
+    #[inline]
+    fn set_keyboard_modes(&mut self, modes: KeyboardModes, behavior: KeyboardModesApplyBehavior) {
+        trace!("Setting keyboard modes {:?} ({:?})", modes, behavior);
+        if self.keyboard_modes.is_empty() {
+            self.keyboard_modes.push(KeyboardModes::empty());
+        }
+        if let Some(current) = self.keyboard_modes.last_mut() {
+            *current = supported_keyboard_modes(match behavior {
+                KeyboardModesApplyBehavior::Replace => modes,
+                KeyboardModesApplyBehavior::Union => *current | modes,
+                KeyboardModesApplyBehavior::Difference => *current - modes,
+            });
+        }
+        self.update_keyboard_mode();
+    }
+
+    #[inline]
+    fn report_keyboard_modes<W: io::Write>(&mut self, writer: &mut W) {
+        let modes = self.keyboard_modes.last().cloned().unwrap_or_default();
+        trace!("Reporting keyboard modes {:?}", modes);
+        let _ = write!(writer, "\x1b[?{}u", modes.bits());
+    }
+
+    #[inline]
+    fn notify(&mut self, notification: ansi::Notification) {
+        trace!("Notifying {:?}", notification);
+        if self.notifications.len() == MAX_NOTIFICATIONS {
+            self.notifications.pop_front();
+        }
+        self.notifications.push_back(notification);
+    }
+
+    /// Set the clipboard
+    #[inline]
+    fn set_clipboard(&mut self, string: &str) {
+        trace!("Setting the clipboard to {} bytes", string.len());
+        self.next_clipboard = Some(string.to_owned());
+    }
+
+    #[inline]
+    fn dectest(&mut self) {
+        trace!("Dectesting");
+        let mut template = self.cursor.template;
+        template.c = 'E';
+
+        self.grid.region_mut(..).each(|c| c.reset(&template));
+    }
+}
+
+/// An image that a program showed in the terminal, with the cells it takes up
+#[derive(Clone, Debug)]
+pub struct Image {
+    pub name: Option<String>,
+    /// How many columns the image takes up
+    pub width: usize,
+    /// How many lines the image takes up
+    pub height: usize,
+    /// The width and height of the image in pixels, if they are known
+    pub pixels: Option<(usize, usize)>,
+    pub format: ImageFormat,
+    /// The pixels of the image, shared between the places where the same image is shown
+    pub data: Arc<Vec<u8>>,
+}
+
+/// How the pixels of an image are stored
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ImageFormat {
+    /// An image file, like a PNG, GIF or JPEG file
+    File,
+    /// Red, green and blue bytes for each pixel, row by row
+    Rgb,
+    /// Red, green, blue and alpha bytes for each pixel, row by row
+    Rgba,
+}
+
+/// The id that the cells of the next image get; they are unique in the whole process, so that
+/// renderers can tell images from different terminals apart
+static NEXT_IMAGE_ID: AtomicUsize = AtomicUsize::new(1);
+
+#[allow(clippy::cast_possible_truncation)]
+fn next_image_id() -> u32 {
+    NEXT_IMAGE_ID.fetch_add(1, Ordering::Relaxed) as u32
+}
+
+/// Decode the image that a kitty graphics command transmits.
+#[allow(clippy::cast_possible_truncation)]
+fn decode_graphics(command: &ansi::GraphicsCommand) -> Result<Image, &'static str> {
+    if command.value('t').map_or(false, |medium| medium != "d") {
+        return Err("EINVAL:Only direct transmission is supported");
+    }
+    if command.value('o').is_some() {
+        return Err("EINVAL:Compression is not supported");
+    }
+    let data = base64::decode(&command.payload).map_err(|_| "EINVAL:Invalid base64")?;
+
+    let pixels = (command.number('s') as usize, command.number('v') as usize);
+    let len = |channels| pixels.0.checked_mul(pixels.1)?.checked_mul(channels);
+    let (format, pixels) = match command.number('f') {
+        100 => (ImageFormat::File, image_pixels(&data)),
+        24 if Some(data.len()) == len(3) => (ImageFormat::Rgb, Some(pixels)),
+        0 | 32 if Some(data.len()) == len(4) => (ImageFormat::Rgba, Some(pixels)),
+        24 | 0 | 32 => return Err("ENODATA:The size doesn't match the data"),
+        _ => return Err("EINVAL:Unknown format"),
+    };
+    Ok(Image {
+        name: None,
+        width: 0,
+        height: 0,
+        pixels,
+        format,
+        data: Arc::new(data),
+    })
+}
+
+/// Forget the oldest of `images` while there are more than `MAX_IMAGES` of them or they take up
+/// more than `MAX_IMAGE_BYTES`.
+fn limit_images(images: &mut VecDeque<(u32, Image)>) {
+    let mut bytes = images
+        .iter()
+        .map(|(_, image)| image.data.len())
+        .sum::<usize>();
+    while images.len() > MAX_IMAGES || bytes > MAX_IMAGE_BYTES {
+        match images.pop_front() {
+            Some((_, image)) => bytes -= image.data.len(),
+            None => break,
+        }
+    }
+}
+
+/// How many columns and lines an image takes up, with `pixels` for the image's own size and
+/// `cols` columns left on the line.
+fn image_size(
+    (width_size, height_size): (ImageSize, ImageSize),
+    preserve_aspect_ratio: bool,
+    pixels: Option<(usize, usize)>,
+    cols: usize,
+    lines: usize,
+) -> (usize, usize) {
+    // The sizes come from the program, so they can be as big as it likes
+    let cells = |pixels: usize, cell_pixels: usize| {
+        pixels / cell_pixels + if pixels % cell_pixels == 0 { 0 } else { 1 }
+    };
+    let pixels = pixels.filter(|&(width, height)| width > 0 && height > 0);
+    let side = |size, total: usize, cell_pixels: usize, image_pixels: Option<usize>| match size {
+        ImageSize::Auto => image_pixels.map(|pixels| cells(pixels, cell_pixels)),
+        ImageSize::Cells(cells) => Some(cells),
+        ImageSize::Pixels(pixels) => Some(cells(pixels, cell_pixels)),
+        ImageSize::Percent(percent) => Some(total.saturating_mul(percent) / 100),
+    };
+    let mut width = side(width_size, cols, CELL_PIXELS.0, pixels.map(|p| p.0));
+    let mut height = side(height_size, lines, CELL_PIXELS.1, pixels.map(|p| p.1));
+
+    // A side that is left to the image follows the other one
+    if let Some((width_pixels, height_pixels)) = pixels.filter(|_| preserve_aspect_ratio) {
+        match (width_size, height_size) {
+            (ImageSize::Auto, ImageSize::Auto) => (),
+            (ImageSize::Auto, _) => {
+                width = height.map(|height| {
+                    let pixels = height
+                        .saturating_mul(CELL_PIXELS.1)
+                        .saturating_mul(width_pixels)
+                        / height_pixels;
+                    cells(pixels, CELL_PIXELS.0)
+                })
+            }
+            (_, ImageSize::Auto) => {
+                height = width.map(|width| {
+                    let pixels = width
+                        .saturating_mul(CELL_PIXELS.0)
+                        .saturating_mul(height_pixels)
+                        / width_pixels;
+                    cells(pixels, CELL_PIXELS.1)
+                })
+            }
+            _ => (),
+        }
+    }
+
+    (
+        min(width.unwrap_or(cols), cols).max(1),
+        min(height.unwrap_or(1), lines).max(1),
+    )
+}
+
+/// The size in pixels of a PNG or GIF image, from its header.
+fn image_pixels(data: &[u8]) -> Option<(usize, usize)> {
+    let be32 = |bytes: &[u8]| bytes.iter().fold(0, |n, &byte| n << 8 | byte as usize);
+    let le16 = |bytes: &[u8]| bytes[0] as usize | (bytes[1] as usize) << 8;
+    if data.len() >= 24 && data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some((be32(&data[16..20]), be32(&data[20..24])))
+    } else if data.len() >= 10 && data.starts_with(b"GIF8") {
+        Some((le16(&data[6..8]), le16(&data[8..10])))
+    } else {
+        None
+    }
+}
+
+struct TabStops {
+    tabs: Vec<bool>,
+}
+
+impl TabStops {
+    fn new(num_cols: index::Column, tabspaces: usize) -> TabStops {
+        TabStops {
+            tabs: index::Range::from(index::Column(0)..num_cols)
+                .map(|i| (*i as usize) % tabspaces == 0)
+                .collect::<Vec<bool>>(),
+        }
+    }
+
+    fn clear_all(&mut self) {
         unsafe {
             ptr::write_bytes(self.tabs.as_mut_ptr(), 0, self.tabs.len());
         }
@@ -1944,14 +3918,20 @@ impl IndexMut<index::Column> for TabStops {
 
 #[cfg(test)]
 mod tests {
-    use super::{Cell, SizeInfo, Term};
+    use super::{
+        color, image_size, Cell, CursorState, Damage, Direction, ImageFormat, Prompt, Search,
+        SizeInfo, Term, TermMode, Url, VisualBell, VisualBellAnimation, BLINK_INTERVAL,
+        MAX_HYPERLINKS,
+    };
     use crate::term::cell;
 
-    use crate::ansi::{self, CharsetIndex, Handler, StandardCharset};
+    use crate::ansi::{self, CharsetIndex, Color, Handler, LineSize, NamedColor, StandardCharset};
     use crate::grid::{Grid, Scroll};
     use crate::index;
     use crate::selection::Selection;
+    use std::io;
     use std::mem;
+    use std::time::Duration;
 
     #[test]
     fn semantic_selection_works() {
@@ -2008,6 +3988,58 @@ mod tests {
         }
     }
 
+    #[test]
+    fn url_search_works() {
+        let size = SizeInfo {
+            width: 21.0,
+            height: 51.0,
+            cell_width: 3.0,
+            cell_height: 3.0,
+            padding_x: 0.0,
+            padding_y: 0.0,
+            dpr: 1.0,
+        };
+        let mut term = Term::new(size);
+        let mut grid: Grid<Cell> = Grid::new(index::Line(3), index::Column(10), 0, Cell::default());
+        for (line, text) in ["see (http:", "//x.io/a).", "ftp: x"].iter().enumerate() {
+            for (col, c) in text.chars().enumerate() {
+                grid[index::Line(line)][index::Column(col)].c = c;
+            }
+        }
+        grid[index::Line(0)][index::Column(9)]
+            .flags
+            .insert(cell::Flags::WRAPLINE);
+
+        mem::swap(&mut term.grid, &mut grid);
+
+        let url = Some(Url {
+            text: String::from("http://x.io/a"),
+            start: index::Point {
+                line: 2,
+                col: index::Column(5),
+            },
+            end: index::Point {
+                line: 1,
+                col: index::Column(7),
+            },
+        });
+        for &(line, col) in &[(2, 5), (2, 9), (1, 0), (1, 7)] {
+            let point = index::Point {
+                line,
+                col: index::Column(col),
+            };
+            assert_eq!(term.url_search(point), url);
+        }
+
+        for &(line, col) in &[(2, 0), (2, 4), (1, 8), (1, 9), (0, 0), (0, 3)] {
+            let point = index::Point {
+                line,
+                col: index::Column(col),
+            };
+            assert_eq!(term.url_search(point), None);
+        }
+    }
+
     #[test]
     fn line_selection_works() {
         let size = SizeInfo {
@@ -2077,6 +4109,34 @@ mod tests {
         assert_eq!(term.selection_to_string(), Some("aaa\n\naaa\n".into()));
     }
 
+    #[test]
+    fn renderable_cells_mark_the_selection() {
+        let mut term = term_with_output(b"abc\r\nde");
+        let mut selection = Selection::simple(
+            index::Point {
+                line: 1,
+                col: index::Column(1),
+            },
+            index::Side::Left,
+        );
+        selection.update(
+            index::Point {
+                line: 1,
+                col: index::Column(3),
+            },
+            index::Side::Right,
+        );
+        *term.selection_mut() = Some(selection);
+
+        let selected: Vec<_> = term
+            .renderable_cells()
+            .filter(|cell| cell.selected)
+            .map(|cell| (cell.line.0, cell.column.0, cell.chars[0]))
+            .collect();
+        // The empty cell after "abc" is drawn too, since it is selected
+        assert_eq!(selected, vec![(0, 1, 'b'), (0, 2, 'c'), (0, 3, ' ')]);
+    }
+
     #[test]
     fn input_line_drawing_character() {
         let size = SizeInfo {
@@ -2127,6 +4187,965 @@ mod tests {
         scrolled_grid.scroll_display(Scroll::Top);
         assert_eq!(term.grid, scrolled_grid);
     }
+
+    fn term_with_output(output: &[u8]) -> Term {
+        let size = SizeInfo {
+            width: 5.0,
+            height: 2.0,
+            cell_width: 1.0,
+            cell_height: 1.0,
+            padding_x: 0.0,
+            padding_y: 0.0,
+            dpr: 1.0,
+        };
+        let mut term = Term::new(size);
+        let mut processor = ansi::Processor::new();
+        for byte in output {
+            processor.advance(&mut term, *byte, &mut io::sink());
+        }
+        term
+    }
+
+    fn resize(term: &mut Term, cols: f32) {
+        let size = SizeInfo {
+            width: cols,
+            ..*term.size_info()
+        };
+        term.resize(&size);
+    }
+
+    #[test]
+    fn resizing_rewraps_lines() {
+        let mut term = term_with_output(b"abcdefgh\r\nij");
+
+        resize(&mut term, 10.0);
+        assert_eq!(term.grid().history_size(), 0);
+        assert_eq!(term.history_to_string(false), "abcdefgh\nij\n");
+        assert_eq!(term.cursor_position(), Some((2, 1)));
+
+        resize(&mut term, 3.0);
+        assert_eq!(term.grid().history_size(), 2);
+        assert_eq!(term.history_to_string(false), "abcdefgh\nij\n");
+        assert_eq!(term.cursor_position(), Some((2, 1)));
+
+        resize(&mut term, 5.0);
+        let mut processor = ansi::Processor::new();
+        for byte in b"klm" {
+            processor.advance(&mut term, *byte, &mut io::sink());
+        }
+        assert_eq!(term.history_to_string(false), "abcdefgh\nijklm\n");
+        assert_eq!(term.cursor_position(), Some((4, 1)));
+    }
+
+    /// What `term` writes back when it gets `input`
+    fn replies(term: &mut Term, input: &[u8]) -> String {
+        let mut processor = ansi::Processor::new();
+        let mut replies = Vec::new();
+        for byte in input {
+            processor.advance(term, *byte, &mut replies);
+        }
+        String::from_utf8(replies).unwrap()
+    }
+
+    #[test]
+    fn tab_stops_follow_tabspaces() {
+        // The first tab stop is past the end of the line by default
+        let mut term = term_with_output(b"\ta");
+
+        term.set_tabspaces(2);
+        let mut processor = ansi::Processor::new();
+        for byte in b"\r\n\tb" {
+            processor.advance(&mut term, *byte, &mut io::sink());
+        }
+        // The tab stays in the cell that it started at, so that it is copied as a tab
+        assert_eq!(term.recent_lines(2), vec!["\t b", "\t   a"]);
+    }
+
+    #[test]
+    fn huge_tab_counts_stop_at_the_margin() {
+        let term = term_with_output(b"\x1b[3G\x1b[1000000000Zb\x1b[1000000000Ia");
+        assert_eq!(term.recent_lines(1), vec!["b   a"]);
+    }
+
+    #[test]
+    fn answerback_is_sent_on_enq() {
+        let mut term = term_with_output(b"");
+        assert_eq!(replies(&mut term, b"\x05"), "");
+
+        term.set_answerback("mux");
+        assert_eq!(replies(&mut term, b"a\x05b"), "mux");
+        assert_eq!(term.recent_lines(1), vec!["ab"]);
+    }
+
+    #[test]
+    fn device_attributes_are_reported() {
+        let mut term = term_with_output(b"");
+
+        assert_eq!(replies(&mut term, b"\x1b[c"), "\x1b[?6c");
+        assert!(replies(&mut term, b"\x1b[>c").starts_with("\x1b[>1;"));
+        assert_eq!(replies(&mut term, b"\x1b[=c"), "\x1bP!|00000000\x1b\\");
+    }
+
+    #[test]
+    fn settings_are_reported() {
+        let mut term = term_with_output(b"\x1b[1;31m\x1b[4 q");
+
+        assert_eq!(replies(&mut term, b"\x1bP$q q\x1b\\"), "\x1bP1$r4 q\x1b\\");
+        assert_eq!(replies(&mut term, b"\x1bP$qr\x1b\\"), "\x1bP1$r1;2r\x1b\\");
+        assert_eq!(
+            replies(&mut term, b"\x1bP$qm\x1b\\"),
+            "\x1bP1$r0;1;31m\x1b\\"
+        );
+        assert_eq!(replies(&mut term, b"\x1bP$qx\x1b\\"), "\x1bP0$r\x1b\\");
+    }
+
+    #[test]
+    fn styled_underlines_are_parsed() {
+        let term = term_with_output(b"\x1b[4:3;58:2::1:2:3ma\x1b[4;58;5;9mb\x1b[4:0;59mc");
+        let row = &term.grid()[index::Line(0)];
+
+        assert!(row[index::Column(0)].flags.contains(cell::Flags::UNDERCURL));
+        assert!(!row[index::Column(0)].flags.contains(cell::Flags::UNDERLINE));
+        assert_eq!(
+            row[index::Column(0)].underline_color,
+            Some(Color::Spec(ansi::Rgb { r: 1, g: 2, b: 3 }))
+        );
+        assert!(row[index::Column(1)].flags.contains(cell::Flags::UNDERLINE));
+        assert!(!row[index::Column(1)].flags.contains(cell::Flags::UNDERCURL));
+        assert_eq!(
+            row[index::Column(1)].underline_color,
+            Some(Color::Indexed(9))
+        );
+        assert!(!row[index::Column(2)]
+            .flags
+            .intersects(cell::Flags::ALL_UNDERLINES));
+        assert_eq!(row[index::Column(2)].underline_color, None);
+
+        // A colon without the style after it
+        let term = term_with_output(b"\x1b[4:md");
+        assert!(term.grid()[index::Line(0)][index::Column(0)]
+            .flags
+            .contains(cell::Flags::UNDERLINE));
+    }
+
+    #[test]
+    fn hyperlinks_are_kept_with_cells() {
+        let term = term_with_output(b"\x1b]8;id=a;http://x/?a;b\x1b\\ab\x1b]8;;\x1b\\c");
+        let row = &term.grid()[index::Line(0)];
+
+        let index = row[index::Column(0)].hyperlink.unwrap();
+        assert_eq!(row[index::Column(1)].hyperlink, Some(index));
+        assert_eq!(row[index::Column(2)].hyperlink, None);
+        assert_eq!(
+            term.hyperlink(index),
+            Some(&ansi::Hyperlink {
+                id: Some(String::from("a")),
+                uri: String::from("http://x/?a;b"),
+            })
+        );
+    }
+
+    #[test]
+    fn hyperlinks_that_no_cell_has_are_forgotten() {
+        let mut term = term_with_output(b"");
+        let mut processor = ansi::Processor::new();
+        for i in 0..=MAX_HYPERLINKS {
+            let link = format!("\x1b]8;;http://x/{}\x1b\\a\x1b]8;;\x1b\\\r", i);
+            processor.advance_bytes(&mut term, link.as_bytes(), &mut io::sink());
+        }
+        assert!(term.hyperlinks.len() <= MAX_HYPERLINKS);
+
+        let index = term.grid()[index::Line(0)][index::Column(0)]
+            .hyperlink
+            .unwrap();
+        let uri = format!("http://x/{}", MAX_HYPERLINKS);
+        assert_eq!(term.hyperlink(index).map(|link| &link.uri), Some(&uri));
+    }
+
+    #[test]
+    fn inline_images_take_up_cells() {
+        // A PNG header for an image of 24 by 20 pixels, made longer than `vte` keeps OSC strings
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR\0\0\0\x18\0\0\0\x14".to_vec();
+        png.resize(2048, 0);
+        let mut output = b"a\x1b]1337;File=name=YS5wbmc=;inline=1:".to_vec();
+        output.extend(base64::encode(&png).bytes());
+        output.extend(b"\x07b");
+        let term = term_with_output(&output);
+        let grid = term.grid();
+
+        assert_eq!(grid[index::Line(0)][index::Column(0)].c, 'a');
+        assert_eq!(grid[index::Line(0)][index::Column(1)].c, '[');
+        assert_eq!(grid[index::Line(1)][index::Column(4)].c, 'b');
+        let image = grid[index::Line(1)][index::Column(2)].image.unwrap();
+        assert_eq!((image.col, image.line), (1, 1));
+        assert_eq!(grid[index::Line(1)][index::Column(4)].image, None);
+
+        let image = term.image(image.id).unwrap();
+        assert_eq!(image.name.as_ref().map(String::as_str), Some("a.png"));
+        assert_eq!((image.width, image.height), (3, 2));
+        assert_eq!(*image.data, png);
+    }
+
+    #[test]
+    fn huge_image_sizes_fill_the_screen() {
+        use crate::ansi::ImageSize;
+
+        let huge = usize::max_value();
+        let size = |sizes| image_size(sizes, true, Some((16, 16)), 80, 24);
+        assert_eq!(size((ImageSize::Pixels(huge), ImageSize::Auto)), (80, 24));
+        assert_eq!(size((ImageSize::Percent(huge), ImageSize::Auto)), (80, 24));
+        assert_eq!(size((ImageSize::Auto, ImageSize::Cells(huge))), (80, 24));
+    }
+
+    #[test]
+    fn kitty_graphics_are_placed_and_deleted() {
+        let mut term = term_with_output(b"");
+
+        // Two black RGB pixels, sent in two chunks
+        let transmit = b"\x1b_Ga=T,f=24,s=2,v=1,i=7,c=2,r=1,m=1;AAAA\x1b\\\x1b_Gm=0;AAAA\x1b\\x";
+        assert_eq!(replies(&mut term, transmit), "\x1b_Gi=7;OK\x1b\\");
+        let image = term.grid()[index::Line(0)][index::Column(1)].image.unwrap();
+        assert_eq!((image.col, image.line), (1, 0));
+        assert_eq!(term.grid()[index::Line(0)][index::Column(2)].c, 'x');
+        let image = term.image(image.id).unwrap();
+        assert_eq!(image.format, ImageFormat::Rgb);
+        assert_eq!(*image.data, vec![0; 6]);
+        // A size with more pixels than there are bytes
+        assert_eq!(
+            replies(
+                &mut term,
+                b"\x1b_Ga=t,f=24,s=4294967295,v=4294967295,i=8;AAAA\x1b\\"
+            ),
+            "\x1b_Gi=8;ENODATA:The size doesn't match the data\x1b\\"
+        );
+
+        // Programs are only told that images work when the terminal outside can show them
+        assert_eq!(replies(&mut term, b"\x1b_Ga=q,i=1;AAAA\x1b\\"), "");
+        term.set_graphics_supported(true);
+        assert_eq!(
+            replies(&mut term, b"\x1b_Ga=q,i=1,f=24,s=2,v=1;AAAAAAAA\x1b\\"),
+            "\x1b_Gi=1;OK\x1b\\"
+        );
+
+        replies(&mut term, b"\x1b_Ga=d,d=i,i=7\x1b\\");
+        assert_eq!(term.grid()[index::Line(0)][index::Column(1)].image, None);
+        assert_eq!(
+            replies(&mut term, b"\x1b_Ga=p,i=7\x1b\\"),
+            "\x1b_Gi=7;OK\x1b\\"
+        );
+        replies(&mut term, b"\x1b_Ga=d,d=I,i=7\x1b\\");
+        assert_eq!(
+            replies(&mut term, b"\x1b_Ga=p,i=7\x1b\\"),
+            "\x1b_Gi=7;ENOENT:No such image\x1b\\"
+        );
+    }
+
+    #[test]
+    fn double_width_lines_hold_half_the_columns() {
+        let term = term_with_output(b"\x1b#6abc");
+
+        assert_eq!(term.line_size(0), LineSize::DoubleWidth);
+        assert_eq!(term.line_size(1), LineSize::Single);
+        assert_eq!(term.grid()[index::Line(0)][index::Column(1)].c, 'b');
+        assert_eq!(term.grid()[index::Line(1)][index::Column(0)].c, 'c');
+
+        let term = term_with_output(b"\x1b#3a\x1b#5");
+        assert_eq!(term.line_size(0), LineSize::Single);
+    }
+
+    /// Check that `advance_bytes` leaves the same terminal and replies as `advance` byte by byte
+    fn assert_processed_like_one_by_one(input: &[u8], c1_controls: bool) -> Term {
+        let processor = || {
+            let mut processor = ansi::Processor::new();
+            processor.set_c1_controls(c1_controls);
+            processor
+        };
+
+        let mut one_by_one = term_with_output(b"");
+        let mut expected = Vec::new();
+        let mut one_by_one_processor = processor();
+        for byte in input {
+            one_by_one_processor.advance(&mut one_by_one, *byte, &mut expected);
+        }
+
+        let mut batch = term_with_output(b"");
+        let mut replies = Vec::new();
+        processor().advance_bytes(&mut batch, input, &mut replies);
+        assert_eq!(batch.grid(), one_by_one.grid(), "input: {:?}", input);
+        assert_eq!(replies, expected);
+        batch
+    }
+
+    #[test]
+    fn bytes_are_processed_like_one_by_one() {
+        let mut term = assert_processed_like_one_by_one(
+            b"ab\x1b[31mc\x1b(0qx\x1b(Bd\x1b[2b\xc3\xa9\x1bP$qm\x1b\\\x1b]2;t\x07f\x1b_xy\x1b\\g",
+            false,
+        );
+        assert_eq!(term.get_next_title(), Some("t".to_owned()));
+        // A UTF-8 character in a string that ends like ST
+        assert_processed_like_one_by_one(b"\x1bP\xc3\x9cpq\x1b\\rs", true);
+
+        // Runs of bytes that start and end sequences and characters, from a fixed seed
+        const BYTES: &[u8] = b"\x1b\x1b\x07\x18\x90\x9b\x9c\x9d\xc3\xe2\x82P[]_\\;:Gqa1";
+        let mut seed = 0x2545_f491u32;
+        for _ in 0..2000 {
+            let input = (0..12)
+                .map(|_| {
+                    seed ^= seed << 13;
+                    seed ^= seed >> 17;
+                    seed ^= seed << 5;
+                    BYTES[seed as usize % BYTES.len()]
+                })
+                .collect::<Vec<_>>();
+            assert_processed_like_one_by_one(&input, false);
+            assert_processed_like_one_by_one(&input, true);
+        }
+    }
+
+    #[test]
+    fn vt52_mode_has_its_own_escape_sequences() {
+        let mut term = term_with_output(b"\x1b[?2l\x1bY!#ab\x1bA\x1bDc");
+        assert!(term.mode().contains(TermMode::VT52));
+        assert_eq!(term.grid()[index::Line(1)][index::Column(3)].c, 'a');
+        assert_eq!(term.grid()[index::Line(0)][index::Column(3)].c, 'c');
+        assert_eq!(replies(&mut term, b"\x1b[?2l\x1bZ"), "\x1b/Z");
+
+        let term = term_with_output(b"\x1b[?2l\x1b<\x1b[2;2Hx");
+        assert!(!term.mode().contains(TermMode::VT52));
+        assert_eq!(term.grid()[index::Line(1)][index::Column(1)].c, 'x');
+    }
+
+    #[test]
+    fn c1_controls_are_understood() {
+        let mut term = term_with_output(b"");
+        let mut processor = ansi::Processor::new();
+        processor.set_c1_controls(true);
+        processor.advance_bytes(
+            &mut term,
+            b"\x9b31ma\xc3\x9b\x9d2;title\x9c",
+            &mut io::sink(),
+        );
+        let cell = term.grid()[index::Line(0)][index::Column(0)];
+        assert_eq!(cell.c, 'a');
+        assert_eq!(cell.fg, Color::Named(NamedColor::Red));
+        // A UTF-8 character that ends like CSI
+        assert_eq!(term.grid()[index::Line(0)][index::Column(1)].c, '\u{db}');
+        assert_eq!(term.get_next_title(), Some("title".to_owned()));
+
+        processor.set_c1_controls(false);
+        for byte in b"\x9b32m" {
+            processor.advance(&mut term, *byte, &mut io::sink());
+        }
+        // The parameters and final byte are printed instead
+        let cell = term.grid()[index::Line(0)][index::Column(2)];
+        assert_eq!(cell.c, '3');
+        assert_eq!(cell.fg, Color::Named(NamedColor::Red));
+    }
+
+    #[test]
+    fn stray_c1_bytes_dont_hide_the_output() {
+        // DCS and OSC, which would wait for a string terminator if they were understood
+        let term = term_with_output(b"a\x90b\r\nc\x9dd");
+        let row = |line| {
+            (0..2)
+                .map(|col| term.grid()[index::Line(line)][index::Column(col)].c)
+                .collect::<String>()
+        };
+        assert_eq!(row(0), "ab");
+        assert_eq!(row(1), "cd");
+    }
+
+    #[test]
+    fn left_and_right_margins_hold_the_text() {
+        fn row(term: &Term, line: usize) -> String {
+            (0..5)
+                .map(|col| term.grid()[index::Line(line)][index::Column(col)].c)
+                .collect()
+        }
+
+        // Without margin mode, CSI s still saves the cursor
+        let term = term_with_output(b"12345\r\n67890\x1b[2;4s\x1b[1;2Habcde");
+        assert_eq!(row(&term, 0), "1abcd");
+
+        let mut term = term_with_output(b"12345\r\n67890\x1b[?69h\x1b[2;4s\x1b[1;2Habcde");
+        assert_eq!(row(&term, 0), "1abc5");
+        assert_eq!(row(&term, 1), "6de90");
+
+        let mut processor = ansi::Processor::new();
+        for byte in b"\r\n\x1b[1;2H\x1b[P" {
+            processor.advance(&mut term, *byte, &mut io::sink());
+        }
+        assert_eq!(row(&term, 0), "1e9 5");
+        assert_eq!(row(&term, 1), "6   0");
+        assert_eq!(term.grid().scroll_limit(), 0);
+    }
+
+    #[test]
+    fn rectangular_areas_are_copied_filled_and_erased() {
+        let term = term_with_output(b"abcde\r\nfghij\x1b[1;1;1;2;1;2;4;1$v");
+        assert_eq!(term.grid()[index::Line(1)][index::Column(3)].c, 'a');
+        assert_eq!(term.grid()[index::Line(1)][index::Column(4)].c, 'b');
+
+        let term = term_with_output(b"abcde\r\nfghij\x1b[31m\x1b[88;2;2;2;3$x");
+        let cell = term.grid()[index::Line(1)][index::Column(2)];
+        assert_eq!(cell.c, 'X');
+        assert_eq!(cell.fg, Color::Named(NamedColor::Red));
+        assert_eq!(term.grid()[index::Line(1)][index::Column(3)].c, 'i');
+
+        let term = term_with_output(b"ab\x1b[41mcde\x1b[m\x1b[1;2;1;3$z\x1b[1;4;1;4${");
+        assert_eq!(term.grid()[index::Line(0)][index::Column(0)].c, 'a');
+        assert_eq!(
+            term.grid()[index::Line(0)][index::Column(2)],
+            Cell::default()
+        );
+        let cell = term.grid()[index::Line(0)][index::Column(3)];
+        assert_eq!(cell.c, ' ');
+        assert_eq!(cell.bg, Color::Named(NamedColor::Red));
+    }
+
+    #[test]
+    fn forward_tabs_move_to_tab_stops() {
+        // The first tab stop is past the end of the 5 columns, so the last column stops the cursor
+        let term = term_with_output(b"a\x1b[Ib\r\n\x1b[3Ic");
+
+        assert_eq!(term.grid()[index::Line(0)][index::Column(4)].c, 'b');
+        assert_eq!(term.grid()[index::Line(0)][index::Column(1)].c, ' ');
+        assert_eq!(term.grid()[index::Line(1)][index::Column(4)].c, 'c');
+    }
+
+    #[test]
+    fn control_characters_are_executed() {
+        let term = term_with_output(b"a\x1ab\x7fc");
+        assert_eq!(term.grid()[index::Line(0)][index::Column(1)].c, '\u{fffd}');
+        assert_eq!(term.grid()[index::Line(0)][index::Column(3)].c, 'c');
+
+        // IND and NEL, as 8-bit controls
+        let term = term_with_output(b"a\x84b\x85c");
+        assert_eq!(term.cursor_position(), Some((1, 1)));
+
+        let term = term_with_output(b"a\r\nb\x8dc");
+        assert_eq!(term.grid()[index::Line(0)][index::Column(1)].c, 'c');
+
+        // Line feeds return the carriage in new line mode
+        let term = term_with_output(b"ab\x1b[20h\nc");
+        assert_eq!(term.grid()[index::Line(1)][index::Column(0)].c, 'c');
+    }
+
+    #[test]
+    fn renderable_cells_resolve_colors() {
+        let term = term_with_output(b"\x1b]4;1;rgb:01/02/03\x07\x1b[31ma\x1b[2;38;5;2mb\x1b[0;7mc");
+        let colors = color::List::default();
+        let cells = term.renderable_cells_rgb(&colors).collect::<Vec<_>>();
+        let rgb = |r, g, b| ansi::Rgb { r, g, b };
+
+        assert_eq!(cells[0].fg, rgb(1, 2, 3));
+        assert_eq!(cells[1].fg, rgb(0, 136, 0));
+        assert_eq!(
+            (cells[2].fg, cells[2].bg),
+            (rgb(0, 0, 0), rgb(229, 229, 229))
+        );
+        assert_eq!(cells[0].bg, colors[ansi::NamedColor::Background]);
+    }
+
+    #[test]
+    fn dim_colors_are_resolved_for_every_kind_of_color() {
+        let term = term_with_output(
+            b"\x1b]4;1;rgb:30/60/90\x07\x1b[2;31ma\x1b[1;38;5;3mb\x1b[38;5;196mc\x1b[38;2;90;60;30md",
+        );
+        let colors = color::List::default();
+        let cells = term.renderable_cells_rgb(&colors).collect::<Vec<_>>();
+        let rgb = |r, g, b| ansi::Rgb { r, g, b };
+
+        // The dim red follows the red that the program set
+        assert_eq!(cells[0].cell.fg, Color::Named(NamedColor::DimRed));
+        assert_eq!(cells[0].fg, rgb(32, 64, 96));
+        // Dim wins over bold
+        assert_eq!(cells[1].cell.fg, Color::Named(NamedColor::DimYellow));
+        assert_eq!(cells[2].fg, rgb(170, 0, 0));
+        assert_eq!(cells[3].fg, rgb(60, 40, 20));
+    }
+
+    #[test]
+    fn prompts_are_found_with_their_output() {
+        let mut term = term_with_output(
+            b"\x1b]133;A\x07$ \x1b]133;B\x07ls\r\n\x1b]133;C\x07a\r\nb\r\n\
+              \x1b]133;D;0\x07\x1b]133;A\x07$ \x1b]133;B\x07",
+        );
+        let prompts = term.prompts();
+
+        assert_eq!(
+            prompts,
+            vec![
+                Prompt {
+                    line: 0,
+                    output: Some(1..3),
+                },
+                Prompt {
+                    line: 3,
+                    output: None,
+                },
+            ]
+        );
+        assert_eq!(term.lines_to_string(1..3), "a\nb\n");
+
+        term.scroll_display(Scroll::ToLine(0));
+        assert_eq!(term.grid().display_offset(), 2);
+    }
+
+    #[test]
+    fn damage_covers_changed_lines() {
+        let mut term = term_with_output(b"one\r\ntwo");
+        assert_eq!(term.take_damage(), Damage::Full);
+        assert_eq!(term.take_damage(), Damage::Lines(vec![1..2]));
+
+        // The line that is written to and the one that the cursor left
+        let mut processor = ansi::Processor::new();
+        for &byte in b"\x1b[1;3Hx" {
+            processor.advance(&mut term, byte, &mut io::sink());
+        }
+        assert_eq!(term.take_damage(), Damage::Lines(vec![0..2]));
+        assert_eq!(term.take_damage(), Damage::Lines(vec![0..1]));
+
+        // Scrolling into the history moves every line
+        for &byte in b"\r\n\r\n" {
+            processor.advance(&mut term, byte, &mut io::sink());
+        }
+        term.take_damage();
+        term.scroll_display(Scroll::Lines(1));
+        assert_eq!(term.take_damage(), Damage::Full);
+    }
+
+    #[test]
+    fn blinking_text_and_cursors_blink_together() {
+        let term = term_with_output(b"\x1b[5ma\x1b[25mb\x1b[3 q");
+        assert!(term.grid()[index::Line(0)][index::Column(0)]
+            .flags
+            .contains(cell::Flags::BLINK));
+        assert!(!term.grid()[index::Line(0)][index::Column(1)]
+            .flags
+            .contains(cell::Flags::BLINK));
+        assert!(term.cursor_blinks());
+        assert!(term.blink_visible(term.blink_start));
+        assert!(!term.blink_visible(term.blink_start + BLINK_INTERVAL));
+        assert!(term.blink_visible(term.blink_start + BLINK_INTERVAL * 2));
+
+        assert!(!term_with_output(b"\x1b[?12h\x1b[4 q").cursor_blinks());
+        assert!(term_with_output(b"\x1b[4 q\x1b[?12h").cursor_blinks());
+    }
+
+    #[test]
+    fn cursor_position_follows_output() {
+        let term = term_with_output(b"ab\r\nc");
+
+        assert_eq!(term.cursor_position(), Some((1, 1)));
+    }
+
+    #[test]
+    fn cursor_position_is_none_when_hidden() {
+        let term = term_with_output(b"ab\x1b[?25l");
+
+        assert_eq!(term.cursor_position(), None);
+    }
+
+    #[test]
+    fn cursor_position_is_none_when_scrolled_out_of_view() {
+        let mut term = term_with_output(b"one\r\ntwo\r\nthree\r\nfour");
+        term.scroll_display(Scroll::Lines(2));
+
+        assert_eq!(term.cursor_position(), None);
+    }
+
+    #[test]
+    fn alt_screen_is_reported() {
+        let term = term_with_output(b"one\x1b[?1049htwo");
+        assert!(term.alt_screen());
+
+        let term = term_with_output(b"one\x1b[?1049htwo\x1b[?1049l");
+        assert!(!term.alt_screen());
+    }
+
+    #[test]
+    fn bell_is_taken_once() {
+        let mut term = term_with_output(b"a\x07");
+        assert!(term.take_bell());
+        assert!(!term.take_bell());
+
+        // BEL also ends OSC sequences, which doesn't ring the bell
+        let mut term = term_with_output(b"\x1b]2;title\x07");
+        assert!(!term.take_bell());
+    }
+
+    #[test]
+    fn notifications_are_taken_once() {
+        let mut term = term_with_output(
+            b"\x1b]9;done; all 3\x07\x1b]9;4;1;50\x07\x1b]777;notify;build;failed\x1b\\",
+        );
+
+        assert_eq!(
+            term.take_notifications(),
+            vec![
+                ansi::Notification {
+                    title: None,
+                    body: "done; all 3".to_owned(),
+                },
+                ansi::Notification {
+                    title: Some("build".to_owned()),
+                    body: "failed".to_owned(),
+                },
+            ]
+        );
+        assert_eq!(term.take_notifications(), vec![]);
+    }
+
+    #[test]
+    fn invalid_utf8_is_printed_as_the_policy_says() {
+        let printed = |policy, output: &[u8]| {
+            let mut term = term_with_output(b"");
+            let mut processor = ansi::Processor::new();
+            processor.set_invalid_utf8(policy);
+            processor.advance_bytes(&mut term, output, &mut io::sink());
+            (0..2)
+                .flat_map(|line| {
+                    term.grid()[index::Line(line)]
+                        .into_iter()
+                        .map(|cell| cell.c)
+                })
+                .collect::<String>()
+        };
+
+        // The `b` and the `A` after broken characters are kept
+        assert_eq!(
+            printed(ansi::InvalidUtf8::Replace, b"\xffb\xc3A\xc3\xa9"),
+            "\u{fffd}b\u{fffd}Aé     "
+        );
+        assert_eq!(
+            printed(ansi::InvalidUtf8::Latin1, b"\xffb\xc3A\xc3\xa9"),
+            "ÿbÃAé     "
+        );
+        assert_eq!(
+            printed(ansi::InvalidUtf8::Hex, b"\xffb\xc3A"),
+            "\\xffb\\xc3A"
+        );
+    }
+
+    #[test]
+    fn clipboard_is_set_by_osc_52() {
+        // "aGVsbG8=" is "hello" in base64
+        let mut term = term_with_output(b"\x1b]52;c;aGVsbG8=\x07");
+
+        assert_eq!(term.get_next_clipboard(), Some(String::from("hello")));
+        assert_eq!(term.get_next_clipboard(), None);
+    }
+
+    #[test]
+    fn colors_are_set_and_reset_by_osc() {
+        let rgb = |r, g, b| Some(ansi::Rgb { r, g, b });
+        let mut term =
+            term_with_output(b"\x1b]4;1;#ff0000;200;rgb:00/80/ff\x07\x1b]11;#102030\x07");
+
+        assert_eq!(
+            term.dynamic_color(Color::Named(NamedColor::Red)),
+            rgb(255, 0, 0)
+        );
+        assert_eq!(term.dynamic_color(Color::Indexed(200)), rgb(0, 128, 255));
+        assert_eq!(
+            term.dynamic_color(Color::Named(NamedColor::Background)),
+            rgb(16, 32, 48)
+        );
+        assert_eq!(
+            term.dynamic_color(Color::Named(NamedColor::Foreground)),
+            None
+        );
+
+        let mut processor = ansi::Processor::new();
+        for byte in b"\x1b]104;1\x07\x1b]111\x07" {
+            processor.advance(&mut term, *byte, &mut io::sink());
+        }
+
+        assert_eq!(term.dynamic_color(Color::Named(NamedColor::Red)), None);
+        assert_eq!(term.dynamic_color(Color::Indexed(200)), rgb(0, 128, 255));
+        assert_eq!(
+            term.dynamic_color(Color::Named(NamedColor::Background)),
+            None
+        );
+    }
+
+    #[test]
+    fn visual_bell_ramps_down() {
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        let mut bell = VisualBell::new();
+        bell.configure(VisualBellAnimation::Linear, Duration::from_secs(2));
+        assert!(close(bell.intensity(), 0.0));
+
+        assert!(close(bell.ring(), 1.0));
+        let start = bell.start_time.unwrap();
+        let intensity = bell.intensity_at_instant(start + Duration::from_millis(500));
+        assert!(close(intensity, 0.75));
+        let intensity = bell.intensity_at_instant(start + Duration::from_secs(3));
+        assert!(close(intensity, 0.0));
+
+        bell.configure(VisualBellAnimation::EaseOutExpo, Duration::from_secs(2));
+        let intensity = bell.intensity_at_instant(start + Duration::from_millis(500));
+        assert!(intensity > 0.0 && intensity < 0.75);
+
+        bell.configure(VisualBellAnimation::Linear, Duration::from_secs(0));
+        assert!(close(bell.intensity_at_instant(start), 0.0));
+    }
+
+    #[test]
+    fn visual_bell_animations_ramp_from_full_to_off() {
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        let animations = [
+            VisualBellAnimation::Ease,
+            VisualBellAnimation::EaseOut,
+            VisualBellAnimation::EaseOutSine,
+            VisualBellAnimation::EaseOutQuad,
+            VisualBellAnimation::EaseOutCubic,
+            VisualBellAnimation::EaseOutQuart,
+            VisualBellAnimation::EaseOutQuint,
+            VisualBellAnimation::EaseOutExpo,
+            VisualBellAnimation::EaseOutCirc,
+            VisualBellAnimation::Linear,
+        ];
+        let mut bell = VisualBell::new();
+        bell.ring();
+        let start = bell.start_time.unwrap();
+        for &animation in &animations {
+            bell.configure(animation, Duration::from_secs(1));
+            let at = |millis| bell.intensity_at_instant(start + Duration::from_millis(millis));
+
+            assert!(close(at(0), 1.0), "{:?} starts at {}", animation, at(0));
+            assert!(close(at(1000), 0.0), "{:?} ends at {}", animation, at(1000));
+            let mut last = 1.0;
+            for millis in (100..1000).step_by(100) {
+                let intensity = at(millis);
+                assert!(intensity < last && intensity > 0.0, "{:?}", animation);
+                last = intensity;
+            }
+        }
+
+        // Easing out goes quicker at first
+        bell.configure(VisualBellAnimation::EaseOutQuad, Duration::from_secs(1));
+        let intensity = bell.intensity_at_instant(start + Duration::from_millis(500));
+        assert!((intensity - 0.25).abs() < 0.03, "{}", intensity);
+    }
+
+    #[test]
+    fn keyboard_modes_are_kept_per_screen() {
+        let mut term = term_with_output(b"\x1b[>1u\x1b[>9u");
+
+        assert!(term.mode().contains(TermMode::REPORT_ALL_KEYS));
+        assert_eq!(replies(&mut term, b"\x1b[?u"), "\x1b[?9u");
+
+        // The alternate screen starts without any, and the main screen's come back after it
+        assert_eq!(replies(&mut term, b"\x1b[?1049h\x1b[?u"), "\x1b[?0u");
+        assert_eq!(
+            replies(&mut term, b"\x1b[=3;1u\x1b[=2;3u\x1b[?u"),
+            "\x1b[?1u"
+        );
+        replies(&mut term, b"\x1b[?1049l");
+        assert!(term
+            .mode()
+            .contains(TermMode::DISAMBIGUATE_KEYS | TermMode::REPORT_ALL_KEYS));
+
+        assert_eq!(replies(&mut term, b"\x1b[<u\x1b[?u"), "\x1b[?1u");
+        replies(&mut term, b"\x1b[<5u");
+        assert!(!term.mode().contains(TermMode::DISAMBIGUATE_KEYS));
+    }
+
+    #[test]
+    fn alt_screen_has_no_scrollback() {
+        let mut term = term_with_output(b"\x1b[?1049hone\r\ntwo\r\nthree\r\nfour");
+        term.scroll_display(Scroll::Lines(2));
+
+        assert_eq!(term.grid().display_offset(), 0);
+        assert_eq!(term.history_to_string(false), "three\nfour\n");
+    }
+
+    #[test]
+    fn leaving_alt_screen_restores_view() {
+        let mut term = term_with_output(b"one\r\ntwo\r\nthree\r\nfour");
+        term.set_auto_scroll(false);
+        term.scroll_display(Scroll::Lines(1));
+
+        let mut processor = ansi::Processor::new();
+        for byte in b"\x1b[?1049hfull\r\nscreen\x1b[?1049l" {
+            processor.advance(&mut term, *byte, &mut io::sink());
+        }
+
+        assert_eq!(term.grid().display_offset(), 1);
+        assert_eq!(term.history_to_string(false), "one\ntwo\nthree\nfour\n");
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn restore_grid_keeps_serialized_output() {
+        let term = term_with_output("one\r\ntwo\u{302}\r\nthre\u{301}e".as_bytes());
+        let serialized = serde_json::to_string(term.grid()).unwrap();
+        let zero_width = serde_json::to_string(term.zero_width()).unwrap();
+
+        // With other zero-width characters than the saved ones
+        let mut restored = term_with_output("x\u{20e3}".as_bytes());
+        restored.restore_grid(
+            serde_json::from_str(&serialized).unwrap(),
+            &serde_json::from_str(&zero_width).unwrap(),
+        );
+
+        assert_eq!(
+            restored.history_to_string(false),
+            "one\ntwo\u{302}\nthre\u{301}e\n"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn cursor_and_modes_survive_serialization() {
+        let term = term_with_output(b"\x1b[1;31m\x1b(0ab\x1b[?1h");
+        let cursor: super::Cursor =
+            serde_json::from_str(&serde_json::to_string(&term.cursor).unwrap()).unwrap();
+        let mode: super::TermMode =
+            serde_json::from_str(&serde_json::to_string(&term.mode).unwrap()).unwrap();
+
+        assert_eq!(cursor.point, term.cursor.point);
+        assert_eq!(cursor.template, term.cursor.template);
+        assert_eq!(cursor.charsets.0, term.cursor.charsets.0);
+        assert_eq!(mode, term.mode);
+    }
+
+    #[test]
+    fn search_finds_matches_across_wrapped_lines() {
+        let term = term_with_output(b"abcdefg\r\nxcdx\r\n");
+        let at = |line, col| index::Point::new(line, index::Column(col));
+
+        // "abcde" wrapped onto "fg", and the cursor's empty line is at the bottom
+        assert_eq!(
+            term.search("cd", Direction::Forward, at(3, 0)),
+            vec![at(3, 2)..=at(3, 3), at(1, 1)..=at(1, 2)]
+        );
+        assert_eq!(
+            term.search("efg", Direction::Forward, at(3, 3)),
+            vec![at(3, 4)..=at(2, 1)]
+        );
+        assert_eq!(
+            term.search("cd", Direction::Backward, at(1, 1)),
+            vec![at(3, 2)..=at(3, 3)]
+        );
+        assert!(term.search("gx", Direction::Forward, at(3, 0)).is_empty());
+    }
+
+    #[test]
+    fn scrolling_stays_within_the_history() {
+        let mut term = term_with_output(b"one\r\ntwo\r\nthree\r\nfour");
+
+        term.scroll_display(Scroll::ToLine(1));
+        assert_eq!(term.grid().display_offset(), 1);
+        term.scroll_display(Scroll::PageUp);
+        assert_eq!(term.grid().display_offset(), 2);
+        term.scroll_display(Scroll::ToLine(5));
+        assert_eq!(term.grid().display_offset(), 0);
+        term.scroll_display(Scroll::Lines(-1));
+        assert_eq!(term.grid().display_offset(), 0);
+    }
+
+    #[test]
+    fn scrollback_budget_drops_the_oldest_lines() {
+        let mut term = term_with_output(b"1\r\n2\r\n3\r\n4\r\n5\r\n6");
+        let budget = term.line_bytes() * 5;
+        assert!(term.memory_usage() > budget);
+
+        // The two screens take up four lines of it
+        term.set_scrollback_budget(Some(budget));
+        assert_eq!(term.history_to_string(false), "4\n5\n6\n");
+        assert!(term.memory_usage() <= budget);
+
+        let mut processor = ansi::Processor::new();
+        processor.advance_bytes(&mut term, b"\r\n7\r\n8", &mut io::sink());
+        assert_eq!(term.history_to_string(false), "6\n7\n8\n");
+        assert!(term.memory_usage() <= budget);
+    }
+
+    #[test]
+    fn cursor_state_follows_the_program() {
+        let mut term = term_with_output(b"ab\x1b[?25l\x1b[5 q");
+
+        assert_eq!(
+            term.cursor_state(),
+            CursorState {
+                position: Some((2, 0)),
+                visible: false,
+                style: ansi::CursorStyle::Beam,
+                blinking: true,
+            }
+        );
+        assert_eq!(term.cursor_position(), None);
+
+        term.set_mode(ansi::Mode::ShowCursor);
+        assert_eq!(term.cursor_position(), Some((2, 0)));
+    }
+
+    #[test]
+    fn clearing_the_history_and_screen_keeps_the_modes() {
+        let mut term = term_with_output(b"one\r\ntwo\r\nthree\x1b[?1h");
+        term.scroll_display(Scroll::PageUp);
+
+        term.clear_history_and_screen();
+
+        assert_eq!(term.grid().display_offset(), 0);
+        assert_eq!(term.history_to_string(false), "");
+        assert_eq!(
+            (term.cursor().point.line, term.cursor().point.col),
+            (index::Line(0), index::Column(0))
+        );
+        assert!(term.mode().contains(TermMode::APP_CURSOR));
+    }
+
+    #[test]
+    fn clearing_the_history_on_the_alternate_screen_keeps_the_screen() {
+        let mut term = term_with_output(b"one\r\ntwo\r\nthree\x1b[?1049hvim");
+        let screen = term.recent_lines(2);
+
+        term.clear_history_and_screen();
+        assert_eq!(term.recent_lines(2), screen);
+
+        let mut processor = ansi::Processor::new();
+        processor.advance_bytes(&mut term, b"\x1b[?1049l", &mut io::sink());
+        // The normal screen is left as it was, without the line that scrolled off it
+        assert_eq!(term.history_to_string(false), "two\nthree\n");
+    }
+
+    #[test]
+    fn recent_lines_start_at_the_cursor() {
+        let term = term_with_output(b"one\r\ntwo\r\n42%");
+
+        assert_eq!(term.recent_lines(3), vec!["42%", "two"]);
+        assert_eq!(term.recent_lines(1), vec!["42%"]);
+    }
+
+    #[test]
+    fn history_to_string_includes_scrollback() {
+        let term = term_with_output(b"one\r\ntwo\r\nthree");
+
+        assert_eq!(term.history_to_string(false), "one\ntwo\nthree\n");
+    }
+
+    #[test]
+    fn history_to_string_joins_wrapped_lines() {
+        let term = term_with_output(b"abcdefg");
+
+        assert_eq!(term.history_to_string(false), "abcdefg\n");
+    }
+
+    #[test]
+    fn history_to_string_keeps_attributes() {
+        let term = term_with_output(b"\x1b[1;31mred\x1b[0m ok");
+
+        assert_eq!(
+            term.history_to_string(true),
+            "\x1b[0;1;31mred\x1b[0m ok\n\x1b[0m"
+        );
+        assert_eq!(term.history_to_string(false), "red ok\n");
+    }
 }
 
 #[cfg(all(test, feature = "bench"))]
@@ -0,0 +1,98 @@
+//! Ref tests: output that programs printed, in `tests/ref`, runs through a terminal of the size it
+//! was recorded at and has to leave the grid and zero-width characters that were saved with it.
+//! New ones are recorded with `examples/record.rs`.
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use terminal_emulator::term::cell::{Cell, ZeroWidthRuns};
+use terminal_emulator::term::{SizeInfo, Term};
+use terminal_emulator::{Grid, Processor};
+
+/// How many differences between the grids a failing test shows at most
+const MAX_DIFFERENCES: usize = 20;
+
+macro_rules! ref_tests {
+    ($($name:ident)*) => {
+        $(
+            #[test]
+            fn $name() {
+                ref_test(&Path::new(env!("CARGO_MANIFEST_DIR"))
+                    .join("tests/ref")
+                    .join(stringify!($name)));
+            }
+        )*
+    }
+}
+
+ref_tests! {
+    colors_and_attributes
+    scrolling_region
+    tabs_and_wrapping
+    wide_and_combining_chars
+}
+
+fn ref_test(dir: &Path) {
+    let read = |name| fs::read(dir.join(name)).unwrap();
+    let recording = read("recording");
+    let size: SizeInfo = serde_json::from_slice(&read("size.json")).unwrap();
+    let grid: Grid<Cell> = serde_json::from_slice(&read("grid.json")).unwrap();
+    let zero_width: ZeroWidthRuns = serde_json::from_slice(&read("zero_width.json")).unwrap();
+
+    let mut term = Term::new(size);
+    Processor::new().advance_bytes(&mut term, &recording, &mut io::sink());
+
+    assert_eq!(
+        *term.zero_width(),
+        zero_width,
+        "the zero-width characters differ from the ones in {}",
+        dir.display()
+    );
+    if *term.grid() != grid {
+        let mut found = Vec::new();
+        differences(
+            "grid",
+            &serde_json::to_value(&grid).unwrap(),
+            &serde_json::to_value(term.grid()).unwrap(),
+            &mut found,
+        );
+        found.truncate(MAX_DIFFERENCES);
+        panic!(
+            "the grid differs from the one in {}:\n{}",
+            dir.display(),
+            found.join("\n")
+        );
+    }
+}
+
+/// Collect where `actual` differs from `expected` into `found`, as paths into the JSON of the
+/// grid starting at `path`.
+fn differences(
+    path: &str,
+    expected: &serde_json::Value,
+    actual: &serde_json::Value,
+    found: &mut Vec<String>,
+) {
+    use serde_json::Value;
+
+    match (expected, actual) {
+        (Value::Array(expected), Value::Array(actual)) if expected.len() == actual.len() => {
+            for (index, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+                differences(&format!("{}[{}]", path, index), expected, actual, found);
+            }
+        }
+        (Value::Object(expected), Value::Object(actual)) => {
+            for (key, expected) in expected {
+                let path = format!("{}.{}", path, key);
+                match actual.get(key) {
+                    Some(actual) => differences(&path, expected, actual, found),
+                    None => found.push(format!("{}: missing", path)),
+                }
+            }
+        }
+        _ if expected != actual => {
+            found.push(format!("{}: expected {}, got {}", path, expected, actual));
+        }
+        _ => {}
+    }
+}